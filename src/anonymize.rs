@@ -0,0 +1,38 @@
+//! Pseudonyms of IP addresses for output which is shared. The mapping is a keyed FNV-1a hash,
+//! mixed by SplitMix64 so that close addresses get unrelated pseudonyms: it's consistent, so
+//! flows stay apart, but it's not cryptographically strong. Addresses of a known small range
+//! can be recovered by hashing all of them, unless the key is kept secret
+
+use crate::packet::fnv1a;
+use crate::sampling::Rng;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub struct Anonymizer {
+    key: Vec<u8>,
+}
+
+impl Anonymizer {
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.as_bytes().to_vec(),
+        }
+    }
+
+    /// Pseudonym of address, of the same family
+    pub fn pseudonym(&self, ip: IpAddr) -> IpAddr {
+        let octets = match ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        let hash = |salt: u8| {
+            let mut bytes = self.key.clone();
+            bytes.push(salt);
+            bytes.extend_from_slice(&octets);
+            Rng::new(fnv1a(&bytes)).next_u64()
+        };
+        match ip {
+            IpAddr::V4(_) => Ipv4Addr::from(hash(0) as u32).into(),
+            IpAddr::V6(_) => Ipv6Addr::from((hash(0) as u128) << 64 | hash(1) as u128).into(),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Compact binary format of the per-packet stream.
+//!
+//! Stream is a sequence of fixed-width 9-byte records without any header:
+//!
+//! | Offset | Size | Field                                                                      |
+//! |--------|------|----------------------------------------------------------------------------|
+//! | 0      | 8    | latency in usec, little-endian `i64` (0 for misses)                        |
+//! | 8      | 1    | match flag: 0 = miss, 1 = exact match, 2 = fuzzy match, 3 = fallback match |
+
+use std::io::{self, ErrorKind, Read, Write};
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum MatchKind {
+    Miss = 0,
+    Exact = 1,
+    Fuzzy = 2,
+    Fallback = 3,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Record {
+    pub latency: i64,
+    pub kind: MatchKind,
+}
+
+impl Record {
+    pub const SIZE: usize = 9;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.latency.to_le_bytes());
+        bytes[8] = self.kind as u8;
+        bytes
+    }
+
+    /// Returns `None` if the match flag is unknown
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Option<Self> {
+        let kind = match bytes[8] {
+            0 => MatchKind::Miss,
+            1 => MatchKind::Exact,
+            2 => MatchKind::Fuzzy,
+            3 => MatchKind::Fallback,
+            _ => return None,
+        };
+        let mut latency = [0u8; 8];
+        latency.copy_from_slice(&bytes[..8]);
+        Some(Self {
+            latency: i64::from_le_bytes(latency),
+            kind,
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+}
+
+/// Companion reader of the binary stream
+pub struct RecordReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> RecordReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: Read> Iterator for RecordReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = [0u8; Record::SIZE];
+        match self.reader.read_exact(&mut bytes) {
+            Ok(()) => Some(Record::from_bytes(&bytes).ok_or_else(|| {
+                io::Error::new(ErrorKind::InvalidData, "unknown match flag in record")
+            })),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Comparison of summaries of two runs, e.g. before and after a configuration change, and
+//! checking it against limits of regression
+
+use crate::summary::Summary;
+use serde::Serialize;
+
+/// Change of one metric between the runs
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Delta {
+    pub metric: &'static str,
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+    /// Change relative to `before`, in percent. Missing if either value is, or `before` is 0
+    pub change_percent: Option<f64>,
+}
+
+impl Delta {
+    fn new(metric: &'static str, before: Option<f64>, after: Option<f64>) -> Self {
+        let change_percent = match (before, after) {
+            (Some(before), Some(after)) if before != 0.0 => Some((after - before) / before * 100.0),
+            _ => None,
+        };
+        Self {
+            metric,
+            before,
+            after,
+            change_percent,
+        }
+    }
+}
+
+/// Value of metric in summary, missing if the run has no data for it
+type Metric = fn(&Summary) -> Option<f64>;
+
+/// Changes of latencies (usec), miss rate (percent of inbound packets) and counts
+pub fn deltas(before: &Summary, after: &Summary) -> Vec<Delta> {
+    let metrics: [(&'static str, Metric); 9] = [
+        ("average latency", |s| {
+            (s.matches > 0).then(|| s.average_latency() as f64)
+        }),
+        ("p50 latency", |s| s.percentiles.p50.map(|p| p as f64)),
+        ("p90 latency", |s| s.percentiles.p90.map(|p| p as f64)),
+        ("p99 latency", |s| s.percentiles.p99.map(|p| p as f64)),
+        ("max latency", |s| s.latency_max.map(|max| max as f64)),
+        ("rfc3550 jitter", |s| Some(s.rfc3550_jitter)),
+        ("miss rate", miss_rate),
+        ("packets", |s| Some(s.packets as f64)),
+        ("matches", |s| Some(s.matches as f64)),
+    ];
+    metrics
+        .iter()
+        .map(|(metric, value)| Delta::new(metric, value(before), value(after)))
+        .collect()
+}
+
+/// Percent of inbound packets without a match, missing if there were no packets
+fn miss_rate(summary: &Summary) -> Option<f64> {
+    (summary.packets > 0).then(|| summary.miss_percentage())
+}
+
+/// Limits of regression from the first run to the second one. Unset limits aren't checked
+#[derive(Debug, Clone, Default)]
+pub struct Thresholds {
+    /// Increase of average latency, percent
+    pub average_increase: Option<f64>,
+    /// Increase of p99 latency, percent
+    pub p99_increase: Option<f64>,
+    /// Increase of miss rate, percentage points
+    pub miss_rate_increase: Option<f64>,
+}
+
+impl Thresholds {
+    pub fn is_empty(&self) -> bool {
+        self.average_increase.is_none()
+            && self.p99_increase.is_none()
+            && self.miss_rate_increase.is_none()
+    }
+
+    /// Descriptions of limits which the change exceeds, empty if it passes. A metric missing
+    /// in either run isn't checked
+    pub fn violations(&self, before: &Summary, after: &Summary) -> Vec<String> {
+        let mut violations = Vec::new();
+        let deltas = deltas(before, after);
+        let change_percent = |metric: &str| {
+            deltas
+                .iter()
+                .find(|delta| delta.metric == metric)
+                .and_then(|delta| delta.change_percent)
+        };
+        for (metric, limit) in [
+            ("average latency", self.average_increase),
+            ("p99 latency", self.p99_increase),
+        ] {
+            if let (Some(limit), Some(change)) = (limit, change_percent(metric)) {
+                if change > limit {
+                    violations.push(format!(
+                        "{} increased by {:.1}% (limit {}%)",
+                        metric, change, limit
+                    ));
+                }
+            }
+        }
+        if let (Some(limit), Some(before), Some(after)) =
+            (self.miss_rate_increase, miss_rate(before), miss_rate(after))
+        {
+            if after - before > limit {
+                violations.push(format!(
+                    "miss rate increased by {:.2} percentage points (limit {})",
+                    after - before,
+                    limit
+                ));
+            }
+        }
+        violations
+    }
+}
@@ -0,0 +1,211 @@
+//! Reassembly of IPv4 packets from their fragments, so that a packet fragmented between the
+//! capture points is identified like the whole packet captured on the other side.
+//!
+//! Fragments are kept by addresses, identification and protocol until all of them are there,
+//! in any order. The reassembled packet follows the link-layer header of its first fragment and
+//! gets time of the fragment which completes it. Fragments of encapsulated packets are left as
+//! they are, and so are IPv6 ones: routers don't fragment IPv6 packets, only their source does
+
+use crate::packet;
+use crate::time::PacketTime;
+use pcap_parser::Linktype;
+use pnet::packet::ipv4::Ipv4Packet;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::Ipv4Addr;
+
+/// Packets whose fragments are kept at once, the oldest one is dropped beyond that
+const MAX_PARTIAL_PACKETS: usize = 4096;
+
+/// Fragments of a packet kept at most, it's dropped if it has more
+const MAX_FRAGMENTS: usize = 256;
+
+/// Time after the first fragment of a packet seen when its fragments are dropped if it isn't
+/// complete yet, as Linux does by default
+const FRAGMENT_TIMEOUT_NSEC: u64 = 30_000_000_000;
+
+/// What became of a frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Defragmented {
+    /// The frame isn't a fragment
+    Whole,
+    /// Fragment kept until the rest of its packet is there
+    Held,
+    /// The frame completed a packet: link-layer header of its first fragment and the
+    /// reassembled packet
+    Complete(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    identification: u16,
+    protocol: u8,
+}
+
+struct Partial {
+    /// Time of the first fragment seen
+    started: PacketTime,
+    /// Link-layer and IP header of the fragment at offset 0, once it's there
+    first: Option<(Vec<u8>, Vec<u8>)>,
+    /// Payload of fragments by offset in bytes
+    pieces: BTreeMap<usize, Vec<u8>>,
+    /// Length of the payload of the packet, known from its last fragment
+    len: Option<usize>,
+}
+
+impl Partial {
+    /// Reassembled frame if all fragments are there
+    fn complete(&self) -> Option<Vec<u8>> {
+        let (link_header, ip_header) = self.first.as_ref()?;
+        let len = self.len?;
+        let mut covered = 0;
+        for (offset, piece) in &self.pieces {
+            if *offset > covered {
+                return None;
+            }
+            covered = covered.max(offset + piece.len());
+        }
+        if covered < len || ip_header.len() + len > u16::MAX as usize {
+            return None;
+        }
+        let mut frame = link_header.clone();
+        let header_at = frame.len();
+        frame.extend_from_slice(ip_header);
+        let payload_at = frame.len();
+        frame.resize(payload_at + len, 0);
+        for (offset, piece) in &self.pieces {
+            let end = (offset + piece.len()).min(len);
+            if *offset < end {
+                frame[payload_at + offset..payload_at + end]
+                    .copy_from_slice(&piece[..end - offset]);
+            }
+        }
+        let total_len = (ip_header.len() + len) as u16;
+        let header = &mut frame[header_at..payload_at];
+        header[2..4].copy_from_slice(&total_len.to_be_bytes());
+        // Don't Fragment stays, More Fragments and the offset are cleared
+        header[6] &= 0x40;
+        header[7] = 0;
+        header[10..12].fill(0);
+        let checksum = header_checksum(header);
+        header[10..12].copy_from_slice(&checksum.to_be_bytes());
+        Some(frame)
+    }
+}
+
+/// Internet checksum of IPv4 header with zero checksum field
+fn header_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// IPv4 header of frame and its offset in the frame, if the frame is a fragment
+fn fragment(link_type: Linktype, bytes: &[u8]) -> Option<(usize, Ipv4Packet<'_>)> {
+    let ip = packet::ip_payload(link_type, bytes)?;
+    if *ip.first()? >> 4 != 4 {
+        return None;
+    }
+    let l3 = Ipv4Packet::new(ip)?;
+    // A header shorter than its fixed part is malformed, left to the rest of the reading
+    if l3.get_header_length() < 5 {
+        return None;
+    }
+    let more_fragments = l3.get_flags() & 0x1 != 0;
+    if !more_fragments && l3.get_fragment_offset() == 0 {
+        return None;
+    }
+    Some((bytes.len() - ip.len(), l3))
+}
+
+/// Whether frame is a fragment of an IPv4 packet
+pub fn is_fragment(link_type: Linktype, bytes: &[u8]) -> bool {
+    fragment(link_type, bytes).is_some()
+}
+
+#[derive(Default)]
+pub struct Defragmenter {
+    partial: HashMap<Key, Partial>,
+    /// Packets in order of their first fragment, to drop the oldest ones
+    order: VecDeque<(Key, PacketTime)>,
+}
+
+impl Defragmenter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps frame if it's a fragment, returns the packet it completes, if any
+    pub fn push(&mut self, link_type: Linktype, bytes: &[u8], time: PacketTime) -> Defragmented {
+        let Some((ip_at, l3)) = fragment(link_type, bytes) else {
+            return Defragmented::Whole;
+        };
+        self.expire(time);
+        let key = Key {
+            src: l3.get_source(),
+            dst: l3.get_destination(),
+            identification: l3.get_identification(),
+            protocol: l3.get_next_level_protocol().0,
+        };
+        let header_len = l3.get_header_length() as usize * 4;
+        let ip = &bytes[ip_at..];
+        let end = (l3.get_total_length() as usize).min(ip.len());
+        let Some(payload) = ip.get(header_len..end) else {
+            return Defragmented::Held;
+        };
+        let offset = l3.get_fragment_offset() as usize * 8;
+        let partial = self.partial.entry(key).or_insert_with(|| {
+            self.order.push_back((key, time));
+            Partial {
+                started: time,
+                first: None,
+                pieces: BTreeMap::new(),
+                len: None,
+            }
+        });
+        if offset == 0 {
+            partial.first = Some((bytes[..ip_at].to_vec(), ip[..header_len].to_vec()));
+        }
+        if l3.get_flags() & 0x1 == 0 {
+            partial.len = Some(offset + payload.len());
+        }
+        partial.pieces.insert(offset, payload.to_vec());
+        if partial.pieces.len() > MAX_FRAGMENTS {
+            self.partial.remove(&key);
+            return Defragmented::Held;
+        }
+        match partial.complete() {
+            Some(frame) => {
+                self.partial.remove(&key);
+                Defragmented::Complete(frame)
+            }
+            None => Defragmented::Held,
+        }
+    }
+
+    /// Drops packets whose first fragment is older than the timeout before `time`, and the
+    /// oldest ones beyond the limit of packets
+    fn expire(&mut self, time: PacketTime) {
+        while let Some((key, started)) = self.order.front().copied() {
+            // The packet may be complete already, and its key reused by a later one
+            let live = self
+                .partial
+                .get(&key)
+                .is_some_and(|partial| partial.started == started);
+            let expired = started.nsec + FRAGMENT_TIMEOUT_NSEC < time.nsec;
+            if live && !expired && self.partial.len() < MAX_PARTIAL_PACKETS {
+                break;
+            }
+            self.order.pop_front();
+            if live {
+                self.partial.remove(&key);
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Capture files of one side given by a directory or a wildcard pattern, e.g. rotated captures
+//! of `tcpdump -C` or `-G`, read one after another as a single capture.
+//!
+//! A pattern has `*` (any run of characters) or `?` (any one character) in its file name, the
+//! directory part is taken as it is. Files are ordered by name, with runs of digits compared by
+//! value, so `out.pcap9` comes before `out.pcap10` and names with a timestamp sort by time
+
+use std::cmp::Ordering;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+/// Whether path is a wildcard pattern rather than a path of a file
+pub fn is_pattern(path: &str) -> bool {
+    file_name(path).contains(['*', '?'])
+}
+
+/// Files of path in order: the files of a directory or the ones matching a pattern, skipping
+/// hidden ones and subdirectories, or the path itself otherwise. Fails if there are none
+pub fn files(path: &str) -> io::Result<Vec<String>> {
+    let (dir, pattern) = if is_pattern(path) {
+        let dir = &path[..path.len() - file_name(path).len()];
+        (if dir.is_empty() { "." } else { dir }, file_name(path))
+    } else if Path::new(path).is_dir() {
+        (path, "*")
+    } else {
+        return Ok(vec![path.to_string()]);
+    };
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if name.starts_with('.') || !matches(pattern, &name) || entry.path().is_dir() {
+            continue;
+        }
+        names.push(name);
+    }
+    if names.is_empty() {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("no capture files match {}", path),
+        ));
+    }
+    names.sort_by(|a, b| natural_cmp(a, b));
+    Ok(names
+        .into_iter()
+        .map(|name| Path::new(dir).join(name).to_string_lossy().into_owned())
+        .collect())
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Whether name matches pattern of `*` and `?` wildcards as a whole
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Position after the last `*` and the position in name it was matched up to, to backtrack
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((after, matched)) => {
+                    star = Some((after, matched + 1));
+                    p = after;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Order of names with runs of digits compared by value
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+                let (x_run, y_run) = (&a[..digits(a)], &b[..digits(b)]);
+                let (x_value, y_value) =
+                    (x_run.trim_start_matches('0'), y_run.trim_start_matches('0'));
+                let order = x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x_run.len().cmp(&y_run.len()));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a = &a[x_run.len()..];
+                b = &b[y_run.len()..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a = &a[x.len_utf8()..];
+                b = &b[y.len_utf8()..];
+            }
+        }
+    }
+}
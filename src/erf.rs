@@ -0,0 +1,135 @@
+//! Reader of Endace ERF (Extensible Record Format) captures of DAG cards.
+//!
+//! A file is a sequence of records without file header. Record header is 16 bytes: 64-bit
+//! little-endian timestamp (seconds in the upper 32 bits, binary fraction of second in the
+//! lower 32 bits), type, flags, big-endian record length, loss counter and wire length. Type's
+//! upper bit tells that 8-byte extension headers follow. Ethernet records have 2 bytes of
+//! offset and padding before the frame
+
+use crate::error::{Error, Result};
+use pcap_parser::Linktype;
+use std::io::Read;
+
+const HEADER_LEN: usize = 16;
+const EXTENSION_HEADER_LEN: usize = 8;
+const ETHERNET_PAD_LEN: usize = 2;
+
+const TYPE_ETH: u8 = 2;
+const TYPE_COLOR_ETH: u8 = 11;
+const TYPE_DSM_COLOR_ETH: u8 = 16;
+const TYPE_COLOR_HASH_ETH: u8 = 20;
+const TYPE_IPV4: u8 = 22;
+/// Highest record type defined, apart from padding records
+const TYPE_MAX: u8 = 27;
+const TYPE_PAD: u8 = 48;
+
+/// Timestamp fraction units per second
+pub const TS_RESOLUTION: u64 = 1 << 32;
+
+/// Checks that bytes look like header of ERF record, the format has no magic number
+pub fn looks_like_erf(header: &[u8]) -> bool {
+    let Some(header) = header.get(..HEADER_LEN) else {
+        return false;
+    };
+    let record_type = header[8] & 0x7f;
+    let rlen = u16::from_be_bytes([header[10], header[11]]) as usize;
+    ((1..=TYPE_MAX).contains(&record_type) || record_type == TYPE_PAD) && rlen >= HEADER_LEN
+}
+
+pub struct Record {
+    /// Timestamp in `TS_RESOLUTION` units since the epoch
+    pub ts: u64,
+    /// Link-layer type of data, `None` for records not carrying Ethernet or IPv4
+    pub link_type: Option<Linktype>,
+    pub data: Vec<u8>,
+}
+
+/// Outcome of reading a record
+pub enum Next {
+    Record(Record),
+    Eof,
+    /// Stream ended in the middle of a record. It may continue if the stream is a FIFO
+    Incomplete,
+}
+
+pub struct ErfReader<R: Read> {
+    stream: R,
+    /// Bytes of the record read so far
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> ErfReader<R> {
+    pub fn new(stream: R) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn next_record(&mut self) -> Result<Next> {
+        if !self.fill(HEADER_LEN)? {
+            return Ok(self.partial());
+        }
+        let rlen = u16::from_be_bytes([self.buffer[10], self.buffer[11]]) as usize;
+        if rlen < HEADER_LEN {
+            return Err(Error::Parse(format!(
+                "ERF record length {} is too small",
+                rlen
+            )));
+        }
+        if !self.fill(rlen)? {
+            return Ok(self.partial());
+        }
+        let record = std::mem::take(&mut self.buffer);
+        Ok(Next::Record(Self::parse(&record)))
+    }
+
+    /// Reads until buffer has `len` bytes. Returns `false` if the stream ended before
+    fn fill(&mut self, len: usize) -> Result<bool> {
+        let missing = len.saturating_sub(self.buffer.len());
+        (&mut self.stream)
+            .take(missing as u64)
+            .read_to_end(&mut self.buffer)?;
+        Ok(self.buffer.len() >= len)
+    }
+
+    fn partial(&self) -> Next {
+        if self.buffer.is_empty() {
+            Next::Eof
+        } else {
+            Next::Incomplete
+        }
+    }
+
+    fn parse(record: &[u8]) -> Record {
+        let ts = u64::from_le_bytes(record[0..8].try_into().expect("header is 16 bytes"));
+        let record_type = record[8] & 0x7f;
+        let wlen = u16::from_be_bytes([record[14], record[15]]) as usize;
+        // Every extension header has "more headers follow" flag in the upper bit
+        let mut offset = HEADER_LEN;
+        let mut more = record[8] & 0x80 != 0;
+        while more {
+            let Some(extension) = record.get(offset) else {
+                break;
+            };
+            more = extension & 0x80 != 0;
+            offset += EXTENSION_HEADER_LEN;
+        }
+        let link_type = match record_type {
+            TYPE_ETH | TYPE_COLOR_ETH | TYPE_DSM_COLOR_ETH | TYPE_COLOR_HASH_ETH => {
+                offset += ETHERNET_PAD_LEN;
+                Some(Linktype::ETHERNET)
+            }
+            TYPE_IPV4 => Some(Linktype::RAW),
+            _ => None,
+        };
+        // Record can be padded beyond the captured frame
+        let data = record.get(offset..).unwrap_or_default();
+        let data = &data[..data.len().min(wlen)];
+        Record {
+            ts,
+            link_type,
+            data: data.to_vec(),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::io;
+use thiserror::Error;
+
+/// Errors returned by the library API
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("file doesn't look like a pcap file (magic bytes {0:02x?})")]
+    BadMagic([u8; 4]),
+
+    #[error("unsupported link-layer type {0}")]
+    UnsupportedLinkType(i32),
+
+    #[error("file is truncated (last packet is incomplete)")]
+    Truncated,
+
+    #[error("error while parsing pcap: {0}")]
+    Parse(String),
+
+    #[error("live capture error: {0}")]
+    Capture(String),
+
+    #[error("invalid summary: {0}")]
+    BadSummary(String),
+
+    #[error("invalid filter: {0}")]
+    BadFilter(String),
+
+    #[error("invalid reference log: {0}")]
+    BadReference(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
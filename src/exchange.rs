@@ -0,0 +1,155 @@
+//! Request/response identities of a single capture taken on one host.
+//!
+//! Packets sent by the local host are requests, packets it receives are responses. A request
+//! and its response get the same identity, so reading the capture twice, once for each
+//! direction, gives the two sides for matching. A TCP segment with data (or SYN, FIN) is
+//! answered by the first segment whose acknowledgement number is exactly its end (`seq + len`),
+//! so of segments acknowledged at once by a cumulative ACK only the last one is answered and
+//! the earlier ones are misses. An ICMP echo request is answered by the echo reply with its
+//! identifier and sequence number. With no local host given, every host is one: a packet is a
+//! request of its sender and a response to its receiver. HTTP exchanges are built from
+//! reassembled streams instead, see `http`
+
+use crate::packet::{self, PacketId, ParseOptions};
+use pcap_parser::Linktype;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::tcp::{TcpFlags, TcpPacket};
+use pnet::packet::Packet;
+use std::net::IpAddr;
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// How packets of the local host are told from packets of other hosts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Local {
+    Ip(IpAddr),
+    /// MAC address, only Ethernet frames have both source and destination ones
+    Mac([u8; 6]),
+    /// Every host, the sender of a request is the local host of its exchange
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Packets sent by the local host
+    Request,
+    /// Packets received by the local host
+    Response,
+}
+
+/// Options of building identities of one direction of request/response exchanges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exchange {
+    pub local: Local,
+    pub direction: Direction,
+    /// Pair HTTP/1.1 requests and responses of reassembled TCP streams instead of packets
+    pub http: bool,
+}
+
+impl Exchange {
+    /// Direction of frame, `None` if it's neither sent nor received by the local host
+    pub(crate) fn direction_of(
+        &self,
+        link_type: Linktype,
+        bytes: &[u8],
+        ip_src: IpAddr,
+        ip_dst: IpAddr,
+    ) -> Option<Direction> {
+        let (is_src, is_dst) = match self.local {
+            Local::Ip(ip) => (ip_src == ip, ip_dst == ip),
+            Local::Mac(mac) => {
+                if link_type != Linktype::ETHERNET {
+                    return None;
+                }
+                (bytes.get(6..12)? == mac, bytes.get(0..6)? == mac)
+            }
+            Local::Any => return Some(self.direction),
+        };
+        match (is_src, is_dst) {
+            (true, false) => Some(Direction::Request),
+            (false, true) => Some(Direction::Response),
+            _ => None,
+        }
+    }
+
+    /// Identity of frame of `direction`, `None` for frames of the other direction and for
+    /// those which are neither requests nor responses
+    pub(crate) fn identity(
+        &self,
+        link_type: Linktype,
+        bytes: &[u8],
+        options: &ParseOptions,
+    ) -> Option<PacketId> {
+        if self.http {
+            return None;
+        }
+        let (ip_src, ip_dst, protocol, l4) =
+            packet::transport(packet::ip_payload(link_type, bytes)?)?;
+        if !options.allows(protocol) {
+            return None;
+        }
+        let direction = self.direction_of(link_type, bytes, ip_src, ip_dst)?;
+        if direction != self.direction {
+            return None;
+        }
+        let (ip_local, ip_remote) = match direction {
+            Direction::Request => (ip_src, ip_dst),
+            Direction::Response => (ip_dst, ip_src),
+        };
+        match protocol {
+            IpNextHeaderProtocols::Tcp => {
+                let l4 = TcpPacket::new(l4)?;
+                let flags = l4.get_flags();
+                let (port_local, port_remote) = match direction {
+                    Direction::Request => (l4.get_source(), l4.get_destination()),
+                    Direction::Response => (l4.get_destination(), l4.get_source()),
+                };
+                let tcp_ack = match direction {
+                    // Sequence number following the data, SYN and FIN take one each
+                    Direction::Request => {
+                        let len = l4.payload().len() as u32
+                            + (flags & TcpFlags::SYN != 0) as u32
+                            + (flags & TcpFlags::FIN != 0) as u32;
+                        if len == 0 {
+                            return None;
+                        }
+                        l4.get_sequence().wrapping_add(len)
+                    }
+                    Direction::Response if flags & TcpFlags::ACK != 0 => l4.get_acknowledgement(),
+                    Direction::Response => return None,
+                };
+                Some(PacketId::TcpExchange {
+                    ip_local,
+                    ip_remote,
+                    port_local,
+                    port_remote,
+                    tcp_ack,
+                })
+            }
+            IpNextHeaderProtocols::Icmp | IpNextHeaderProtocols::Icmpv6 => {
+                let (request, reply) = match protocol {
+                    IpNextHeaderProtocols::Icmp => (ICMP_ECHO_REQUEST, ICMP_ECHO_REPLY),
+                    _ => (ICMPV6_ECHO_REQUEST, ICMPV6_ECHO_REPLY),
+                };
+                let expected = match direction {
+                    Direction::Request => request,
+                    Direction::Response => reply,
+                };
+                if *l4.first()? != expected {
+                    return None;
+                }
+                let header = l4.get(4..8)?;
+                Some(PacketId::IcmpEcho {
+                    ip_local,
+                    ip_remote,
+                    identifier: u16::from_be_bytes([header[0], header[1]]),
+                    sequence: u16::from_be_bytes([header[2], header[3]]),
+                })
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Extension point of packet identification, for protocols the tool doesn't know. Custom
+//! extractors are added to `ParseOptions::extractors` and get every frame before the built-in
+//! identification, which is itself the `IdentityExtractor` of `ParseOptions`
+
+use crate::packet::{PacketId, ParseOptions};
+use pcap_parser::Linktype;
+use std::fmt;
+use std::sync::Arc;
+
+/// Builds identity of frame. Custom extractors usually return `PacketId::Custom`
+pub trait IdentityExtractor: Send + Sync {
+    /// Identity of frame of link-layer type, without FCS. `None` leaves the frame to the next
+    /// extractor
+    fn extract(&self, link_type: Linktype, bytes: &[u8]) -> Option<PacketId>;
+}
+
+/// Functions are extractors, so one can be registered without a type of its own
+impl<F> IdentityExtractor for F
+where
+    F: Fn(Linktype, &[u8]) -> Option<PacketId> + Send + Sync,
+{
+    fn extract(&self, link_type: Linktype, bytes: &[u8]) -> Option<PacketId> {
+        self(link_type, bytes)
+    }
+}
+
+/// Built-in identification: TCP, ICMP, RTP, DNS and the others of `Protocol`
+impl IdentityExtractor for ParseOptions {
+    fn extract(&self, link_type: Linktype, bytes: &[u8]) -> Option<PacketId> {
+        PacketId::new_from_frame(link_type, bytes, self)
+    }
+}
+
+/// Custom extractors, tried in order of adding. The first identity given wins
+#[derive(Clone, Default)]
+pub struct Extractors(Vec<Arc<dyn IdentityExtractor>>);
+
+impl Extractors {
+    pub fn push(&mut self, extractor: impl IdentityExtractor + 'static) {
+        self.0.push(Arc::new(extractor));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl IdentityExtractor for Extractors {
+    fn extract(&self, link_type: Linktype, bytes: &[u8]) -> Option<PacketId> {
+        self.0
+            .iter()
+            .find_map(|extractor| extractor.extract(link_type, bytes))
+    }
+}
+
+impl fmt::Debug for Extractors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Extractors({})", self.0.len())
+    }
+}
@@ -0,0 +1,144 @@
+//! Grid of miss rate by flow and time interval, for rendering as a heatmap. Intervals are
+//! aligned to the epoch, so grids of runs with the same interval line up
+
+use crate::time::PacketTime;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// Intervals of a grid at most for every one from the first to the last to be a column, e.g.
+/// about 3 hours of 1 s intervals. Beyond it only intervals with packets are
+const MAX_CONTIGUOUS_INTERVALS: u64 = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct Heatmap {
+    interval_nsec: u64,
+    /// Inbound packets and misses of each flow, by number of interval since the epoch
+    cells: BTreeMap<String, BTreeMap<u64, (u64, u64)>>,
+}
+
+/// JSON form: `packets` and `misses` of each flow have a value for every interval
+#[derive(Serialize)]
+struct Grid<'a> {
+    interval_sec: f64,
+    /// Start of every interval, seconds since the epoch
+    intervals: Vec<String>,
+    flows: Vec<FlowRow<'a>>,
+}
+
+#[derive(Serialize)]
+struct FlowRow<'a> {
+    flow: &'a str,
+    packets: Vec<u64>,
+    misses: Vec<u64>,
+}
+
+impl Heatmap {
+    pub fn new(interval_nsec: u64) -> Self {
+        Self {
+            interval_nsec: interval_nsec.max(1),
+            cells: BTreeMap::new(),
+        }
+    }
+
+    /// Counts inbound packet of flow at time, matched or missed
+    pub fn add(&mut self, flow: String, time: PacketTime, missed: bool) {
+        let cell = self
+            .cells
+            .entry(flow)
+            .or_default()
+            .entry(time.nsec / self.interval_nsec)
+            .or_default();
+        cell.0 += 1;
+        cell.1 += missed as u64;
+    }
+
+    /// Every interval from the first to the last one with packets of any flow, or only the
+    /// ones with packets if there are more than `MAX_CONTIGUOUS_INTERVALS`, e.g. because of a
+    /// packet with a bogus timestamp
+    fn intervals(&self) -> Vec<u64> {
+        let intervals = || self.cells.values().flat_map(|row| row.keys().copied());
+        match (intervals().min(), intervals().max()) {
+            (Some(first), Some(last)) if last - first < MAX_CONTIGUOUS_INTERVALS => {
+                (first..=last).collect()
+            }
+            (Some(_), Some(_)) => {
+                let mut intervals: Vec<u64> = intervals().collect();
+                intervals.sort_unstable();
+                intervals.dedup();
+                intervals
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn interval_start(&self, interval: u64) -> String {
+        PacketTime {
+            nsec: interval * self.interval_nsec,
+        }
+        .to_string()
+    }
+
+    /// CSV with a row per flow and a column per interval (headed by its start). Cells are miss
+    /// rates, empty for intervals without packets of the flow
+    pub fn write_csv(&self, w: &mut impl Write) -> io::Result<()> {
+        let intervals = self.intervals();
+        write!(w, "flow")?;
+        for interval in &intervals {
+            write!(w, ",{}", self.interval_start(*interval))?;
+        }
+        writeln!(w)?;
+        for (flow, row) in &self.cells {
+            write!(w, "{}", csv_field(flow))?;
+            for interval in &intervals {
+                match row.get(interval) {
+                    Some((packets, misses)) => write!(w, ",{}", *misses as f64 / *packets as f64)?,
+                    None => write!(w, ",")?,
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// JSON object with counts of packets and misses, so rates of sparse cells can be weighted
+    pub fn write_json(&self, w: &mut impl Write, pretty: bool) -> io::Result<()> {
+        let intervals = self.intervals();
+        let count = |row: &BTreeMap<u64, (u64, u64)>, field: fn(&(u64, u64)) -> u64| {
+            intervals
+                .iter()
+                .map(|interval| row.get(interval).map_or(0, field))
+                .collect()
+        };
+        let grid = Grid {
+            interval_sec: self.interval_nsec as f64 / 1e9,
+            intervals: intervals
+                .iter()
+                .map(|interval| self.interval_start(*interval))
+                .collect(),
+            flows: self
+                .cells
+                .iter()
+                .map(|(flow, row)| FlowRow {
+                    flow,
+                    packets: count(row, |cell| cell.0),
+                    misses: count(row, |cell| cell.1),
+                })
+                .collect(),
+        };
+        match pretty {
+            true => serde_json::to_writer_pretty(&mut *w, &grid)?,
+            false => serde_json::to_writer(&mut *w, &grid)?,
+        }
+        writeln!(w)
+    }
+}
+
+/// Field quoted if it has a delimiter or quote
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,364 @@
+//! HTTP/1.1 exchanges of reassembled TCP streams, for `exchange` with `Exchange::http`.
+//!
+//! Both directions of every connection are reassembled (see `reassembly`) and cut into
+//! messages by their header: `Content-Length`, chunked transfer coding, or the end of the
+//! connection for responses with neither. Only the request and status lines and these fields
+//! are parsed. Requests of a connection are answered in order, also when pipelined, so the n-th
+//! request and the n-th final (not 1xx) response get one identity: the connection and n. A
+//! request gets time of the segment which completes it, a response of the segment which
+//! completes its header, so their latency is the time the server took to start responding.
+//!
+//! Connections are followed from their SYN: requests and responses of connections open when
+//! the capture started can't be paired by order. A connection is dropped if its stream can't
+//! be followed (a segment wasn't captured), isn't HTTP or switches protocols
+
+use crate::exchange::{Direction, Exchange, Local};
+use crate::packet::{PacketId, TcpSegment};
+use crate::reassembly::{Flow, FlowKey};
+use crate::time::PacketTime;
+use pcap_parser::Linktype;
+use std::collections::{HashMap, VecDeque};
+
+/// Longest header (or chunk size line) of a message, the connection is dropped if it's
+/// exceeded
+const MAX_HEADER_LEN: usize = 64 * 1024;
+
+/// Where the stream of one direction is within its messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Before the header of a message
+    Header,
+    /// Bytes of body left
+    Body(u64),
+    /// Before the size line of a chunk
+    ChunkSize,
+    /// Bytes of chunk left, with the CRLF ending it
+    Chunk(u64),
+    /// Trailer fields after the last chunk, ended by an empty line
+    Trailer,
+    /// Body ends with the connection
+    UntilClose,
+}
+
+/// Messages of one direction of a connection
+struct Side {
+    flow: Flow,
+    state: State,
+    /// Requests or final responses counted so far
+    messages: u32,
+    /// FIN or RST was seen
+    ended: bool,
+}
+
+impl Side {
+    fn new(next_seq: u32) -> Self {
+        Self {
+            flow: Flow::new(next_seq),
+            state: State::Header,
+            messages: 0,
+            ended: false,
+        }
+    }
+}
+
+struct Connection {
+    /// The server is the local host of the exchange, otherwise the client is
+    local_is_server: bool,
+    requests: Side,
+    /// Responses of the server, from its SYN-ACK
+    responses: Option<Side>,
+    /// Whether requests not answered yet are HEAD ones, whose responses have no body
+    unanswered_heads: VecDeque<bool>,
+}
+
+/// Request or status line and the fields of header which frame the body
+struct Head {
+    /// Method of request, `None` for responses
+    method: Option<Vec<u8>>,
+    /// Status code of response, 0 for requests
+    status: u16,
+    content_length: Option<u64>,
+    chunked: bool,
+}
+
+impl Head {
+    /// Parses header up to the empty line ending it. `None` if it's not HTTP/1.x
+    fn parse(header: &[u8]) -> Option<Self> {
+        let mut lines = header
+            .split(|byte| *byte == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+        let start_line = lines.next()?;
+        let (method, status) = match start_line.strip_prefix(b"HTTP/1.") {
+            Some(rest) => {
+                let status = std::str::from_utf8(rest.get(2..5)?).ok()?;
+                if rest.get(1) != Some(&b' ') || !status.bytes().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                (None, status.parse().ok()?)
+            }
+            None => {
+                let mut parts = start_line.split(|byte| *byte == b' ');
+                let method = parts.next()?;
+                let target = parts.next()?;
+                let version = parts.next()?;
+                if method.is_empty()
+                    || !method.iter().all(u8::is_ascii_uppercase)
+                    || target.is_empty()
+                    || !version.starts_with(b"HTTP/1.")
+                    || parts.next().is_some()
+                {
+                    return None;
+                }
+                (Some(method.to_vec()), 0)
+            }
+        };
+        let mut head = Self {
+            method,
+            status,
+            content_length: None,
+            chunked: false,
+        };
+        for line in lines.filter(|line| !line.is_empty()) {
+            let colon = line.iter().position(|byte| *byte == b':')?;
+            let name = &line[..colon];
+            let value = std::str::from_utf8(&line[colon + 1..]).ok()?.trim();
+            if name.eq_ignore_ascii_case(b"content-length") {
+                let len = value.parse().ok()?;
+                if head.content_length.is_some_and(|previous| previous != len) {
+                    return None;
+                }
+                head.content_length = Some(len);
+            } else if name.eq_ignore_ascii_case(b"transfer-encoding") {
+                head.chunked = value
+                    .rsplit(',')
+                    .next()
+                    .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"));
+            }
+        }
+        Some(head)
+    }
+
+    /// State of stream after the header, framing the body of message
+    fn body(&self, bodiless: bool) -> State {
+        match (bodiless, self.chunked, self.content_length) {
+            (true, _, _) => State::Body(0),
+            (false, true, _) => State::ChunkSize,
+            (false, false, Some(len)) => State::Body(len),
+            // A request without either has no body, a response lasts until the connection ends
+            (false, false, None) if self.method.is_some() => State::Body(0),
+            (false, false, None) => State::UntilClose,
+        }
+    }
+}
+
+/// Builds identities of HTTP requests or responses of one direction of exchanges from TCP
+/// segments of all connections
+pub struct HttpExchanges {
+    exchange: Exchange,
+    /// Connections by key of their client to server direction
+    connections: HashMap<FlowKey, Connection>,
+}
+
+impl HttpExchanges {
+    pub fn new(exchange: Exchange) -> Self {
+        Self {
+            exchange,
+            connections: HashMap::new(),
+        }
+    }
+
+    /// Adds segment of frame to its connection, appends to `messages` identities of requests or
+    /// responses (by direction of the exchange) it completes
+    pub(crate) fn push(
+        &mut self,
+        link_type: Linktype,
+        bytes: &[u8],
+        segment: &TcpSegment,
+        time: PacketTime,
+        messages: &mut VecDeque<(PacketId, PacketTime)>,
+    ) {
+        let key = FlowKey::of(segment);
+        // SYN takes one sequence number before the data
+        let seq = segment.seq.wrapping_add(segment.syn as u32);
+        if segment.syn {
+            match self.connections.get_mut(&key.reversed()) {
+                // SYN-ACK of the server
+                Some(connection) => connection.responses = Some(Side::new(seq)),
+                None => {
+                    // Connections of other hosts than the local one are left out
+                    let local_is_server = match self.exchange.local {
+                        Local::Any => false,
+                        _ => match self.exchange.direction_of(
+                            link_type,
+                            bytes,
+                            segment.ip_src,
+                            segment.ip_dst,
+                        ) {
+                            Some(direction) => direction == Direction::Response,
+                            None => return,
+                        },
+                    };
+                    let connection = Connection {
+                        local_is_server,
+                        requests: Side::new(seq),
+                        responses: None,
+                        unanswered_heads: VecDeque::new(),
+                    };
+                    self.connections.insert(key, connection);
+                }
+            }
+        }
+        let (client, is_request) = if self.connections.contains_key(&key) {
+            (key, true)
+        } else {
+            (key.reversed(), false)
+        };
+        let Some(connection) = self.connections.get_mut(&client) else {
+            return;
+        };
+        let Connection {
+            local_is_server,
+            requests,
+            responses,
+            unanswered_heads,
+        } = connection;
+        let side = match is_request {
+            true => requests,
+            false => match responses {
+                Some(side) => side,
+                None => return,
+            },
+        };
+        let wanted = match is_request {
+            true => Direction::Request,
+            false => Direction::Response,
+        } == self.exchange.direction;
+        let mut emit = |index: u32| {
+            if wanted {
+                messages.push_back((exchange_id(&client, *local_is_server, index), time));
+            }
+        };
+        let followed = side.flow.add_segment(seq, segment)
+            && cut(side, is_request, unanswered_heads, &mut emit);
+        side.ended |= segment.end;
+        let ended = connection.requests.ended
+            && connection.responses.as_ref().is_none_or(|side| side.ended);
+        if !followed || ended {
+            self.connections.remove(&client);
+        }
+    }
+}
+
+/// Cuts messages of one direction from its stream, calls `emit` with the index of every
+/// request or final response. Returns `false` if the stream isn't HTTP
+fn cut(
+    side: &mut Side,
+    is_request: bool,
+    unanswered_heads: &mut VecDeque<bool>,
+    emit: &mut impl FnMut(u32),
+) -> bool {
+    let buffer = &mut side.flow.buffer;
+    loop {
+        match side.state {
+            State::Header => {
+                let Some(end) = find(buffer, b"\r\n\r\n") else {
+                    return buffer.len() <= MAX_HEADER_LEN;
+                };
+                let Some(head) = Head::parse(&buffer[..end]) else {
+                    return false;
+                };
+                buffer.drain(..end + 4);
+                if head.method.is_some() != is_request {
+                    return false;
+                }
+                if is_request {
+                    unanswered_heads.push_back(head.method.as_deref() == Some(b"HEAD"));
+                    side.state = head.body(false);
+                    continue;
+                }
+                match head.status {
+                    101 => return false,
+                    // Interim response, the final one follows
+                    100..=199 => continue,
+                    _ => {}
+                }
+                let is_head = unanswered_heads.pop_front().unwrap_or(false);
+                side.state = head.body(is_head || head.status == 204 || head.status == 304);
+                emit(side.messages);
+                side.messages += 1;
+            }
+            State::Body(left) | State::Chunk(left) => {
+                let taken = left.min(buffer.len() as u64);
+                buffer.drain(..taken as usize);
+                let is_chunk = matches!(side.state, State::Chunk(_));
+                let left = left - taken;
+                if left > 0 {
+                    side.state = match is_chunk {
+                        true => State::Chunk(left),
+                        false => State::Body(left),
+                    };
+                    return true;
+                }
+                if is_chunk {
+                    side.state = State::ChunkSize;
+                    continue;
+                }
+                if is_request {
+                    emit(side.messages);
+                    side.messages += 1;
+                }
+                side.state = State::Header;
+            }
+            State::ChunkSize => {
+                let Some(end) = find(buffer, b"\r\n") else {
+                    return buffer.len() <= MAX_HEADER_LEN;
+                };
+                let line = String::from_utf8_lossy(&buffer[..end]);
+                let size = line.split(';').next().unwrap_or_default().trim();
+                let Ok(size) = u64::from_str_radix(size, 16) else {
+                    return false;
+                };
+                buffer.drain(..end + 2);
+                side.state = match size {
+                    0 => State::Trailer,
+                    _ => State::Chunk(size + 2),
+                };
+            }
+            State::Trailer => {
+                let Some(end) = find(buffer, b"\r\n") else {
+                    return buffer.len() <= MAX_HEADER_LEN;
+                };
+                buffer.drain(..end + 2);
+                if end == 0 {
+                    // The body ended, as if it had its length
+                    side.state = State::Body(0);
+                }
+            }
+            State::UntilClose => {
+                buffer.clear();
+                return true;
+            }
+        }
+    }
+}
+
+fn find(bytes: &[u8], needle: &[u8]) -> Option<usize> {
+    bytes
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Identity of the exchange of connection of index
+fn exchange_id(client: &FlowKey, local_is_server: bool, index: u32) -> PacketId {
+    let (local, remote) = match local_is_server {
+        true => (client.reversed(), *client),
+        false => (*client, client.reversed()),
+    };
+    PacketId::HttpExchange {
+        ip_local: local.ip_src,
+        ip_remote: remote.ip_src,
+        port_local: local.port_src,
+        port_remote: remote.port_src,
+        index,
+    }
+}
@@ -0,0 +1,392 @@
+//! Fields which make up identities, see `--key`. Identities are built as usual and the fields
+//! left out of the key are then cleared, so packets which differ only in them get one identity
+
+use crate::packet::PacketId;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// Kinds of identities whose fields can be chosen, with their fields in order of `PacketId`.
+/// Neighbor Discovery and custom identities are left out, their fields don't stand alone
+pub const KINDS: &[(&str, &[&str])] = &[
+    (
+        "tcp",
+        &[
+            "ip_src", "ip_dst", "port_src", "port_dst", "tcp_seq", "tcp_ack",
+        ],
+    ),
+    (
+        "tcp_payload",
+        &[
+            "ip_src",
+            "ip_dst",
+            "port_src",
+            "port_dst",
+            "payload_len",
+            "payload_hash",
+        ],
+    ),
+    (
+        "tcp_message",
+        &[
+            "ip_src",
+            "ip_dst",
+            "port_src",
+            "port_dst",
+            "message_len",
+            "message_hash",
+        ],
+    ),
+    ("icmp", &["ip_src", "ip_dst", "checksum"]),
+    ("rtp", &["ssrc", "seq", "timestamp"]),
+    (
+        "tcp_exchange",
+        &[
+            "ip_local",
+            "ip_remote",
+            "port_local",
+            "port_remote",
+            "tcp_ack",
+        ],
+    ),
+    (
+        "http_exchange",
+        &[
+            "ip_local",
+            "ip_remote",
+            "port_local",
+            "port_remote",
+            "index",
+        ],
+    ),
+    (
+        "icmp_echo",
+        &["ip_local", "ip_remote", "identifier", "sequence"],
+    ),
+    ("dns", &["transaction_id", "query_name", "qtype"]),
+    (
+        "ip_id",
+        &[
+            "ip_src",
+            "ip_dst",
+            "ip_id",
+            "fragment_offset",
+            "payload_len",
+        ],
+    ),
+    (
+        "ethernet",
+        &["src_mac", "dst_mac", "ethertype", "payload_hash"],
+    ),
+    ("bytes", &["ip_src", "ip_dst", "key"]),
+];
+
+/// One `--key` value: `[KIND:]FIELD,FIELD...`. Without a kind the fields apply to every kind
+/// which has all of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySpec(Vec<(&'static str, Vec<&'static str>)>);
+
+impl FromStr for KeySpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, fields) = match s.split_once(':') {
+            Some((kind, fields)) => (Some(kind), fields),
+            None => (None, s),
+        };
+        let names: Vec<&str> = fields.split(',').map(str::trim).collect();
+        if names.iter().any(|name| name.is_empty()) {
+            return Err(format!("expected [KIND:]FIELD,FIELD..., got `{}`", s));
+        }
+        let kinds: Vec<(&'static str, &'static [&'static str])> = match kind {
+            Some(kind) => {
+                let found = KINDS
+                    .iter()
+                    .find(|(name, _)| *name == kind)
+                    .ok_or_else(|| {
+                        let kinds: Vec<&str> = KINDS.iter().map(|(name, _)| *name).collect();
+                        format!(
+                            "unknown identity `{}`, expected one of: {}",
+                            kind,
+                            kinds.join(", ")
+                        )
+                    })?;
+                if let Some(name) = names.iter().find(|name| !found.1.contains(name)) {
+                    return Err(format!(
+                        "unknown field `{}` of {} identities, expected: {}",
+                        name,
+                        found.0,
+                        found.1.join(", ")
+                    ));
+                }
+                vec![*found]
+            }
+            None => {
+                if let Some(name) = names
+                    .iter()
+                    .find(|name| !KINDS.iter().any(|(_, fields)| fields.contains(name)))
+                {
+                    return Err(format!("unknown field `{}`", name));
+                }
+                let kinds: Vec<_> = KINDS
+                    .iter()
+                    .filter(|(_, fields)| names.iter().all(|name| fields.contains(name)))
+                    .copied()
+                    .collect();
+                if kinds.is_empty() {
+                    return Err(format!(
+                        "no identity has all of fields {}, give fields of each kind in a --key of its own",
+                        names.join(",")
+                    ));
+                }
+                kinds
+            }
+        };
+        Ok(Self(
+            kinds
+                .into_iter()
+                .map(|(kind, fields)| {
+                    let kept = fields
+                        .iter()
+                        .copied()
+                        .filter(|field| names.contains(field))
+                        .collect();
+                    (kind, kept)
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Fields kept in identities by kind. Kinds without a key keep all their fields
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct KeyFields(BTreeMap<&'static str, Vec<&'static str>>);
+
+impl KeyFields {
+    /// Combines `--key` values. A kind may get its fields from one value only
+    pub fn new(specs: &[KeySpec]) -> Result<Self, String> {
+        let mut kinds = BTreeMap::new();
+        for KeySpec(spec) in specs {
+            for (kind, fields) in spec {
+                if kinds.insert(*kind, fields.clone()).is_some() {
+                    return Err(format!("fields of {} identities are given twice", kind));
+                }
+            }
+        }
+        Ok(Self(kinds))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Clears fields of identity which are left out of its key. Wrapped identities (TCP flags,
+    /// VNI, VLAN IDs) get their inner identity cleared
+    pub fn apply(&self, id: &mut PacketId) {
+        let fields = |kind: &str| self.0.get(kind);
+        match id {
+            PacketId::Tcp {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                tcp_seq,
+                tcp_ack,
+            } => {
+                let Some(fields) = fields("tcp") else { return };
+                let keep = |field: &str| fields.contains(&field);
+                clear_ip(keep("ip_src"), ip_src);
+                clear_ip(keep("ip_dst"), ip_dst);
+                clear(keep("port_src"), port_src);
+                clear(keep("port_dst"), port_dst);
+                clear(keep("tcp_seq"), tcp_seq);
+                clear(keep("tcp_ack"), tcp_ack);
+            }
+            PacketId::TcpPayload {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                payload_len,
+                payload_hash,
+            } => {
+                let Some(fields) = fields("tcp_payload") else {
+                    return;
+                };
+                let keep = |field: &str| fields.contains(&field);
+                clear_ip(keep("ip_src"), ip_src);
+                clear_ip(keep("ip_dst"), ip_dst);
+                clear(keep("port_src"), port_src);
+                clear(keep("port_dst"), port_dst);
+                clear(keep("payload_len"), payload_len);
+                clear(keep("payload_hash"), payload_hash);
+            }
+            PacketId::TcpMessage {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                message_len,
+                message_hash,
+            } => {
+                let Some(fields) = fields("tcp_message") else {
+                    return;
+                };
+                let keep = |field: &str| fields.contains(&field);
+                clear_ip(keep("ip_src"), ip_src);
+                clear_ip(keep("ip_dst"), ip_dst);
+                clear(keep("port_src"), port_src);
+                clear(keep("port_dst"), port_dst);
+                clear(keep("message_len"), message_len);
+                clear(keep("message_hash"), message_hash);
+            }
+            PacketId::Icmp {
+                ip_src,
+                ip_dst,
+                checksum,
+            } => {
+                let Some(fields) = fields("icmp") else { return };
+                let keep = |field: &str| fields.contains(&field);
+                clear_ip(keep("ip_src"), ip_src);
+                clear_ip(keep("ip_dst"), ip_dst);
+                clear(keep("checksum"), checksum);
+            }
+            PacketId::Rtp {
+                ssrc,
+                seq,
+                timestamp,
+            } => {
+                let Some(fields) = fields("rtp") else { return };
+                let keep = |field: &str| fields.contains(&field);
+                clear(keep("ssrc"), ssrc);
+                clear(keep("seq"), seq);
+                clear(keep("timestamp"), timestamp);
+            }
+            PacketId::TcpExchange {
+                ip_local,
+                ip_remote,
+                port_local,
+                port_remote,
+                tcp_ack,
+            } => {
+                let Some(fields) = fields("tcp_exchange") else {
+                    return;
+                };
+                let keep = |field: &str| fields.contains(&field);
+                clear_ip(keep("ip_local"), ip_local);
+                clear_ip(keep("ip_remote"), ip_remote);
+                clear(keep("port_local"), port_local);
+                clear(keep("port_remote"), port_remote);
+                clear(keep("tcp_ack"), tcp_ack);
+            }
+            PacketId::HttpExchange {
+                ip_local,
+                ip_remote,
+                port_local,
+                port_remote,
+                index,
+            } => {
+                let Some(fields) = fields("http_exchange") else {
+                    return;
+                };
+                let keep = |field: &str| fields.contains(&field);
+                clear_ip(keep("ip_local"), ip_local);
+                clear_ip(keep("ip_remote"), ip_remote);
+                clear(keep("port_local"), port_local);
+                clear(keep("port_remote"), port_remote);
+                clear(keep("index"), index);
+            }
+            PacketId::IcmpEcho {
+                ip_local,
+                ip_remote,
+                identifier,
+                sequence,
+            } => {
+                let Some(fields) = fields("icmp_echo") else {
+                    return;
+                };
+                let keep = |field: &str| fields.contains(&field);
+                clear_ip(keep("ip_local"), ip_local);
+                clear_ip(keep("ip_remote"), ip_remote);
+                clear(keep("identifier"), identifier);
+                clear(keep("sequence"), sequence);
+            }
+            PacketId::Dns {
+                transaction_id,
+                query_name,
+                qtype,
+            } => {
+                let Some(fields) = fields("dns") else { return };
+                let keep = |field: &str| fields.contains(&field);
+                clear(keep("transaction_id"), transaction_id);
+                clear(keep("query_name"), query_name);
+                clear(keep("qtype"), qtype);
+            }
+            PacketId::IpId {
+                ip_src,
+                ip_dst,
+                ip_id,
+                fragment_offset,
+                payload_len,
+            } => {
+                let Some(fields) = fields("ip_id") else {
+                    return;
+                };
+                let keep = |field: &str| fields.contains(&field);
+                clear_ip(keep("ip_src"), ip_src);
+                clear_ip(keep("ip_dst"), ip_dst);
+                clear(keep("ip_id"), ip_id);
+                clear(keep("fragment_offset"), fragment_offset);
+                clear(keep("payload_len"), payload_len);
+            }
+            PacketId::Ethernet {
+                src_mac,
+                dst_mac,
+                ethertype,
+                payload_hash,
+            } => {
+                let Some(fields) = fields("ethernet") else {
+                    return;
+                };
+                let keep = |field: &str| fields.contains(&field);
+                clear(keep("src_mac"), src_mac);
+                clear(keep("dst_mac"), dst_mac);
+                clear(keep("ethertype"), ethertype);
+                clear(keep("payload_hash"), payload_hash);
+            }
+            PacketId::Bytes {
+                ip_src,
+                ip_dst,
+                key,
+            } => {
+                let Some(fields) = fields("bytes") else {
+                    return;
+                };
+                let keep = |field: &str| fields.contains(&field);
+                clear(keep("ip_src"), ip_src);
+                clear(keep("ip_dst"), ip_dst);
+                clear(keep("key"), key);
+            }
+            PacketId::Flagged { inner, .. }
+            | PacketId::Geneve { inner, .. }
+            | PacketId::Vlan { inner, .. } => self.apply(inner),
+            PacketId::NeighborDiscovery { .. } | PacketId::Custom { .. } => {}
+        }
+    }
+}
+
+fn clear<T: Default>(keep: bool, field: &mut T) {
+    if !keep {
+        *field = T::default();
+    }
+}
+
+/// Addresses are cleared to the unspecified one of their family
+fn clear_ip(keep: bool, ip: &mut IpAddr) {
+    if !keep {
+        *ip = match ip {
+            IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+    }
+}
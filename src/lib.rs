@@ -0,0 +1,34 @@
+pub mod anonymize;
+pub mod binary;
+pub mod compare;
+pub mod compress;
+pub mod defrag;
+pub mod discover;
+pub mod erf;
+pub mod error;
+pub mod exchange;
+pub mod extractor;
+pub mod heatmap;
+pub mod http;
+pub mod key;
+pub mod live;
+pub mod npy;
+pub mod openmetrics;
+pub mod packet;
+pub mod parquet;
+pub mod reader;
+pub mod reassembly;
+pub mod reference;
+pub mod sampling;
+pub mod stats;
+pub mod summary;
+pub mod synthetic;
+pub mod table;
+pub mod time;
+pub mod timeline;
+
+pub use error::{Error, Result};
+pub use packet::PacketId;
+pub use reader::PcapReader;
+pub use table::OutboundTable;
+pub use time::PacketTime;
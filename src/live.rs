@@ -0,0 +1,233 @@
+//! Live capture from two network interfaces and matching of packets as they arrive
+
+use crate::error::{Error, Result};
+use crate::packet::{self, PacketId, ParseOptions};
+use crate::reader::{identify, match_filter};
+use crate::reassembly::Reassembler;
+use crate::time::PacketTime;
+use pcap_parser::Linktype;
+use pnet::datalink::{self, Channel};
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Side {
+    In,
+    Out,
+}
+
+/// Packets captured on both interfaces, in order of arrival
+pub struct LiveCapture {
+    packets: Receiver<Result<(Side, PacketId, PacketTime)>>,
+}
+
+impl LiveCapture {
+    /// Starts capture threads. Requires privileges for opening raw sockets
+    pub fn start(
+        in_interface: &str,
+        out_interface: &str,
+        filter: Vec<(usize, u8)>,
+        options: ParseOptions,
+    ) -> Result<Self> {
+        let (sender, packets) = mpsc::channel();
+        let in_options = options.clone();
+        Self::spawn(
+            in_interface,
+            Side::In,
+            filter.clone(),
+            in_options,
+            sender.clone(),
+        )?;
+        Self::spawn(out_interface, Side::Out, filter, options, sender)?;
+        Ok(Self { packets })
+    }
+
+    fn spawn(
+        name: &str,
+        side: Side,
+        filter: Vec<(usize, u8)>,
+        options: ParseOptions,
+        sender: Sender<Result<(Side, PacketId, PacketTime)>>,
+    ) -> Result<()> {
+        let interface = datalink::interfaces()
+            .into_iter()
+            .find(|interface| interface.name == name)
+            .ok_or_else(|| Error::Capture(format!("no such interface: {}", name)))?;
+        let mut rx = match datalink::channel(&interface, Default::default())? {
+            Channel::Ethernet(_tx, rx) => rx,
+            _ => {
+                return Err(Error::Capture(format!(
+                    "unsupported channel type: {}",
+                    name
+                )))
+            }
+        };
+        let mut reassembler = Reassembler::for_options(&options);
+        let mut packets = VecDeque::new();
+        thread::spawn(move || loop {
+            match rx.next() {
+                Ok(bytes) => {
+                    let bytes = match options.has_fcs {
+                        true => packet::strip_fcs(bytes, packet::ETHERNET_FCS_LEN),
+                        false => bytes,
+                    };
+                    if !match_filter(bytes, &filter) {
+                        continue;
+                    }
+                    let Some((link_type, bytes)) = options.at_l3_offset(Linktype::ETHERNET, bytes)
+                    else {
+                        continue;
+                    };
+                    if !options.allows_size(link_type, bytes) {
+                        continue;
+                    }
+                    let time = now();
+                    identify(
+                        link_type,
+                        bytes,
+                        time,
+                        &options,
+                        &mut reassembler,
+                        &mut packets,
+                    );
+                    for (tuple_id, time) in packets.drain(..) {
+                        if sender.send(Ok((side, tuple_id, time))).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    let _ = sender.send(Err(Error::Io(e)));
+                    return;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Next captured packet, or `None` if nothing arrived within `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<(Side, PacketId, PacketTime)>> {
+        match self.packets.recv_timeout(timeout) {
+            Ok(packet) => packet.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(Error::Capture("capture threads stopped".to_string()))
+            }
+        }
+    }
+}
+
+fn now() -> PacketTime {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    PacketTime {
+        nsec: since_epoch.as_nanos() as u64,
+    }
+}
+
+/// Outcome of a packet passed to `LiveMatcher`
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum LiveEvent {
+    /// Latency (usec) of packet seen on both interfaces
+    Matched(i64),
+    /// Packet is waiting for its pair
+    Pending,
+}
+
+/// Matches packets of both interfaces in any order. Packets which don't get a pair within
+/// `timeout` are dropped and counted as misses
+pub struct LiveMatcher {
+    timeout: Duration,
+    pending: [HashMap<PacketId, PacketTime>; 2],
+    /// Arrival order of pending packets of each side for expiration. Inbound packets are
+    /// flagged if their pair may have expired before they arrived
+    order: [VecDeque<(PacketId, PacketTime, bool)>; 2],
+    /// Some outbound packet expired without a pair
+    out_expired: bool,
+    misses: u64,
+    possibly_evicted: u64,
+}
+
+impl LiveMatcher {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            pending: [HashMap::new(), HashMap::new()],
+            order: [VecDeque::new(), VecDeque::new()],
+            out_expired: false,
+            misses: 0,
+            possibly_evicted: 0,
+        }
+    }
+
+    pub fn push(&mut self, side: Side, tuple_id: PacketId, time: PacketTime) -> LiveEvent {
+        self.expire(time);
+        let (own, other) = match side {
+            Side::In => (0, 1),
+            Side::Out => (1, 0),
+        };
+        if let Some(other_time) = self.pending[other].remove(&tuple_id) {
+            let latency = match side {
+                Side::In => PacketTime::diff(other_time, time),
+                Side::Out => PacketTime::diff(time, other_time),
+            } / 1000;
+            return LiveEvent::Matched(latency);
+        }
+        // Outbound packets older than the oldest retained one are gone, the pair of an inbound
+        // packet older than it may be among them
+        let possibly_evicted = side == Side::In
+            && self.out_expired
+            && self.oldest_out().is_none_or(|oldest| time < oldest);
+        self.pending[own].insert(tuple_id.clone(), time);
+        self.order[own].push_back((tuple_id, time, possibly_evicted));
+        LiveEvent::Pending
+    }
+
+    /// Time of the oldest pending outbound packet. Drops entries of matched packets off the
+    /// front of the order on the way
+    fn oldest_out(&mut self) -> Option<PacketTime> {
+        while let Some((tuple_id, time, _)) = self.order[1].front() {
+            if self.pending[1].get(tuple_id) == Some(time) {
+                return Some(*time);
+            }
+            self.order[1].pop_front();
+        }
+        None
+    }
+
+    /// Drops pending packets older than timeout. Only unmatched inbound packets are misses
+    fn expire(&mut self, now: PacketTime) {
+        let timeout_nsec = self.timeout.as_nanos() as u64;
+        for index in 0..2 {
+            while let Some((tuple_id, time, possibly_evicted)) = self.order[index].front() {
+                if now.nsec.saturating_sub(time.nsec) <= timeout_nsec {
+                    break;
+                }
+                if self.pending[index].get(tuple_id) == Some(time) {
+                    self.pending[index].remove(tuple_id);
+                    if index == 0 {
+                        self.misses += 1;
+                        self.possibly_evicted += *possibly_evicted as u64;
+                    } else {
+                        self.out_expired = true;
+                    }
+                }
+                self.order[index].pop_front();
+            }
+        }
+    }
+
+    /// All misses, including `possibly_evicted` ones
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Misses which arrived when outbound packets of their time had already expired: their
+    /// pair may have been dropped because the timeout is too short
+    pub fn possibly_evicted(&self) -> u64 {
+        self.possibly_evicted
+    }
+}
@@ -1,15 +1,85 @@
-use clap::Parser;
-use pcap_parser::traits::PcapReaderIterator;
-use pcap_parser::*;
-use pnet::packet::ethernet::EthernetPacket;
-use pnet::packet::icmp::IcmpPacket;
-use pnet::packet::ip::IpNextHeaderProtocols;
-use pnet::packet::ipv4::Ipv4Packet;
-use pnet::packet::tcp::TcpPacket;
-use pnet::packet::Packet;
-use std::collections::HashMap;
-use std::fs::File;
-use std::net::Ipv4Addr;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use latency_measurement_tool::anonymize::Anonymizer;
+use latency_measurement_tool::binary::{MatchKind, Record};
+use latency_measurement_tool::compare::{self, Thresholds};
+use latency_measurement_tool::compress::OutputFile;
+use latency_measurement_tool::discover;
+use latency_measurement_tool::exchange::{Direction, Exchange, Local};
+use latency_measurement_tool::extractor::Extractors;
+use latency_measurement_tool::heatmap::Heatmap;
+use latency_measurement_tool::key::{KeyFields, KeySpec};
+use latency_measurement_tool::live::{LiveCapture, LiveEvent, LiveMatcher};
+use latency_measurement_tool::npy;
+use latency_measurement_tool::openmetrics::Histogram;
+use latency_measurement_tool::packet::{
+    Decap, Endpoint, Fallback, KeyBytes, MatchMode, ParseOptions, Protocol, TcpFlagGroup,
+    TcpMatchMode, VlanIds,
+};
+use latency_measurement_tool::parquet::{self, ParquetWriter};
+use latency_measurement_tool::reader::{self, TsSource};
+use latency_measurement_tool::reassembly::Framing;
+use latency_measurement_tool::sampling::Sampler;
+use latency_measurement_tool::stats::{
+    Alert, AlertChange, Ewma, InterarrivalJitter, MatchRate, SlidingWindow, Top,
+};
+use latency_measurement_tool::summary::{
+    self, FileMetadata, FileSide, Percentiles, ProtocolCounts, Summary, Uncorrected,
+};
+use latency_measurement_tool::synthetic::{Generator, PcapWriter, SyntheticPacket};
+use latency_measurement_tool::table::{MissReason, Pairing};
+use latency_measurement_tool::timeline::{Alignment, Span};
+use latency_measurement_tool::{Error, OutboundTable, PacketId, PacketTime, PcapReader};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter, StdoutLock, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::{Range, RangeInclusive};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Share of inbound packets missed with their pair possibly expired, above which follow mode
+/// suggests a longer `--match-timeout`
+const FOLLOW_EVICTED_WARNING_SHARE: f64 = 0.01;
+
+/// Minimal interval between progress lines of `--progress`
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of most recent matches the follow mode quantiles are computed over
+const FOLLOW_WINDOW_SIZE: usize = 1024;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// Latency/miss as text line for every packet
+    Text,
+    /// Fixed-width 9-byte records (see `binary` module for layout), summary goes to stderr
+    Binary,
+    /// NumPy `.npy` file of latencies in usec (`f64`, NaN for misses) written at the end, summary goes to stderr
+    Npy,
+    /// JSON array of records with match kind and latency in usec, written element by element as packets are matched, summary goes to stderr
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SummaryFormat {
+    /// Human-readable line
+    Text,
+    /// JSON object on a single line (or indented with `--json-pretty`), can be merged later with `merge`
+    Json,
+}
+
+/// What is done when reading a capture fails in the middle
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ReadErrorAction {
+    /// Report only the error
+    Abort,
+    /// Report statistics of packets read before the error, then exit with its code
+    Partial,
+}
 
 #[derive(Parser, Debug)]
 #[command(
@@ -23,215 +93,3128 @@ Identical packets:
 - ICMP packets with identical source IP, destination IP and checksum.
 
 Measured latency - difference between timestamp of identical packet in inbound and outbound dumps.
-"###
+
+Without a subcommand `<PCAP FILE IN> <PCAP FILE OUT>` are analyzed as with `analyze`.
+"###,
+    subcommand_negates_reqs = true,
+    override_usage = "latency_measurement_tool [OPTIONS] <PCAP FILE IN> <PCAP FILE OUT>\n       latency_measurement_tool [OPTIONS] <COMMAND>"
 )]
+struct Cli {
+    #[command(flatten)]
+    shared: SharedArgs,
 
-struct Args {
-    /// Path for pcap file on inbound interface
-    #[arg(name = "PCAP FILE IN")]
-    in_interface_pcap_file_path: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Path for pcap file on outbound interface
-    #[arg(name = "PCAP FILE OUT")]
-    out_interface_pcap_file_path: String,
+    /// Arguments of `analyze` given without the subcommand, as before subcommands existed
+    #[command(flatten)]
+    analyze: Option<AnalyzeArgs>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Measure latency of packets of two pcap files
+    Analyze(AnalyzeArgs),
+    /// Measure latency between requests and responses to them (round-trip time) in one pcap file
+    Exchange(ExchangeArgs),
+    /// Capture live on two interfaces, print latency readouts periodically
+    Follow(FollowArgs),
+    /// Print statistics of packets of a single pcap file
+    Stats(StatsArgs),
+    /// Merge JSON summaries of previous runs into one
+    Merge(MergeArgs),
+    /// Compare JSON summaries of two runs (e.g. before and after a change), optionally failing on regression beyond limits
+    Compare(CompareArgs),
+    /// Print JSON Schema of the JSON summary
+    Schema,
+    /// Print protocols packets can be identified by, as text or (with `--summary-format json`) as JSON
+    ListProtocols,
+    /// Generate a pair of synthetic captures with known latencies, analyze them and check the measured latencies, reporting speed of the analysis
+    Selftest(SelftestArgs),
+}
 
+/// Options of parsing packets and of output, common to all subcommands
+#[derive(Args, Debug)]
+struct SharedArgs {
     /// Disable output of latency/miss for every packet
-    #[arg(short = 'p', long = "disable-printing")]
+    #[arg(short = 'p', long = "disable-printing", global = true)]
     disable_printing: bool,
 
+    /// Print per-packet lines only of misses (`miss`) or of matches with absolute latency above a threshold in usec (`outlier:<USEC>`). The summary still covers all packets
+    #[arg(long = "print-only", value_name = "SUBSET", value_parser = parse_print_only, conflicts_with = "disable_printing", global = true)]
+    print_only: Option<PrintOnly>,
+
+    /// Stop per-packet output after N lines (records of binary and npy output), noting that it was truncated. The summary still covers all packets
+    #[arg(
+        long = "max-print-lines",
+        value_name = "N",
+        conflicts_with = "disable_printing",
+        global = true
+    )]
+    max_print_lines: Option<u64>,
+
+    /// Decimal places of per-packet latencies (usec) in text and npy output, up to 3 for nanoseconds. Timestamps of microsecond captures have none to give. Binary records, the summary and its tables stay whole usec
+    #[arg(long = "precision", value_name = "DIGITS", default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=3), global = true)]
+    precision: u8,
+
     /// Filter by byte value (byte_number:byte value)
-    #[arg(short = 'f', long = "filter", num_args = 0.., value_delimiter = ' ')]
-    filter_strings: Vec<String>,
-}
-
-#[derive(Eq, PartialEq, Hash, Debug)]
-enum PacketId {
-    Tcp {
-        ip_src: Ipv4Addr,
-        ip_dst: Ipv4Addr,
-        port_src: u16,
-        port_dst: u16,
-        tcp_seq: u32,
-        tcp_ack: u32,
-    },
-    Icmp {
-        ip_src: Ipv4Addr,
-        ip_dst: Ipv4Addr,
-        checksum: u16,
-    },
-}
-
-impl PacketId {
-    fn new_from_bytes(bytes: &[u8]) -> Option<Self> {
-        let l2 = EthernetPacket::new(bytes)?;
-        let l3 = Ipv4Packet::new(l2.payload())?;
-        let ip_src = l3.get_source();
-        let ip_dst = l3.get_destination();
-        match l3.get_next_level_protocol() {
-            IpNextHeaderProtocols::Tcp => {
-                let l4 = TcpPacket::new(l3.payload()).unwrap();
-                let tcp_seq = l4.get_sequence();
-                let tcp_ack = l4.get_acknowledgement();
-                let port_src = l4.get_source();
-                let port_dst = l4.get_destination();
-                return Some(Self::Tcp {
-                    ip_src,
-                    ip_dst,
-                    port_src,
-                    port_dst,
-                    tcp_seq,
-                    tcp_ack,
-                });
-            }
-            IpNextHeaderProtocols::Icmp => {
-                let l4 = IcmpPacket::new(l3.payload()).unwrap();
-                let checksum = l4.get_checksum();
-                return Some(Self::Icmp {
-                    ip_src,
-                    ip_dst,
-                    checksum,
-                });
-            }
-            _ => return None,
-        }
-    }
-}
-
-#[derive(Eq, PartialEq, Hash, Debug)]
-struct PacketTime {
-    sec: u32,
-    usec: u32,
-}
-
-impl PacketTime {
-    fn diff(t1: Self, t2: Self) -> i64 {
-        t1.sec as i64 * 1_000_000 + t1.usec as i64 - t2.sec as i64 * 1_000_000 - t2.usec as i64
-    }
-}
-
-struct PcapReader {
-    reader: LegacyPcapReader<File>,
+    #[arg(short = 'f', long = "filter", num_args = 0.., value_delimiter = ' ', value_parser = reader::parse_filter_entry, global = true)]
     filter: Vec<(usize, u8)>,
+
+    /// Read more byte filter entries from file: whitespace-separated, `#` starts a comment
+    #[arg(long = "filter-file", value_name = "FILE", global = true)]
+    filter_file: Option<String>,
+
+    /// Skip packets smaller than this many bytes (IP total length, or frame length of non-IP frames) in both captures
+    #[arg(long = "min-size", value_name = "BYTES", global = true)]
+    min_size: Option<usize>,
+
+    /// Skip packets larger than this many bytes (IP total length, or frame length of non-IP frames) in both captures
+    #[arg(long = "max-size", value_name = "BYTES", global = true)]
+    max_size: Option<usize>,
+
+    /// Format of output for every packet
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text, global = true)]
+    format: OutputFormat,
+
+    /// Format of the summary
+    #[arg(long = "summary-format", value_enum, default_value_t = SummaryFormat::Text, global = true)]
+    summary_format: SummaryFormat,
+
+    /// Pretty-print JSON output (summary, `--format json` records, reports and the heatmap file) indented over several lines instead of compact
+    #[arg(long = "json-pretty", global = true)]
+    json_pretty: bool,
+
+    /// Print counts of the text summary with thousands separators and latencies with units. JSON summary is not affected
+    #[arg(long = "human", global = true)]
+    human: bool,
+
+    /// Analyze only packets of these protocols, e.g. `tcp,icmp`. Packets of other protocols are skipped before decoding their transport header
+    #[arg(long = "protocol", value_enum, value_delimiter = ',', global = true)]
+    protocols: Vec<Protocol>,
+
+    /// Match RTP by SSRC, sequence number and timestamp in UDP packets with source or destination port in range (e.g. 16384-32767)
+    #[arg(long = "rtp-ports", value_name = "PORT[-PORT]", value_parser = parse_port_range, global = true)]
+    rtp_ports: Option<RangeInclusive<u16>>,
+
+    /// Fields identifying packets. `ip-id` identifies IPv4 packets of any transport by addresses, identification and payload length, for middleboxes which rewrite ports or sequence numbers but keep the identification. `l2` identifies Ethernet frames of any protocol by addresses, EtherType and hash of the frame, for latency of switches
+    #[arg(long = "match-mode", value_enum, default_value_t = MatchMode::Transport, global = true)]
+    match_mode: MatchMode,
+
+    /// Shorthand for `--match-mode l2`
+    #[arg(long = "l2", conflicts_with = "match_mode", global = true)]
+    l2: bool,
+
+    /// Identify packets of any protocol by `len` bytes from `offset` of the frame (offsets as of the byte filter), e.g. an application field at a known position. Frames too short for the key are skipped
+    #[arg(long = "key-bytes", value_name = "OFFSET:LEN", value_parser = parse_hash_range, conflicts_with_all = ["match_mode", "l2"], global = true)]
+    key_bytes: Option<Range<usize>>,
+
+    /// Add source and destination IP addresses to the key of `--key-bytes`. Frames which aren't IP are skipped
+    #[arg(long = "key-bytes-ips", requires = "key_bytes", global = true)]
+    key_bytes_ips: bool,
+
+    /// Fields identifying TCP packets. `payload` survives middleboxes which rewrite sequence numbers
+    #[arg(long = "tcp-match-mode", value_enum, default_value_t = TcpMatchMode::SeqAck, global = true)]
+    tcp_match_mode: TcpMatchMode,
+
+    /// Leave the acknowledgement number out of TCP identities, matching on addresses, ports and sequence number, for paths which rewrite it. Segments sharing a sequence number (e.g. pure ACKs) collide, their count is reported
+    #[arg(long = "tcp-ignore-ack", global = true)]
+    tcp_ignore_ack: bool,
+
+    /// Fields which make up identities, the others are left out: `[KIND:]FIELD,FIELD...`, e.g. `ip_src,ip_dst,tcp_seq` or `icmp:ip_dst,checksum`. Without KIND the fields apply to every kind of identity which has all of them. Repeat for other kinds. See the README for fields of each kind
+    #[arg(long = "key", value_name = "[KIND:]FIELDS", global = true)]
+    key: Vec<KeySpec>,
+
+    /// Delimiting of application messages in `--tcp-match-mode message`: `length:N` (N-byte big-endian length prefix, N is 1, 2 or 4) or `delimiter:HEX` (e.g. `delimiter:0d0a`)
+    #[arg(long = "message-framing", value_name = "FRAMING", value_parser = parse_framing, global = true)]
+    message_framing: Option<Framing>,
+
+    /// Hash only `len` bytes of TCP payload (or message) from `start` in `--tcp-match-mode payload` or `message`, e.g. an application message ID. Shorter payloads are skipped
+    #[arg(long = "hash-range", value_name = "START:LEN", value_parser = parse_hash_range, global = true)]
+    hash_range: Option<Range<usize>>,
+
+    /// Match ICMPv6 Neighbor Solicitations and Advertisements by message type and target address, to measure neighbor resolution latency
+    #[arg(long = "neighbor-discovery", global = true)]
+    neighbor_discovery: bool,
+
+    /// Identify ICMP error messages (e.g. Time Exceeded, Destination Unreachable) as the packet they quote, to measure how long a device takes to report an error about a packet
+    #[arg(long = "icmp-errors", global = true)]
+    icmp_errors: bool,
+
+    /// Reassemble IPv4 packets from their fragments before identifying them, to match packets fragmented (or reassembled) between the capture points with whole ones
+    #[arg(long = "defragment", global = true)]
+    defragment: bool,
+
+    /// Parse the IP header at this byte offset of every frame, bypassing link-layer detection, for captures with custom or proprietary headers. Shorter frames are skipped
+    #[arg(long = "l3-offset", value_name = "BYTES", global = true)]
+    l3_offset: Option<usize>,
+
+    /// Match DNS queries with responses (UDP or TCP port 53) by transaction ID, query name and type, to measure resolver latency
+    #[arg(long = "dns", global = true)]
+    dns: bool,
+
+    /// Report latency of TCP packets grouped by flags (SYN, SYN-ACK, ACK, FIN, RST) in a table before the summary. This changes matching too: the group becomes part of the identity, so packets are matched only if their flags agree (PSH, URG and ECN flags aside), and packets whose flags a device changes are misses
+    #[arg(long = "by-tcp-flags", global = true)]
+    by_tcp_flags: bool,
+
+    /// Identify frames encapsulated in packets of this protocol instead of the outer packets. Packets which aren't encapsulated are identified as they are
+    #[arg(long = "decap", value_enum, global = true)]
+    decap: Option<Decap>,
+
+    /// Include VNI of decapsulated frames in their identities, for overlays whose tenants may share addresses
+    #[arg(long = "decap-vni", requires = "decap", global = true)]
+    decap_vni: bool,
+
+    /// Leave VLAN IDs of tagged frames out of identities, for devices which translate them (802.1Q rewrite). Flows seen with other VLAN IDs on each side are reported before the summary
+    #[arg(long = "ignore-vlan", global = true)]
+    ignore_vlan: bool,
+
+    /// Replace IP addresses in output with consistent pseudonyms (keyed hash, not cryptographically strong). Matching is not affected
+    #[arg(long = "anonymize", global = true)]
+    anonymize: bool,
+
+    /// Key of --anonymize pseudonyms. Reports anonymized with the same key share pseudonyms, a secret key makes them harder to reverse
+    #[arg(
+        long = "anonymize-key",
+        value_name = "KEY",
+        default_value = "",
+        requires = "anonymize",
+        global = true
+    )]
+    anonymize_key: String,
+
+    /// Timestamp of pcapng packets which carry both the standard (software) timestamp and a hardware timestamp option of the vendor of `--hw-ts-pen`. The source used is reported in the summary
+    #[arg(long = "ts-source", value_enum, default_value_t = TsSource::Hardware, global = true)]
+    ts_source: TsSource,
+
+    /// Private Enterprise Number of the vendor whose pcapng custom option 2989 or 19373 carries a hardware timestamp: the number (4 bytes) followed by 64-bit timestamp in units of the interface. pcapng has no standard option for it, so without this option none is read
+    #[arg(long = "hw-ts-pen", value_name = "PEN", global = true)]
+    hw_ts_pen: Option<u32>,
+
+    /// Ethernet frames include the trailing 4-byte FCS, strip it (needed only if the capture file doesn't record it)
+    #[arg(long = "has-fcs", global = true)]
+    has_fcs: bool,
+}
+
+// The positionals join the group of the struct explicitly: clap leaves the group of a struct
+// with flattened fields empty, but it tells if `Cli::analyze` is given
+#[derive(Args, Debug)]
+struct AnalyzeArgs {
+    /// Path for pcap file on inbound interface
+    #[arg(name = "PCAP FILE IN", group = "AnalyzeArgs")]
+    in_interface_pcap_file_path: String,
+
+    /// Path for pcap file on outbound interface
+    #[arg(name = "PCAP FILE OUT", group = "AnalyzeArgs")]
+    out_interface_pcap_file_path: String,
+
+    /// If a pcap file is a FIFO, wait for more data on EOF instead of stopping (e.g. for live capture through a pipe)
+    #[arg(short = 'w', long = "wait")]
+    wait: bool,
+
+    #[command(flatten)]
+    matching: MatchArgs,
+}
+
+#[derive(Args, Debug)]
+struct ExchangeArgs {
+    /// Path for pcap file captured on the local host
+    #[arg(name = "PCAP FILE")]
+    pcap_file_path: String,
+
+    #[command(flatten)]
+    local: LocalArgs,
+
+    /// Match HTTP/1.1 requests with their responses in reassembled TCP streams, for latency of
+    /// the server instead of the network
+    #[arg(long = "http")]
+    http: bool,
+
+    #[command(flatten)]
+    matching: MatchArgs,
+}
+
+/// Address telling packets of the local host in `exchange`, every host is one without it
+#[derive(Args, Debug)]
+#[group(multiple = false)]
+struct LocalArgs {
+    /// IP address of the local host
+    #[arg(long = "local-ip", value_name = "IP")]
+    local_ip: Option<IpAddr>,
+
+    /// MAC address of the local host, e.g. `00:11:22:33:44:55` (Ethernet captures only)
+    #[arg(long = "local-mac", value_name = "MAC", value_parser = parse_mac)]
+    local_mac: Option<[u8; 6]>,
+}
+
+/// Options of matching packets of the two sides and of its output
+#[derive(Args, Debug)]
+struct MatchArgs {
+    /// Fall back to fuzzy matching (IP addresses and TCP source port are ignored) within the time window (usec)
+    #[arg(long = "fuzzy", value_name = "USEC", value_parser = clap::value_parser!(i64).range(0..=i64::MAX / 1000))]
+    fuzzy_window: Option<i64>,
+
+    /// Retry inbound packets without an exact match by a fallback key, which ignores IP addresses and TCP source port. Matches are counted by the key which caught them
+    #[arg(long = "fallback", value_name = "KEY")]
+    fallback: Option<Fallback>,
+
+    /// Before matching, print a timeline of the time spans of both captures and their overlap. Reads the captures twice
+    #[arg(long = "show-alignment")]
+    show_alignment: bool,
+
+    /// Before matching, check that the time spans of the captures overlap and fail with exit code 13 if they don't, since only misses could be found. Reads the captures twice
+    #[arg(long = "require-overlap")]
+    require_overlap: bool,
+
+    /// Gap between the captures which `--require-overlap` still accepts, for clocks which differ by up to this much
+    #[arg(long = "overlap-tolerance", value_name = "SECONDS", value_parser = parse_positive_seconds, requires = "require_overlap")]
+    overlap_tolerance: Option<f64>,
+
+    /// What is done when reading a capture fails in the middle
+    #[arg(long = "on-read-error", value_enum, default_value_t = ReadErrorAction::Abort)]
+    on_read_error: ReadErrorAction,
+
+    /// Analyze only a random fraction of inbound packets, (0, 1]
+    #[arg(long = "sample", default_value_t = 1.0, value_parser = parse_fraction)]
+    sample_rate: f64,
+
+    /// Seed for randomized components (sampling), the same seed gives identical output
+    #[arg(long = "seed", default_value_t = 0)]
+    seed: u64,
+
+    /// Bound memory by keeping at most N outbound packets in the table, evicting the oldest ones
+    #[arg(long = "max-table-entries", value_name = "N")]
+    max_table_entries: Option<usize>,
+
+    /// Bound memory of the table by a budget in bytes, with suffix K, M or G (e.g. 2G): if the table estimated from the size of the file wouldn't fit, it's bounded to the entries which do, evicting the oldest ones. The choice is reported to stderr
+    #[arg(long = "max-memory", value_name = "SIZE", value_parser = parse_memory, conflicts_with = "max_table_entries")]
+    max_memory: Option<u64>,
+
+    /// Warn once if more than N outbound packets are waiting in the table for a match. Unlike
+    /// --max-table-entries, nothing is dropped
+    #[arg(
+        long = "max-packets-in-flight",
+        value_name = "N",
+        default_value_t = 10_000_000
+    )]
+    max_packets_in_flight: usize,
+
+    /// Print wall time spent parsing the files, building the outbound table and matching
+    #[arg(long = "timing")]
+    timing: bool,
+
+    /// Report progress of building the outbound table and of matching to stderr every second
+    #[arg(long = "progress")]
+    progress: bool,
+
+    /// Report to stderr every second the share of inbound packets matched so far, and since the
+    /// previous report, to see early whether matching works at all
+    #[arg(long = "match-rate")]
+    match_rate: bool,
+
+    /// Which outbound occurrence of a recurring identity (e.g. retransmitted packet) is paired with an inbound packet
+    #[arg(long = "pairing", value_enum, default_value_t = Pairing::First)]
+    pairing: Pairing,
+
+    /// Pair packets only with an occurrence of their identity at most this far apart in time (usec), chosen by `--pairing` among such ones, so identities recurring over a long capture aren't paired across it. The summary counts packets rejected by it
+    #[arg(long = "match-window", value_name = "USEC", value_parser = clap::value_parser!(u64).range(..=i64::MAX as u64 / 1000))]
+    match_window: Option<u64>,
+
+    /// Round timestamps of both files to this resolution (e.g. `1us`, `100ns`) before computing latency. Defaults to the coarser timestamp resolution of the two files
+    #[arg(long = "round-to", value_name = "RESOLUTION", value_parser = parse_resolution)]
+    round_to: Option<u64>,
+
+    /// Known offset of the outbound capture clock ahead of the inbound one (nsec, e.g. measured by PTP or GPS), subtracted from every latency to get one-way delay. The summary reports uncorrected latency too
+    #[arg(
+        long = "clock-offset-ns",
+        value_name = "NSEC",
+        allow_negative_numbers = true
+    )]
+    clock_offset: Option<i64>,
+
+    /// Build the table from the smaller of the two files instead of always the outbound one, and stream the other. Reported to stderr. If the inbound file becomes the table, per-packet output follows the order of outbound packets with misses at the end, RFC 3550 jitter is of that order, `--pairing` chooses among recurring inbound packets, and recurring identities counted are inbound ones
+    #[arg(long = "auto-table-side")]
+    auto_table_side: bool,
+
+    /// Print a table of misses by inferred reason (identity absent on the outbound side, pair taken by an earlier packet, pair evicted) before the summary
+    #[arg(long = "miss-reasons")]
+    miss_reasons: bool,
+
+    /// Count TCP retransmissions between the captures: extra copies of a paired packet which occur in one capture only, a sign of loss on the path. Reported in the summary with their rate. Identities of paired packets are kept in memory
+    #[arg(long = "retransmissions")]
+    retransmissions: bool,
+
+    /// Print latency stats of exact matches by DSCP of the inbound packet (IPv4 ToS or IPv6 traffic class) before the summary, to check that a QoS policy gives marked traffic lower latency
+    #[arg(long = "by-dscp", conflicts_with = "auto_table_side")]
+    by_dscp: bool,
+
+    /// Print latency stats of the two directions of connections separately before the summary: forward packets (from the end which sent the first inbound packet of the connection, usually its client) and reverse ones, whose latency is taken from the outbound capture to the inbound one. Give the client-side capture first
+    #[arg(long = "by-direction", conflicts_with = "auto_table_side")]
+    by_direction: bool,
+
+    /// Print the N matches with the highest absolute latency before the summary, with their inbound time and identity. Equal latencies are ordered by flow, then by inbound time, so the list is the same on every run
+    #[arg(long = "top", value_name = "N")]
+    top: Option<usize>,
+
+    /// Report what became of packets of an identity, given as printed by `--dump-unmatched-out` (e.g. `tcp 10.0.0.1:1000 > 10.0.0.2:80 seq 100 ack 5`): where it was seen, whether each inbound packet matched or why not, and whether the filter excluded packets of it. Printed before the summary
+    #[arg(
+        long = "explain-miss",
+        value_name = "IDENTITY",
+        conflicts_with = "auto_table_side"
+    )]
+    explain_miss: Option<String>,
+
+    /// Write identities of outbound packets left unmatched to file, one `<time> <identity>` line per packet
+    #[arg(long = "dump-unmatched-out", value_name = "FILE")]
+    dump_unmatched_out: Option<String>,
+
+    /// Write the latency histogram in OpenMetrics text format to file, each bucket with an exemplar latency and the identity of its packet
+    #[arg(long = "openmetrics", value_name = "FILE")]
+    openmetrics: Option<String>,
+
+    /// Write miss rate of every flow by time interval to file, as CSV (or JSON with `--summary-format json`) for rendering as a heatmap
+    #[arg(long = "loss-heatmap", value_name = "FILE")]
+    loss_heatmap: Option<String>,
+
+    /// Length of time intervals of `--loss-heatmap` (seconds)
+    #[arg(long = "heatmap-interval", default_value_t = 1.0, value_parser = parse_positive_seconds)]
+    heatmap_interval: f64,
+
+    /// Show host names of pcapng name resolution blocks of either capture instead of addresses in flows of `--loss-heatmap`. Reads the captures twice
+    #[arg(long = "resolve-names", conflicts_with = "anonymize")]
+    resolve_names: bool,
+
+    /// Group flows of `--loss-heatmap` by subnets of IPv4 (and IPv6) addresses of these prefix lengths instead of by addresses and ports, e.g. `24` or `24,64`
+    #[arg(long = "group-prefix", value_name = "V4[,V6]", value_parser = parse_group_prefix, requires = "loss_heatmap", conflicts_with_all = ["anonymize", "resolve_names"])]
+    group_prefix: Option<GroupPrefix>,
+
+    /// Write a row per matched packet (times, latency, protocol, addresses and ports) to file in Apache Parquet format
+    #[arg(long = "parquet", value_name = "FILE")]
+    parquet: Option<String>,
+
+    /// Only count matches and misses, without computing latencies, e.g. to check that a filter selects the expected packets. No per-packet lines are printed and the summary has counts only
+    #[arg(long = "count-only", conflicts_with_all = ["clock_offset", "by_tcp_flags", "by_dscp", "by_direction", "top", "openmetrics", "loss_heatmap", "parquet"])]
+    count_only: bool,
+}
+
+#[derive(Args, Debug)]
+struct FollowArgs {
+    /// Name of inbound network interface
+    #[arg(name = "INTERFACE IN")]
+    in_interface: String,
+
+    /// Name of outbound network interface
+    #[arg(name = "INTERFACE OUT")]
+    out_interface: String,
+
+    /// Weight of the newest latency in moving average, (0, 1]
+    #[arg(long = "ewma-alpha", default_value_t = 0.1, value_parser = parse_fraction)]
+    ewma_alpha: f64,
+
+    /// Interval between latency readouts (seconds)
+    #[arg(long = "print-interval", default_value_t = 1.0, value_parser = parse_positive_seconds)]
+    print_interval: f64,
+
+    /// Packets not seen on the other interface within this time (seconds) are misses
+    #[arg(long = "match-timeout", default_value_t = 1.0, value_parser = parse_positive_seconds)]
+    match_timeout: f64,
+
+    /// Alert when EWMA latency (usec) is above this for `--alert-intervals` readouts in a row
+    #[arg(long = "alert-above", value_name = "USEC")]
+    alert_above: Option<f64>,
+
+    /// Clear the alert when EWMA latency (usec) is below this for `--alert-intervals` readouts in a row. Defaults to `--alert-above`
+    #[arg(
+        long = "alert-clear-below",
+        value_name = "USEC",
+        requires = "alert_above"
+    )]
+    alert_clear_below: Option<f64>,
+
+    /// Readouts in a row beyond a threshold which raise or clear the alert
+    #[arg(long = "alert-intervals", value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), requires = "alert_above")]
+    alert_intervals: u32,
 }
 
-impl PcapReader {
-    fn new_from_path(file_path: &str, filter: Vec<(usize, u8)>) -> Self {
-        let file = File::open(file_path).expect("Error opening file");
-        let reader = LegacyPcapReader::new(1 * 1024 * 1024, file).expect("LegacyPcapReader");
-        Self { reader, filter }
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// Path for pcap file
+    #[arg(name = "PCAP FILE")]
+    pcap_file_path: String,
+}
+
+#[derive(Args, Debug)]
+struct MergeArgs {
+    /// Files with JSON summaries (the last line of each file)
+    #[arg(name = "JSON FILE", required = true)]
+    summary_file_paths: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct CompareArgs {
+    /// File with JSON summary of the baseline run (the last line of the file)
+    #[arg(name = "BEFORE")]
+    before_path: String,
+
+    /// File with JSON summary of the run compared with the baseline
+    #[arg(name = "AFTER")]
+    after_path: String,
+
+    /// Fail if average latency increased by more than PCT percent
+    #[arg(long = "max-average-increase", value_name = "PCT")]
+    max_average_increase: Option<f64>,
+
+    /// Fail if p99 latency increased by more than PCT percent
+    #[arg(long = "max-p99-increase", value_name = "PCT")]
+    max_p99_increase: Option<f64>,
+
+    /// Fail if miss rate increased by more than POINTS percentage points
+    #[arg(long = "max-miss-rate-increase", value_name = "POINTS")]
+    max_miss_rate_increase: Option<f64>,
+}
+
+#[derive(Args, Debug)]
+struct SelftestArgs {
+    /// Number of packets of the synthetic captures
+    #[arg(long = "packets", default_value_t = 100_000)]
+    packets: usize,
+
+    /// Number of TCP flows the packets are spread over
+    #[arg(long = "flows", default_value_t = 16)]
+    flows: u16,
+
+    /// Seed of the generator, the same seed gives the same captures
+    #[arg(long = "seed", default_value_t = 0)]
+    seed: u64,
+
+    /// Write the captures to this directory and keep them, instead of a temporary directory
+    #[arg(long = "dir", value_name = "DIR")]
+    dir: Option<PathBuf>,
+}
+
+impl SharedArgs {
+    fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            match_mode: self.match_mode,
+            tcp_match_mode: self.tcp_match_mode,
+            rtp_ports: self.rtp_ports.clone(),
+            has_fcs: self.has_fcs,
+            hash_range: self.hash_range.clone(),
+            message_framing: self.message_framing.clone(),
+            protocols: self.protocols.clone(),
+            exchange: None,
+            neighbor_discovery: self.neighbor_discovery,
+            icmp_errors: self.icmp_errors,
+            defragment: self.defragment,
+            l3_offset: self.l3_offset,
+            decap: self.decap,
+            decap_vni: self.decap_vni,
+            ignore_vlan: self.ignore_vlan,
+            dns: self.dns,
+            tcp_flags: self.by_tcp_flags,
+            tcp_ignore_ack: self.tcp_ignore_ack,
+            // Checked in `main`
+            key: KeyFields::new(&self.key).unwrap_or_default(),
+            size_range: self.size_range(),
+            key_bytes: self.key_bytes.clone().map(|range| KeyBytes {
+                range,
+                with_ips: self.key_bytes_ips,
+            }),
+            extractors: Extractors::default(),
+        }
+    }
+
+    /// Range of `--min-size` and `--max-size`, `None` if neither is given
+    fn size_range(&self) -> Option<RangeInclusive<usize>> {
+        if self.min_size.is_none() && self.max_size.is_none() {
+            return None;
+        }
+        Some(self.min_size.unwrap_or(0)..=self.max_size.unwrap_or(usize::MAX))
+    }
+
+    fn anonymizer(&self) -> Option<Anonymizer> {
+        self.anonymize.then(|| Anonymizer::new(&self.anonymize_key))
     }
 
-    fn match_filter(bytes: &[u8], filter: &Vec<(usize, u8)>) -> bool {
-        if filter.len() == 0 {
-            return true;
+    /// Entries of `--filter` and `--filter-file`
+    fn filter(&self) -> Vec<(usize, u8)> {
+        let mut filter = self.filter.clone();
+        if let Some(path) = &self.filter_file {
+            let entries = fs::read_to_string(path)
+                .map_err(Error::from)
+                .and_then(|text| reader::parse_filter(&text))
+                .unwrap_or_else(|e| fail(path, e));
+            filter.extend(entries);
         }
-        for (byte_number, byte_value) in filter.into_iter() {
-            if bytes.len() <= *byte_number {
-                return false;
+        filter
+    }
+}
+
+/// Packets of `--print-only`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PrintOnly {
+    Miss,
+    /// Matches of any kind with absolute latency above the threshold (usec)
+    Outlier(i64),
+}
+
+impl PrintOnly {
+    fn includes(self, record: &Record) -> bool {
+        match self {
+            Self::Miss => record.kind == MatchKind::Miss,
+            Self::Outlier(threshold) => {
+                record.kind != MatchKind::Miss && record.latency.abs() > threshold
             }
-            if bytes[*byte_number] == *byte_value {
-                continue;
-            } else {
-                return false;
+        }
+    }
+}
+
+fn parse_print_only(s: &str) -> Result<PrintOnly, String> {
+    match s.split_once(':') {
+        None if s == "miss" => Ok(PrintOnly::Miss),
+        Some(("outlier", threshold)) => match threshold.parse::<i64>() {
+            Ok(threshold) if threshold >= 0 => Ok(PrintOnly::Outlier(threshold)),
+            _ => Err("outlier threshold must be a non-negative number of usec".to_string()),
+        },
+        _ => Err("expected miss or outlier:USEC".to_string()),
+    }
+}
+
+/// Prefix lengths of `--group-prefix`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct GroupPrefix {
+    v4: u8,
+    /// IPv6 addresses are kept whole without it
+    v6: Option<u8>,
+}
+
+impl GroupPrefix {
+    /// Subnet of address, e.g. `10.0.0.0/24`
+    fn subnet(&self, ip: IpAddr) -> String {
+        match (ip, self.v6) {
+            (IpAddr::V4(ip), _) => {
+                let mask = u32::MAX.checked_shl(32 - self.v4 as u32).unwrap_or(0);
+                format!("{}/{}", Ipv4Addr::from(u32::from(ip) & mask), self.v4)
             }
+            (IpAddr::V6(ip), Some(v6)) => {
+                let mask = u128::MAX.checked_shl(128 - v6 as u32).unwrap_or(0);
+                format!("{}/{}", Ipv6Addr::from(u128::from(ip) & mask), v6)
+            }
+            (IpAddr::V6(ip), None) => ip.to_string(),
         }
-        return true;
     }
 }
 
-impl Iterator for PcapReader {
-    type Item = (PacketId, PacketTime);
+fn parse_group_prefix(s: &str) -> Result<GroupPrefix, String> {
+    let (v4, v6) = match s.split_once(',') {
+        Some((v4, v6)) => (v4, Some(v6)),
+        None => (s, None),
+    };
+    let len = |s: &str, max: u8| match s.trim().parse::<u8>() {
+        Ok(len) if len <= max => Ok(len),
+        _ => Err(format!("prefix length must be 0 to {}, got `{}`", max, s)),
+    };
+    Ok(GroupPrefix {
+        v4: len(v4, 32)?,
+        v6: v6.map(|v6| len(v6, 128)).transpose()?,
+    })
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let filter = &self.filter;
-        loop {
-            let mut tuple_id: Option<PacketId> = None;
-            let mut time: PacketTime = PacketTime { sec: 0, usec: 0 };
-            match self.reader.next() {
-                Ok((offset, block)) => {
-                    match block {
-                        PcapBlockOwned::LegacyHeader(_hdr) => {}
-                        PcapBlockOwned::Legacy(_b) => {
-                            if PcapReader::match_filter(_b.data, filter) {
-                                tuple_id = PacketId::new_from_bytes(_b.data);
-                                time = PacketTime {
-                                    sec: _b.ts_sec,
-                                    usec: _b.ts_usec,
-                                };
-                            }
-                        }
-                        PcapBlockOwned::NG(_) => unreachable!(),
-                    }
-                    self.reader.consume(offset);
-                    match tuple_id {
-                        Some(tuple_id) => return Some((tuple_id, time)),
-                        None => continue,
-                    }
-                }
-                Err(PcapError::Eof) => return None,
-                Err(PcapError::Incomplete) => {
-                    self.reader.refill().unwrap();
-                }
-                Err(e) => panic!("Error while reading: {:?}", e),
+fn parse_port_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    let (first, last) = s.split_once('-').unwrap_or((s, s));
+    let first: u16 = first.parse().map_err(|e| format!("{}", e))?;
+    let last: u16 = last.parse().map_err(|e| format!("{}", e))?;
+    if first > last {
+        return Err("first port of range is greater than last".to_string());
+    }
+    Ok(first..=last)
+}
+
+fn parse_hash_range(s: &str) -> Result<Range<usize>, String> {
+    let (start, len) = s
+        .split_once(':')
+        .ok_or_else(|| "expected START:LEN".to_string())?;
+    let start: usize = start.parse().map_err(|e| format!("{}", e))?;
+    let len: usize = len.parse().map_err(|e| format!("{}", e))?;
+    if len == 0 {
+        return Err("length of range must be positive".to_string());
+    }
+    let end = start
+        .checked_add(len)
+        .ok_or_else(|| "range is too large".to_string())?;
+    Ok(start..end)
+}
+
+fn parse_framing(s: &str) -> Result<Framing, String> {
+    match s.split_once(':') {
+        Some(("length", len)) => match len.parse::<usize>() {
+            Ok(len @ (1 | 2 | 4)) => Ok(Framing::LengthPrefix(len)),
+            _ => Err("length prefix must be 1, 2 or 4 bytes".to_string()),
+        },
+        Some(("delimiter", hex)) => {
+            if hex.is_empty() || hex.len() % 2 != 0 {
+                return Err("delimiter must be a non-empty even number of hex digits".to_string());
             }
+            let delimiter = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|e| format!("{}", e))?;
+            Ok(Framing::Delimiter(delimiter))
         }
+        _ => Err("expected length:N or delimiter:HEX".to_string()),
     }
 }
 
-fn main() {
-    let args = Args::parse();
-    //TODO: rewrite. Need to be parsed with CLAP
-    let filter = args
-        .filter_strings
+/// Parses time resolution with unit `ns`, `us`, `ms` or `s`, e.g. `10us`, into nanoseconds
+fn parse_resolution(s: &str) -> Result<u64, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (count, unit) = s.split_at(split);
+    let unit_nsec = match unit {
+        "ns" => 1,
+        "us" => 1_000,
+        "ms" => 1_000_000,
+        "s" => 1_000_000_000,
+        _ => return Err("expected number with unit ns, us, ms or s, e.g. 10us".to_string()),
+    };
+    let count: u64 = count.parse().map_err(|e| format!("{}", e))?;
+    if count == 0 {
+        return Err("must be positive".to_string());
+    }
+    count
+        .checked_mul(unit_nsec)
+        .ok_or_else(|| "too large".to_string())
+}
+
+/// Parses size in bytes with optional suffix `K`, `M` or `G` (powers of 1024), e.g. `2G`
+fn parse_memory(s: &str) -> Result<u64, String> {
+    let (count, unit) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    let count: u64 = count.parse().map_err(|_| {
+        "expected number of bytes with optional suffix K, M or G, e.g. 2G".to_string()
+    })?;
+    if count == 0 {
+        return Err("must be positive".to_string());
+    }
+    count
+        .checked_mul(unit)
+        .ok_or_else(|| "too large".to_string())
+}
+
+/// Size in the largest binary unit it reaches, e.g. `1.5 GiB`
+fn format_bytes(bytes: u64) -> String {
+    let (unit_bytes, unit) = [(1 << 30, "GiB"), (1 << 20, "MiB"), (1 << 10, "KiB")]
         .into_iter()
-        .map(|x| {
-            let (a, b) = x.split_once(':').unwrap();
-            (a.parse::<usize>().unwrap(), b.parse::<u8>().unwrap())
+        .find(|(unit_bytes, _)| bytes >= *unit_bytes)
+        .unwrap_or((1, "bytes"));
+    match unit_bytes {
+        1 => format!("{} {}", bytes, unit),
+        _ => format!("{:.1} {}", bytes as f64 / unit_bytes as f64, unit),
+    }
+}
+
+/// Resolution in the largest unit which divides it, e.g. `10 us`
+fn format_resolution(nsec: u64) -> String {
+    let (unit_nsec, unit) = [(1_000_000_000, "s"), (1_000_000, "ms"), (1_000, "us")]
+        .into_iter()
+        .find(|(unit_nsec, _)| nsec.is_multiple_of(*unit_nsec))
+        .unwrap_or((1, "ns"));
+    format!("{} {}", nsec / unit_nsec, unit)
+}
+
+fn parse_mac(s: &str) -> Result<[u8; 6], String> {
+    let bytes = s
+        .split(':')
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|e| format!("{}", e))?;
+    bytes
+        .try_into()
+        .map_err(|_| "expected 6 bytes separated by ':'".to_string())
+}
+
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let fraction: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if fraction > 0.0 && fraction <= 1.0 {
+        Ok(fraction)
+    } else {
+        Err("must be in range (0, 1]".to_string())
+    }
+}
+
+fn parse_positive_seconds(s: &str) -> Result<f64, String> {
+    let seconds: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if seconds < 1e-9 || Duration::try_from_secs_f64(seconds).is_err() {
+        Err("must be at least 1 nsec and fit a duration".to_string())
+    } else {
+        Ok(seconds)
+    }
+}
+
+/// Exit code of `compare` if the change exceeds a limit of regression
+const EXIT_REGRESSION: i32 = 11;
+
+/// Exit code of `selftest` if a measured latency differs from the injected one
+const EXIT_SELFTEST_FAILED: i32 = 12;
+
+/// Exit code of `--require-overlap` if the captures don't overlap
+const EXIT_NO_OVERLAP: i32 = 13;
+
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::Io(_) => 2,
+        Error::BadMagic(_) => 3,
+        Error::UnsupportedLinkType(_) => 4,
+        Error::Truncated => 5,
+        Error::Parse(_) => 6,
+        Error::Capture(_) => 7,
+        Error::BadSummary(_) => 8,
+        Error::BadFilter(_) => 9,
+        Error::BadReference(_) => 10,
+    }
+}
+
+/// Identified packets sampled from the start of the table file to estimate how many it holds
+const TABLE_SAMPLE_PACKETS: usize = 10_000;
+
+/// Bound of the table of `--max-memory`: `None` if the table estimated from the size of the
+/// file fits in `budget`, otherwise the number of entries which do. The size of a FIFO is
+/// unknown, so its table is always bounded. The choice is reported to stderr
+fn memory_bound(
+    budget: u64,
+    path: &str,
+    filter: Vec<(usize, u8)>,
+    options: &ParseOptions,
+    side: &str,
+) -> Option<usize> {
+    let bounded = (budget / OutboundTable::entry_bytes(true) as u64) as usize;
+    let files = discover::files(path).unwrap_or_else(|e| fail(path, e.into()));
+    if !files
+        .iter()
+        .all(|file| fs::metadata(file).is_ok_and(|metadata| metadata.is_file()))
+    {
+        eprintln!(
+            "Size of the {} file is unknown, so its table is bounded to {} entries to fit in --max-memory of {}. The oldest are evicted",
+            side,
+            bounded,
+            format_bytes(budget)
+        );
+        return Some(bounded);
+    }
+    // Packets are counted as the table would get them, and the rest of the file is assumed to
+    // be like its start
+    let mut reader = PcapReader::new_from_path(path, filter)
+        .unwrap_or_else(|e| fail(path, e))
+        .with_parse_options(options.clone());
+    let sampled = reader.by_ref().take(TABLE_SAMPLE_PACKETS).count() as u64;
+    let packets = match sampled < TABLE_SAMPLE_PACKETS as u64 {
+        true => sampled,
+        false => sampled * file_len(path) / reader.consumed_bytes().max(1),
+    };
+    let needed = packets * OutboundTable::entry_bytes(false) as u64;
+    if needed <= budget {
+        eprintln!(
+            "Table of about {} {} packets needs about {}, within --max-memory of {}, so it holds them all",
+            packets,
+            side,
+            format_bytes(needed),
+            format_bytes(budget)
+        );
+        return None;
+    }
+    eprintln!(
+        "Table of about {} {} packets would need about {}, beyond --max-memory of {}, so it's bounded to {} entries. The oldest are evicted",
+        packets,
+        side,
+        format_bytes(needed),
+        format_bytes(budget),
+        bounded
+    );
+    Some(bounded)
+}
+
+/// Size of file, or of the files of a directory or a pattern, 0 if it's unknown
+fn file_len(path: &str) -> u64 {
+    discover::files(path)
+        .unwrap_or_default()
+        .iter()
+        .map(|path| fs::metadata(path).map_or(0, |metadata| metadata.len()))
+        .sum()
+}
+
+/// Checks files of a directory or a pattern before they're read: reports how many there are
+/// and warns if their link-layer types differ, since byte filter offsets then apply to
+/// different headers
+fn check_capture_files(path: &str) {
+    let Ok(files) = discover::files(path) else {
+        return;
+    };
+    if files.len() < 2 && !discover::is_pattern(path) {
+        return;
+    }
+    eprintln!(
+        "{}: {} capture files, {} to {}",
+        path,
+        files.len(),
+        files[0],
+        files[files.len() - 1]
+    );
+    let link_types: Vec<_> = files
+        .iter()
+        .map(|file| {
+            let reader =
+                PcapReader::new_from_path(file, Vec::new()).unwrap_or_else(|e| fail(file, e));
+            (file, reader.link_type())
         })
-        .collect::<Vec<_>>();
-    let out_interface_reader =
-        PcapReader::new_from_path(&args.out_interface_pcap_file_path, filter.clone());
-    let mut out_interface_table: HashMap<PacketId, PacketTime> = HashMap::new();
-    for (tuple_id, packet_time) in out_interface_reader.into_iter() {
-        out_interface_table.insert(tuple_id, packet_time);
-    }
-
-    let in_interface_reader = PcapReader::new_from_path(&args.in_interface_pcap_file_path, filter);
-
-    let mut latency_sum: i64 = 0;
-    let mut latency_min: i64 = i64::MAX;
-    let mut latency_max: i64 = 0;
-    let mut latency_hit_count: i64 = 0;
-    let mut miss_count: u64 = 0;
-    let mut in_interface_packet_count: u64 = 0;
-    for (tuple_id, packet_time) in in_interface_reader.into_iter() {
-        in_interface_packet_count += 1;
-        if let Some(out_interface_time) = out_interface_table.remove(&tuple_id) {
-            let latency = PacketTime::diff(out_interface_time, packet_time);
-            if !args.disable_printing {
-                println!("{}", latency)
+        .collect();
+    if link_types
+        .iter()
+        .any(|(_, link_type)| *link_type != link_types[0].1)
+    {
+        let types: Vec<String> = link_types
+            .iter()
+            .map(|(file, link_type)| match link_type {
+                Some(link_type) => format!("{} {}", file, link_type),
+                None => format!("{} -", file),
+            })
+            .collect();
+        eprintln!(
+            "WARNING: link-layer types of the files of {} differ ({}). Packets are compared starting from IP layer, but byte filter offsets apply to different headers",
+            path,
+            types.join(", ")
+        );
+    }
+}
+
+/// Metadata of file read by reader, for the JSON summary
+fn file_metadata(side: FileSide, path: &str, reader: &PcapReader) -> FileMetadata {
+    let span = reader.span();
+    FileMetadata {
+        side,
+        path: path.to_string(),
+        size_bytes: reader.input_len(),
+        format: reader.format().to_string(),
+        link_types: reader
+            .link_types()
+            .iter()
+            .map(|link_type| link_type.to_string())
+            .collect(),
+        snaplen: reader.snaplen(),
+        timestamp_resolution_nsec: reader.timestamp_resolution_nsec(),
+        packets: reader.frame_count(),
+        first_packet_nsec: span.map(|span| span.first.nsec),
+        last_packet_nsec: span.map(|span| span.last.nsec),
+    }
+}
+
+fn fail(file_path: &str, error: Error) -> ! {
+    eprintln!("{}: {}", file_path, error);
+    process::exit(exit_code(&error));
+}
+
+/// Prints per-packet line of latency given in nsec, in usec with `--precision` decimal places.
+/// Binary records have whole usec
+fn print_record(args: &SharedArgs, out: &mut Printer, kind: MatchKind, latency_nsec: i64) {
+    let record = Record {
+        latency: latency_nsec / 1000,
+        kind,
+    };
+    if args.disable_printing
+        || args
+            .print_only
+            .is_some_and(|print_only| !print_only.includes(&record))
+    {
+        return;
+    }
+    if !out.admit() {
+        return;
+    }
+    match args.format {
+        OutputFormat::Text => match record.kind {
+            MatchKind::Exact => writeln!(out, "{}", format_latency(latency_nsec, args.precision)),
+            MatchKind::Fuzzy => writeln!(
+                out,
+                "fuzzy {}",
+                format_latency(latency_nsec, args.precision)
+            ),
+            MatchKind::Fallback => writeln!(
+                out,
+                "fallback {}",
+                format_latency(latency_nsec, args.precision)
+            ),
+            MatchKind::Miss => writeln!(out, "miss"),
+        },
+        OutputFormat::Binary => record.write_to(out),
+        OutputFormat::Npy => {
+            let value = match record.kind {
+                MatchKind::Miss => f64::NAN,
+                _ => latency_usec(latency_nsec, args.precision),
             };
-            latency_sum += latency.abs();
-            latency_hit_count += 1;
-            if latency.abs() < latency_min {
-                latency_min = latency
-            }
-            if latency.abs() > latency_max {
-                latency_max = latency
+            out.npy.get_or_insert_default().push(value);
+            Ok(())
+        }
+        OutputFormat::Json => {
+            let record = JsonRecord {
+                kind: match record.kind {
+                    MatchKind::Exact => "exact",
+                    MatchKind::Fuzzy => "fuzzy",
+                    MatchKind::Fallback => "fallback",
+                    MatchKind::Miss => "miss",
+                },
+                latency_usec: (record.kind != MatchKind::Miss)
+                    .then(|| latency_usec(latency_nsec, args.precision)),
+            };
+            out.push_json(&record)
+        }
+    }
+    .and_then(|_| match out.live {
+        true => out.flush(),
+        false => Ok(()),
+    })
+    .unwrap_or_else(|e| fail("stdout", e.into()));
+}
+
+/// Per-packet record of `--format json`
+#[derive(Serialize)]
+struct JsonRecord {
+    #[serde(rename = "match")]
+    kind: &'static str,
+    /// `None` for misses
+    latency_usec: Option<f64>,
+}
+
+/// Latency in usec with `precision` decimal places as a number, truncated toward zero
+fn latency_usec(latency_nsec: i64, precision: u8) -> f64 {
+    let digits = precision as u32;
+    (latency_nsec / 10i64.pow(3 - digits)) as f64 / 10f64.powi(digits as i32)
+}
+
+/// Latency in usec with `precision` decimal places (up to 3, nanoseconds), truncated toward
+/// zero like whole usec
+fn format_latency(latency_nsec: i64, precision: u8) -> String {
+    let scaled = latency_nsec / 10i64.pow(3 - precision as u32);
+    if precision == 0 {
+        return scaled.to_string();
+    }
+    let unit = 10i64.pow(precision as u32);
+    format!(
+        "{}{}.{:0width$}",
+        if scaled < 0 { "-" } else { "" },
+        (scaled / unit).abs(),
+        (scaled % unit).abs(),
+        width = precision as usize
+    )
+}
+
+/// Stdout of per-packet records. Values of `--format npy` are kept until `finish`, since the
+/// header of the file gives their count. Records of `--format json` are written right away as
+/// elements of an array closed by `finish`, so memory use doesn't grow with the capture
+struct Printer<'a> {
+    out: BufWriter<StdoutLock<'a>>,
+    format: OutputFormat,
+    npy: Option<Vec<f64>>,
+    json: Option<JsonArray>,
+    /// Records left before output is truncated by `--max-print-lines`
+    lines_left: Option<u64>,
+    truncated: bool,
+    /// Flushes every record, for `--wait` where they come as packets arrive
+    live: bool,
+}
+
+impl Printer<'_> {
+    fn new(args: &SharedArgs) -> Self {
+        Self {
+            out: BufWriter::new(io::stdout().lock()),
+            format: args.format,
+            npy: (args.format == OutputFormat::Npy).then(Vec::new),
+            json: (args.format == OutputFormat::Json).then_some(JsonArray {
+                pretty: args.json_pretty,
+                elements: 0,
+            }),
+            lines_left: args.max_print_lines,
+            truncated: false,
+            live: false,
+        }
+    }
+
+    /// Counts a record against `--max-print-lines`. Returns `false` once they are used up,
+    /// noting the truncation the first time: in text output, or on stderr so that binary and
+    /// npy files stay valid
+    fn admit(&mut self) -> bool {
+        match &mut self.lines_left {
+            None => true,
+            Some(0) => {
+                if !self.truncated {
+                    self.truncated = true;
+                    let notice = "... truncated, per-packet output is limited by --max-print-lines";
+                    match self.format {
+                        OutputFormat::Text => writeln!(self.out, "{}", notice)
+                            .unwrap_or_else(|e| fail("stdout", e.into())),
+                        OutputFormat::Binary | OutputFormat::Npy | OutputFormat::Json => {
+                            eprintln!("{}", notice)
+                        }
+                    }
+                }
+                false
             }
-        } else {
-            miss_count += 1;
-            if !args.disable_printing {
-                println!("miss")
+            Some(left) => {
+                *left -= 1;
+                true
             }
         }
     }
-    println!(
-        "Average latency (usec): {}. Jitter (usec): {}. Packets count: {}. Misses count: {} ({}%)",
-        latency_sum / latency_hit_count,
-        latency_max - latency_min,
-        in_interface_packet_count,
-        miss_count,
-        miss_count as f64 / in_interface_packet_count as f64 * 100f64
-    );
+
+    /// Writes value as the next element of the JSON array
+    fn push_json(&mut self, value: &impl Serialize) -> io::Result<()> {
+        let Some(array) = &mut self.json else {
+            return Ok(());
+        };
+        let separator = match (array.elements, array.pretty) {
+            (0, true) => "[\n  ",
+            (0, false) => "[",
+            (_, true) => ",\n  ",
+            (_, false) => ",",
+        };
+        let element = match array.pretty {
+            true => serde_json::to_string_pretty(value)?.replace('\n', "\n  "),
+            false => serde_json::to_string(value)?,
+        };
+        array.elements += 1;
+        write!(self.out, "{}{}", separator, element)
+    }
+
+    /// Writes the `.npy` file of values so far or closes the JSON array, if any, and flushes
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(values) = self.npy.take() {
+            npy::write_f64(&mut self.out, &values)?;
+        }
+        if let Some(array) = self.json.take() {
+            let end = match (array.elements, array.pretty) {
+                (0, _) => "[]",
+                (_, true) => "\n]",
+                (_, false) => "]",
+            };
+            writeln!(self.out, "{}", end)?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Array of `--format json` written so far
+struct JsonArray {
+    pretty: bool,
+    elements: u64,
+}
+
+impl Write for Printer<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Set by the first Ctrl-C, so loops writing buffered per-packet lines flush them before exiting,
+/// and readers waiting for a FIFO stop
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Exit code after Ctrl-C, 128 + SIGINT by shell convention
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Makes Ctrl-C set `INTERRUPTED` instead of killing the process. A second Ctrl-C kills it, in
+/// case the loop is blocked reading input
+#[cfg(unix)]
+fn catch_interrupt() {
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+        // SAFETY: signal() is async-signal-safe
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+    // SAFETY: the handler only stores an atomic and calls signal(), both async-signal-safe
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn catch_interrupt() {}
+
+/// Flushes out and exits if Ctrl-C was pressed
+fn exit_if_interrupted(out: &mut Printer) {
+    if INTERRUPTED.load(Ordering::Relaxed) {
+        out.finish().unwrap_or_else(|e| fail("stdout", e.into()));
+        process::exit(EXIT_INTERRUPTED);
+    }
+}
+
+/// JSON of value, compact or with `--json-pretty` indented
+fn json_string(args: &SharedArgs, value: &impl Serialize) -> String {
+    match args.json_pretty {
+        true => serde_json::to_string_pretty(value),
+        false => serde_json::to_string(value),
+    }
+    .expect("output is always serializable")
+}
+
+/// Prints report to stdout, or to stderr when stdout carries the per-packet records
+fn print_report(args: &SharedArgs, report: &impl fmt::Display) {
+    match args.format {
+        OutputFormat::Text => println!("{}", report),
+        OutputFormat::Binary | OutputFormat::Npy | OutputFormat::Json => eprintln!("{}", report),
+    }
+}
+
+fn print_summary(args: &SharedArgs, summary: &Summary) {
+    let summary = match args.summary_format {
+        SummaryFormat::Text if args.human => summary.human().to_string(),
+        SummaryFormat::Text => summary.to_string(),
+        SummaryFormat::Json => json_string(args, summary),
+    };
+    print_report(args, &summary);
+}
+
+/// Prints misses by reason, and inbound packets which weren't analyzed for lack of identity
+fn print_miss_table(
+    args: &SharedArgs,
+    miss_reasons: &BTreeMap<MissReason, u64>,
+    unidentified: u64,
+) {
+    let mut table = format!("{:<40} {:>10}", "Miss reason", "Count");
+    for (reason, count) in miss_reasons {
+        if *count > 0 {
+            table += &format!("\n{:<40} {:>10}", reason.description(), count);
+        }
+    }
+    table += &format!(
+        "\nNot analyzed: {} inbound packets without identity (protocol not supported or malformed)",
+        unidentified
+    );
+    print_report(args, &table);
+}
+
+/// Packets of the identity of `--explain-miss` and what became of them
+struct Explanation {
+    identity: String,
+    outbound: Vec<PacketTime>,
+    /// Inbound packets with their outcome
+    inbound: Vec<(PacketTime, String)>,
+    /// Packets of the identity in the files with neither byte filter nor `--protocol`, out and in
+    unfiltered: (u64, u64),
+}
+
+impl Explanation {
+    fn new(identity: &str) -> Self {
+        Self {
+            identity: identity.to_string(),
+            outbound: Vec::new(),
+            inbound: Vec::new(),
+            unfiltered: (0, 0),
+        }
+    }
+
+    fn explains(&self, id: &PacketId) -> bool {
+        id.to_string() == self.identity
+    }
+
+    /// Counts packets of the identity in file read without filters
+    fn count_unfiltered(&self, path: &str, options: ParseOptions) -> u64 {
+        let reader = PcapReader::new_from_path(path, Vec::new())
+            .unwrap_or_else(|e| fail(path, e))
+            .with_parse_options(ParseOptions {
+                protocols: Vec::new(),
+                ..options
+            });
+        reader.filter(|(id, _)| self.explains(id)).count() as u64
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Explanation of {}", self.identity)?;
+        write!(f, "Outbound: {} packets", self.outbound.len())?;
+        for time in &self.outbound {
+            write!(f, "\n  {}", time)?;
+        }
+        write!(f, "\nInbound: {} packets", self.inbound.len())?;
+        for (time, outcome) in &self.inbound {
+            write!(f, "\n  {} {}", time, outcome)?;
+        }
+        let (out, in_) = self.unfiltered;
+        write!(
+            f,
+            "\nWithout byte filter and --protocol: outbound {} packets, inbound {} packets",
+            out, in_
+        )?;
+        if out > self.outbound.len() as u64 || in_ > self.inbound.len() as u64 {
+            write!(
+                f,
+                "\nSome packets of the identity are excluded by the filter"
+            )?;
+        }
+        if out == 0 && in_ == 0 {
+            write!(
+                f,
+                "\nThe identity isn't in either file. Check its form against lines of --dump-unmatched-out and the options of identification"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints latency stats of exact matches of each group of TCP flags, with the summary
+fn print_flag_table(args: &SharedArgs, flag_latencies: &BTreeMap<TcpFlagGroup, Vec<i64>>) {
+    let mut table = format!(
+        "{:<8} {:>10} {:>12} {:>10} {:>10} {:>10}",
+        "Flags", "Matches", "Avg (usec)", "p50", "p99", "Max"
+    );
+    let optional = |value: Option<i64>| value.map_or("-".to_string(), |value| value.to_string());
+    for (flags, latencies) in flag_latencies {
+        let percentiles = Percentiles::of(latencies);
+        let sum: i64 = latencies.iter().map(|latency| latency.abs()).sum();
+        table += &format!(
+            "\n{:<8} {:>10} {:>12} {:>10} {:>10} {:>10}",
+            flags.mnemonic(),
+            latencies.len(),
+            sum / latencies.len() as i64,
+            optional(percentiles.p50),
+            optional(percentiles.p99),
+            optional(latencies.iter().map(|latency| latency.abs()).max())
+        );
+    }
+    print_report(args, &table);
+}
+
+/// Prints average and p99 latency of exact matches of each DSCP, with the summary. Packets
+/// which aren't IP have no DSCP
+fn print_dscp_table(args: &SharedArgs, dscp_latencies: &BTreeMap<Option<u8>, Vec<i64>>) {
+    let mut table = format!(
+        "{:<10} {:>10} {:>12} {:>10}",
+        "DSCP", "Matches", "Avg (usec)", "p99"
+    );
+    for (dscp, latencies) in dscp_latencies {
+        let class = match dscp {
+            Some(dscp) => match dscp_name(*dscp) {
+                Some(name) => format!("{} {}", dscp, name),
+                None => dscp.to_string(),
+            },
+            None => "-".to_string(),
+        };
+        let sum: i64 = latencies.iter().map(|latency| latency.abs()).sum();
+        table += &format!(
+            "\n{:<10} {:>10} {:>12} {:>10}",
+            class,
+            latencies.len(),
+            sum / latencies.len() as i64,
+            Percentiles::of(latencies)
+                .p99
+                .map_or("-".to_string(), |p99| p99.to_string())
+        );
+    }
+    print_report(args, &table);
+}
+
+/// Name of standard code point: default (RFC 2474), class selectors, assured forwarding
+/// (RFC 2597), voice admit (RFC 5865) and expedited forwarding (RFC 3246)
+fn dscp_name(dscp: u8) -> Option<String> {
+    match dscp {
+        0 => Some("BE".to_string()),
+        44 => Some("VA".to_string()),
+        46 => Some("EF".to_string()),
+        _ if dscp.is_multiple_of(8) => Some(format!("CS{}", dscp / 8)),
+        _ if (1..=4).contains(&(dscp / 8)) && [2, 4, 6].contains(&(dscp % 8)) => {
+            Some(format!("AF{}{}", dscp / 8, dscp % 8 / 2))
+        }
+        _ => None,
+    }
+}
+
+/// Match of `--top`, ordered by absolute latency, then by flow and inbound time, the earlier
+/// being greater
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct TopMatch {
+    magnitude: i64,
+    flow: Reverse<String>,
+    in_time: Reverse<PacketTime>,
+    latency: i64,
+    identity: String,
+}
+
+/// Prints matches of `--top` with the summary, the highest latency first
+fn print_top(args: &SharedArgs, top: Top<TopMatch>) {
+    let matches = top.into_sorted();
+    let mut table = format!("Top {} latencies (usec):", matches.len());
+    for top_match in matches {
+        table += &format!(
+            "\n{:>10} {} {}",
+            top_match.latency, top_match.in_time.0, top_match.identity
+        );
+    }
+    print_report(args, &table);
+}
+
+/// Latencies of the two directions of connections, see `--by-direction`. The end which sent
+/// the first inbound packet of a connection is taken for its client
+#[derive(Default)]
+struct DirectionLatencies {
+    /// Client end of every connection, by its ends in order
+    clients: HashMap<(Endpoint, Endpoint), Endpoint>,
+    forward: Vec<i64>,
+    /// Latencies of packets from the server end, which reach the outbound capture first, so
+    /// are negative
+    reverse: Vec<i64>,
+}
+
+impl DirectionLatencies {
+    /// Whether inbound packet is sent by the client of its connection, `None` for identities
+    /// without endpoints
+    fn is_forward(&mut self, tuple_id: &PacketId) -> Option<bool> {
+        let (src, dst) = tuple_id.endpoints()?;
+        let ends = (src.min(dst), src.max(dst));
+        Some(*self.clients.entry(ends).or_insert(src) == src)
+    }
+
+    fn add(&mut self, forward: bool, latency: i64) {
+        match forward {
+            true => self.forward.push(latency),
+            false => self.reverse.push(latency),
+        }
+    }
+}
+
+/// Prints latency stats of matches of each direction, with the summary
+fn print_direction_table(args: &SharedArgs, directions: &DirectionLatencies) {
+    let mut table = format!(
+        "{:<10} {:>10} {:>12} {:>10} {:>10}",
+        "Direction", "Matches", "Avg (usec)", "p50", "p99"
+    );
+    let optional = |value: Option<i64>| value.map_or("-".to_string(), |value| value.to_string());
+    for (name, latencies) in [
+        ("forward", &directions.forward),
+        ("reverse", &directions.reverse),
+    ] {
+        let percentiles = Percentiles::of(latencies);
+        let sum: i64 = latencies.iter().map(|latency| latency.abs()).sum();
+        let average = match latencies.is_empty() {
+            true => None,
+            false => Some(sum / latencies.len() as i64),
+        };
+        table += &format!(
+            "\n{:<10} {:>10} {:>12} {:>10} {:>10}",
+            name,
+            latencies.len(),
+            optional(average),
+            optional(percentiles.p50),
+            optional(percentiles.p99)
+        );
+    }
+    print_report(args, &table);
+}
+
+/// VLAN IDs (the outermost first, empty if untagged) each flow was seen with on one side
+type VlanFlows = BTreeMap<String, BTreeSet<Vec<u16>>>;
+
+/// Records VLAN IDs of packet of flow
+fn add_vlan_ids(
+    flows: &mut VlanFlows,
+    tuple_id: &PacketId,
+    vlan_ids: &[u16],
+    anonymizer: Option<&Anonymizer>,
+) {
+    let seen = flows.entry(flow(tuple_id, anonymizer)).or_default();
+    if !seen.contains(vlan_ids) {
+        seen.insert(vlan_ids.to_vec());
+    }
+}
+
+/// Prints flows seen on both sides with other VLAN IDs, with the summary
+fn print_vlan_translations(args: &SharedArgs, in_vlans: &VlanFlows, out_vlans: &VlanFlows) {
+    let translated: Vec<_> = in_vlans
+        .iter()
+        .filter_map(|(flow, seen_in)| {
+            let seen_out = out_vlans.get(flow)?;
+            (seen_in != seen_out).then_some((flow, seen_in, seen_out))
+        })
+        .collect();
+    let ids = |seen: &BTreeSet<Vec<u16>>| {
+        let ids: Vec<String> = seen
+            .iter()
+            .map(|ids| match ids.is_empty() {
+                true => "untagged".to_string(),
+                false => VlanIds(ids).to_string(),
+            })
+            .collect();
+        ids.join(", ")
+    };
+    let mut text = format!("VLAN translations: {}", translated.len());
+    for (flow, seen_in, seen_out) in translated {
+        text += &format!("\n{}: in {}, out {}", flow, ids(seen_in), ids(seen_out));
+    }
+    print_report(args, &text);
+}
+
+/// Writes entries left in the table sorted by time
+fn dump_unmatched(
+    path: &str,
+    entries: Vec<(&PacketId, &PacketTime)>,
+    anonymizer: Option<&Anonymizer>,
+) -> io::Result<()> {
+    let mut lines: Vec<_> = entries
+        .into_iter()
+        .map(|(tuple_id, packet_time)| {
+            let tuple_id = match anonymizer {
+                Some(anonymizer) => tuple_id.map_addresses(&|ip| anonymizer.pseudonym(ip)),
+                None => tuple_id.clone(),
+            };
+            (*packet_time, tuple_id.to_string())
+        })
+        .collect();
+    lines.sort();
+    let mut file = OutputFile::create(path)?;
+    for (packet_time, tuple_id) in lines {
+        writeln!(file, "{} {}", packet_time, tuple_id)?;
+    }
+    file.finish()
+}
+
+fn write_openmetrics(path: &str, histogram: &Histogram) -> io::Result<()> {
+    let mut file = OutputFile::create(path)?;
+    histogram.write(&mut file)?;
+    file.finish()
+}
+
+fn write_heatmap(path: &str, heatmap: &Heatmap, args: &SharedArgs) -> io::Result<()> {
+    let mut file = OutputFile::create(path)?;
+    match args.summary_format {
+        SummaryFormat::Text => heatmap.write_csv(&mut file)?,
+        SummaryFormat::Json => heatmap.write_json(&mut file, args.json_pretty)?,
+    }
+    file.finish()
+}
+
+/// Flow of packet, with pseudonyms of addresses if anonymized
+fn flow(tuple_id: &PacketId, anonymizer: Option<&Anonymizer>) -> String {
+    match anonymizer {
+        Some(anonymizer) => tuple_id
+            .map_addresses(&|ip| anonymizer.pseudonym(ip))
+            .flow(),
+        None => tuple_id.flow(),
+    }
+}
+
+/// Host names of pcapng Name Resolution Blocks of files, of the first file which names an
+/// address. Files are read through
+fn read_host_names(paths: &[&str]) -> HashMap<IpAddr, String> {
+    let mut names = HashMap::new();
+    for path in paths {
+        let mut reader =
+            PcapReader::new_from_path(path, Vec::new()).unwrap_or_else(|e| fail(path, e));
+        reader.by_ref().for_each(drop);
+        if let Some(e) = reader.take_error() {
+            fail(path, e);
+        }
+        for (ip, name) in reader.host_names() {
+            names.entry(*ip).or_insert_with(|| name.clone());
+        }
+    }
+    names
+}
+
+/// Option which enables identities of protocol, if they aren't built by default
+fn enabling_option(protocol: Protocol) -> Option<&'static str> {
+    match protocol {
+        Protocol::Tcp | Protocol::Icmp => None,
+        Protocol::Rtp => Some("--rtp-ports"),
+        Protocol::Dns => Some("--dns"),
+        Protocol::IpId => Some("--match-mode ip-id"),
+        Protocol::Ethernet => Some("--match-mode l2"),
+        Protocol::Bytes => Some("--key-bytes"),
+        Protocol::Custom => Some("library API"),
+    }
+}
+
+fn list_protocols(shared: &SharedArgs) {
+    let protocols = Protocol::value_variants().iter().map(|protocol| {
+        let value = protocol
+            .to_possible_value()
+            .expect("protocols are never skipped");
+        let description = value
+            .get_help()
+            .map_or(String::new(), |help| help.to_string());
+        (
+            value.get_name().to_string(),
+            enabling_option(*protocol),
+            description,
+        )
+    });
+    match shared.summary_format {
+        SummaryFormat::Text => {
+            println!("{:<10} {:<18} Description", "Protocol", "Enabled by");
+            for (name, option, description) in protocols {
+                println!(
+                    "{:<10} {:<18} {}",
+                    name,
+                    option.unwrap_or("default"),
+                    description
+                );
+            }
+        }
+        SummaryFormat::Json => {
+            let protocols: Vec<_> = protocols
+                .map(|(name, option, description)| {
+                    serde_json::json!({
+                        "name": name,
+                        "enabled_by": option,
+                        "description": description,
+                    })
+                })
+                .collect();
+            println!("{}", json_string(shared, &protocols));
+        }
+    }
+}
+
+fn read_summary(path: &str) -> Summary {
+    fs::read_to_string(path)
+        .map_err(Error::from)
+        .and_then(|output| {
+            // The summary is the last line of output, after per-packet lines, or the lines from
+            // the last unindented `{` if it's pretty-printed
+            let lines: Vec<&str> = output
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .collect();
+            let json = match lines.last() {
+                Some(&"}") => {
+                    let start = lines.iter().rposition(|line| *line == "{").unwrap_or(0);
+                    lines[start..].join("\n")
+                }
+                last => last.copied().unwrap_or_default().to_string(),
+            };
+            Summary::from_json(&json)
+        })
+        .unwrap_or_else(|e| fail(path, e))
+}
+
+fn merge(shared: &SharedArgs, args: &MergeArgs) {
+    let summaries: Vec<Summary> = args
+        .summary_file_paths
+        .iter()
+        .map(|path| read_summary(path))
+        .collect();
+    print_summary(shared, &Summary::merge(&summaries));
+}
+
+fn compare(shared: &SharedArgs, args: &CompareArgs) {
+    let before = read_summary(&args.before_path);
+    let after = read_summary(&args.after_path);
+    let deltas = compare::deltas(&before, &after);
+    let thresholds = Thresholds {
+        average_increase: args.max_average_increase,
+        p99_increase: args.max_p99_increase,
+        miss_rate_increase: args.max_miss_rate_increase,
+    };
+    let violations = thresholds.violations(&before, &after);
+    let report = match shared.summary_format {
+        SummaryFormat::Json => json_string(
+            shared,
+            &serde_json::json!({
+                "deltas": deltas,
+                "pass": violations.is_empty(),
+                "violations": violations,
+            }),
+        ),
+        SummaryFormat::Text => {
+            let optional = |value: Option<f64>, precision: usize| {
+                value.map_or("-".to_string(), |value| format!("{:.*}", precision, value))
+            };
+            let mut report = format!(
+                "{:<16} {:>12} {:>12} {:>10}",
+                "Metric", "Before", "After", "Change"
+            );
+            for delta in &deltas {
+                // Latencies and counts are integers, jitter and miss rate aren't
+                let precision = match delta.metric {
+                    "rfc3550 jitter" => 1,
+                    "miss rate" => 2,
+                    _ => 0,
+                };
+                report += &format!(
+                    "\n{:<16} {:>12} {:>12} {:>10}",
+                    delta.metric,
+                    optional(delta.before, precision),
+                    optional(delta.after, precision),
+                    delta
+                        .change_percent
+                        .map_or("-".to_string(), |change| format!("{:+.1}%", change))
+                );
+            }
+            if !thresholds.is_empty() {
+                match violations.is_empty() {
+                    true => report += "\nPASS",
+                    false => report += &format!("\nFAIL: {}", violations.join(", ")),
+                }
+            }
+            report
+        }
+    };
+    print_report(shared, &report);
+    if !violations.is_empty() {
+        process::exit(EXIT_REGRESSION);
+    }
+}
+
+/// Interval between inbound packets of `selftest`
+const SELFTEST_INTERVAL_NSEC: u64 = 10_000;
+
+/// Range of latencies injected by `selftest`
+const SELFTEST_LATENCY_USEC: RangeInclusive<u64> = 10..=5_000;
+
+fn write_synthetic(
+    path: &str,
+    packets: &[SyntheticPacket],
+    time: fn(&SyntheticPacket) -> PacketTime,
+) {
+    let mut order: Vec<&SyntheticPacket> = packets.iter().collect();
+    order.sort_by_key(|packet| time(packet));
+    fs::File::create(path)
+        .map(BufWriter::new)
+        .and_then(PcapWriter::new)
+        .and_then(|mut writer| {
+            for packet in order {
+                writer.write(time(packet), &packet.frame)?;
+            }
+            writer.finish()?;
+            Ok(())
+        })
+        .unwrap_or_else(|e| fail(path, e.into()));
+}
+
+/// Generates captures with known latencies, matches them with default options of
+/// identification and checks every latency against the injected one
+fn selftest(shared: &SharedArgs, args: &SelftestArgs) {
+    let dir = args.dir.clone().unwrap_or_else(std::env::temp_dir);
+    let path = |side: &str| {
+        let name = match args.dir {
+            Some(_) => format!("selftest_{}.pcap", side),
+            None => format!("selftest-{}-{}.pcap", process::id(), side),
+        };
+        dir.join(name).to_string_lossy().into_owned()
+    };
+    let (in_path, out_path) = (path("in"), path("out"));
+    let generator = Generator::new(
+        args.seed,
+        args.flows,
+        SELFTEST_INTERVAL_NSEC,
+        SELFTEST_LATENCY_USEC,
+    );
+    let packets: Vec<SyntheticPacket> = generator.take(args.packets).collect();
+    write_synthetic(&in_path, &packets, |packet| packet.in_time);
+    write_synthetic(&out_path, &packets, SyntheticPacket::out_time);
+
+    let started = Instant::now();
+    let mut table = OutboundTable::new();
+    let mut out_reader =
+        PcapReader::new_from_path(&out_path, Vec::new()).unwrap_or_else(|e| fail(&out_path, e));
+    for (tuple_id, packet_time) in out_reader.by_ref() {
+        table.insert(tuple_id, packet_time);
+    }
+    if let Some(e) = out_reader.take_error() {
+        fail(&out_path, e);
+    }
+    let mut in_reader =
+        PcapReader::new_from_path(&in_path, Vec::new()).unwrap_or_else(|e| fail(&in_path, e));
+    let (mut read, mut matched, mut wrong, mut max_error) = (0, 0, 0, 0);
+    for ((tuple_id, in_time), packet) in in_reader.by_ref().zip(&packets) {
+        read += 1;
+        let Some(out_time) = table.take(&tuple_id, in_time) else {
+            continue;
+        };
+        matched += 1;
+        let error = (PacketTime::diff(out_time, in_time) - packet.latency_nsec as i64).abs() / 1000;
+        if error > 0 {
+            wrong += 1;
+            max_error = max_error.max(error);
+        }
+    }
+    if let Some(e) = in_reader.take_error() {
+        fail(&in_path, e);
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    if args.dir.is_none() {
+        let _ = fs::remove_file(&in_path);
+        let _ = fs::remove_file(&out_path);
+    }
+    let packets_per_sec = 2.0 * read as f64 / elapsed.max(1e-9);
+    let pass = read == args.packets && matched == read && wrong == 0;
+    let report = match shared.summary_format {
+        SummaryFormat::Json => json_string(
+            shared,
+            &serde_json::json!({
+                "packets": args.packets,
+                "read": read,
+                "matched": matched,
+                "wrong_latencies": wrong,
+                "max_error_usec": max_error,
+                "analysis_sec": elapsed,
+                "packets_per_sec": packets_per_sec,
+                "pass": pass,
+            }),
+        ),
+        SummaryFormat::Text => format!(
+            "Packets: {} of {} flows, injected latencies {}-{} usec\nMatched: {} of {}. Wrong latencies: {} (max error {} usec)\nAnalysis (sec): {:.3}. Packets per second: {:.0}\n{}",
+            args.packets,
+            args.flows,
+            SELFTEST_LATENCY_USEC.start(),
+            SELFTEST_LATENCY_USEC.end(),
+            matched,
+            read,
+            wrong,
+            max_error,
+            elapsed,
+            packets_per_sec,
+            if pass { "PASS" } else { "FAIL" }
+        ),
+    };
+    print_report(shared, &report);
+    if !pass {
+        process::exit(EXIT_SELFTEST_FAILED);
+    }
+}
+
+fn stats(shared: &SharedArgs, args: &StatsArgs) {
+    let path = &args.pcap_file_path;
+    let mut reader = PcapReader::new_from_path(path, shared.filter())
+        .unwrap_or_else(|e| fail(path, e))
+        .with_parse_options(shared.parse_options())
+        .with_ts_source(shared.ts_source)
+        .with_hardware_ts_pen(shared.hw_ts_pen);
+    let mut packets: u64 = 0;
+    let mut protocols: HashMap<Protocol, u64> = HashMap::new();
+    let mut identities = HashSet::new();
+    let mut first: Option<PacketTime> = None;
+    let mut last: Option<PacketTime> = None;
+    for (tuple_id, packet_time) in reader.by_ref() {
+        packets += 1;
+        *protocols.entry(tuple_id.protocol()).or_default() += 1;
+        identities.insert(tuple_id);
+        first = first.min(Some(packet_time)).or(Some(packet_time));
+        last = last.max(Some(packet_time));
+    }
+    if let Some(e) = reader.take_error() {
+        fail(path, e);
+    }
+    let duration = match (first, last) {
+        (Some(first), Some(last)) => PacketTime::diff(last, first) as f64 / 1e9,
+        _ => 0.0,
+    };
+    println!(
+        "Packets count: {}. Protocols: tcp {}, icmp {}, rtp {}, dns {}, ip-id {}, ethernet {}, bytes {}, other {}. Unique identities: {}. Duplicate identities: {}. First packet: {}. Last packet: {}. Duration (sec): {:.6}. Link-layer type: {}. Snapshot length: {}. Hardware timestamps: {}",
+        packets,
+        protocols.get(&Protocol::Tcp).unwrap_or(&0),
+        protocols.get(&Protocol::Icmp).unwrap_or(&0),
+        protocols.get(&Protocol::Rtp).unwrap_or(&0),
+        protocols.get(&Protocol::Dns).unwrap_or(&0),
+        protocols.get(&Protocol::IpId).unwrap_or(&0),
+        protocols.get(&Protocol::Ethernet).unwrap_or(&0),
+        protocols.get(&Protocol::Bytes).unwrap_or(&0),
+        reader.unidentified_count(),
+        identities.len(),
+        packets - identities.len() as u64,
+        first.map_or("-".to_string(), |time| time.to_string()),
+        last.map_or("-".to_string(), |time| time.to_string()),
+        duration,
+        reader
+            .link_type()
+            .map_or("-".to_string(), |link_type| link_type.to_string()),
+        reader
+            .snaplen()
+            .map_or("-".to_string(), |snaplen| snaplen.to_string()),
+        reader.hardware_timestamp_count()
+    );
+    if shared.size_range().is_some() {
+        println!("Excluded by size: {}", reader.size_excluded_count());
+    }
+    if reader.is_pcapng() {
+        println!(
+            "Interfaces: {}. Host names: {}",
+            reader.interface_names().join(", "),
+            reader.host_names().len()
+        );
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Phase {
+    OutboundParse,
+    TableBuild,
+    InboundParse,
+    Matching,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [
+        Phase::OutboundParse,
+        Phase::TableBuild,
+        Phase::InboundParse,
+        Phase::Matching,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Phase::OutboundParse => "parse outbound file",
+            Phase::TableBuild => "build table",
+            Phase::InboundParse => "parse inbound file",
+            Phase::Matching => "match and output",
+        }
+    }
+}
+
+/// Wall time spent in phases of analysis. Measured only if enabled, since taking time for
+/// every packet costs
+struct Timing {
+    enabled: bool,
+    phases: [Duration; 4],
+}
+
+impl Timing {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            phases: [Duration::ZERO; 4],
+        }
+    }
+
+    fn measure<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        if !self.enabled {
+            return f();
+        }
+        let start = Instant::now();
+        let result = f();
+        self.phases[phase as usize] += start.elapsed();
+        result
+    }
+
+    /// Breakdown table, to stderr so it doesn't mix with the output
+    fn print(&self) {
+        if !self.enabled {
+            return;
+        }
+        let total: Duration = self.phases.iter().sum();
+        eprintln!("{:<22} {:>12} {:>7}", "Phase", "Time (ms)", "Share");
+        for phase in Phase::ALL {
+            let time = self.phases[phase as usize];
+            eprintln!(
+                "{:<22} {:>12.3} {:>6.1}%",
+                phase.name(),
+                time.as_secs_f64() * 1000.0,
+                time.as_secs_f64() / total.as_secs_f64().max(f64::MIN_POSITIVE) * 100.0
+            );
+        }
+        eprintln!("{:<22} {:>12.3}", "total", total.as_secs_f64() * 1000.0);
+    }
+}
+
+/// Throttled progress lines of a phase, to stderr so they don't mix with the output
+struct Progress {
+    enabled: bool,
+    phase: &'static str,
+    /// Size of the file read in the phase, `None` if unknown (e.g. a FIFO)
+    input_len: Option<u64>,
+    start: Instant,
+    next_report: Instant,
+}
+
+impl Progress {
+    fn new(enabled: bool, phase: &'static str, input_len: Option<u64>) -> Self {
+        let start = Instant::now();
+        Self {
+            enabled,
+            phase,
+            input_len,
+            start,
+            next_report: start + PROGRESS_INTERVAL,
+        }
+    }
+
+    /// Prints the line given by `report` if the interval since the previous one has passed,
+    /// with how far into the file `consumed` bytes are
+    fn tick(&mut self, consumed: u64, report: impl FnOnce() -> String) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        if now >= self.next_report {
+            eprintln!(
+                "{}: {} ({})",
+                self.phase,
+                report(),
+                self.position(consumed, now - self.start)
+            );
+            self.next_report = now + PROGRESS_INTERVAL;
+        }
+    }
+
+    /// Share of the file read and the time left at the rate so far, or only bytes read and the
+    /// rate if the size of the file is unknown
+    fn position(&self, consumed: u64, elapsed: Duration) -> String {
+        let rate = consumed as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+        match self.input_len.filter(|len| *len > 0) {
+            Some(len) => {
+                let share = consumed.min(len) as f64 / len as f64;
+                let left = (len.saturating_sub(consumed) as f64 / rate.max(1.0)) as u64;
+                format!("{:.0}% - ETA {}:{:02}", share * 100.0, left / 60, left % 60)
+            }
+            None => format!(
+                "{:.1} MB read, {:.1} MB/s",
+                consumed as f64 / 1e6,
+                rate / 1e6
+            ),
+        }
+    }
+
+    fn finish(&self, report: impl FnOnce() -> String) {
+        if self.enabled {
+            eprintln!("{} done: {}", self.phase, report());
+        }
+    }
+}
+
+/// Line of `--match-rate` report with the share matched so far and since the previous report
+fn match_rate_report(match_rate: &mut MatchRate, matched: u64, packets: u64) -> String {
+    let (matched_since, packets_since) = match_rate.update(matched, packets);
+    format!(
+        "{} of {} inbound packets matched ({}), {} since previous report{}",
+        matched,
+        packets,
+        percentage(matched, packets),
+        percentage(matched_since, packets_since),
+        match matched == 0 && packets > 0 {
+            true => " - none matched yet, check filters and that the captures overlap",
+            false => "",
+        }
+    )
+}
+
+fn percentage(part: u64, whole: u64) -> String {
+    match whole {
+        0 => "-".to_string(),
+        _ => format!("{:.1}%", part as f64 / whole as f64 * 100.0),
+    }
+}
+
+/// Latency readout of follow mode, one line of its output
+#[derive(Serialize)]
+struct Readout {
+    /// Wall time of the readout, seconds since the epoch
+    time: f64,
+    /// Moving average of latencies (usec)
+    ewma_latency: Option<f64>,
+    /// p99 of the last `window` latencies (usec)
+    p99_latency: Option<i64>,
+    window: usize,
+    rfc3550_jitter: f64,
+    matches: u64,
+    misses: u64,
+    possibly_evicted: u64,
+    /// Whether the alert of `--alert-above` is active, absent without it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alert: Option<bool>,
+}
+
+impl fmt::Display for Readout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "EWMA latency (usec): {}. p99 latency (usec, last {} matches): {}. RFC 3550 jitter (usec): {:.1}. Matches count: {}. Misses count: {} (possibly evicted: {})",
+            self.ewma_latency
+                .map_or("-".to_string(), |value| format!("{:.1}", value)),
+            self.window,
+            self.p99_latency
+                .map_or("-".to_string(), |value| value.to_string()),
+            self.rfc3550_jitter,
+            self.matches,
+            self.misses,
+            self.possibly_evicted
+        )?;
+        if self.alert == Some(true) {
+            write!(f, ". ALERT")?;
+        }
+        Ok(())
+    }
+}
+
+fn follow(shared: &SharedArgs, args: &FollowArgs) -> ! {
+    let capture = LiveCapture::start(
+        &args.in_interface,
+        &args.out_interface,
+        shared.filter(),
+        shared.parse_options(),
+    )
+    .unwrap_or_else(|e| fail("live capture", e));
+    let mut matcher = LiveMatcher::new(Duration::from_secs_f64(args.match_timeout));
+    let mut ewma = Ewma::new(args.ewma_alpha);
+    let mut window = SlidingWindow::new(FOLLOW_WINDOW_SIZE);
+    let mut jitter = InterarrivalJitter::new();
+    let mut matched: u64 = 0;
+    let interval = Duration::from_secs_f64(args.print_interval);
+    let mut next_readout = Instant::now() + interval;
+    let mut evicted_warned = false;
+    let mut alert = args.alert_above.map(|above| {
+        let below = args.alert_clear_below.unwrap_or(above);
+        Alert::new(above, below, args.alert_intervals)
+    });
+    let mut out = Printer::new(shared);
+    catch_interrupt();
+    loop {
+        exit_if_interrupted(&mut out);
+        let timeout = next_readout.saturating_duration_since(Instant::now());
+        match capture.recv_timeout(timeout) {
+            Ok(Some((side, tuple_id, packet_time))) => {
+                if let LiveEvent::Matched(latency) = matcher.push(side, tuple_id, packet_time) {
+                    matched += 1;
+                    ewma.update(latency as f64);
+                    window.push(latency);
+                    jitter.update(latency);
+                    print_record(shared, &mut out, MatchKind::Exact, latency * 1000);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => fail("live capture", e),
+        }
+        if Instant::now() < next_readout {
+            continue;
+        }
+        next_readout += interval;
+        let change = alert.as_mut().and_then(|alert| alert.update(ewma.value()));
+        let readout = Readout {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            ewma_latency: ewma.value(),
+            p99_latency: window.percentile(99.0),
+            window: FOLLOW_WINDOW_SIZE,
+            rfc3550_jitter: jitter.value(),
+            matches: matched,
+            misses: matcher.misses(),
+            possibly_evicted: matcher.possibly_evicted(),
+            alert: alert.as_ref().map(Alert::is_active),
+        };
+        // JSON lines: every readout is a complete object, written as soon as it's taken
+        let readout = match shared.summary_format {
+            SummaryFormat::Text => readout.to_string(),
+            SummaryFormat::Json => {
+                serde_json::to_string(&readout).expect("readout is always serializable")
+            }
+        };
+        // Records of the interval are flushed along with its readout
+        match shared.format {
+            OutputFormat::Text => writeln!(out, "{}", readout),
+            OutputFormat::Binary | OutputFormat::Npy | OutputFormat::Json => {
+                writeln!(io::stderr(), "{}", readout)
+            }
+        }
+        .and_then(|_| out.flush())
+        .unwrap_or_else(|e| fail("stdout", e.into()));
+        match change {
+            Some(AlertChange::Raised) => eprintln!(
+                "ALERT: EWMA latency is above {} usec for {} readouts",
+                args.alert_above.unwrap_or_default(),
+                args.alert_intervals
+            ),
+            Some(AlertChange::Cleared) => eprintln!(
+                "CLEARED: EWMA latency is below {} usec for {} readouts",
+                args.alert_clear_below
+                    .or(args.alert_above)
+                    .unwrap_or_default(),
+                args.alert_intervals
+            ),
+            None => {}
+        }
+        let inbound = matched + matcher.misses();
+        if !evicted_warned
+            && matcher.possibly_evicted() as f64 > FOLLOW_EVICTED_WARNING_SHARE * inbound as f64
+        {
+            eprintln!(
+                "WARNING: {} inbound packets arrived after outbound packets of their time had expired, so their pairs may have been dropped too early. Consider a --match-timeout longer than {} s",
+                matcher.possibly_evicted(),
+                args.match_timeout
+            );
+            evicted_warned = true;
+        }
+    }
+}
+
+fn main() {
+    let mut cli = Cli::parse();
+    if cli.shared.l2 {
+        cli.shared.match_mode = MatchMode::L2;
+    }
+    if cli.shared.key_bytes.is_some() {
+        cli.shared.match_mode = MatchMode::Bytes;
+    }
+    let shared = &cli.shared;
+    if shared.hash_range.is_some() && shared.tcp_match_mode == TcpMatchMode::SeqAck {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--hash-range requires --tcp-match-mode payload or message",
+            )
+            .exit();
+    }
+    if shared.tcp_ignore_ack && shared.tcp_match_mode != TcpMatchMode::SeqAck {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--tcp-ignore-ack requires --tcp-match-mode seq-ack",
+            )
+            .exit();
+    }
+    if let Err(e) = KeyFields::new(&shared.key) {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("--key: {}", e),
+            )
+            .exit();
+    }
+    if shared.protocols.contains(&Protocol::Rtp) && shared.rtp_ports.is_none() {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "--protocol rtp requires --rtp-ports",
+            )
+            .exit();
+    }
+    if shared.match_mode != MatchMode::Transport && shared.tcp_match_mode != TcpMatchMode::SeqAck {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--tcp-match-mode doesn't apply to --match-mode ip-id and l2, nor --key-bytes",
+            )
+            .exit();
+    }
+    if shared.protocols.contains(&Protocol::IpId) != (shared.match_mode == MatchMode::IpId)
+        && !shared.protocols.is_empty()
+    {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--protocol ip-id is required by and only valid with --match-mode ip-id",
+            )
+            .exit();
+    }
+    if shared.protocols.contains(&Protocol::Ethernet) != (shared.match_mode == MatchMode::L2)
+        && !shared.protocols.is_empty()
+    {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--protocol ethernet is required by and only valid with --match-mode l2",
+            )
+            .exit();
+    }
+    if shared.protocols.contains(&Protocol::Bytes) != (shared.match_mode == MatchMode::Bytes)
+        && !shared.protocols.is_empty()
+    {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--protocol bytes is required by and only valid with --key-bytes",
+            )
+            .exit();
+    }
+    if shared.size_range().is_some_and(|range| range.is_empty()) {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--min-size must not be larger than --max-size",
+            )
+            .exit();
+    }
+    if shared.protocols.contains(&Protocol::Dns) && !shared.dns {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "--protocol dns requires --dns",
+            )
+            .exit();
+    }
+    if shared.message_framing.is_some() != (shared.tcp_match_mode == TcpMatchMode::Message) {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--message-framing is required by and only valid with --tcp-match-mode message",
+            )
+            .exit();
+    }
+    match (&cli.command, &cli.analyze) {
+        (Some(_), Some(_)) | (None, None) => {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "either a subcommand or <PCAP FILE IN> <PCAP FILE OUT> are expected",
+                )
+                .exit();
+        }
+        (Some(Command::Analyze(args)), None) | (None, Some(args)) => analyze(shared, args),
+        (Some(Command::Exchange(args)), None) => exchange(shared, args),
+        (Some(Command::Follow(args)), None) => {
+            if let (Some(above), Some(below)) = (args.alert_above, args.alert_clear_below) {
+                if below > above {
+                    Cli::command()
+                        .error(
+                            clap::error::ErrorKind::ArgumentConflict,
+                            "--alert-clear-below must not be greater than --alert-above",
+                        )
+                        .exit();
+                }
+            }
+            follow(shared, args)
+        }
+        (Some(Command::Stats(args)), None) => stats(shared, args),
+        (Some(Command::Merge(args)), None) => merge(shared, args),
+        (Some(Command::Compare(args)), None) => compare(shared, args),
+        (Some(Command::Schema), None) => println!("{}", summary::json_schema()),
+        (Some(Command::ListProtocols), None) => list_protocols(shared),
+        (Some(Command::Selftest(args)), None) => selftest(shared, args),
+    }
+}
+
+/// File of one side of measurement
+struct Input<'a> {
+    path: &'a str,
+    options: ParseOptions,
+}
+
+fn analyze(shared: &SharedArgs, args: &AnalyzeArgs) {
+    let inbound = Input {
+        path: &args.in_interface_pcap_file_path,
+        options: shared.parse_options(),
+    };
+    let outbound = Input {
+        path: &args.out_interface_pcap_file_path,
+        options: shared.parse_options(),
+    };
+    if args.wait && args.matching.show_alignment {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--show-alignment reads the captures twice, which isn't possible with --wait",
+            )
+            .exit();
+    }
+    if args.wait && args.matching.require_overlap {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--require-overlap reads the captures twice, which isn't possible with --wait",
+            )
+            .exit();
+    }
+    if args.wait && args.matching.resolve_names {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--resolve-names reads the captures twice, which isn't possible with --wait",
+            )
+            .exit();
+    }
+    measure(shared, &args.matching, inbound, outbound, args.wait);
+}
+
+/// Measures requests against responses: the file is read once for each direction
+fn exchange(shared: &SharedArgs, args: &ExchangeArgs) {
+    if shared.tcp_match_mode != TcpMatchMode::SeqAck || shared.match_mode != MatchMode::Transport {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--tcp-match-mode and --match-mode don't apply to `exchange`, responses are paired by acknowledgement",
+            )
+            .exit();
+    }
+    let local = match (args.local.local_ip, args.local.local_mac) {
+        (Some(ip), _) => Local::Ip(ip),
+        (None, Some(mac)) => Local::Mac(mac),
+        (None, None) => Local::Any,
+    };
+    let side = |direction| Input {
+        path: &args.pcap_file_path,
+        options: ParseOptions {
+            exchange: Some(Exchange {
+                local,
+                direction,
+                http: args.http,
+            }),
+            ..shared.parse_options()
+        },
+    };
+    let inbound = side(Direction::Request);
+    let outbound = side(Direction::Response);
+    measure(shared, &args.matching, inbound, outbound, false);
+}
+
+fn measure(shared: &SharedArgs, args: &MatchArgs, inbound: Input, outbound: Input, wait: bool) {
+    let in_path = inbound.path;
+    let out_path = outbound.path;
+    let mut explanation = args.explain_miss.as_deref().map(Explanation::new);
+    // Identification of `--explain-miss` with neither filter, counted after the run
+    let unfiltered_options = explanation
+        .as_ref()
+        .map(|_| (outbound.options.clone(), inbound.options.clone()));
+    let filter = shared.filter();
+    check_capture_files(in_path);
+    check_capture_files(out_path);
+    if args.show_alignment || args.require_overlap {
+        let span = |path: &str, options: &ParseOptions| {
+            let mut reader = PcapReader::new_from_path(path, filter.clone())
+                .unwrap_or_else(|e| fail(path, e))
+                .with_parse_options(options.clone())
+                .with_ts_source(shared.ts_source)
+                .with_hardware_ts_pen(shared.hw_ts_pen);
+            let span = Span::of(reader.by_ref().map(|(_, time)| time));
+            if let Some(e) = reader.take_error() {
+                fail(path, e);
+            }
+            span
+        };
+        let alignment = Alignment {
+            inbound: span(in_path, &inbound.options),
+            outbound: span(out_path, &outbound.options),
+        };
+        if args.show_alignment {
+            print_report(shared, &alignment);
+        }
+        if args.require_overlap {
+            check_overlap(shared, args, &alignment, in_path, out_path);
+        }
+    }
+    // The table is built from the outbound file, unless `--auto-table-side` finds the inbound
+    // one smaller. Latency is out - in either way
+    let swapped = args.auto_table_side && file_len(in_path) < file_len(out_path);
+    let memory_bound = args.max_memory.and_then(|budget| match swapped {
+        true => memory_bound(budget, in_path, filter.clone(), &inbound.options, "inbound"),
+        false => memory_bound(
+            budget,
+            out_path,
+            filter.clone(),
+            &outbound.options,
+            "outbound",
+        ),
+    });
+    // Ctrl-C flushes per-packet lines and exits, or with --wait ends reading of a FIFO and the
+    // run goes on to the summary
+    catch_interrupt();
+    let out_interface_reader = PcapReader::new_from_path(out_path, filter.clone())
+        .unwrap_or_else(|e| fail(out_path, e))
+        .with_parse_options(outbound.options)
+        .with_fallback(args.fallback)
+        .with_ts_source(shared.ts_source)
+        .with_hardware_ts_pen(shared.hw_ts_pen)
+        .with_wait(wait)
+        .with_stop(&INTERRUPTED)
+        .with_vlan_ids(shared.ignore_vlan);
+    let in_interface_reader = PcapReader::new_from_path(in_path, filter)
+        .unwrap_or_else(|e| fail(in_path, e))
+        .with_parse_options(inbound.options)
+        .with_fallback(args.fallback)
+        .with_ts_source(shared.ts_source)
+        .with_hardware_ts_pen(shared.hw_ts_pen)
+        .with_wait(wait)
+        .with_stop(&INTERRUPTED)
+        .with_dscp(args.by_dscp)
+        .with_vlan_ids(shared.ignore_vlan);
+    if let (Some(in_link_type), Some(out_link_type)) = (
+        in_interface_reader.link_type(),
+        out_interface_reader.link_type(),
+    ) {
+        if in_link_type != out_link_type {
+            eprintln!(
+                "WARNING: link-layer types of the files differ (in: {}, out: {}). Packets are compared starting from IP layer, but byte filter offsets apply to different headers",
+                in_link_type, out_link_type
+            );
+        }
+    }
+
+    if let (Some(in_snaplen), Some(out_snaplen)) = (
+        in_interface_reader.snaplen(),
+        out_interface_reader.snaplen(),
+    ) {
+        if in_snaplen != out_snaplen {
+            let consequence = match shared.tcp_match_mode {
+                TcpMatchMode::Payload | TcpMatchMode::Message => "Payloads of packets truncated in only one of them hash differently, so such packets are missed",
+                TcpMatchMode::SeqAck => "Identities are taken from headers, so matching isn't affected unless headers are truncated",
+            };
+            eprintln!(
+                "WARNING: snapshot lengths of the captures differ (in: {}, out: {}). {}",
+                in_snaplen, out_snaplen, consequence
+            );
+        }
+    }
+
+    // Digits of the finer timestamps beyond the resolution of the coarser ones are noise
+    let in_resolution = in_interface_reader.timestamp_resolution_nsec();
+    let out_resolution = out_interface_reader.timestamp_resolution_nsec();
+    let resolution = args
+        .round_to
+        .unwrap_or_else(|| in_resolution.max(out_resolution));
+    if args.round_to.is_some() || in_resolution != out_resolution {
+        eprintln!(
+            "Timestamps are rounded to {} before computing latency (resolution of in: {}, out: {})",
+            format_resolution(resolution),
+            format_resolution(in_resolution),
+            format_resolution(out_resolution)
+        );
+    }
+
+    let (table_side, stream_side) = if swapped {
+        ("inbound", "outbound")
+    } else {
+        ("outbound", "inbound")
+    };
+    if args.auto_table_side {
+        eprintln!(
+            "Table is built from the {} file (the smaller one), the {} file is streamed",
+            table_side, stream_side
+        );
+    }
+    let (mut table_reader, table_path, table_phase, mut stream_reader, stream_path, stream_phase) =
+        if swapped {
+            (
+                in_interface_reader,
+                in_path,
+                Phase::InboundParse,
+                out_interface_reader,
+                out_path,
+                Phase::OutboundParse,
+            )
+        } else {
+            (
+                out_interface_reader,
+                out_path,
+                Phase::OutboundParse,
+                in_interface_reader,
+                in_path,
+                Phase::InboundParse,
+            )
+        };
+
+    let mut table = match args.fuzzy_window {
+        Some(_) => OutboundTable::with_fuzzy_index(),
+        None => OutboundTable::new(),
+    }
+    .with_pairing(args.pairing);
+    if let Some(max_entries) = args.max_table_entries.or(memory_bound) {
+        table = table.with_max_entries(max_entries);
+    }
+    if args.fallback.is_some() {
+        table = table.with_fallback_index();
+    }
+    if let Some(window) = args.match_window {
+        table = table.with_max_distance(window as i64 * 1000);
+    }
+    // Identities of an inbound table are never taken by an earlier packet, so misses are either
+    // left in the table or evicted from it
+    if (args.miss_reasons || explanation.is_some()) && !swapped {
+        table = table.with_miss_reasons();
+    }
+    if args.retransmissions {
+        table = table.with_retransmissions();
+    }
+    let anonymizer = shared.anonymizer();
+    // VLAN IDs each flow was seen with on the table side and the streamed side, if VLAN IDs
+    // are left out of identities
+    let mut table_vlans = VlanFlows::new();
+    let mut stream_vlans = VlanFlows::new();
+    // Identities of table packets reassembled from fragments, with `--defragment`
+    let mut table_reassembled: HashSet<PacketId> = HashSet::new();
+    // Inbound packets, matches and misses by protocol
+    let mut protocols: BTreeMap<Protocol, ProtocolCounts> = BTreeMap::new();
+    // Sampling applies to inbound packets, whichever side they are
+    let mut sampler = Sampler::new(args.sample_rate, args.seed);
+    let mut timing = Timing::new(args.timing);
+    let build_phase = if swapped {
+        "building inbound table"
+    } else {
+        "building outbound table"
+    };
+    let mut build_progress = Progress::new(args.progress, build_phase, table_reader.input_len());
+    let mut inserted: u64 = 0;
+    let mut in_flight_warned = false;
+    while let Some((tuple_id, packet_time)) = timing.measure(table_phase, || table_reader.next()) {
+        if swapped && !sampler.sample() {
+            continue;
+        }
+        if swapped {
+            protocols.entry(tuple_id.protocol()).or_default().packets += 1;
+        }
+        if let Some(explanation) = &mut explanation {
+            if explanation.explains(&tuple_id) {
+                explanation.outbound.push(packet_time);
+            }
+        }
+        if shared.ignore_vlan {
+            add_vlan_ids(
+                &mut table_vlans,
+                &tuple_id,
+                table_reader.vlan_ids(),
+                anonymizer.as_ref(),
+            );
+        }
+        if shared.defragment && table_reader.reassembled() {
+            table_reassembled.insert(tuple_id.clone());
+        }
+        let fallback_key = table_reader.fallback_key();
+        timing.measure(Phase::TableBuild, || {
+            table.insert_with_fallback(tuple_id, packet_time, fallback_key)
+        });
+        inserted += 1;
+        if table.len() > args.max_packets_in_flight && !in_flight_warned {
+            eprintln!(
+                "WARNING: more than {} {} packets are waiting for a match and memory use keeps growing. Bound the table with --max-table-entries, narrow the input with --filter or --protocol, or measure in streaming mode with `follow`",
+                args.max_packets_in_flight, table_side
+            );
+            in_flight_warned = true;
+        }
+        build_progress.tick(table_reader.consumed_bytes(), || {
+            format!(
+                "{} entries inserted, {} collisions",
+                inserted,
+                table.collisions()
+            )
+        });
+    }
+    let mut read_error = None;
+    let mut incomplete = Vec::new();
+    if let Some(e) = table_reader.take_error() {
+        read_error = Some(read_failure(args, &mut incomplete, table_path, e));
+    }
+    build_progress.finish(|| {
+        format!(
+            "{} entries ({} identities), {} collisions, {} evicted",
+            table.len(),
+            table.identities(),
+            table.collisions(),
+            table.evicted()
+        )
+    });
+    if shared.tcp_ignore_ack || !shared.key.is_empty() {
+        let option = match shared.tcp_ignore_ack {
+            true => "--tcp-ignore-ack",
+            false => "--key",
+        };
+        eprintln!(
+            "Collisions with {}: {} {} packets have the identity of an earlier packet and may be paired with the wrong one",
+            option,
+            table.collisions(),
+            table_side
+        );
+    }
+    let mut matching_progress = Progress::new(args.progress, "matching", stream_reader.input_len());
+    let mut match_rate_progress =
+        Progress::new(args.match_rate, "match rate", stream_reader.input_len());
+    let mut match_rate = MatchRate::new();
+
+    let mut out = Printer::new(shared);
+    out.live = wait;
+    let mut summary = Summary {
+        runs: 1,
+        fuzzy_matches: args.fuzzy_window.map(|_| 0),
+        fallback_matches: args.fallback.map(|_| 0),
+        count_only: args.count_only,
+        ..Default::default()
+    };
+    let mut latencies: Vec<i64> = Vec::new();
+    let mut raw_latencies: Vec<i64> = Vec::new();
+    let clock_offset = args.clock_offset.unwrap_or(0);
+    let mut interarrival_jitter = InterarrivalJitter::new();
+    let mut flag_latencies: BTreeMap<TcpFlagGroup, Vec<i64>> = BTreeMap::new();
+    let mut dscp_latencies: BTreeMap<Option<u8>, Vec<i64>> = BTreeMap::new();
+    let mut directions = DirectionLatencies::default();
+    let mut histogram = Histogram::new();
+    let mut miss_reasons: BTreeMap<MissReason, u64> = BTreeMap::new();
+    let host_names = match args.resolve_names {
+        true => read_host_names(&[in_path, out_path]),
+        false => HashMap::new(),
+    };
+    let mut heatmap = Heatmap::new((args.heatmap_interval * 1e9) as u64);
+    // Inbound packet of flow at time counted in heatmap, if requested
+    let mut add_to_heatmap = |tuple_id: &PacketId, time: PacketTime, missed: bool| {
+        if args.loss_heatmap.is_some() {
+            let flow = match (args.group_prefix, args.resolve_names) {
+                (Some(prefix), _) => tuple_id.subnet_flow(&|ip| prefix.subnet(ip)),
+                (None, true) => tuple_id.flow_with_names(&host_names),
+                (None, false) => flow(tuple_id, anonymizer.as_ref()),
+            };
+            heatmap.add(flow, time, missed);
+        }
+    };
+    let mut parquet = args.parquet.as_ref().map(|path| {
+        OutputFile::create(path)
+            .and_then(ParquetWriter::new)
+            .unwrap_or_else(|e| fail(path, e.into()))
+    });
+    // Matched packet written to Parquet file, if requested
+    let mut add_to_parquet = |row: parquet::Row| {
+        if let (Some(writer), Some(path)) = (&mut parquet, &args.parquet) {
+            let id = match &anonymizer {
+                Some(anonymizer) => row.id.map_addresses(&|ip| anonymizer.pseudonym(ip)),
+                None => row.id,
+            };
+            let row = parquet::Row { id, ..row };
+            writer.push(row).unwrap_or_else(|e| fail(path, e.into()));
+        }
+    };
+    let mut top: Top<TopMatch> = Top::new(args.top.unwrap_or(0));
+    let mut add_to_top = |tuple_id: &PacketId, in_time: PacketTime, latency: i64| {
+        if args.top.is_none()
+            || top
+                .threshold()
+                .is_some_and(|least| latency.abs() < least.magnitude)
+        {
+            return;
+        }
+        let id = match &anonymizer {
+            Some(anonymizer) => tuple_id.map_addresses(&|ip| anonymizer.pseudonym(ip)),
+            None => tuple_id.clone(),
+        };
+        top.push(TopMatch {
+            magnitude: latency.abs(),
+            flow: Reverse(id.flow()),
+            in_time: Reverse(in_time),
+            latency,
+            identity: id.to_string(),
+        });
+    };
+    // Streamed outbound packets without a pair, if the table is of inbound ones
+    let mut unmatched_out = Vec::new();
+    // Streamed TCP packets without a pair whose identity was paired before
+    let mut stream_retransmissions: u64 = 0;
+    let mut defragmented_matches: u64 = 0;
+    let retransmitted = |table: &OutboundTable, tuple_id: &PacketId| {
+        tuple_id.protocol() == Protocol::Tcp && table.was_paired(tuple_id)
+    };
+    let mut streamed: u64 = 0;
+    while let Some((tuple_id, packet_time)) = timing.measure(stream_phase, || stream_reader.next())
+    {
+        if !wait {
+            exit_if_interrupted(&mut out);
+        }
+        let fallback_key = stream_reader.fallback_key();
+        let dscp = stream_reader.dscp();
+        let stream_reassembled = stream_reader.reassembled();
+        if shared.ignore_vlan {
+            add_vlan_ids(
+                &mut stream_vlans,
+                &tuple_id,
+                stream_reader.vlan_ids(),
+                anonymizer.as_ref(),
+            );
+        }
+        // Outcome of the inbound packet if it's of the identity of `--explain-miss`
+        let explained = explanation
+            .as_ref()
+            .is_some_and(|explanation| explanation.explains(&tuple_id));
+        let mut outcome = |text: String| {
+            if let Some(explanation) = explanation.as_mut().filter(|_| explained) {
+                explanation.inbound.push((packet_time, text));
+            }
+        };
+        if !swapped && !sampler.sample() {
+            outcome("skipped by --sample".to_string());
+            continue;
+        }
+        streamed += 1;
+        let forward = match args.by_direction {
+            true => directions.is_forward(&tuple_id),
+            false => None,
+        };
+        // Latency corrected by the clock offset, and uncorrected one (nsec)
+        let latency_to = |table_time: PacketTime| {
+            let (table_time, packet_time) = (
+                table_time.round_to(resolution),
+                packet_time.round_to(resolution),
+            );
+            let raw = if swapped {
+                PacketTime::diff(packet_time, table_time)
+            } else {
+                PacketTime::diff(table_time, packet_time)
+            };
+            (raw - clock_offset, raw)
+        };
+        timing.measure(Phase::Matching, || {
+            let counts = protocols.entry(tuple_id.protocol()).or_default();
+            if !swapped {
+                summary.packets += 1;
+                counts.packets += 1;
+            }
+            let exact = table.take(&tuple_id, packet_time);
+            // Fragmented between the captures if reassembled on one side only
+            if exact.is_some()
+                && shared.defragment
+                && stream_reassembled != table_reassembled.contains(&tuple_id)
+            {
+                defragmented_matches += 1;
+            }
+            let caught = exact.map(|time| (time, MatchKind::Exact)).or_else(|| {
+                let key = fallback_key?;
+                Some((table.take_fallback(&key)?, MatchKind::Fallback))
+            });
+            if caught.is_some() {
+                counts.matches += 1;
+            }
+            if let Some((_, kind)) = caught.filter(|_| args.count_only) {
+                summary.matches += 1;
+                if kind == MatchKind::Fallback {
+                    outcome("matched by fallback key".to_string());
+                    summary.fallback_matches = summary.fallback_matches.map(|count| count + 1);
+                } else {
+                    outcome("matched".to_string());
+                }
+            } else if let Some((table_time, kind)) = caught {
+                let (latency_nsec, raw_nsec) = latency_to(table_time);
+                let (latency, raw) = (latency_nsec / 1000, raw_nsec / 1000);
+                if args.clock_offset.is_some() {
+                    raw_latencies.push(raw);
+                }
+                if kind == MatchKind::Fallback {
+                    outcome(format!("matched by fallback key, latency {} usec", latency));
+                    summary.fallback_matches = summary.fallback_matches.map(|count| count + 1);
+                } else {
+                    outcome(format!("matched, latency {} usec", latency));
+                }
+                print_record(shared, &mut out, kind, latency_nsec);
+                summary.latency_sum += latency.abs();
+                summary.matches += 1;
+                latencies.push(latency);
+                interarrival_jitter.update(latency);
+                if let Some(flags) = tuple_id.tcp_flags() {
+                    flag_latencies.entry(flags).or_default().push(latency);
+                }
+                if args.by_dscp {
+                    dscp_latencies.entry(dscp).or_default().push(latency);
+                }
+                if let Some(forward) = forward {
+                    directions.add(forward, latency);
+                }
+                let (in_time, out_time) = match swapped {
+                    true => (table_time, packet_time),
+                    false => (packet_time, table_time),
+                };
+                add_to_heatmap(&tuple_id, in_time, false);
+                add_to_top(&tuple_id, in_time, latency);
+                if args.parquet.is_some() {
+                    let (id, fuzzy) = (tuple_id.clone(), false);
+                    add_to_parquet(parquet::Row {
+                        in_time,
+                        out_time,
+                        latency,
+                        fuzzy,
+                        id,
+                    });
+                }
+                if args.openmetrics.is_some() {
+                    histogram.observe(latency, || match &anonymizer {
+                        Some(anonymizer) => tuple_id
+                            .map_addresses(&|ip| anonymizer.pseudonym(ip))
+                            .to_string(),
+                        None => tuple_id.to_string(),
+                    });
+                }
+            } else if let Some(table_time) = args
+                .fuzzy_window
+                .and_then(|window| table.take_fuzzy(&tuple_id, packet_time, window))
+            {
+                summary.fuzzy_matches = summary.fuzzy_matches.map(|count| count + 1);
+                counts.matches += 1;
+                if args.count_only {
+                    outcome("fuzzy match".to_string());
+                    return;
+                }
+                let (latency_nsec, _) = latency_to(table_time);
+                let latency = latency_nsec / 1000;
+                outcome(format!("fuzzy match, latency {} usec", latency));
+                print_record(shared, &mut out, MatchKind::Fuzzy, latency_nsec);
+                summary.fuzzy_latency_sum += latency.abs();
+                let (in_time, out_time) = match swapped {
+                    true => (table_time, packet_time),
+                    false => (packet_time, table_time),
+                };
+                add_to_heatmap(&tuple_id, in_time, false);
+                add_to_top(&tuple_id, in_time, latency);
+                if args.parquet.is_some() {
+                    let (id, fuzzy) = (tuple_id.clone(), true);
+                    add_to_parquet(parquet::Row {
+                        in_time,
+                        out_time,
+                        latency,
+                        fuzzy,
+                        id,
+                    });
+                }
+            } else if swapped {
+                if retransmitted(&table, &tuple_id) {
+                    stream_retransmissions += 1;
+                }
+                if args.dump_unmatched_out.is_some() {
+                    unmatched_out.push((tuple_id, packet_time));
+                }
+            } else {
+                summary.misses += 1;
+                counts.misses += 1;
+                if retransmitted(&table, &tuple_id) {
+                    stream_retransmissions += 1;
+                }
+                let reason = table.miss_reason(&tuple_id);
+                outcome(format!("missed: {}", reason.description()));
+                if args.miss_reasons {
+                    *miss_reasons.entry(reason).or_default() += 1;
+                }
+                add_to_heatmap(&tuple_id, packet_time, true);
+                if !args.count_only {
+                    print_record(shared, &mut out, MatchKind::Miss, 0);
+                }
+            }
+        });
+        matching_progress.tick(stream_reader.consumed_bytes(), || match swapped {
+            true => format!("{} outbound packets, {} matches", streamed, summary.matches),
+            false => format!(
+                "{} inbound packets, {} misses",
+                summary.packets, summary.misses
+            ),
+        });
+        match_rate_progress.tick(stream_reader.consumed_bytes(), || {
+            // Swapped, the inbound packets are all in the table and a match may be any of them
+            let (matched, packets) = match swapped {
+                true => (summary.matches, inserted),
+                false => (summary.packets - summary.misses, summary.packets),
+            };
+            match_rate_report(&mut match_rate, matched, packets)
+        });
+    }
+    // Inbound packets left in the table (or evicted from it) are misses, reported after all
+    // matches
+    if swapped {
+        summary.packets = inserted;
+        summary.misses = table.len() as u64 + table.evicted();
+        miss_reasons.insert(MissReason::Absent, table.len() as u64);
+        miss_reasons.insert(MissReason::Evicted, table.evicted());
+        // Evicted packets are gone with their flows, so are missing from the heatmap
+        for (tuple_id, packet_time) in table.iter() {
+            add_to_heatmap(tuple_id, *packet_time, true);
+        }
+        if !args.count_only {
+            for _ in 0..summary.misses {
+                print_record(shared, &mut out, MatchKind::Miss, 0);
+            }
+        }
+    }
+    matching_progress.finish(|| {
+        format!(
+            "{} inbound packets, {} misses",
+            summary.packets, summary.misses
+        )
+    });
+    match_rate_progress.finish(|| {
+        let matched = summary.packets - summary.misses;
+        format!(
+            "{} of {} inbound packets matched ({})",
+            matched,
+            summary.packets,
+            percentage(matched, summary.packets)
+        )
+    });
+    out.finish().unwrap_or_else(|e| fail("stdout", e.into()));
+    drop(out);
+    if let Some(e) = stream_reader.take_error() {
+        let e = read_failure(args, &mut incomplete, stream_path, e);
+        read_error = read_error.or(Some(e));
+    }
+    summary.incomplete = incomplete;
+    summary.latency_min = latencies.iter().map(|latency| latency.abs()).min();
+    summary.latency_max = latencies.iter().map(|latency| latency.abs()).max();
+    summary.rfc3550_jitter = interarrival_jitter.value();
+    summary.percentiles = Percentiles::of(&latencies);
+    summary.uncorrected = args.clock_offset.map(|clock_offset_nsec| Uncorrected {
+        clock_offset_nsec,
+        latency_sum: raw_latencies.iter().map(|latency| latency.abs()).sum(),
+        percentiles: Percentiles::of(&raw_latencies),
+    });
+    summary.evicted = table.evicted();
+    summary.recurring_identities = table.recurring();
+    if args.retransmissions {
+        let table_retransmissions = table
+            .iter()
+            .filter(|(tuple_id, _)| retransmitted(&table, tuple_id))
+            .count() as u64;
+        let (retransmissions_in, retransmissions_out) = match swapped {
+            true => (table_retransmissions, stream_retransmissions),
+            false => (stream_retransmissions, table_retransmissions),
+        };
+        summary.retransmissions_in = Some(retransmissions_in);
+        summary.retransmissions_out = Some(retransmissions_out);
+    }
+    summary.defragmented_matches = shared.defragment.then_some(defragmented_matches);
+    summary.window_rejections = args.match_window.map(|_| table.rejected());
+    // Streamed outbound packets of protocols no inbound packet has get entries too
+    summary.protocols = protocols
+        .into_iter()
+        .filter(|(_, counts)| counts.packets > 0)
+        .map(|(protocol, mut counts)| {
+            // Inbound packets in the table are misses unless they were taken
+            if swapped {
+                counts.misses = counts.packets - counts.matches;
+            }
+            (protocol.name(), counts)
+        })
+        .collect();
+    let (in_reader, out_reader) = match swapped {
+        true => (&table_reader, &stream_reader),
+        false => (&stream_reader, &table_reader),
+    };
+    if !shared.defragment && (in_reader.fragment_count() > 0) != (out_reader.fragment_count() > 0) {
+        let side = match in_reader.fragment_count() > 0 {
+            true => "inbound",
+            false => "outbound",
+        };
+        eprintln!(
+            "WARNING: only the {} file has IPv4 fragments, packets were probably fragmented between the captures. Reassemble them with --defragment to match whole packets",
+            side
+        );
+    }
+    summary.capture_drops_in = in_reader.capture_drops();
+    summary.capture_drops_out = out_reader.capture_drops();
+    if shared.size_range().is_some() {
+        summary.size_excluded_in = Some(in_reader.size_excluded_count());
+        summary.size_excluded_out = Some(out_reader.size_excluded_count());
+    }
+    summary.hardware_timestamps_in = in_reader.hardware_timestamp_count();
+    summary.hardware_timestamps_out = out_reader.hardware_timestamp_count();
+    summary.snaplen_in = in_reader.snaplen();
+    summary.snaplen_out = out_reader.snaplen();
+    summary.files = vec![
+        file_metadata(FileSide::Inbound, in_path, in_reader),
+        file_metadata(FileSide::Outbound, out_path, out_reader),
+    ];
+    if let Some(path) = &args.dump_unmatched_out {
+        let unmatched = if swapped {
+            unmatched_out.iter().map(|(id, time)| (id, time)).collect()
+        } else {
+            table.iter().collect()
+        };
+        dump_unmatched(path, unmatched, anonymizer.as_ref())
+            .unwrap_or_else(|e| fail(path, e.into()));
+    }
+    if let Some(path) = &args.openmetrics {
+        write_openmetrics(path, &histogram).unwrap_or_else(|e| fail(path, e.into()));
+    }
+    if let Some(path) = &args.loss_heatmap {
+        write_heatmap(path, &heatmap, shared).unwrap_or_else(|e| fail(path, e.into()));
+    }
+    if let (Some(writer), Some(path)) = (parquet, &args.parquet) {
+        writer
+            .finish()
+            .and_then(OutputFile::finish)
+            .unwrap_or_else(|e| fail(path, e.into()));
+    }
+    if shared.by_tcp_flags {
+        print_flag_table(shared, &flag_latencies);
+    }
+    if args.by_dscp {
+        print_dscp_table(shared, &dscp_latencies);
+    }
+    if args.by_direction {
+        print_direction_table(shared, &directions);
+    }
+    if args.top.is_some() {
+        print_top(shared, top);
+    }
+    if shared.ignore_vlan {
+        let (in_vlans, out_vlans) = match swapped {
+            true => (&table_vlans, &stream_vlans),
+            false => (&stream_vlans, &table_vlans),
+        };
+        print_vlan_translations(shared, in_vlans, out_vlans);
+    }
+    if args.miss_reasons {
+        print_miss_table(shared, &miss_reasons, in_reader.unidentified_count());
+    }
+    if let (Some(explanation), Some((out_options, in_options))) =
+        (&mut explanation, unfiltered_options)
+    {
+        explanation.unfiltered = (
+            explanation.count_unfiltered(out_path, out_options),
+            explanation.count_unfiltered(in_path, in_options),
+        );
+        print_report(shared, explanation);
+    }
+    print_summary(shared, &summary);
+    timing.print();
+    if let Some(e) = read_error {
+        process::exit(exit_code(&e));
+    }
+}
+
+/// Fails unless the captures overlap, or are apart by no more than `--overlap-tolerance`
+fn check_overlap(
+    shared: &SharedArgs,
+    args: &MatchArgs,
+    alignment: &Alignment,
+    in_path: &str,
+    out_path: &str,
+) {
+    let tolerance = args.overlap_tolerance.unwrap_or(0.0);
+    let overlap = match alignment.overlap_nsec() {
+        Some(overlap_nsec) => overlap_nsec as f64 / 1e9,
+        None => {
+            let path = match alignment.inbound {
+                None => in_path,
+                Some(_) => out_path,
+            };
+            eprintln!("ERROR: {} has no packets to match", path);
+            process::exit(EXIT_NO_OVERLAP);
+        }
+    };
+    if overlap < -tolerance {
+        eprintln!(
+            "ERROR: the captures don't overlap, they are {:.6} sec apart (tolerance {} sec). Packets can't be matched unless the clocks differ by about this much",
+            -overlap, tolerance
+        );
+        process::exit(EXIT_NO_OVERLAP);
+    }
+    let report = match overlap >= 0.0 {
+        true => format!("Captures overlap (sec): {:.6}", overlap),
+        false => format!(
+            "Captures are {:.6} sec apart, within --overlap-tolerance",
+            -overlap
+        ),
+    };
+    print_report(shared, &report);
+}
+
+/// Error of reading a capture in the middle, reported at once unless partial statistics are
+/// asked for, then appended to `incomplete` of the summary
+fn read_failure(args: &MatchArgs, incomplete: &mut Vec<String>, path: &str, error: Error) -> Error {
+    match args.on_read_error {
+        ReadErrorAction::Abort => fail(path, error),
+        ReadErrorAction::Partial => {
+            eprintln!(
+                "WARNING: {}: {}, statistics are of packets read before it",
+                path, error
+            );
+            incomplete.push(format!("{}: {}", path, error));
+            error
+        }
+    }
 }
@@ -0,0 +1,36 @@
+//! NumPy `.npy` files of one-dimensional `f64` arrays, which `numpy.load` reads without parsing.
+//!
+//! Version 1.0 of the format: magic `\x93NUMPY`, version bytes 1 and 0, little-endian `u16`
+//! length of the header, and the header itself, a Python dict literal padded with spaces and
+//! ended by a newline so that the data starts at a multiple of 64 bytes. The values follow as
+//! little-endian `f64`
+
+use std::io::{self, Write};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Data starts at a multiple of this, as NumPy itself aligns it
+const ALIGNMENT: usize = 64;
+
+/// Length of magic, version and header length
+const PREAMBLE_LEN: usize = 10;
+
+/// Writes values as `.npy` file of shape `(len,)`
+pub fn write_f64<W: Write>(out: &mut W, values: &[f64]) -> io::Result<()> {
+    let mut header = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': ({},), }}",
+        values.len()
+    );
+    let unpadded = PREAMBLE_LEN + header.len() + 1;
+    let padding = (ALIGNMENT - unpadded % ALIGNMENT) % ALIGNMENT;
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+    out.write_all(MAGIC)?;
+    out.write_all(&[1, 0])?;
+    out.write_all(&(header.len() as u16).to_le_bytes())?;
+    out.write_all(header.as_bytes())?;
+    for value in values {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
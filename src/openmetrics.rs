@@ -0,0 +1,97 @@
+//! OpenMetrics text exposition of latencies: a histogram whose buckets carry exemplars, the
+//! first latency counted in the bucket with the identity of its packet, so a bucket count can be
+//! traced back to an example flow
+
+use std::io::{self, Write};
+
+/// Upper bounds of buckets (usec), 1-2-5 steps from 1 usec to 1 s. Larger latencies are counted
+/// only in the `+Inf` bucket
+pub const BUCKETS: [i64; 19] = [
+    1, 2, 5, 10, 20, 50, 100, 200, 500, 1_000, 2_000, 5_000, 10_000, 20_000, 50_000, 100_000,
+    200_000, 500_000, 1_000_000,
+];
+
+/// Metric family name. OpenMetrics requires the unit as its suffix
+const NAME: &str = "latency_measurement_latency_microseconds";
+
+/// Combined length limit of exemplar label names and values, in characters
+const EXEMPLAR_LABELS_MAX: usize = 128;
+
+/// Histogram of absolute latencies (usec)
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    /// Non-cumulative counts of `BUCKETS` and the `+Inf` bucket
+    counts: [u64; BUCKETS.len() + 1],
+    exemplars: [Option<(i64, String)>; BUCKETS.len() + 1],
+    sum: i64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            counts: [0; BUCKETS.len() + 1],
+            exemplars: std::array::from_fn(|_| None),
+            sum: 0,
+        }
+    }
+
+    /// Counts latency. `flow` renders the identity of its packet, called only if the latency
+    /// becomes the exemplar of its bucket
+    pub fn observe(&mut self, latency: i64, flow: impl FnOnce() -> String) {
+        let latency = latency.abs();
+        let bucket = BUCKETS.partition_point(|bound| *bound < latency);
+        self.counts[bucket] += 1;
+        self.sum += latency;
+        if self.exemplars[bucket].is_none() {
+            self.exemplars[bucket] = Some((latency, flow()));
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Writes the exposition, terminated by `# EOF`
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "# TYPE {} histogram", NAME)?;
+        writeln!(w, "# UNIT {} microseconds", NAME)?;
+        writeln!(
+            w,
+            "# HELP {} Latency of packets between the inbound and outbound captures.",
+            NAME
+        )?;
+        let mut cumulative = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            let bound = BUCKETS
+                .get(bucket)
+                .map_or("+Inf".to_string(), |bound| format!("{}.0", bound));
+            write!(w, "{}_bucket{{le=\"{}\"}} {}", NAME, bound, cumulative)?;
+            if let Some((latency, flow)) = &self.exemplars[bucket] {
+                write!(w, " # {{flow=\"{}\"}} {}.0", exemplar_label(flow), latency)?;
+            }
+            writeln!(w)?;
+        }
+        writeln!(w, "{}_sum {}.0", NAME, self.sum)?;
+        writeln!(w, "{}_count {}", NAME, self.count())?;
+        writeln!(w, "# EOF")
+    }
+}
+
+/// Escaped label value, truncated to fit the exemplar length limit along with the `flow` name
+fn exemplar_label(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .take(EXEMPLAR_LABELS_MAX - "flow".len())
+        .collect();
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,1688 @@
+use crate::exchange::Exchange;
+use crate::extractor::{Extractors, IdentityExtractor};
+use crate::key::KeyFields;
+use crate::reassembly::Framing;
+use pcap_parser::Linktype;
+use pnet::packet::icmp::IcmpPacket;
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::ipv6::Ipv6Packet;
+use pnet::packet::tcp::{TcpFlags, TcpPacket};
+use pnet::packet::udp::UdpPacket;
+use pnet::packet::Packet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::ops::{Range, RangeInclusive};
+
+/// Linux cooked capture v2 (`tcpdump -i any` on recent libpcap), not defined by pcap_parser
+pub const LINKTYPE_LINUX_SLL2: Linktype = Linktype(276);
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+/// EtherTypes of VLAN tags: 802.1Q, 802.1ad (QinQ) and the pre-standard QinQ one
+const ETHERTYPES_VLAN: [u16; 3] = [0x8100, 0x88a8, 0x9100];
+
+/// Length of Ethernet Frame Check Sequence (CRC-32)
+pub const ETHERNET_FCS_LEN: usize = 4;
+
+/// `AF_INET` in the address family header of BSD loopback frames
+const LOOPBACK_AF_INET: u32 = 2;
+
+/// `AF_INET6` of BSD loopback frames, differs between systems: Linux, NetBSD/OpenBSD,
+/// FreeBSD, macOS
+const LOOPBACK_AF_INET6: [u32; 4] = [10, 24, 28, 30];
+
+const IPV6_HEADER_LEN: usize = 40;
+
+const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+const GENEVE_PORT: u16 = 6081;
+
+const DNS_PORT: u16 = 53;
+
+const DNS_HEADER_LEN: usize = 12;
+
+/// Longest domain name in wire format
+const DNS_MAX_NAME_LEN: usize = 255;
+
+/// Protocol type of Geneve payload which is an Ethernet frame
+const ETHERTYPE_TRANSPARENT_ETHERNET_BRIDGING: u16 = 0x6558;
+
+/// Link-layer types which packets can be parsed from
+pub fn is_supported_link_type(link_type: Linktype) -> bool {
+    matches!(
+        link_type,
+        Linktype::ETHERNET
+            | Linktype::LINUX_SLL
+            | Linktype::RAW
+            | Linktype::IPV4
+            | Linktype::IPV6
+            | Linktype::NULL
+            | Linktype::LOOP
+    ) || link_type == LINKTYPE_LINUX_SLL2
+}
+
+/// Strips `fcs_len` trailing bytes of Frame Check Sequence
+pub fn strip_fcs(bytes: &[u8], fcs_len: usize) -> &[u8] {
+    &bytes[..bytes.len().saturating_sub(fcs_len)]
+}
+
+/// Size of packet for `ParseOptions::size_range`: IP total length, taken from the header so
+/// that truncation by the snapshot length doesn't change it, or length of non-IP frame
+fn packet_size(link_type: Linktype, bytes: &[u8]) -> usize {
+    let Some(ip) = ip_payload(link_type, bytes) else {
+        return bytes.len();
+    };
+    match *ip.first().unwrap_or(&0) >> 4 {
+        4 if ip.len() >= 4 => u16::from_be_bytes([ip[2], ip[3]]) as usize,
+        6 if ip.len() >= 6 => 40 + u16::from_be_bytes([ip[4], ip[5]]) as usize,
+        _ => bytes.len(),
+    }
+}
+
+/// Differentiated Services Code Point of IP packet: the upper 6 bits of IPv4 ToS or IPv6
+/// traffic class. `None` if the frame doesn't carry IP
+pub fn dscp(link_type: Linktype, bytes: &[u8]) -> Option<u8> {
+    let ip = ip_payload(link_type, bytes)?;
+    let traffic_class = match ip.first()? >> 4 {
+        4 => *ip.get(1)?,
+        6 => (ip[0] << 4) | (ip.get(1)? >> 4),
+        _ => return None,
+    };
+    Some(traffic_class >> 2)
+}
+
+/// Strips link-layer header of the frame. Returns `None` if the frame doesn't carry IP
+pub(crate) fn ip_payload(link_type: Linktype, bytes: &[u8]) -> Option<&[u8]> {
+    let ethertype_at = |offset: usize| {
+        let ethertype = bytes.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([ethertype[0], ethertype[1]]))
+    };
+    let (ethertype, header_len) = match link_type {
+        Linktype::ETHERNET => {
+            let at = skip_vlan_tags(bytes, 12);
+            (ethertype_at(at)?, at + 2)
+        }
+        Linktype::LINUX_SLL => (ethertype_at(14)?, 16),
+        _ if link_type == LINKTYPE_LINUX_SLL2 => (ethertype_at(0)?, 20),
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => match bytes.first()? >> 4 {
+            4 => (ETHERTYPE_IPV4, 0),
+            6 => (ETHERTYPE_IPV6, 0),
+            _ => return None,
+        },
+        Linktype::NULL | Linktype::LOOP => {
+            let family = bytes.get(0..4)?;
+            let family = [family[0], family[1], family[2], family[3]];
+            // DLT_NULL family is in byte order of the capturing host, DLT_LOOP - in network order
+            let family = match link_type {
+                Linktype::NULL if family[0] != 0 => u32::from_le_bytes(family),
+                _ => u32::from_be_bytes(family),
+            };
+            match family {
+                LOOPBACK_AF_INET => (ETHERTYPE_IPV4, 4),
+                _ if LOOPBACK_AF_INET6.contains(&family) => (ETHERTYPE_IPV6, 4),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    if ethertype != ETHERTYPE_IPV4 && ethertype != ETHERTYPE_IPV6 {
+        return None;
+    }
+    bytes.get(header_len..)
+}
+
+/// Offset of EtherType of Ethernet frame following VLAN tags, if any, which start at `offset`
+fn skip_vlan_tags(bytes: &[u8], mut offset: usize) -> usize {
+    while let Some(ethertype) = bytes.get(offset..offset + 2) {
+        if !ETHERTYPES_VLAN.contains(&u16::from_be_bytes([ethertype[0], ethertype[1]])) {
+            break;
+        }
+        offset += 4;
+    }
+    offset
+}
+
+/// VLAN IDs of tags of Ethernet frame, the outermost first. Empty for untagged frames and
+/// other link-layer types
+pub fn vlan_ids(link_type: Linktype, bytes: &[u8]) -> Vec<u16> {
+    let mut ids = Vec::new();
+    if link_type != Linktype::ETHERNET {
+        return ids;
+    }
+    let end = skip_vlan_tags(bytes, 12);
+    for tag in (12..end).step_by(4) {
+        if let Some(tci) = bytes.get(tag + 2..tag + 4) {
+            ids.push(u16::from_be_bytes([tci[0], tci[1]]) & 0x0fff);
+        }
+    }
+    ids
+}
+
+/// Addresses, transport protocol and transport header with payload of IP packet. Returns
+/// `None` for non-first fragments, which don't carry the transport header
+pub(crate) fn transport(ip: &[u8]) -> Option<(IpAddr, IpAddr, IpNextHeaderProtocol, &[u8])> {
+    match ip.first()? >> 4 {
+        4 => {
+            let l3 = Ipv4Packet::new(ip)?;
+            if l3.get_fragment_offset() != 0 {
+                return None;
+            }
+            let header_len = l3.get_header_length() as usize * 4;
+            let end = (l3.get_total_length() as usize).min(ip.len());
+            Some((
+                l3.get_source().into(),
+                l3.get_destination().into(),
+                l3.get_next_level_protocol(),
+                ip.get(header_len..end)?,
+            ))
+        }
+        6 => {
+            let l3 = Ipv6Packet::new(ip)?;
+            let end = (IPV6_HEADER_LEN + l3.get_payload_length() as usize).min(ip.len());
+            let (protocol, l4) =
+                skip_extension_headers(l3.get_next_header(), ip.get(IPV6_HEADER_LEN..end)?)?;
+            Some((
+                l3.get_source().into(),
+                l3.get_destination().into(),
+                protocol,
+                l4,
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Walks chain of IPv6 extension headers to the transport header
+fn skip_extension_headers(
+    mut next_header: IpNextHeaderProtocol,
+    mut bytes: &[u8],
+) -> Option<(IpNextHeaderProtocol, &[u8])> {
+    loop {
+        let header_len = match next_header {
+            IpNextHeaderProtocols::Hopopt
+            | IpNextHeaderProtocols::Ipv6Route
+            | IpNextHeaderProtocols::Ipv6Opts
+            | IpNextHeaderProtocols::MobilityHeader => (*bytes.get(1)? as usize + 1) * 8,
+            IpNextHeaderProtocols::Ah => (*bytes.get(1)? as usize + 2) * 4,
+            IpNextHeaderProtocols::Ipv6Frag => {
+                // Like in IPv4, only the first fragment carries the transport header
+                let offset = u16::from_be_bytes([*bytes.get(2)?, *bytes.get(3)?]) >> 3;
+                if offset != 0 {
+                    return None;
+                }
+                8
+            }
+            _ => return Some((next_header, bytes)),
+        };
+        next_header = IpNextHeaderProtocol(*bytes.first()?);
+        bytes = bytes.get(header_len..)?;
+    }
+}
+
+/// Encapsulation stripped before building identities, for `ParseOptions::decap`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decap {
+    /// Geneve (UDP port 6081) carrying Ethernet frames
+    Geneve,
+    /// IP in IP tunnels of either version in the other: IPv4 (protocol 4) or IPv6 (protocol 41)
+    /// inside IPv4 or IPv6
+    IpInIp,
+}
+
+/// Parses Geneve header of UDP payload, returns VNI and the inner Ethernet frame. The fixed
+/// header is followed by options, whose length is given in 4-byte words
+fn parse_geneve(payload: &[u8]) -> Option<(u32, &[u8])> {
+    let header = payload.get(0..8)?;
+    if header[0] >> 6 != 0 {
+        return None;
+    }
+    let options_len = (header[0] & 0x3f) as usize * 4;
+    if u16::from_be_bytes([header[2], header[3]]) != ETHERTYPE_TRANSPARENT_ETHERNET_BRIDGING {
+        return None;
+    }
+    let vni = u32::from_be_bytes([0, header[4], header[5], header[6]]);
+    Some((vni, payload.get(8 + options_len..)?))
+}
+
+/// Strips encapsulation of `decap` from frame. Returns link-layer type and bytes of the inner
+/// frame with its VNI, or the frame itself if it's not encapsulated
+pub(crate) fn decapsulate(
+    link_type: Linktype,
+    bytes: &[u8],
+    decap: Option<Decap>,
+) -> (Linktype, &[u8], Option<u32>) {
+    let geneve = || {
+        let (_, _, protocol, l4) = transport(ip_payload(link_type, bytes)?)?;
+        if protocol != IpNextHeaderProtocols::Udp {
+            return None;
+        }
+        if UdpPacket::new(l4)?.get_destination() != GENEVE_PORT {
+            return None;
+        }
+        let (vni, frame) = parse_geneve(l4.get(8..)?)?;
+        Some((Linktype::ETHERNET, frame, Some(vni)))
+    };
+    // Version of the inner packet is given by the protocol number of the outer one, whatever
+    // its own version is
+    let ip_in_ip = || {
+        let (_, _, protocol, inner) = transport(ip_payload(link_type, bytes)?)?;
+        let version = match protocol {
+            IpNextHeaderProtocols::Ipv4 => 4,
+            IpNextHeaderProtocols::Ipv6 => 6,
+            _ => return None,
+        };
+        if inner.first()? >> 4 != version {
+            return None;
+        }
+        Some((Linktype::RAW, inner, None))
+    };
+    match decap {
+        Some(Decap::Geneve) => geneve().unwrap_or((link_type, bytes, None)),
+        Some(Decap::IpInIp) => ip_in_ip().unwrap_or((link_type, bytes, None)),
+        None => (link_type, bytes, None),
+    }
+}
+
+/// Which fields identify a TCP packet
+#[derive(clap::ValueEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpMatchMode {
+    /// IP addresses, ports, sequence and acknowledgement numbers
+    #[default]
+    SeqAck,
+    /// IP addresses, ports, payload length and payload hash. Survives rewriting of sequence
+    /// numbers, packets without payload are skipped
+    Payload,
+    /// IP addresses, ports, length and hash of application messages of the reassembled byte
+    /// stream, delimited by `ParseOptions::message_framing`
+    Message,
+}
+
+/// Identity tried when the exact one finds no pair, see `PcapReader::with_fallback`. Its
+/// reduced key (`PacketId::fuzzy_key`) is looked up, so it survives NAT
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// Identity of the packet without IP addresses and TCP source port, as of fuzzy matching
+    /// but without a time window
+    Fuzzy,
+    /// TCP destination port, payload length and payload hash, which also survive rewriting of
+    /// sequence numbers. Segments without payload have no fallback key
+    Payload,
+}
+
+impl Fallback {
+    /// Options building the identity whose reduced key is the fallback key
+    pub fn options(self, options: &ParseOptions) -> ParseOptions {
+        match self {
+            Self::Fuzzy => options.clone(),
+            Self::Payload => ParseOptions {
+                tcp_match_mode: TcpMatchMode::Payload,
+                ..options.clone()
+            },
+        }
+    }
+}
+
+/// What identifies a packet
+#[derive(clap::ValueEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Fields of transport protocol, see `TcpMatchMode` for TCP
+    #[default]
+    Transport,
+    /// IPv4 addresses, identification, fragment offset and payload length, whatever the
+    /// transport protocol. Survives rewriting of ports and TCP sequence numbers as long as the
+    /// identification is kept. IPv6 packets are skipped
+    IpId,
+    /// Ethernet addresses, EtherType and hash of the rest of the frame, whatever it carries
+    /// (including non-IP protocols), for switches which forward frames unchanged. Frames of
+    /// other link-layer types are skipped
+    L2,
+    /// Bytes at a fixed offset of the frame, see `ParseOptions::key_bytes`. Chosen by giving
+    /// the key
+    #[value(skip)]
+    Bytes,
+}
+
+/// Key of `MatchMode::Bytes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBytes {
+    /// Offsets of the key in the frame, as of the byte filter. Shorter frames are skipped
+    pub range: Range<usize>,
+    /// Add IP addresses to the key. Frames which aren't IP are skipped then
+    pub with_ips: bool,
+}
+
+/// Protocol of identity, for `ParseOptions::protocols`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Protocol {
+    /// TCP over IPv4 and IPv6
+    Tcp,
+    /// ICMP and ICMPv6
+    Icmp,
+    /// RTP over UDP
+    Rtp,
+    /// DNS over UDP and TCP
+    Dns,
+    /// IPv4 packets of any transport protocol, by identification field
+    IpId,
+    /// Ethernet frames of any protocol, by addresses and hash of the frame
+    Ethernet,
+    /// Frames of any protocol, by bytes at a fixed offset
+    Bytes,
+    /// Identities of custom extractors, see `ParseOptions::extractors`. Available through the
+    /// library only
+    #[value(skip)]
+    Custom,
+}
+
+impl Protocol {
+    /// Protocols of identities which packets of IP protocol can get
+    fn of(protocol: IpNextHeaderProtocol) -> &'static [Self] {
+        match protocol {
+            IpNextHeaderProtocols::Tcp => &[Self::Tcp, Self::Dns],
+            IpNextHeaderProtocols::Icmp | IpNextHeaderProtocols::Icmpv6 => &[Self::Icmp],
+            IpNextHeaderProtocols::Udp => &[Self::Rtp, Self::Dns],
+            _ => &[],
+        }
+    }
+
+    /// Name as given to `--protocol`, `custom` for identities of an `IdentityExtractor`
+    pub fn name(self) -> String {
+        clap::ValueEnum::to_possible_value(&self)
+            .map_or("custom".to_string(), |value| value.get_name().to_string())
+    }
+}
+
+/// Options of building packet identities
+#[derive(Default, Debug, Clone)]
+pub struct ParseOptions {
+    pub match_mode: MatchMode,
+    pub tcp_match_mode: TcpMatchMode,
+    /// UDP ports (source or destination) whose payload is parsed as RTP. RTP is not matched
+    /// if `None`, since it can't be told from other UDP traffic reliably
+    pub rtp_ports: Option<RangeInclusive<u16>>,
+    /// Ethernet frames end with FCS even if the capture doesn't say so
+    pub has_fcs: bool,
+    /// Window of TCP payload hashed in `TcpMatchMode::Payload` instead of the whole payload.
+    /// Packets whose payload doesn't cover it are skipped
+    pub hash_range: Option<Range<usize>>,
+    /// How application messages are delimited in `TcpMatchMode::Message`
+    pub message_framing: Option<Framing>,
+    /// Protocols which get identities, all if empty. Others are skipped before their
+    /// transport header is decoded
+    pub protocols: Vec<Protocol>,
+    /// Build identities of requests or responses of the local host instead, for measuring
+    /// within a single capture
+    pub exchange: Option<Exchange>,
+    /// Identify ICMPv6 Neighbor Solicitations and Advertisements by message type and target
+    /// address, otherwise they are identified as other ICMPv6 messages
+    pub neighbor_discovery: bool,
+    /// Identify ICMP error messages (Destination Unreachable, Time Exceeded and the like) as
+    /// the packet they quote, so they are matched with the packet which triggered them.
+    /// Quotes too short for the identity of the packet are skipped
+    pub icmp_errors: bool,
+    /// Reassemble IPv4 packets from their fragments before identifying them, see `defrag`.
+    /// Applied by `PcapReader`
+    pub defragment: bool,
+    /// Offset of the IP header in every frame, for captures with link-layer headers the tool
+    /// doesn't know: the bytes from it are parsed as a raw IP packet whatever the link-layer
+    /// type. Applied by `PcapReader` and live capture, see `ParseOptions::at_l3_offset`
+    pub l3_offset: Option<usize>,
+    /// Build identities of frames encapsulated in packets of this protocol. Packets which
+    /// aren't encapsulated are identified as they are
+    pub decap: Option<Decap>,
+    /// Include VNI of encapsulated frames in their identities, for overlays whose tenants may
+    /// share addresses. Identities of reassembled messages don't include it
+    pub decap_vni: bool,
+    /// Leave VLAN IDs of tagged Ethernet frames out of their identities, for devices which
+    /// translate them. Identities of reassembled messages never include them
+    pub ignore_vlan: bool,
+    /// Identify DNS messages (UDP or TCP port 53) by transaction ID and question, so a query
+    /// is matched with its response. Other packets of port 53 are skipped
+    pub dns: bool,
+    /// Add group of TCP flags (SYN, SYN-ACK, ACK, FIN, RST) to TCP identities, so latency can be
+    /// told by stage of connection. Reassembled messages and exchanges don't get it
+    pub tcp_flags: bool,
+    /// Leave the acknowledgement number out of identities of `TcpMatchMode::SeqAck` (their
+    /// `tcp_ack` is 0), for paths which rewrite it. Segments of a flow which share a sequence
+    /// number, e.g. pure ACKs, get one identity
+    pub tcp_ignore_ack: bool,
+    /// Range of packet sizes in bytes which get identities: IP total length, or frame length of
+    /// non-IP frames. Other packets are skipped and counted by `PcapReader`
+    pub size_range: Option<RangeInclusive<usize>>,
+    /// Key of `MatchMode::Bytes`, which skips every frame without it
+    pub key_bytes: Option<KeyBytes>,
+    /// Fields kept in identities by kind, the others are cleared. Applies to reassembled
+    /// messages and custom extractors too
+    pub key: KeyFields,
+    /// Custom extractors of identities, which get every frame before the built-in ones (except
+    /// TCP segments taken by reassembly of `TcpMatchMode::Message`). Skipped if `protocols`
+    /// doesn't include `Protocol::Custom`
+    pub extractors: Extractors,
+}
+
+impl ParseOptions {
+    /// Checks that packets of IP protocol are not excluded by `protocols`
+    pub(crate) fn allows(&self, protocol: IpNextHeaderProtocol) -> bool {
+        Protocol::of(protocol)
+            .iter()
+            .any(|protocol| self.includes(*protocol))
+    }
+
+    /// Checks that identities of protocol are not excluded by `protocols`
+    pub(crate) fn includes(&self, protocol: Protocol) -> bool {
+        self.protocols.is_empty() || self.protocols.contains(&protocol)
+    }
+
+    /// Checks that size of frame (see `size_range`) is within the range
+    pub(crate) fn allows_size(&self, link_type: Linktype, bytes: &[u8]) -> bool {
+        self.size_range
+            .as_ref()
+            .is_none_or(|range| range.contains(&packet_size(link_type, bytes)))
+    }
+
+    /// Link-layer type and bytes of frame as parsed: the raw IP packet at `l3_offset`, if it's
+    /// set, or the frame itself. `None` if the frame ends before the offset
+    pub fn at_l3_offset<'a>(
+        &self,
+        link_type: Linktype,
+        bytes: &'a [u8],
+    ) -> Option<(Linktype, &'a [u8])> {
+        match self.l3_offset {
+            Some(offset) => Some((
+                Linktype::RAW,
+                bytes.get(offset..).filter(|ip| !ip.is_empty())?,
+            )),
+            None => Some((link_type, bytes)),
+        }
+    }
+
+    /// Checks that packet of ports is identified as DNS message
+    fn is_dns(&self, port_src: u16, port_dst: u16) -> bool {
+        self.dns && (port_src == DNS_PORT || port_dst == DNS_PORT)
+    }
+}
+
+/// Fields are also the schema of reference log lines, see `reference`
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PacketId {
+    Tcp {
+        ip_src: IpAddr,
+        ip_dst: IpAddr,
+        port_src: u16,
+        port_dst: u16,
+        tcp_seq: u32,
+        tcp_ack: u32,
+    },
+    TcpPayload {
+        ip_src: IpAddr,
+        ip_dst: IpAddr,
+        port_src: u16,
+        port_dst: u16,
+        payload_len: u32,
+        payload_hash: u64,
+    },
+    /// Application message of reassembled TCP stream
+    TcpMessage {
+        ip_src: IpAddr,
+        ip_dst: IpAddr,
+        port_src: u16,
+        port_dst: u16,
+        message_len: u32,
+        message_hash: u64,
+    },
+    Icmp {
+        ip_src: IpAddr,
+        ip_dst: IpAddr,
+        checksum: u16,
+    },
+    Rtp {
+        ssrc: u32,
+        seq: u16,
+        timestamp: u32,
+    },
+    /// TCP request or its response of `ParseOptions::exchange`: sequence number following the
+    /// request, which the response acknowledges
+    TcpExchange {
+        ip_local: IpAddr,
+        ip_remote: IpAddr,
+        port_local: u16,
+        port_remote: u16,
+        tcp_ack: u32,
+    },
+    /// HTTP request or its response of `ParseOptions::exchange`: index of the request within
+    /// its connection, see `http`
+    HttpExchange {
+        ip_local: IpAddr,
+        ip_remote: IpAddr,
+        port_local: u16,
+        port_remote: u16,
+        index: u32,
+    },
+    /// ICMP echo request or its reply of `ParseOptions::exchange`
+    IcmpEcho {
+        ip_local: IpAddr,
+        ip_remote: IpAddr,
+        identifier: u16,
+        sequence: u16,
+    },
+    /// ICMPv6 Neighbor Discovery message of `ParseOptions::neighbor_discovery`. Solicitations
+    /// go to multicast addresses, so only the target address identifies them
+    NeighborDiscovery {
+        message: NdMessage,
+        target: Ipv6Addr,
+    },
+    /// DNS query or response of `ParseOptions::dns`: both carry the transaction ID and the
+    /// question. Addresses are left out, since they are swapped in the response
+    Dns {
+        transaction_id: u16,
+        query_name: String,
+        qtype: u16,
+    },
+    /// TCP identity with flags of the packet, see `ParseOptions::tcp_flags`
+    Flagged {
+        flags: TcpFlagGroup,
+        inner: Box<PacketId>,
+    },
+    /// IPv4 packet of `MatchMode::IpId`. Fragment offset tells apart equally long fragments
+    /// of one datagram
+    IpId {
+        ip_src: IpAddr,
+        ip_dst: IpAddr,
+        ip_id: u16,
+        fragment_offset: u16,
+        payload_len: u16,
+    },
+    /// Ethernet frame of `MatchMode::L2`. The hash covers everything following the EtherType
+    /// (of the payload, following VLAN tags, whose IDs are kept by `PacketId::Vlan`)
+    Ethernet {
+        src_mac: [u8; 6],
+        dst_mac: [u8; 6],
+        ethertype: u16,
+        payload_hash: u64,
+    },
+    /// Frame of `MatchMode::Bytes`: bytes of the key, with IP addresses if
+    /// `KeyBytes::with_ips`
+    Bytes {
+        ip_src: Option<IpAddr>,
+        ip_dst: Option<IpAddr>,
+        key: Vec<u8>,
+    },
+    /// Identity of frame encapsulated in Geneve with its VNI, see `ParseOptions::decap_vni`
+    Geneve {
+        vni: u32,
+        inner: Box<PacketId>,
+    },
+    /// Identity of VLAN-tagged Ethernet frame with its VLAN IDs, the outermost first, unless
+    /// `ParseOptions::ignore_vlan`
+    Vlan {
+        ids: Vec<u16>,
+        inner: Box<PacketId>,
+    },
+    /// Identity built by a custom extractor, see `ParseOptions::extractors`. `name` keeps keys
+    /// of different extractors apart
+    Custom {
+        name: String,
+        key: Vec<u8>,
+    },
+}
+
+/// Address with port, if the protocol has ports, see `PacketId::endpoints`
+pub type Endpoint = (IpAddr, Option<u16>);
+
+/// Combination of TCP flags which marks stage of connection, see `ParseOptions::tcp_flags`
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TcpFlagGroup {
+    Syn,
+    SynAck,
+    Ack,
+    /// FIN, with or without ACK
+    Fin,
+    /// RST, with or without ACK
+    Rst,
+    /// None of SYN, ACK, FIN and RST
+    Other,
+}
+
+impl TcpFlagGroup {
+    /// Group of flags of TCP header. PSH, URG and ECN flags are ignored, since middleboxes may
+    /// change them
+    pub fn of(flags: u16) -> Self {
+        let set = |flag: u16| flags & flag != 0;
+        if set(TcpFlags::RST) {
+            Self::Rst
+        } else if set(TcpFlags::FIN) {
+            Self::Fin
+        } else if set(TcpFlags::SYN) && set(TcpFlags::ACK) {
+            Self::SynAck
+        } else if set(TcpFlags::SYN) {
+            Self::Syn
+        } else if set(TcpFlags::ACK) {
+            Self::Ack
+        } else {
+            Self::Other
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Self::Syn => "SYN",
+            Self::SynAck => "SYN-ACK",
+            Self::Ack => "ACK",
+            Self::Fin => "FIN",
+            Self::Rst => "RST",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Neighbor Discovery message type
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NdMessage {
+    Solicitation,
+    Advertisement,
+}
+
+/// One-line text form, e.g. `tcp 10.0.0.1:1000 > 10.0.0.2:80 seq 100 ack 5`
+impl fmt::Display for PacketId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Tcp {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                tcp_seq,
+                tcp_ack,
+            } => write!(
+                f,
+                "tcp {} > {} seq {} ack {}",
+                SocketAddr::new(ip_src, port_src),
+                SocketAddr::new(ip_dst, port_dst),
+                tcp_seq,
+                tcp_ack
+            ),
+            Self::TcpPayload {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                payload_len,
+                payload_hash,
+            } => write!(
+                f,
+                "tcp {} > {} len {} hash {:016x}",
+                SocketAddr::new(ip_src, port_src),
+                SocketAddr::new(ip_dst, port_dst),
+                payload_len,
+                payload_hash
+            ),
+            Self::TcpMessage {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                message_len,
+                message_hash,
+            } => write!(
+                f,
+                "tcp {} > {} message len {} hash {:016x}",
+                SocketAddr::new(ip_src, port_src),
+                SocketAddr::new(ip_dst, port_dst),
+                message_len,
+                message_hash
+            ),
+            Self::Icmp {
+                ip_src,
+                ip_dst,
+                checksum,
+            } => write!(f, "icmp {} > {} checksum {:#06x}", ip_src, ip_dst, checksum),
+            Self::Rtp {
+                ssrc,
+                seq,
+                timestamp,
+            } => write!(f, "rtp ssrc {:#010x} seq {} ts {}", ssrc, seq, timestamp),
+            Self::TcpExchange {
+                ip_local,
+                ip_remote,
+                port_local,
+                port_remote,
+                tcp_ack,
+            } => write!(
+                f,
+                "tcp {} <> {} ack {}",
+                SocketAddr::new(ip_local, port_local),
+                SocketAddr::new(ip_remote, port_remote),
+                tcp_ack
+            ),
+            Self::HttpExchange {
+                ip_local,
+                ip_remote,
+                port_local,
+                port_remote,
+                index,
+            } => write!(
+                f,
+                "http {} <> {} request {}",
+                SocketAddr::new(ip_local, port_local),
+                SocketAddr::new(ip_remote, port_remote),
+                index
+            ),
+            Self::IcmpEcho {
+                ip_local,
+                ip_remote,
+                identifier,
+                sequence,
+            } => write!(
+                f,
+                "icmp echo {} <> {} id {} seq {}",
+                ip_local, ip_remote, identifier, sequence
+            ),
+            Self::NeighborDiscovery { message, target } => {
+                let message = match message {
+                    NdMessage::Solicitation => "solicitation",
+                    NdMessage::Advertisement => "advertisement",
+                };
+                write!(f, "nd neighbor {} target {}", message, target)
+            }
+            Self::Dns {
+                transaction_id,
+                ref query_name,
+                qtype,
+            } => write!(f, "dns id {} {} type {}", transaction_id, query_name, qtype),
+            Self::Flagged { flags, ref inner } => {
+                write!(f, "{} flags {}", inner, flags.mnemonic())
+            }
+            Self::IpId {
+                ip_src,
+                ip_dst,
+                ip_id,
+                fragment_offset,
+                payload_len,
+            } => {
+                write!(
+                    f,
+                    "ip {} > {} id {:#06x} len {}",
+                    ip_src, ip_dst, ip_id, payload_len
+                )?;
+                if fragment_offset != 0 {
+                    write!(f, " offset {}", fragment_offset)?;
+                }
+                Ok(())
+            }
+            Self::Ethernet {
+                src_mac,
+                dst_mac,
+                ethertype,
+                payload_hash,
+            } => write!(
+                f,
+                "eth {} > {} type {:#06x} hash {:016x}",
+                Mac(src_mac),
+                Mac(dst_mac),
+                ethertype,
+                payload_hash
+            ),
+            Self::Geneve { vni, ref inner } => write!(f, "geneve vni {} {}", vni, inner),
+            Self::Vlan { ref ids, ref inner } => write!(f, "vlan {} {}", VlanIds(ids), inner),
+            Self::Bytes {
+                ip_src,
+                ip_dst,
+                ref key,
+            } => {
+                write!(f, "bytes ")?;
+                if let (Some(ip_src), Some(ip_dst)) = (ip_src, ip_dst) {
+                    write!(f, "{} > {} ", ip_src, ip_dst)?;
+                }
+                write!(f, "key ")?;
+                key.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            }
+            Self::Custom { ref name, ref key } => {
+                write!(f, "{} key ", name)?;
+                key.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            }
+        }
+    }
+}
+
+/// Reduced identity used by fuzzy matching. It leaves out IP addresses and the TCP source port,
+/// so it survives rewriting of these fields (e.g. by NAT)
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub enum FuzzyKey {
+    Tcp {
+        port_dst: u16,
+        tcp_seq: u32,
+        tcp_ack: u32,
+    },
+    TcpPayload {
+        port_dst: u16,
+        payload_len: u32,
+        payload_hash: u64,
+    },
+    TcpMessage {
+        port_dst: u16,
+        message_len: u32,
+        message_hash: u64,
+    },
+    Icmp {
+        checksum: u16,
+    },
+    Rtp {
+        ssrc: u32,
+        seq: u16,
+        timestamp: u32,
+    },
+    TcpExchange {
+        port_remote: u16,
+        tcp_ack: u32,
+    },
+    HttpExchange {
+        port_remote: u16,
+        index: u32,
+    },
+    IcmpEcho {
+        identifier: u16,
+        sequence: u16,
+    },
+    NeighborDiscovery {
+        message: NdMessage,
+        target: Ipv6Addr,
+    },
+    Dns {
+        transaction_id: u16,
+        query_name_hash: u64,
+        qtype: u16,
+    },
+    IpId {
+        ip_id: u16,
+        fragment_offset: u16,
+        payload_len: u16,
+    },
+    Ethernet {
+        ethertype: u16,
+        payload_hash: u64,
+    },
+    Bytes {
+        hash: u64,
+    },
+    Custom {
+        hash: u64,
+    },
+}
+
+/// 64-bit FNV-1a hash. Unlike `std` hashers, its value is stable across Rust versions and runs
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Parses RTP header, returns SSRC, sequence number and timestamp. Checks are heuristic: the
+/// version must be 2, the header must fit and the payload type must not be one of RTCP
+fn parse_rtp(payload: &[u8]) -> Option<(u32, u16, u32)> {
+    let header = payload.get(0..12)?;
+    if header[0] >> 6 != 2 {
+        return None;
+    }
+    // RTCP packet types 200-204 look like RTP payload types 72-76 with marker bit set
+    if (72..=76).contains(&(header[1] & 0x7f)) {
+        return None;
+    }
+    let csrc_count = (header[0] & 0x0f) as usize;
+    let padding = if header[0] & 0x20 != 0 {
+        *payload.last()? as usize
+    } else {
+        0
+    };
+    if payload.len() < 12 + 4 * csrc_count + padding {
+        return None;
+    }
+    let seq = u16::from_be_bytes([header[2], header[3]]);
+    let timestamp = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let ssrc = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+    Some((ssrc, seq, timestamp))
+}
+
+/// Parses DNS message header and its first question, returns transaction ID, query name and
+/// type. The name is in dotted form, `.` for the root
+fn parse_dns(message: &[u8]) -> Option<(u16, String, u16)> {
+    let header = message.get(0..DNS_HEADER_LEN)?;
+    let question_count = u16::from_be_bytes([header[4], header[5]]);
+    if question_count == 0 {
+        return None;
+    }
+    let mut name = String::new();
+    let mut offset = DNS_HEADER_LEN;
+    loop {
+        let len = *message.get(offset)? as usize;
+        offset += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression pointers can't appear in the first name of the message
+        if len & 0xc0 != 0 || offset - DNS_HEADER_LEN + len > DNS_MAX_NAME_LEN {
+            return None;
+        }
+        let label = message.get(offset..offset + len)?;
+        name.extend(label.iter().map(|byte| *byte as char));
+        name.push('.');
+        offset += len;
+    }
+    if name.len() > 1 {
+        name.pop();
+    } else {
+        name.push('.');
+    }
+    let qtype = message.get(offset..offset + 2)?;
+    Some((
+        u16::from_be_bytes([header[0], header[1]]),
+        name,
+        u16::from_be_bytes([qtype[0], qtype[1]]),
+    ))
+}
+
+fn dns_id(message: &[u8]) -> Option<PacketId> {
+    let (transaction_id, query_name, qtype) = parse_dns(message)?;
+    Some(PacketId::Dns {
+        transaction_id,
+        query_name,
+        qtype,
+    })
+}
+
+/// TCP segment as seen by `Reassembler`
+pub(crate) struct TcpSegment<'a> {
+    pub ip_src: IpAddr,
+    pub ip_dst: IpAddr,
+    pub port_src: u16,
+    pub port_dst: u16,
+    pub seq: u32,
+    pub syn: bool,
+    /// FIN or RST: the stream ends after this segment
+    pub end: bool,
+    pub payload: &'a [u8],
+}
+
+/// Parses TCP segment of frame. Returns `None` if the frame doesn't carry TCP
+pub(crate) fn tcp_segment(link_type: Linktype, bytes: &[u8]) -> Option<TcpSegment<'_>> {
+    let (ip_src, ip_dst, protocol, l4) = transport(ip_payload(link_type, bytes)?)?;
+    if protocol != IpNextHeaderProtocols::Tcp {
+        return None;
+    }
+    let header = TcpPacket::new(l4)?;
+    let flags = header.get_flags();
+    let payload_offset = (header.get_data_offset() as usize * 4).min(l4.len());
+    Some(TcpSegment {
+        ip_src,
+        ip_dst,
+        port_src: header.get_source(),
+        port_dst: header.get_destination(),
+        seq: header.get_sequence(),
+        syn: flags & TcpFlags::SYN != 0,
+        end: flags & (TcpFlags::FIN | TcpFlags::RST) != 0,
+        payload: &l4[payload_offset..],
+    })
+}
+
+// ICMPv6 checksum is at the same offset as ICMP one
+fn icmp_id(ip_src: IpAddr, ip_dst: IpAddr, l4: &[u8]) -> Option<PacketId> {
+    let l4 = IcmpPacket::new(l4)?;
+    Some(PacketId::Icmp {
+        ip_src,
+        ip_dst,
+        checksum: l4.get_checksum(),
+    })
+}
+
+/// IP packet quoted by ICMP or ICMPv6 error message, `None` for other messages. The quote
+/// follows the 8-byte header of the error and is usually truncated
+fn icmp_error_quote(protocol: IpNextHeaderProtocol, l4: &[u8]) -> Option<&[u8]> {
+    let is_error = match protocol {
+        // Destination Unreachable, Source Quench, Redirect, Time Exceeded, Parameter Problem
+        IpNextHeaderProtocols::Icmp => matches!(l4.first()?, 3 | 4 | 5 | 11 | 12),
+        // Destination Unreachable, Packet Too Big, Time Exceeded, Parameter Problem
+        IpNextHeaderProtocols::Icmpv6 => matches!(l4.first()?, 1..=4),
+        _ => false,
+    };
+    match is_error {
+        true => l4.get(8..),
+        false => None,
+    }
+}
+
+/// Identity of IPv4 packet of `MatchMode::IpId`. Unlike in `transport`, non-first fragments are
+/// identified too
+fn ip_id(ip: &[u8]) -> Option<PacketId> {
+    if *ip.first()? >> 4 != 4 {
+        return None;
+    }
+    let l3 = Ipv4Packet::new(ip)?;
+    let header_len = l3.get_header_length() as u16 * 4;
+    Some(PacketId::IpId {
+        ip_src: l3.get_source().into(),
+        ip_dst: l3.get_destination().into(),
+        ip_id: l3.get_identification(),
+        fragment_offset: l3.get_fragment_offset(),
+        payload_len: l3.get_total_length().checked_sub(header_len)?,
+    })
+}
+
+/// Identity of Ethernet frame of `MatchMode::L2`. Broadcast and multicast frames are identified
+/// like unicast ones
+fn ethernet_id(link_type: Linktype, bytes: &[u8]) -> Option<PacketId> {
+    if link_type != Linktype::ETHERNET {
+        return None;
+    }
+    let header = bytes.get(..14)?;
+    let at = skip_vlan_tags(bytes, 12);
+    let ethertype = bytes.get(at..at + 2)?;
+    Some(PacketId::Ethernet {
+        src_mac: header[6..12].try_into().ok()?,
+        dst_mac: header[0..6].try_into().ok()?,
+        ethertype: u16::from_be_bytes([ethertype[0], ethertype[1]]),
+        payload_hash: fnv1a(&bytes[at + 2..]),
+    })
+}
+
+/// Identity of frame of `MatchMode::Bytes`, `None` if the frame is too short for the key (or
+/// not IP if addresses are in the key)
+fn bytes_id(link_type: Linktype, bytes: &[u8], key_bytes: &KeyBytes) -> Option<PacketId> {
+    let key = bytes.get(key_bytes.range.clone())?.to_vec();
+    let (ip_src, ip_dst) = match key_bytes.with_ips {
+        true => {
+            let (ip_src, ip_dst, _, _) = transport(ip_payload(link_type, bytes)?)?;
+            (Some(ip_src), Some(ip_dst))
+        }
+        false => (None, None),
+    };
+    Some(PacketId::Bytes {
+        ip_src,
+        ip_dst,
+        key,
+    })
+}
+
+/// VLAN IDs in text form, the outermost first, e.g. `100/10` for QinQ
+pub struct VlanIds<'a>(pub &'a [u16]);
+
+impl fmt::Display for VlanIds<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, id) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            write!(f, "{}", id)?;
+        }
+        Ok(())
+    }
+}
+
+/// MAC address in text form, e.g. `00:11:22:33:44:55`
+struct Mac([u8; 6]);
+
+impl fmt::Display for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+/// Identity of Neighbor Solicitation or Advertisement: type, code, checksum and 4 bytes of
+/// flags are followed by the target address. `None` for other ICMPv6 messages
+fn neighbor_discovery(l4: &[u8]) -> Option<PacketId> {
+    let message = match *l4.first()? {
+        ICMPV6_NEIGHBOR_SOLICITATION => NdMessage::Solicitation,
+        ICMPV6_NEIGHBOR_ADVERTISEMENT => NdMessage::Advertisement,
+        _ => return None,
+    };
+    let target: [u8; 16] = l4.get(8..24)?.try_into().ok()?;
+    Some(PacketId::NeighborDiscovery {
+        message,
+        target: target.into(),
+    })
+}
+
+impl PacketId {
+    /// Builds identity of Ethernet frame. Returns `None` for unsupported or malformed packets
+    pub fn new_from_bytes(bytes: &[u8]) -> Option<Self> {
+        Self::new_from_frame(Linktype::ETHERNET, bytes, &ParseOptions::default())
+    }
+
+    /// Builds identity of frame with given link-layer type. Identity depends only on the IP
+    /// layer and above, so frames captured with different link-layer types can be matched
+    pub fn new_from_frame(
+        link_type: Linktype,
+        bytes: &[u8],
+        options: &ParseOptions,
+    ) -> Option<Self> {
+        if options.includes(Protocol::Custom) {
+            if let Some(id) = options.extractors.extract(link_type, bytes) {
+                return Some(id);
+            }
+        }
+        let ids = match options.ignore_vlan {
+            true => Vec::new(),
+            false => vlan_ids(link_type, bytes),
+        };
+        let (link_type, bytes, vni) = decapsulate(link_type, bytes, options.decap);
+        let id = Self::new_from_inner_frame(link_type, bytes, options)?;
+        let id = match vni {
+            Some(vni) if options.decap_vni => Self::Geneve {
+                vni,
+                inner: Box::new(id),
+            },
+            _ => id,
+        };
+        match ids.is_empty() {
+            true => Some(id),
+            false => Some(Self::Vlan {
+                ids,
+                inner: Box::new(id),
+            }),
+        }
+    }
+
+    fn new_from_inner_frame(
+        link_type: Linktype,
+        bytes: &[u8],
+        options: &ParseOptions,
+    ) -> Option<Self> {
+        if let Some(exchange) = &options.exchange {
+            return exchange.identity(link_type, bytes, options);
+        }
+        if options.match_mode == MatchMode::IpId {
+            if !options.includes(Protocol::IpId) {
+                return None;
+            }
+            return ip_id(ip_payload(link_type, bytes)?);
+        }
+        if options.match_mode == MatchMode::L2 {
+            if !options.includes(Protocol::Ethernet) {
+                return None;
+            }
+            return ethernet_id(link_type, bytes);
+        }
+        if options.match_mode == MatchMode::Bytes {
+            if !options.includes(Protocol::Bytes) {
+                return None;
+            }
+            return bytes_id(link_type, bytes, options.key_bytes.as_ref()?);
+        }
+        let (ip_src, ip_dst, protocol, l4) = transport(ip_payload(link_type, bytes)?)?;
+        // The quoted packet is shorter than the error, so this ends even for errors quoting
+        // errors
+        if options.icmp_errors {
+            if let Some(quoted) = icmp_error_quote(protocol, l4) {
+                return Self::new_from_inner_frame(Linktype::RAW, quoted, options);
+            }
+        }
+        if !options.allows(protocol) {
+            return None;
+        }
+        match protocol {
+            IpNextHeaderProtocols::Tcp => {
+                let l4 = TcpPacket::new(l4)?;
+                if options.is_dns(l4.get_source(), l4.get_destination()) {
+                    if !options.includes(Protocol::Dns) {
+                        return None;
+                    }
+                    // Messages are prefixed with 2-byte length. Only segments starting a
+                    // message have its header
+                    let payload = l4.payload();
+                    let len = u16::from_be_bytes([*payload.first()?, *payload.get(1)?]) as usize;
+                    let message = payload.get(2..)?;
+                    return dns_id(&message[..len.min(message.len())]);
+                }
+                if !options.includes(Protocol::Tcp) {
+                    return None;
+                }
+                // Messages are built by `Reassembler` from a sequence of segments
+                if options.tcp_match_mode == TcpMatchMode::Message {
+                    return None;
+                }
+                let id = if options.tcp_match_mode == TcpMatchMode::Payload {
+                    let payload = l4.payload();
+                    // Pure ACKs and other empty segments would all share one identity
+                    if payload.is_empty() {
+                        return None;
+                    }
+                    let payload = match &options.hash_range {
+                        Some(range) => payload.get(range.clone())?,
+                        None => payload,
+                    };
+                    Self::TcpPayload {
+                        ip_src,
+                        ip_dst,
+                        port_src: l4.get_source(),
+                        port_dst: l4.get_destination(),
+                        payload_len: payload.len() as u32,
+                        payload_hash: fnv1a(payload),
+                    }
+                } else {
+                    let tcp_seq = l4.get_sequence();
+                    let tcp_ack = if options.tcp_ignore_ack {
+                        0
+                    } else {
+                        l4.get_acknowledgement()
+                    };
+                    let port_src = l4.get_source();
+                    let port_dst = l4.get_destination();
+                    Self::Tcp {
+                        ip_src,
+                        ip_dst,
+                        port_src,
+                        port_dst,
+                        tcp_seq,
+                        tcp_ack,
+                    }
+                };
+                if !options.tcp_flags {
+                    return Some(id);
+                }
+                Some(Self::Flagged {
+                    flags: TcpFlagGroup::of(l4.get_flags()),
+                    inner: Box::new(id),
+                })
+            }
+            IpNextHeaderProtocols::Icmpv6 if options.neighbor_discovery => {
+                neighbor_discovery(l4).or_else(|| icmp_id(ip_src, ip_dst, l4))
+            }
+            IpNextHeaderProtocols::Icmp | IpNextHeaderProtocols::Icmpv6 => {
+                icmp_id(ip_src, ip_dst, l4)
+            }
+            IpNextHeaderProtocols::Udp => {
+                let l4 = UdpPacket::new(l4)?;
+                if options.is_dns(l4.get_source(), l4.get_destination()) {
+                    if !options.includes(Protocol::Dns) {
+                        return None;
+                    }
+                    return dns_id(l4.payload());
+                }
+                if !options.includes(Protocol::Rtp) {
+                    return None;
+                }
+                let rtp_ports = options.rtp_ports.as_ref()?;
+                if !rtp_ports.contains(&l4.get_source())
+                    && !rtp_ports.contains(&l4.get_destination())
+                {
+                    return None;
+                }
+                let (ssrc, seq, timestamp) = parse_rtp(l4.payload())?;
+                Some(Self::Rtp {
+                    ssrc,
+                    seq,
+                    timestamp,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        match self {
+            Self::Tcp { .. }
+            | Self::TcpPayload { .. }
+            | Self::TcpMessage { .. }
+            | Self::TcpExchange { .. }
+            | Self::HttpExchange { .. } => Protocol::Tcp,
+            Self::Icmp { .. } | Self::IcmpEcho { .. } | Self::NeighborDiscovery { .. } => {
+                Protocol::Icmp
+            }
+            Self::Rtp { .. } => Protocol::Rtp,
+            Self::Dns { .. } => Protocol::Dns,
+            Self::IpId { .. } => Protocol::IpId,
+            Self::Ethernet { .. } => Protocol::Ethernet,
+            Self::Bytes { .. } => Protocol::Bytes,
+            Self::Flagged { inner, .. } | Self::Geneve { inner, .. } | Self::Vlan { inner, .. } => {
+                inner.protocol()
+            }
+            Self::Custom { .. } => Protocol::Custom,
+        }
+    }
+
+    /// Source and destination of packet (local and remote end of an exchange): address, and
+    /// port for TCP. `None` for identities without addresses
+    pub fn endpoints(&self) -> Option<(Endpoint, Endpoint)> {
+        match self {
+            Self::Tcp {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                ..
+            }
+            | Self::TcpPayload {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                ..
+            }
+            | Self::TcpMessage {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                ..
+            } => Some(((*ip_src, Some(*port_src)), (*ip_dst, Some(*port_dst)))),
+            Self::TcpExchange {
+                ip_local,
+                ip_remote,
+                port_local,
+                port_remote,
+                ..
+            }
+            | Self::HttpExchange {
+                ip_local,
+                ip_remote,
+                port_local,
+                port_remote,
+                ..
+            } => Some((
+                (*ip_local, Some(*port_local)),
+                (*ip_remote, Some(*port_remote)),
+            )),
+            Self::Icmp { ip_src, ip_dst, .. } | Self::IpId { ip_src, ip_dst, .. } => {
+                Some(((*ip_src, None), (*ip_dst, None)))
+            }
+            Self::IcmpEcho {
+                ip_local,
+                ip_remote,
+                ..
+            } => Some(((*ip_local, None), (*ip_remote, None))),
+            Self::Bytes {
+                ip_src: Some(ip_src),
+                ip_dst: Some(ip_dst),
+                ..
+            } => Some(((*ip_src, None), (*ip_dst, None))),
+            Self::Flagged { inner, .. } | Self::Geneve { inner, .. } | Self::Vlan { inner, .. } => {
+                inner.endpoints()
+            }
+            Self::Bytes { .. }
+            | Self::Rtp { .. }
+            | Self::Dns { .. }
+            | Self::NeighborDiscovery { .. }
+            | Self::Ethernet { .. }
+            | Self::Custom { .. } => None,
+        }
+    }
+
+    /// Flow of packet in text form: protocol, addresses and ports of one direction, e.g.
+    /// `tcp 10.0.0.1:1000 > 10.0.0.2:80`. RTP flows are told by SSRC, DNS messages and Neighbor
+    /// Discovery by the queried name or target
+    pub fn flow(&self) -> String {
+        self.flow_with_names(&HashMap::new())
+    }
+
+    /// Flow of packet in text form with addresses replaced by their host names, where known,
+    /// e.g. `tcp client:1000 > 10.0.0.2:80`
+    pub fn flow_with_names(&self, names: &HashMap<IpAddr, String>) -> String {
+        let host = |ip: &IpAddr| names.get(ip).cloned().unwrap_or_else(|| ip.to_string());
+        let endpoint = |ip: &IpAddr, port: &u16| match names.get(ip) {
+            Some(name) => format!("{}:{}", name, port),
+            None => SocketAddr::new(*ip, *port).to_string(),
+        };
+        self.flow_by(&host, &endpoint)
+    }
+
+    /// Flow of packet between subnets, with addresses given by `subnet` and ports left out,
+    /// e.g. `tcp 10.0.0.0/24 > 10.0.2.0/24`, so flows of many hosts are one
+    pub fn subnet_flow(&self, subnet: &dyn Fn(IpAddr) -> String) -> String {
+        self.flow_by(&|ip| subnet(*ip), &|ip, _| subnet(*ip))
+    }
+
+    /// Flow of packet with addresses given by `host`, and with ports by `endpoint`
+    fn flow_by(
+        &self,
+        host: &dyn Fn(&IpAddr) -> String,
+        endpoint: &dyn Fn(&IpAddr, &u16) -> String,
+    ) -> String {
+        match self {
+            Self::Tcp {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                ..
+            }
+            | Self::TcpPayload {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                ..
+            }
+            | Self::TcpMessage {
+                ip_src,
+                ip_dst,
+                port_src,
+                port_dst,
+                ..
+            } => format!(
+                "tcp {} > {}",
+                endpoint(ip_src, port_src),
+                endpoint(ip_dst, port_dst)
+            ),
+            Self::TcpExchange {
+                ip_local,
+                ip_remote,
+                port_local,
+                port_remote,
+                ..
+            } => format!(
+                "tcp {} <> {}",
+                endpoint(ip_local, port_local),
+                endpoint(ip_remote, port_remote)
+            ),
+            Self::HttpExchange {
+                ip_local,
+                ip_remote,
+                port_local,
+                port_remote,
+                ..
+            } => format!(
+                "http {} <> {}",
+                endpoint(ip_local, port_local),
+                endpoint(ip_remote, port_remote)
+            ),
+            Self::Icmp { ip_src, ip_dst, .. } => {
+                format!("icmp {} > {}", host(ip_src), host(ip_dst))
+            }
+            Self::IcmpEcho {
+                ip_local,
+                ip_remote,
+                ..
+            } => format!("icmp echo {} <> {}", host(ip_local), host(ip_remote)),
+            Self::IpId { ip_src, ip_dst, .. } => format!("ip {} > {}", host(ip_src), host(ip_dst)),
+            Self::Ethernet {
+                src_mac, dst_mac, ..
+            } => format!("eth {} > {}", Mac(*src_mac), Mac(*dst_mac)),
+            Self::Rtp { ssrc, .. } => format!("rtp ssrc {:#010x}", ssrc),
+            Self::Dns { query_name, .. } => format!("dns {}", query_name),
+            Self::NeighborDiscovery { target, .. } => format!("nd target {}", target),
+            Self::Flagged { inner, .. } => inner.flow_by(host, endpoint),
+            Self::Geneve { vni, inner } => {
+                format!("geneve vni {} {}", vni, inner.flow_by(host, endpoint))
+            }
+            Self::Vlan { ids, inner } => {
+                format!("vlan {} {}", VlanIds(ids), inner.flow_by(host, endpoint))
+            }
+            Self::Bytes {
+                ip_src: Some(ip_src),
+                ip_dst: Some(ip_dst),
+                ..
+            } => format!("bytes {} > {}", host(ip_src), host(ip_dst)),
+            Self::Bytes { .. } => "bytes".to_string(),
+            Self::Custom { name, .. } => name.clone(),
+        }
+    }
+
+    /// Group of TCP flags of `ParseOptions::tcp_flags` identity
+    pub fn tcp_flags(&self) -> Option<TcpFlagGroup> {
+        match self {
+            Self::Flagged { flags, .. } => Some(*flags),
+            Self::Geneve { inner, .. } | Self::Vlan { inner, .. } => inner.tcp_flags(),
+            _ => None,
+        }
+    }
+
+    /// Identity with every IP address replaced by `map`, e.g. for output
+    pub fn map_addresses(&self, map: &dyn Fn(IpAddr) -> IpAddr) -> Self {
+        let mut id = self.clone();
+        match &mut id {
+            Self::Tcp { ip_src, ip_dst, .. }
+            | Self::TcpPayload { ip_src, ip_dst, .. }
+            | Self::TcpMessage { ip_src, ip_dst, .. }
+            | Self::Icmp { ip_src, ip_dst, .. }
+            | Self::IpId { ip_src, ip_dst, .. } => {
+                *ip_src = map(*ip_src);
+                *ip_dst = map(*ip_dst);
+            }
+            Self::TcpExchange {
+                ip_local,
+                ip_remote,
+                ..
+            }
+            | Self::HttpExchange {
+                ip_local,
+                ip_remote,
+                ..
+            }
+            | Self::IcmpEcho {
+                ip_local,
+                ip_remote,
+                ..
+            } => {
+                *ip_local = map(*ip_local);
+                *ip_remote = map(*ip_remote);
+            }
+            Self::NeighborDiscovery { target, .. } => {
+                if let IpAddr::V6(mapped) = map((*target).into()) {
+                    *target = mapped;
+                }
+            }
+            Self::Bytes { ip_src, ip_dst, .. } => {
+                *ip_src = ip_src.map(map);
+                *ip_dst = ip_dst.map(map);
+            }
+            Self::Flagged { inner, .. } | Self::Geneve { inner, .. } | Self::Vlan { inner, .. } => {
+                **inner = inner.map_addresses(map)
+            }
+            Self::Rtp { .. } | Self::Dns { .. } | Self::Ethernet { .. } | Self::Custom { .. } => {}
+        }
+        id
+    }
+
+    /// Reduced identity for fuzzy matching. VNI and VLAN IDs are left out like addresses
+    pub fn fuzzy_key(&self) -> FuzzyKey {
+        match *self {
+            Self::Tcp {
+                port_dst,
+                tcp_seq,
+                tcp_ack,
+                ..
+            } => FuzzyKey::Tcp {
+                port_dst,
+                tcp_seq,
+                tcp_ack,
+            },
+            Self::TcpPayload {
+                port_dst,
+                payload_len,
+                payload_hash,
+                ..
+            } => FuzzyKey::TcpPayload {
+                port_dst,
+                payload_len,
+                payload_hash,
+            },
+            Self::TcpMessage {
+                port_dst,
+                message_len,
+                message_hash,
+                ..
+            } => FuzzyKey::TcpMessage {
+                port_dst,
+                message_len,
+                message_hash,
+            },
+            Self::Icmp { checksum, .. } => FuzzyKey::Icmp { checksum },
+            Self::Rtp {
+                ssrc,
+                seq,
+                timestamp,
+            } => FuzzyKey::Rtp {
+                ssrc,
+                seq,
+                timestamp,
+            },
+            Self::TcpExchange {
+                port_remote,
+                tcp_ack,
+                ..
+            } => FuzzyKey::TcpExchange {
+                port_remote,
+                tcp_ack,
+            },
+            Self::HttpExchange {
+                port_remote, index, ..
+            } => FuzzyKey::HttpExchange { port_remote, index },
+            Self::IcmpEcho {
+                identifier,
+                sequence,
+                ..
+            } => FuzzyKey::IcmpEcho {
+                identifier,
+                sequence,
+            },
+            Self::NeighborDiscovery { message, target } => {
+                FuzzyKey::NeighborDiscovery { message, target }
+            }
+            Self::Dns {
+                transaction_id,
+                ref query_name,
+                qtype,
+            } => FuzzyKey::Dns {
+                transaction_id,
+                query_name_hash: fnv1a(query_name.as_bytes()),
+                qtype,
+            },
+            Self::IpId {
+                ip_id,
+                fragment_offset,
+                payload_len,
+                ..
+            } => FuzzyKey::IpId {
+                ip_id,
+                fragment_offset,
+                payload_len,
+            },
+            Self::Ethernet {
+                ethertype,
+                payload_hash,
+                ..
+            } => FuzzyKey::Ethernet {
+                ethertype,
+                payload_hash,
+            },
+            Self::Flagged { ref inner, .. }
+            | Self::Geneve { ref inner, .. }
+            | Self::Vlan { ref inner, .. } => inner.fuzzy_key(),
+            Self::Bytes { ref key, .. } => FuzzyKey::Bytes { hash: fnv1a(key) },
+            Self::Custom { ref name, ref key } => FuzzyKey::Custom {
+                hash: fnv1a(&[name.as_bytes(), &[0], key].concat()),
+            },
+        }
+    }
+}
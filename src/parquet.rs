@@ -0,0 +1,129 @@
+//! Writer of matched packets in Apache Parquet format, for data tooling (Arrow, pandas, Spark).
+//!
+//! Rows are buffered and handed to the `ArrowWriter` of the `parquet` crate as record batches,
+//! one per row group
+
+use crate::packet::PacketId;
+use crate::time::PacketTime;
+use arrow_array::builder::{BooleanBuilder, Int64Builder, StringBuilder, UInt16Builder};
+use arrow_array::{ArrayRef, RecordBatch, TimestampNanosecondArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// Rows buffered before they are written as a row group
+pub const ROW_GROUP_ROWS: usize = 65_536;
+
+/// Matched packet, one row of the file
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub in_time: PacketTime,
+    pub out_time: PacketTime,
+    /// Latency (usec) as reported, corrected by clock offset if any
+    pub latency: i64,
+    pub fuzzy: bool,
+    pub id: PacketId,
+}
+
+/// Columns of the file, nullable ones for identities without addresses or ports
+fn schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into()));
+    Arc::new(Schema::new(vec![
+        Field::new("in_time", timestamp.clone(), false),
+        Field::new("out_time", timestamp, false),
+        Field::new("latency_usec", DataType::Int64, false),
+        Field::new("fuzzy", DataType::Boolean, false),
+        Field::new("protocol", DataType::Utf8, false),
+        Field::new("ip_src", DataType::Utf8, true),
+        Field::new("ip_dst", DataType::Utf8, true),
+        Field::new("port_src", DataType::UInt16, true),
+        Field::new("port_dst", DataType::UInt16, true),
+    ]))
+}
+
+/// Writes rows to `w` in row groups of `ROW_GROUP_ROWS`. `finish` writes the footer, without it
+/// the file isn't readable
+pub struct ParquetWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    rows: Vec<Row>,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    pub fn new(w: W) -> io::Result<Self> {
+        let schema = schema();
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(ROW_GROUP_ROWS)
+            .set_created_by(format!(
+                "latency_measurement_tool version {}",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .build();
+        let writer =
+            ArrowWriter::try_new(w, schema.clone(), Some(properties)).map_err(io::Error::other)?;
+        Ok(Self {
+            writer,
+            schema,
+            rows: Vec::new(),
+        })
+    }
+
+    pub fn push(&mut self, row: Row) -> io::Result<()> {
+        self.rows.push(row);
+        if self.rows.len() >= ROW_GROUP_ROWS {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    /// Writes buffered rows and the footer, returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.rows.is_empty() {
+            self.write_row_group()?;
+        }
+        self.writer.into_inner().map_err(io::Error::other)
+    }
+
+    fn write_row_group(&mut self) -> io::Result<()> {
+        let mut in_times = Vec::with_capacity(self.rows.len());
+        let mut out_times = Vec::with_capacity(self.rows.len());
+        let mut latencies = Int64Builder::with_capacity(self.rows.len());
+        let mut fuzzy = BooleanBuilder::with_capacity(self.rows.len());
+        let mut protocols = StringBuilder::new();
+        let mut ip_src = StringBuilder::new();
+        let mut ip_dst = StringBuilder::new();
+        let mut port_src = UInt16Builder::with_capacity(self.rows.len());
+        let mut port_dst = UInt16Builder::with_capacity(self.rows.len());
+        for row in &self.rows {
+            in_times.push(row.in_time.nsec as i64);
+            out_times.push(row.out_time.nsec as i64);
+            latencies.append_value(row.latency);
+            fuzzy.append_value(row.fuzzy);
+            protocols.append_value(row.id.protocol().name());
+            let endpoints = row.id.endpoints();
+            let ip = |i: usize| endpoints.map(|(src, dst)| [src, dst][i].0.to_string());
+            let port = |i: usize| endpoints.and_then(|(src, dst)| [src, dst][i].1);
+            ip_src.append_option(ip(0));
+            ip_dst.append_option(ip(1));
+            port_src.append_option(port(0));
+            port_dst.append_option(port(1));
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(TimestampNanosecondArray::from(in_times).with_timezone("UTC")),
+            Arc::new(TimestampNanosecondArray::from(out_times).with_timezone("UTC")),
+            Arc::new(latencies.finish()),
+            Arc::new(fuzzy.finish()),
+            Arc::new(protocols.finish()),
+            Arc::new(ip_src.finish()),
+            Arc::new(ip_dst.finish()),
+            Arc::new(port_src.finish()),
+            Arc::new(port_dst.finish()),
+        ];
+        let batch = RecordBatch::try_new(self.schema.clone(), columns).map_err(io::Error::other)?;
+        self.writer.write(&batch).map_err(io::Error::other)?;
+        self.rows.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,1322 @@
+use crate::defrag::{self, Defragmented, Defragmenter};
+use crate::discover;
+use crate::erf::{self, ErfReader};
+use crate::error::{Error, Result};
+use crate::extractor::IdentityExtractor;
+use crate::packet::{self, Fallback, FuzzyKey, PacketId, ParseOptions};
+use crate::reassembly::Reassembler;
+use crate::reference::{self, ReferenceReader};
+use crate::time::PacketTime;
+use crate::timeline::Span;
+use pcap_parser::pcapng::{
+    EnhancedPacketBlock, InterfaceDescriptionBlock, InterfaceStatisticsBlock, NameRecordType,
+    NameResolutionBlock, PcapNGOption,
+};
+use pcap_parser::traits::PcapReaderIterator;
+use pcap_parser::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// Delay between attempts to read more data from a FIFO in wait mode
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Custom EPB options which may carry a hardware timestamp: 4 bytes of Private Enterprise
+/// Number followed by 64-bit timestamp in units of the interface `if_tsresol`. pcapng has no
+/// standard option for it, so only options of the vendor given to `with_hardware_ts_pen` are read
+const HARDWARE_TIMESTAMP_OPTIONS: [OptionCode; 2] =
+    [OptionCode::Custom2989, OptionCode::Custom19373];
+
+/// `if_name` IDB option: name of the interface, e.g. `eth0`
+const OPTION_IF_NAME: OptionCode = OptionCode(2);
+
+/// `if_fcslen` IDB option: length of FCS at the end of every frame of the interface, in bytes
+const OPTION_IF_FCSLEN: OptionCode = OptionCode(13);
+
+/// `epb_flags` EPB option, bits 13-16 of which are length of FCS of the frame in bytes
+const OPTION_EPB_FLAGS: OptionCode = OptionCode(2);
+
+/// `epb_dropcount` EPB option: packets lost between this packet and the preceding one
+const OPTION_EPB_DROPCOUNT: OptionCode = OptionCode(4);
+
+/// `isb_ifdrop` and `isb_osdrop` ISB options: packets dropped by the interface and by the
+/// operating system since the start of the capture
+const OPTIONS_ISB_DROPS: [OptionCode; 2] = [OptionCode(5), OptionCode(7)];
+
+/// Magic numbers of legacy pcap (the first 4 bytes of file) with their timestamp units per
+/// second: microsecond and nanosecond resolution, each written by a little- or big-endian host.
+/// Fields of a big-endian file are byte-swapped by pcap_parser
+const PCAP_MAGICS: [([u8; 4], u64); 4] = [
+    ([0xd4, 0xc3, 0xb2, 0xa1], 1_000_000),
+    ([0x4d, 0x3c, 0xb2, 0xa1], 1_000_000_000),
+    ([0xa1, 0xb2, 0xc3, 0xd4], 1_000_000),
+    ([0xa1, 0xb2, 0x3c, 0x4d], 1_000_000_000),
+];
+
+/// Flag of legacy pcap header link type field: bits 29-31 are FCS length in 16-bit words
+const LINKTYPE_FCS_PRESENT: u32 = 0x1000_0000;
+
+/// Timestamp units per second of `if_tsresol` IDB option: power of ten given by the lower 7
+/// bits, or power of two if the upper bit is set. `None` if it doesn't fit into `u64`.
+/// (pcap_parser gets the power of two case wrong.) Default is microseconds
+fn ts_resolution(if_tsresol: u8) -> Option<u64> {
+    let exponent = (if_tsresol & 0x7f) as u32;
+    if if_tsresol & 0x80 == 0 {
+        10u64.checked_pow(exponent)
+    } else {
+        2u64.checked_pow(exponent)
+    }
+}
+
+/// Timestamp of pcapng packets which carry a hardware timestamp option besides the standard
+/// (software) one
+#[derive(clap::ValueEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TsSource {
+    /// The standard EPB timestamp
+    Software,
+    /// The hardware timestamp option
+    #[default]
+    Hardware,
+}
+
+/// Interface the packets were captured on. Legacy pcap file has single interface
+struct Interface {
+    link_type: Linktype,
+    /// Timestamp units per second
+    ts_resolution: u64,
+    /// Seconds to add to timestamps
+    ts_offset: u64,
+    /// Bytes of FCS at the end of every frame
+    fcs_len: usize,
+    /// Maximal number of bytes of a frame captured, 0 if unlimited
+    snaplen: u32,
+    /// Name of `if_name` option of pcapng interface
+    name: Option<String>,
+}
+
+impl Interface {
+    fn from_idb(idb: &InterfaceDescriptionBlock) -> Result<Self> {
+        let fcs_len = idb
+            .options
+            .iter()
+            .find(|option| option.code == OPTION_IF_FCSLEN && option.len == 1)
+            .map_or(0, |option| option.value[0] as usize);
+        let name = idb
+            .options
+            .iter()
+            .find(|option| option.code == OPTION_IF_NAME)
+            .map(|option| {
+                let value = &option.value[..(option.len as usize).min(option.value.len())];
+                String::from_utf8_lossy(value)
+                    .trim_end_matches('\0')
+                    .to_string()
+            })
+            .filter(|name| !name.is_empty());
+        let ts_resolution = ts_resolution(idb.if_tsresol).ok_or_else(|| {
+            Error::Parse(format!("unsupported if_tsresol {:#04x}", idb.if_tsresol))
+        })?;
+        Ok(Self {
+            link_type: idb.linktype,
+            ts_resolution,
+            ts_offset: idb.ts_offset(),
+            fcs_len,
+            snaplen: idb.snaplen,
+            name,
+        })
+    }
+
+    /// Frame without FCS. `--has-fcs` option overrides the length given by the file
+    fn frame<'a>(
+        &self,
+        options: &ParseOptions,
+        data: &'a [u8],
+        fcs_len: Option<usize>,
+    ) -> &'a [u8] {
+        let fcs_len = if options.has_fcs && self.link_type == Linktype::ETHERNET {
+            packet::ETHERNET_FCS_LEN
+        } else {
+            fcs_len.unwrap_or(self.fcs_len)
+        };
+        packet::strip_fcs(data, fcs_len)
+    }
+}
+
+/// 64-bit value of option, in byte order of the section
+fn option_u64(options: &[PcapNGOption], code: OptionCode, big_endian: bool) -> Option<u64> {
+    let option = options
+        .iter()
+        .find(|option| option.code == code && option.len == 8)?;
+    let value: [u8; 8] = option.value.get(0..8)?.try_into().ok()?;
+    Some(if big_endian {
+        u64::from_be_bytes(value)
+    } else {
+        u64::from_le_bytes(value)
+    })
+}
+
+/// Packets the capturing tool reported as dropped, per interface. Statistics blocks and packet
+/// options may both count the same drops, so the larger count of an interface is taken
+#[derive(Default)]
+struct CaptureDrops {
+    /// Drops of the latest Interface Statistics Block, whose counts are cumulative
+    statistics: HashMap<u32, u64>,
+    /// Sum of `epb_dropcount` of packets
+    packets: HashMap<u32, u64>,
+    /// Drops of earlier pcapng sections, whose interfaces are numbered independently
+    earlier_sections: Option<u64>,
+}
+
+impl CaptureDrops {
+    fn add_statistics(&mut self, isb: &InterfaceStatisticsBlock, big_endian: bool) {
+        let drops: Vec<u64> = OPTIONS_ISB_DROPS
+            .iter()
+            .filter_map(|code| option_u64(&isb.options, *code, big_endian))
+            .collect();
+        if !drops.is_empty() {
+            self.statistics.insert(isb.if_id, drops.iter().sum());
+        }
+    }
+
+    fn add_packet(&mut self, epb: &EnhancedPacketBlock, big_endian: bool) {
+        if let Some(drops) = option_u64(&epb.options, OPTION_EPB_DROPCOUNT, big_endian) {
+            *self.packets.entry(epb.if_id).or_default() += drops;
+        }
+    }
+
+    fn start_section(&mut self) {
+        if let Some(total) = self.total() {
+            self.earlier_sections = Some(total);
+        }
+        self.statistics.clear();
+        self.packets.clear();
+    }
+
+    /// `None` if the file carries no drop counts
+    fn total(&self) -> Option<u64> {
+        if self.statistics.is_empty() && self.packets.is_empty() {
+            return self.earlier_sections;
+        }
+        let interfaces: HashSet<&u32> = self.statistics.keys().chain(self.packets.keys()).collect();
+        let count = |counts: &HashMap<u32, u64>, interface| *counts.get(interface).unwrap_or(&0);
+        Some(
+            interfaces
+                .into_iter()
+                .map(|interface| {
+                    count(&self.statistics, interface).max(count(&self.packets, interface))
+                })
+                .sum::<u64>()
+                + self.earlier_sections.unwrap_or(0),
+        )
+    }
+}
+
+/// Addresses of Name Resolution Block with the first name of each. Names of a record are
+/// zero-terminated, the record is padded with zeros
+fn name_records<'a>(nrb: &'a NameResolutionBlock) -> impl Iterator<Item = (IpAddr, String)> + 'a {
+    nrb.nr.iter().filter_map(|record| {
+        let value = record.record_value;
+        let (ip, names): (IpAddr, &[u8]) = match record.record_type {
+            NameRecordType::Ipv4 => {
+                let ip: [u8; 4] = value.get(..4)?.try_into().ok()?;
+                (ip.into(), &value[4..])
+            }
+            NameRecordType::Ipv6 => {
+                let ip: [u8; 16] = value.get(..16)?.try_into().ok()?;
+                (ip.into(), &value[16..])
+            }
+            _ => return None,
+        };
+        let name = names.split(|byte| *byte == 0).next()?;
+        (!name.is_empty()).then(|| (ip, String::from_utf8_lossy(name).into_owned()))
+    })
+}
+
+/// Input file which counts bytes read from it, for progress of long runs. Parsers read ahead,
+/// so the count runs up to a buffer ahead of the packets returned
+struct CountingFile {
+    file: File,
+    consumed: Arc<AtomicU64>,
+    /// Flag of `PcapReader::with_stop`, given once the file is open
+    stop: Arc<OnceLock<&'static AtomicBool>>,
+}
+
+impl Read for CountingFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // A read of a FIFO blocks while the writer has nothing to give, so wait for data in
+        // slices to see the flag in between
+        if let Some(stop) = self.stop.get() {
+            while !readable(&self.file, WAIT_POLL_INTERVAL) {
+                if stop.load(Ordering::Relaxed) {
+                    return Ok(0);
+                }
+            }
+        }
+        let len = self.file.read(buf)?;
+        self.consumed.fetch_add(len as u64, Ordering::Relaxed);
+        Ok(len)
+    }
+}
+
+/// Whether file has data to read (or no writer left) within `timeout`
+#[cfg(unix)]
+fn readable(file: &File, timeout: Duration) -> bool {
+    use std::os::unix::io::AsRawFd;
+    let mut fd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: a single valid pollfd is passed
+    unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) > 0 }
+}
+
+#[cfg(not(unix))]
+fn readable(_file: &File, _timeout: Duration) -> bool {
+    true
+}
+
+/// Reader of capture file: pcap-parser for pcap and pcapng, or own ERF reader. Or reader of
+/// reference log, which has identities instead of frames
+enum Source {
+    Pcap(Box<dyn PcapReaderIterator>),
+    Erf(ErfReader<Box<dyn Read>>),
+    Reference(ReferenceReader<BufReader<Box<dyn Read>>>),
+}
+
+/// Data of frame beyond its identity, kept only if asked for
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// See `PcapReader::with_dscp`
+    pub dscp: Option<u8>,
+    /// See `PcapReader::with_vlan_ids`
+    pub vlan_ids: Vec<u16>,
+    /// See `PcapReader::reassembled`
+    pub reassembled: bool,
+}
+
+/// Packet with its fallback key and frame data
+type Popped = ((PacketId, PacketTime), Option<FuzzyKey>, FrameInfo);
+
+/// Identities read but not returned yet (a segment can complete several messages), with their
+/// fallback keys and frame data
+#[derive(Default)]
+struct Pending {
+    packets: VecDeque<(PacketId, PacketTime)>,
+    /// Options of fallback identities, keys are built only if set
+    fallback: Option<ParseOptions>,
+    /// Fallback key of each of `packets`, if `fallback` is set
+    keys: VecDeque<Option<FuzzyKey>>,
+    /// DSCP is kept only if set
+    dscp: bool,
+    /// VLAN IDs are kept only if set
+    vlan_ids: bool,
+    /// Frame data of each of `packets`, if any of it is kept
+    infos: VecDeque<FrameInfo>,
+    /// Reassembles fragments if `ParseOptions::defragment` is set
+    defragmenter: Option<Defragmenter>,
+    /// Frames which are IPv4 fragments
+    fragments: u64,
+    /// Frames skipped because of `ParseOptions::size_range`
+    size_excluded: u64,
+    /// Frames (or reference log entries) read which passed the byte filter
+    frames: u64,
+    /// Times of `frames`
+    span: Option<Span>,
+}
+
+impl Pending {
+    /// Appends identities of frame, see `identify`. Their fallback key is of the frame itself,
+    /// so messages of reassembled TCP streams have none. Frames of excluded size are counted
+    /// instead, they don't count as unidentified. Frames shorter than `ParseOptions::l3_offset`
+    /// do
+    fn identify(
+        &mut self,
+        link_type: Linktype,
+        data: &[u8],
+        time: PacketTime,
+        options: &ParseOptions,
+        reassembler: &mut Option<Reassembler>,
+    ) -> bool {
+        self.count(time);
+        let Some((link_type, data)) = options.at_l3_offset(link_type, data) else {
+            return false;
+        };
+        if defrag::is_fragment(link_type, data) {
+            self.fragments += 1;
+        }
+        let whole;
+        let (data, reassembled) = match &mut self.defragmenter {
+            Some(defragmenter) => match defragmenter.push(link_type, data, time) {
+                Defragmented::Whole => (data, false),
+                Defragmented::Held => return true,
+                Defragmented::Complete(frame) => {
+                    whole = frame;
+                    (whole.as_slice(), true)
+                }
+            },
+            None => (data, false),
+        };
+        if !options.allows_size(link_type, data) {
+            self.size_excluded += 1;
+            return true;
+        }
+        let count = self.packets.len();
+        let identified = identify(
+            link_type,
+            data,
+            time,
+            options,
+            reassembler,
+            &mut self.packets,
+        );
+        let added = self.packets.len() - count;
+        if let Some(fallback) = &self.fallback {
+            let key = match reassembler {
+                Some(_) => None,
+                None => fallback
+                    .extract(link_type, data)
+                    .map(|tuple_id| tuple_id.fuzzy_key()),
+            };
+            self.keys.extend(std::iter::repeat_n(key, added));
+        }
+        if self.dscp || self.vlan_ids || self.defragmenter.is_some() {
+            let info = FrameInfo {
+                dscp: packet::dscp(link_type, data).filter(|_| self.dscp),
+                vlan_ids: match self.vlan_ids {
+                    true => packet::vlan_ids(link_type, data),
+                    false => Vec::new(),
+                },
+                reassembled,
+            };
+            self.infos.extend(std::iter::repeat_n(info, added));
+        }
+        identified
+    }
+
+    fn count(&mut self, time: PacketTime) {
+        self.frames += 1;
+        self.span = Some(Span::including(self.span, time));
+    }
+
+    fn pop(&mut self) -> Option<Popped> {
+        let packet = self.packets.pop_front()?;
+        Some((
+            packet,
+            self.keys.pop_front().flatten(),
+            self.infos.pop_front().unwrap_or_default(),
+        ))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+}
+
+pub struct PcapReader {
+    source: Source,
+    filter: Vec<(usize, u8)>,
+    options: ParseOptions,
+    /// Reassembler of TCP streams in `TcpMatchMode::Message`
+    reassembler: Option<Reassembler>,
+    pending: Pending,
+    fallback: Option<Fallback>,
+    /// Fallback key of the packet last returned
+    fallback_key: Option<FuzzyKey>,
+    /// Frame data of the packet last returned
+    info: FrameInfo,
+    is_ng: bool,
+    /// Interfaces of every pcapng section in order of appearance
+    interfaces: Vec<Interface>,
+    /// Index of the first interface of the current section, whose packets refer to interfaces
+    /// by index within the section
+    section_start: usize,
+    big_endian: bool,
+    ts_source: TsSource,
+    /// See `with_hardware_ts_pen`
+    hardware_ts_pen: Option<u32>,
+    hardware_timestamps: u64,
+    drops: CaptureDrops,
+    /// Host names of pcapng Name Resolution Blocks, the first one given for an address
+    host_names: HashMap<IpAddr, String>,
+    unidentified: u64,
+    /// Size of the input file, `None` unless it's a regular file (e.g. a FIFO)
+    input_len: Option<u64>,
+    /// Bytes read from the input file so far
+    consumed: Arc<AtomicU64>,
+    error: Option<Error>,
+    is_fifo: bool,
+    wait: bool,
+    /// See `with_stop`, shared with the files read
+    stop: Arc<OnceLock<&'static AtomicBool>>,
+    /// Files of a directory or a pattern still to be read after the current one
+    next_paths: VecDeque<String>,
+    /// Link-layer type of the current file if none of its interfaces is supported, an error of
+    /// the first read unless IP is parsed at `ParseOptions::l3_offset`
+    unsupported: Option<i32>,
+}
+
+impl PcapReader {
+    /// Reader of capture file, or of the files of a directory or a wildcard pattern one after
+    /// another (see `discover`), each of them like a new pcapng section
+    pub fn new_from_path(file_path: &str, filter: Vec<(usize, u8)>) -> Result<Self> {
+        let mut paths: VecDeque<String> = discover::files(file_path)?.into();
+        let first = paths.pop_front().expect("at least one file is found");
+        let consumed = Arc::new(AtomicU64::new(0));
+        let stop = Arc::new(OnceLock::new());
+        let mut reader = Self::open(&first, filter, consumed.clone(), stop.clone())?;
+        for path in &paths {
+            let metadata = fs::metadata(path)?;
+            reader.input_len = reader
+                .input_len
+                .filter(|_| metadata.is_file())
+                .map(|len| len + metadata.len());
+        }
+        reader.consumed = consumed;
+        reader.stop = stop;
+        reader.next_paths = paths;
+        Ok(reader)
+    }
+
+    fn open(
+        file_path: &str,
+        filter: Vec<(usize, u8)>,
+        consumed: Arc<AtomicU64>,
+        stop: Arc<OnceLock<&'static AtomicBool>>,
+    ) -> Result<Self> {
+        let file = File::open(file_path)?;
+        let is_fifo = Self::is_fifo(&file)?;
+        let metadata = file.metadata()?;
+        let file = CountingFile {
+            file,
+            consumed,
+            stop,
+        };
+        let mut reader = Self::from_file(file, filter, is_fifo)?;
+        reader.input_len = metadata.is_file().then_some(metadata.len());
+        Ok(reader)
+    }
+
+    /// Continues with the next file, whose interfaces follow the ones of the files before
+    fn start_file(&mut self, file_path: &str) -> Result<()> {
+        let next = Self::open(
+            file_path,
+            self.filter.clone(),
+            self.consumed.clone(),
+            self.stop.clone(),
+        )?;
+        self.source = next.source;
+        self.is_ng = next.is_ng;
+        self.big_endian = next.big_endian;
+        self.section_start = self.interfaces.len() + next.section_start;
+        self.interfaces.extend(next.interfaces);
+        self.drops.start_section();
+        for (ip, name) in next.host_names {
+            self.host_names.entry(ip).or_insert(name);
+        }
+        self.is_fifo = next.is_fifo;
+        self.unsupported = next.unsupported;
+        Ok(())
+    }
+
+    fn from_file(mut file: CountingFile, filter: Vec<(usize, u8)>, is_fifo: bool) -> Result<Self> {
+        let mut magic = Vec::with_capacity(4);
+        (&mut file).take(4).read_to_end(&mut magic)?;
+        if magic == [0x0a, 0x0d, 0x0d, 0x0a] {
+            let shb_bytes = Self::read_section_header(magic, &mut file)?;
+            let stream: Box<dyn Read> = Box::new(Cursor::new(shb_bytes).chain(file));
+            let reader =
+                PcapNGReader::new(1024 * 1024, stream).map_err(|e| Error::Parse(e.to_string()))?;
+            let source = Source::Pcap(Box::new(reader));
+            let mut reader = Self::new(source, filter, true, Vec::new(), is_fifo);
+            reader.read_interfaces()?;
+            return Ok(reader);
+        }
+        let mut header_bytes = magic;
+        (&mut file)
+            .take(24 - header_bytes.len() as u64)
+            .read_to_end(&mut header_bytes)?;
+        // Header is already consumed from the file, which can't be rewound if it's a FIFO
+        let (header, ts_resolution) = match Self::parse_header(&header_bytes) {
+            Err(Error::BadMagic(_)) if reference::looks_like_reference(&header_bytes) => {
+                let stream: Box<dyn Read> = Box::new(Cursor::new(header_bytes).chain(file));
+                let source = Source::Reference(ReferenceReader::new(BufReader::new(stream)));
+                return Ok(Self::new(source, filter, false, Vec::new(), is_fifo));
+            }
+            Err(Error::BadMagic(_)) if erf::looks_like_erf(&header_bytes) => {
+                let stream: Box<dyn Read> = Box::new(Cursor::new(header_bytes).chain(file));
+                let source = Source::Erf(ErfReader::new(stream));
+                return Ok(Self::new(source, filter, false, Vec::new(), is_fifo));
+            }
+            header => header?,
+        };
+        // Upper bits of the field are flags, link type itself is in the lower 16 bits
+        let network = header.network.0 as u32;
+        let link_type = Linktype((network & 0xffff) as i32);
+        let interface = Interface {
+            link_type,
+            ts_resolution,
+            ts_offset: 0,
+            fcs_len: if network & LINKTYPE_FCS_PRESENT != 0 {
+                (network >> 29) as usize * 2
+            } else {
+                0
+            },
+            snaplen: header.snaplen,
+            name: None,
+        };
+        let stream: Box<dyn Read> = Box::new(Cursor::new(header_bytes).chain(file));
+        let reader =
+            LegacyPcapReader::new(1024 * 1024, stream).map_err(|e| Error::Parse(e.to_string()))?;
+        let mut reader = Self::new(
+            Source::Pcap(Box::new(reader)),
+            filter,
+            false,
+            vec![interface],
+            is_fifo,
+        );
+        reader.unsupported = (!packet::is_supported_link_type(link_type)).then_some(link_type.0);
+        Ok(reader)
+    }
+
+    fn new(
+        source: Source,
+        filter: Vec<(usize, u8)>,
+        is_ng: bool,
+        interfaces: Vec<Interface>,
+        is_fifo: bool,
+    ) -> Self {
+        Self {
+            source,
+            filter,
+            options: ParseOptions::default(),
+            reassembler: None,
+            pending: Pending::default(),
+            fallback: None,
+            fallback_key: None,
+            info: FrameInfo::default(),
+            is_ng,
+            interfaces,
+            section_start: 0,
+            big_endian: false,
+            ts_source: TsSource::default(),
+            hardware_ts_pen: None,
+            hardware_timestamps: 0,
+            drops: CaptureDrops::default(),
+            host_names: HashMap::new(),
+            unidentified: 0,
+            input_len: None,
+            consumed: Arc::default(),
+            error: None,
+            is_fifo,
+            wait: false,
+            stop: Arc::default(),
+            next_paths: VecDeque::new(),
+            unsupported: None,
+        }
+    }
+
+    /// Options of building packet identities
+    pub fn with_parse_options(mut self, options: ParseOptions) -> Self {
+        self.reassembler = Reassembler::for_options(&options);
+        self.pending.defragmenter = options.defragment.then(Defragmenter::new);
+        self.options = options;
+        let fallback = self.fallback;
+        self.with_fallback(fallback)
+    }
+
+    /// Also build fallback keys of packets, see `fallback_key`
+    pub fn with_fallback(mut self, fallback: Option<Fallback>) -> Self {
+        self.fallback = fallback;
+        self.pending.fallback = fallback.map(|fallback| fallback.options(&self.options));
+        self
+    }
+
+    /// Fallback key of the packet last returned by `next`, if the reader was created
+    /// `with_fallback`. Reference logs have no frames, so their packets have none
+    pub fn fallback_key(&self) -> Option<FuzzyKey> {
+        self.fallback_key
+    }
+
+    /// Also keep DSCP of frames, see `dscp`
+    pub fn with_dscp(mut self, dscp: bool) -> Self {
+        self.pending.dscp = dscp;
+        self
+    }
+
+    /// DSCP of the frame of the packet last returned by `next`, if the reader was created
+    /// `with_dscp` and the frame is IP. Packets of reference logs have none
+    pub fn dscp(&self) -> Option<u8> {
+        self.info.dscp
+    }
+
+    /// Also keep VLAN IDs of frames, see `vlan_ids`
+    pub fn with_vlan_ids(mut self, vlan_ids: bool) -> Self {
+        self.pending.vlan_ids = vlan_ids;
+        self
+    }
+
+    /// VLAN IDs of the frame of the packet last returned by `next`, the outermost first, if
+    /// the reader was created `with_vlan_ids`. Empty for untagged frames
+    pub fn vlan_ids(&self) -> &[u16] {
+        &self.info.vlan_ids
+    }
+
+    /// Whether the packet last returned by `next` was reassembled from IPv4 fragments, with
+    /// `ParseOptions::defragment`
+    pub fn reassembled(&self) -> bool {
+        self.info.reassembled
+    }
+
+    /// Frames read so far which are IPv4 fragments, whether they are reassembled or not
+    pub fn fragment_count(&self) -> u64 {
+        self.pending.fragments
+    }
+
+    /// Which timestamp of pcapng packets with both is used
+    pub fn with_ts_source(mut self, ts_source: TsSource) -> Self {
+        self.ts_source = ts_source;
+        self
+    }
+
+    /// If the file is a FIFO, wait for more data on EOF instead of stopping the iteration.
+    /// Regular files are not affected
+    pub fn with_wait(mut self, wait: bool) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// In wait mode, ends the iteration of a FIFO once `stop` is set (e.g. by Ctrl-C), also
+    /// while waiting for the writer
+    pub fn with_stop(self, stop: &'static AtomicBool) -> Self {
+        // A second call keeps the first flag
+        let _ = self.stop.set(stop);
+        self
+    }
+
+    /// Reads hardware timestamps of custom options of the vendor with Private Enterprise Number
+    /// `pen`, see `HARDWARE_TIMESTAMP_OPTIONS`. Without it packets have the standard timestamp,
+    /// since custom options of other vendors have a layout of their own
+    pub fn with_hardware_ts_pen(mut self, pen: Option<u32>) -> Self {
+        self.hardware_ts_pen = pen;
+        self
+    }
+
+    /// Size of the input file in bytes, `None` if it isn't a regular file (e.g. a FIFO)
+    pub fn input_len(&self) -> Option<u64> {
+        self.input_len
+    }
+
+    /// Bytes read from the input file so far, up to a read-ahead buffer beyond the packets
+    /// returned
+    pub fn consumed_bytes(&self) -> u64 {
+        self.consumed.load(Ordering::Relaxed)
+    }
+
+    /// Link-layer type of the file (of the first interface for pcapng)
+    pub fn link_type(&self) -> Option<Linktype> {
+        self.interfaces.first().map(|interface| interface.link_type)
+    }
+
+    /// Maximal number of bytes captured of a frame, from the file header (of the most limiting
+    /// interface for pcapng). `None` if unlimited, or unknown as for ERF files and reference logs
+    pub fn snaplen(&self) -> Option<u32> {
+        self.interfaces
+            .iter()
+            .map(|interface| interface.snaplen)
+            .filter(|snaplen| *snaplen > 0)
+            .min()
+    }
+
+    /// Names of interfaces in order of their index (over all sections of pcapng): of `if_name`
+    /// option of pcapng interface, or the index if it has none (as the interface of pcap and
+    /// ERF files)
+    pub fn interface_names(&self) -> Vec<String> {
+        self.interfaces
+            .iter()
+            .enumerate()
+            .map(|(index, interface)| interface.name.clone().unwrap_or_else(|| index.to_string()))
+            .collect()
+    }
+
+    /// Host names of addresses given by pcapng Name Resolution Blocks read so far
+    pub fn host_names(&self) -> &HashMap<IpAddr, String> {
+        &self.host_names
+    }
+
+    pub fn is_pcapng(&self) -> bool {
+        self.is_ng
+    }
+
+    /// Format of the file: `pcap`, `pcapng`, `erf` or `reference`
+    pub fn format(&self) -> &'static str {
+        match self.source {
+            Source::Pcap(_) if self.is_ng => "pcapng",
+            Source::Pcap(_) => "pcap",
+            Source::Erf(_) => "erf",
+            Source::Reference(_) => "reference",
+        }
+    }
+
+    /// Link-layer types of interfaces in order of their index, over all sections of pcapng.
+    /// ERF files give a type with every
+    /// record and reference logs have none, so they have no interfaces
+    pub fn link_types(&self) -> Vec<Linktype> {
+        self.interfaces
+            .iter()
+            .map(|interface| interface.link_type)
+            .collect()
+    }
+
+    /// Timestamp resolution in nanoseconds, of the coarsest interface for pcapng. ERF files and
+    /// reference logs have nanosecond resolution (or finer)
+    pub fn timestamp_resolution_nsec(&self) -> u64 {
+        self.interfaces
+            .iter()
+            .map(|interface| (1_000_000_000 / interface.ts_resolution).max(1))
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Number of packets read so far whose time was taken from a hardware timestamp option
+    pub fn hardware_timestamp_count(&self) -> u64 {
+        self.hardware_timestamps
+    }
+
+    /// Number of packets the capturing tool dropped, as reported by pcapng interface statistics
+    /// and packet options read so far. `None` if the file carries no such counts (as pcap and
+    /// ERF files don't)
+    pub fn capture_drops(&self) -> Option<u64> {
+        self.drops.total()
+    }
+
+    /// Number of frames read so far which passed the byte filter but got no identity: of other
+    /// protocols (including those excluded by `ParseOptions::protocols`) or malformed
+    pub fn unidentified_count(&self) -> u64 {
+        self.unidentified
+    }
+
+    /// Number of frames read so far which passed the byte filter but were skipped because of
+    /// `ParseOptions::size_range`
+    pub fn size_excluded_count(&self) -> u64 {
+        self.pending.size_excluded
+    }
+
+    /// Number of frames (or reference log entries) read so far which passed the byte filter,
+    /// whether they got identities or not
+    pub fn frame_count(&self) -> u64 {
+        self.pending.frames
+    }
+
+    /// Time of the earliest and the latest of `frame_count` frames
+    pub fn span(&self) -> Option<Span> {
+        self.pending.span
+    }
+
+    /// Error which stopped the iteration, if any
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
+    #[cfg(unix)]
+    fn is_fifo(file: &File) -> Result<bool> {
+        use std::os::unix::fs::FileTypeExt;
+        Ok(file.metadata()?.file_type().is_fifo())
+    }
+
+    #[cfg(not(unix))]
+    fn is_fifo(_file: &File) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Parses legacy pcap header, and gets timestamp units per second from its magic
+    fn parse_header(bytes: &[u8]) -> Result<(PcapHeader, u64)> {
+        let mut magic = [0u8; 4];
+        magic[..bytes.len().min(4)].copy_from_slice(&bytes[..bytes.len().min(4)]);
+        let ts_resolution = PCAP_MAGICS
+            .iter()
+            .find(|(known, _)| bytes.len() >= 4 && *known == magic)
+            .map(|(_, ts_resolution)| *ts_resolution)
+            .ok_or(Error::BadMagic(magic))?;
+        match parse_pcap_header(bytes) {
+            Ok((_, header)) => Ok((header, ts_resolution)),
+            Err(nom::Err::Incomplete(_)) => Err(Error::Truncated),
+            Err(e) => Err(Error::Parse(e.to_string())),
+        }
+    }
+
+    /// Reads whole pcapng Section Header Block, which the reader expects to get at once
+    fn read_section_header(magic: Vec<u8>, file: &mut CountingFile) -> Result<Vec<u8>> {
+        let mut bytes = magic;
+        file.take(8).read_to_end(&mut bytes)?;
+        if bytes.len() < 12 {
+            return Err(Error::Truncated);
+        }
+        let len = [bytes[4], bytes[5], bytes[6], bytes[7]];
+        let len = match [bytes[8], bytes[9], bytes[10], bytes[11]] {
+            [0x4d, 0x3c, 0x2b, 0x1a] => u32::from_le_bytes(len),
+            [0x1a, 0x2b, 0x3c, 0x4d] => u32::from_be_bytes(len),
+            _ => return Err(Error::BadMagic([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        } as u64;
+        file.take(len.saturating_sub(12)).read_to_end(&mut bytes)?;
+        if (bytes.len() as u64) < len {
+            return Err(Error::Truncated);
+        }
+        Ok(bytes)
+    }
+
+    /// Reads pcapng blocks preceding the first packet, so link-layer types are known before
+    /// iteration starts
+    fn read_interfaces(&mut self) -> Result<()> {
+        let Source::Pcap(reader) = &mut self.source else {
+            return Ok(());
+        };
+        loop {
+            match reader.next() {
+                Ok((offset, PcapBlockOwned::NG(Block::SectionHeader(shb)))) => {
+                    self.big_endian = shb.big_endian();
+                    self.section_start = self.interfaces.len();
+                    reader.consume(offset);
+                }
+                Ok((offset, PcapBlockOwned::NG(Block::InterfaceDescription(idb)))) => {
+                    self.interfaces.push(Interface::from_idb(&idb)?);
+                    reader.consume(offset);
+                }
+                Ok(_) | Err(PcapError::Eof) => break,
+                Err(PcapError::Incomplete) => {
+                    if reader.reader_exhausted() {
+                        break;
+                    }
+                    reader.refill().map_err(|e| Error::Parse(e.to_string()))?
+                }
+                Err(e) => return Err(Error::Parse(e.to_string())),
+            }
+        }
+        let supported = self
+            .interfaces
+            .iter()
+            .any(|interface| packet::is_supported_link_type(interface.link_type));
+        self.unsupported = self
+            .interfaces
+            .first()
+            .filter(|_| !supported)
+            .map(|first| first.link_type.0);
+        Ok(())
+    }
+
+    /// Reads more data from the FIFO, sleeping while the writer has nothing to give
+    fn wait_for_data(reader: &mut Box<dyn PcapReaderIterator>) -> Result<()> {
+        thread::sleep(WAIT_POLL_INTERVAL);
+        reader.refill().map_err(|e| Error::Parse(e.to_string()))
+    }
+
+    /// FCS length from `epb_flags` option, if the flags give it
+    fn epb_fcs_len(epb: &EnhancedPacketBlock, big_endian: bool) -> Option<usize> {
+        let option = epb
+            .options
+            .iter()
+            .find(|option| option.code == OPTION_EPB_FLAGS && option.len == 4)?;
+        let flags = [
+            option.value[0],
+            option.value[1],
+            option.value[2],
+            option.value[3],
+        ];
+        let flags = if big_endian {
+            u32::from_be_bytes(flags)
+        } else {
+            u32::from_le_bytes(flags)
+        };
+        match (flags >> 13) & 0xf {
+            0 => None,
+            fcs_len => Some(fcs_len as usize),
+        }
+    }
+
+    /// Hardware timestamp from custom EPB option of vendor `pen`, in interface timestamp units
+    fn hardware_timestamp(epb: &EnhancedPacketBlock, big_endian: bool, pen: u32) -> Option<u64> {
+        let pen = match big_endian {
+            true => pen.to_be_bytes(),
+            false => pen.to_le_bytes(),
+        };
+        let option = epb.options.iter().find(|option| {
+            HARDWARE_TIMESTAMP_OPTIONS.contains(&option.code)
+                && option.len == 12
+                && option.value[..4] == pen
+        })?;
+        let mut ts = [0u8; 8];
+        ts.copy_from_slice(&option.value[4..12]);
+        Some(if big_endian {
+            u64::from_be_bytes(ts)
+        } else {
+            u64::from_le_bytes(ts)
+        })
+    }
+}
+
+/// Parses byte filter entry `byte_number:byte_value`
+pub fn parse_filter_entry(entry: &str) -> Result<(usize, u8)> {
+    let bad_filter = || Error::BadFilter(format!("'{}' is not byte_number:byte_value", entry));
+    let (byte_number, byte_value) = entry.split_once(':').ok_or_else(bad_filter)?;
+    Ok((
+        byte_number.parse().map_err(|_| bad_filter())?,
+        byte_value.parse().map_err(|_| bad_filter())?,
+    ))
+}
+
+/// Parses byte filter entries separated by whitespace. `#` starts a comment till end of line
+pub fn parse_filter(text: &str) -> Result<Vec<(usize, u8)>> {
+    let mut filter = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.split_once('#').map_or(line, |(entries, _)| entries);
+        for entry in line.split_whitespace() {
+            let entry = parse_filter_entry(entry).map_err(|e| match e {
+                Error::BadFilter(message) => {
+                    Error::BadFilter(format!("line {}: {}", line_number + 1, message))
+                }
+                e => e,
+            })?;
+            filter.push(entry);
+        }
+    }
+    Ok(filter)
+}
+
+/// Checks that frame has given values at given byte offsets
+pub(crate) fn match_filter(bytes: &[u8], filter: &[(usize, u8)]) -> bool {
+    filter
+        .iter()
+        .all(|(byte_number, byte_value)| bytes.get(*byte_number) == Some(byte_value))
+}
+
+/// Appends identities of frame to `packets`: its own, or of messages it completes if TCP
+/// streams are reassembled. Returns `false` if the frame has no identity nor is a segment of
+/// reassembled stream
+pub(crate) fn identify(
+    link_type: Linktype,
+    data: &[u8],
+    time: PacketTime,
+    options: &ParseOptions,
+    reassembler: &mut Option<Reassembler>,
+    packets: &mut VecDeque<(PacketId, PacketTime)>,
+) -> bool {
+    let count = packets.len();
+    let reassembled = reassembler
+        .as_mut()
+        .is_some_and(|reassembler| reassembler.push(link_type, data, time, packets));
+    let identified = reassembled
+        || match options.extract(link_type, data) {
+            Some(tuple_id) => {
+                packets.push_back((tuple_id, time));
+                true
+            }
+            None => false,
+        };
+    if !options.key.is_empty() {
+        for (tuple_id, _) in packets.range_mut(count..) {
+            options.key.apply(tuple_id);
+        }
+    }
+    identified
+}
+
+/// Identities of packet from Enhanced Packet Block. Hardware timestamp of vendor
+/// `hardware_ts_pen` is preferred. Returns `false` if the packet passed the byte filter but got
+/// no identity
+#[allow(clippy::too_many_arguments)]
+fn read_epb(
+    interfaces: &[Interface],
+    filter: &[(usize, u8)],
+    options: &ParseOptions,
+    big_endian: bool,
+    hardware_ts_pen: Option<u32>,
+    hardware_timestamps: &mut u64,
+    epb: &EnhancedPacketBlock,
+    reassembler: &mut Option<Reassembler>,
+    pending: &mut Pending,
+) -> bool {
+    let Some(interface) = interfaces.get(epb.if_id as usize) else {
+        return true;
+    };
+    let fcs_len = PcapReader::epb_fcs_len(epb, big_endian);
+    let data = interface.frame(options, epb.data, fcs_len);
+    if !match_filter(data, filter) {
+        return true;
+    }
+    let hardware =
+        hardware_ts_pen.and_then(|pen| PcapReader::hardware_timestamp(epb, big_endian, pen));
+    let ts = match hardware {
+        Some(ts) => {
+            *hardware_timestamps += 1;
+            ts
+        }
+        None => ((epb.ts_high as u64) << 32) | epb.ts_low as u64,
+    };
+    let time = PacketTime::from_units(ts, interface.ts_resolution, interface.ts_offset);
+    pending.identify(interface.link_type, data, time, options, reassembler)
+}
+
+/// Reads ERF records until some have identities. Returns `false` at the end of file, or once
+/// `stopped` while waiting for more
+#[allow(clippy::too_many_arguments)]
+fn next_erf(
+    erf: &mut ErfReader<Box<dyn Read>>,
+    filter: &[(usize, u8)],
+    options: &ParseOptions,
+    waiting: bool,
+    stopped: &dyn Fn() -> bool,
+    reassembler: &mut Option<Reassembler>,
+    pending: &mut Pending,
+    unidentified: &mut u64,
+) -> Result<bool> {
+    while pending.is_empty() {
+        let record = match erf.next_record()? {
+            erf::Next::Record(record) => record,
+            erf::Next::Eof | erf::Next::Incomplete if waiting && stopped() => return Ok(false),
+            erf::Next::Eof | erf::Next::Incomplete if waiting => {
+                thread::sleep(WAIT_POLL_INTERVAL);
+                continue;
+            }
+            erf::Next::Eof => return Ok(false),
+            erf::Next::Incomplete => return Err(Error::Truncated),
+        };
+        let Some(link_type) = record.link_type else {
+            continue;
+        };
+        let interface = Interface {
+            link_type,
+            ts_resolution: erf::TS_RESOLUTION,
+            ts_offset: 0,
+            fcs_len: 0,
+            snaplen: 0,
+            name: None,
+        };
+        let data = interface.frame(options, &record.data, None);
+        if !match_filter(data, filter) {
+            continue;
+        }
+        let time = PacketTime::from_units(record.ts, erf::TS_RESOLUTION, 0);
+        if !pending.identify(link_type, data, time, options, reassembler) {
+            *unidentified += 1;
+        }
+    }
+    Ok(true)
+}
+
+/// Reads reference log until an identity of allowed protocol
+fn next_reference(
+    log: &mut ReferenceReader<BufReader<Box<dyn Read>>>,
+    options: &ParseOptions,
+    unidentified: &mut u64,
+    pending: &mut Pending,
+) -> Result<Option<(PacketId, PacketTime)>> {
+    while let Some((id, time)) = log.next_entry()? {
+        pending.count(time);
+        if options.protocols.is_empty() || options.protocols.contains(&id.protocol()) {
+            return Ok(Some((id, time)));
+        }
+        *unidentified += 1;
+    }
+    Ok(None)
+}
+
+impl PcapReader {
+    /// Next packet with its fallback key and DSCP
+    fn next_packet(&mut self) -> Option<Popped> {
+        loop {
+            if let Some(packet) = self.next_packet_of_file() {
+                return Some(packet);
+            }
+            if self.error.is_some() {
+                return None;
+            }
+            let path = self.next_paths.pop_front()?;
+            if let Err(e) = self.start_file(&path) {
+                self.error = Some(e);
+                return None;
+            }
+        }
+    }
+
+    fn next_packet_of_file(&mut self) -> Option<Popped> {
+        if let Some(packet) = self.pending.pop() {
+            return Some(packet);
+        }
+        if self.error.is_some() {
+            return None;
+        }
+        if let Some(link_type) = self.unsupported.take() {
+            if self.options.l3_offset.is_none() {
+                self.error = Some(Error::UnsupportedLinkType(link_type));
+                return None;
+            }
+        }
+        let waiting = self.wait && self.is_fifo;
+        let stop = self.stop.get().copied();
+        let stopped = || stop.is_some_and(|stop| stop.load(Ordering::Relaxed));
+        if waiting && stopped() {
+            return None;
+        }
+        let reader = match &mut self.source {
+            Source::Pcap(reader) => reader,
+            Source::Erf(erf) => {
+                return match next_erf(
+                    erf,
+                    &self.filter,
+                    &self.options,
+                    waiting,
+                    &stopped,
+                    &mut self.reassembler,
+                    &mut self.pending,
+                    &mut self.unidentified,
+                ) {
+                    Ok(_) => self.pending.pop(),
+                    Err(e) => {
+                        self.error = Some(e);
+                        None
+                    }
+                };
+            }
+            Source::Reference(log) => {
+                return match next_reference(
+                    log,
+                    &self.options,
+                    &mut self.unidentified,
+                    &mut self.pending,
+                ) {
+                    Ok(packet) => packet.map(|packet| (packet, None, FrameInfo::default())),
+                    Err(e) => {
+                        self.error = Some(e);
+                        None
+                    }
+                };
+            }
+        };
+        loop {
+            match reader.next() {
+                Ok((offset, block)) => {
+                    match block {
+                        PcapBlockOwned::LegacyHeader(_hdr) => {}
+                        PcapBlockOwned::Legacy(_b) => {
+                            let interface = &self.interfaces[self.section_start];
+                            let data = interface.frame(&self.options, _b.data, None);
+                            if match_filter(data, &self.filter) {
+                                let time = PacketTime::from_sec_fraction(
+                                    _b.ts_sec as u64,
+                                    _b.ts_usec as u64,
+                                    interface.ts_resolution,
+                                );
+                                if !self.pending.identify(
+                                    interface.link_type,
+                                    data,
+                                    time,
+                                    &self.options,
+                                    &mut self.reassembler,
+                                ) {
+                                    self.unidentified += 1;
+                                }
+                            }
+                        }
+                        // New section, e.g. of concatenated captures: its interfaces are
+                        // numbered from 0 again, and its blocks may be of other byte order
+                        PcapBlockOwned::NG(Block::SectionHeader(shb)) => {
+                            self.big_endian = shb.big_endian();
+                            self.section_start = self.interfaces.len();
+                            self.drops.start_section();
+                        }
+                        PcapBlockOwned::NG(Block::InterfaceDescription(idb)) => {
+                            match Interface::from_idb(&idb) {
+                                Ok(interface) => self.interfaces.push(interface),
+                                Err(e) => {
+                                    self.error = Some(e);
+                                    return None;
+                                }
+                            }
+                        }
+                        PcapBlockOwned::NG(Block::NameResolution(nrb)) => {
+                            for (ip, name) in name_records(&nrb) {
+                                self.host_names.entry(ip).or_insert(name);
+                            }
+                        }
+                        PcapBlockOwned::NG(Block::InterfaceStatistics(isb)) => {
+                            self.drops.add_statistics(&isb, self.big_endian);
+                        }
+                        PcapBlockOwned::NG(Block::EnhancedPacket(epb)) => {
+                            self.drops.add_packet(&epb, self.big_endian);
+                            let identified = read_epb(
+                                &self.interfaces[self.section_start..],
+                                &self.filter,
+                                &self.options,
+                                self.big_endian,
+                                match self.ts_source {
+                                    TsSource::Hardware => self.hardware_ts_pen,
+                                    TsSource::Software => None,
+                                },
+                                &mut self.hardware_timestamps,
+                                &epb,
+                                &mut self.reassembler,
+                                &mut self.pending,
+                            );
+                            if !identified {
+                                self.unidentified += 1;
+                            }
+                        }
+                        PcapBlockOwned::NG(_) => {}
+                    }
+                    reader.consume(offset);
+                    if let Some(packet) = self.pending.pop() {
+                        return Some(packet);
+                    }
+                }
+                Err(PcapError::Eof) => {
+                    if !(waiting && reader.reader_exhausted()) || stopped() {
+                        return None;
+                    }
+                    if let Err(e) = PcapReader::wait_for_data(reader) {
+                        self.error = Some(e);
+                        return None;
+                    }
+                }
+                Err(PcapError::Incomplete) => {
+                    if reader.reader_exhausted() {
+                        if waiting && stopped() {
+                            return None;
+                        }
+                        if waiting {
+                            if let Err(e) = PcapReader::wait_for_data(reader) {
+                                self.error = Some(e);
+                                return None;
+                            }
+                            continue;
+                        }
+                        self.error = Some(Error::Truncated);
+                        return None;
+                    }
+                    if let Err(e) = reader.refill() {
+                        self.error = Some(Error::Parse(e.to_string()));
+                        return None;
+                    }
+                }
+                Err(e) => {
+                    self.error = Some(Error::Parse(e.to_string()));
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for PcapReader {
+    type Item = (PacketId, PacketTime);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (packet, fallback_key, info) = self.next_packet()?;
+        self.fallback_key = fallback_key;
+        self.info = info;
+        Some(packet)
+    }
+}
@@ -0,0 +1,277 @@
+//! Reassembly of TCP byte streams into application messages.
+//!
+//! Every flow (direction of a connection) is reassembled in sequence number order: data which
+//! was already seen (retransmissions) is skipped, and segments ahead of a gap are kept until
+//! the gap is filled. The stream is cut into messages by `Framing`. A message gets identity of
+//! its length and hash, and time of the segment which completes it. The first segment seen
+//! of a flow (or its SYN) is assumed to start a message. HTTP exchanges are cut by `http`
+
+use crate::exchange::Exchange;
+use crate::http::HttpExchanges;
+use crate::packet::{self, Decap, PacketId, ParseOptions, Protocol, TcpMatchMode, TcpSegment};
+use crate::time::PacketTime;
+use pcap_parser::Linktype;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::ops::Range;
+
+/// Segments ahead of a gap kept per flow. If the gap isn't filled before (e.g. the segment
+/// wasn't captured), the flow is restarted at the next segment
+const MAX_PENDING_SEGMENTS: usize = 64;
+
+/// Longest message, the flow is restarted if a message exceeds it (e.g. framing is wrong)
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// How application messages are delimited in the byte stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Framing {
+    /// Big-endian length of the rest of the message in the first 1, 2 or 4 bytes
+    LengthPrefix(usize),
+    /// Message ends with these bytes
+    Delimiter(Vec<u8>),
+}
+
+impl Framing {
+    /// Length of the first message of `bytes`, `None` if it's not complete yet
+    fn message_len(&self, bytes: &[u8]) -> Option<usize> {
+        match self {
+            Self::LengthPrefix(prefix_len) => {
+                let prefix = bytes.get(..*prefix_len)?;
+                let len = prefix
+                    .iter()
+                    .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+                Some(prefix_len + len)
+            }
+            Self::Delimiter(delimiter) => bytes
+                .windows(delimiter.len())
+                .position(|window| window == delimiter.as_slice())
+                .map(|position| position + delimiter.len()),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Clone, Copy)]
+pub(crate) struct FlowKey {
+    pub ip_src: IpAddr,
+    pub ip_dst: IpAddr,
+    pub port_src: u16,
+    pub port_dst: u16,
+}
+
+impl FlowKey {
+    pub fn of(segment: &TcpSegment) -> Self {
+        Self {
+            ip_src: segment.ip_src,
+            ip_dst: segment.ip_dst,
+            port_src: segment.port_src,
+            port_dst: segment.port_dst,
+        }
+    }
+
+    /// Key of the other direction of the connection
+    pub fn reversed(&self) -> Self {
+        Self {
+            ip_src: self.ip_dst,
+            ip_dst: self.ip_src,
+            port_src: self.port_dst,
+            port_dst: self.port_src,
+        }
+    }
+}
+
+pub(crate) struct Flow {
+    /// Sequence number of the next byte of the stream
+    next_seq: u32,
+    /// Bytes of the stream not cut into messages yet
+    pub buffer: Vec<u8>,
+    /// Segments ahead of `next_seq`
+    pending: Vec<(u32, Vec<u8>)>,
+}
+
+impl Flow {
+    pub fn new(next_seq: u32) -> Self {
+        Self {
+            next_seq,
+            buffer: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Appends part of segment which is new to the stream. Returns `false` if the segment is
+    /// ahead of the stream
+    fn append(&mut self, seq: u32, payload: &[u8]) -> bool {
+        let offset = seq.wrapping_sub(self.next_seq) as i32;
+        if offset > 0 {
+            return false;
+        }
+        if let Some(new) = payload.get(offset.unsigned_abs() as usize..) {
+            self.buffer.extend_from_slice(new);
+            self.next_seq = self.next_seq.wrapping_add(new.len() as u32);
+        }
+        true
+    }
+
+    /// Adds payload of segment to the stream, together with pending segments which follow it.
+    /// Returns `false` if the flow lost track of the stream
+    pub fn add_segment(&mut self, seq: u32, segment: &TcpSegment) -> bool {
+        if segment.payload.is_empty() {
+            return true;
+        }
+        if !self.append(seq, segment.payload) {
+            self.pending.push((seq, segment.payload.to_vec()));
+            return self.pending.len() <= MAX_PENDING_SEGMENTS;
+        }
+        while let Some(position) = self
+            .pending
+            .iter()
+            .position(|(seq, _)| seq.wrapping_sub(self.next_seq) as i32 <= 0)
+        {
+            let (seq, payload) = self.pending.swap_remove(position);
+            self.append(seq, &payload);
+        }
+        true
+    }
+}
+
+/// Builds identities of application messages from TCP segments of all flows
+pub struct Reassembler {
+    /// Encapsulation stripped from frames before reassembly
+    decap: Option<Decap>,
+    streams: Streams,
+}
+
+enum Streams {
+    /// Messages cut by framing
+    Messages {
+        framing: Framing,
+        /// Window of message which is hashed, the whole message if `None`
+        hash_range: Option<Range<usize>>,
+        flows: HashMap<FlowKey, Flow>,
+    },
+    /// Requests or responses of HTTP exchanges
+    Http(HttpExchanges),
+}
+
+impl Reassembler {
+    pub fn new(framing: Framing, hash_range: Option<Range<usize>>) -> Self {
+        Self {
+            decap: None,
+            streams: Streams::Messages {
+                framing,
+                hash_range,
+                flows: HashMap::new(),
+            },
+        }
+    }
+
+    /// Reassembler of HTTP requests or responses of exchange, see `http`
+    pub fn http(exchange: Exchange) -> Self {
+        Self {
+            decap: None,
+            streams: Streams::Http(HttpExchanges::new(exchange)),
+        }
+    }
+
+    /// Reassembler for `TcpMatchMode::Message` or HTTP exchanges, `None` for other modes, if
+    /// TCP is excluded or identities are of other request/response exchanges
+    pub fn for_options(options: &ParseOptions) -> Option<Self> {
+        if !options.protocols.is_empty() && !options.protocols.contains(&Protocol::Tcp) {
+            return None;
+        }
+        let reassembler = match (options.exchange, options.tcp_match_mode) {
+            (Some(exchange), _) if exchange.http => Self::http(exchange),
+            (Some(_), _) => return None,
+            (None, TcpMatchMode::Message) => {
+                Self::new(options.message_framing.clone()?, options.hash_range.clone())
+            }
+            (None, _) => return None,
+        };
+        Some(Self {
+            decap: options.decap,
+            ..reassembler
+        })
+    }
+
+    /// Adds frame to its flow, appends to `messages` identities of messages it completes.
+    /// Returns `false` if the frame is not a TCP segment
+    pub fn push(
+        &mut self,
+        link_type: Linktype,
+        bytes: &[u8],
+        time: PacketTime,
+        messages: &mut VecDeque<(PacketId, PacketTime)>,
+    ) -> bool {
+        let (link_type, bytes, _) = packet::decapsulate(link_type, bytes, self.decap);
+        let Some(segment) = packet::tcp_segment(link_type, bytes) else {
+            return false;
+        };
+        match &mut self.streams {
+            Streams::Messages {
+                framing,
+                hash_range,
+                flows,
+            } => push_message_segment(framing, hash_range, flows, &segment, time, messages),
+            Streams::Http(exchanges) => exchanges.push(link_type, bytes, &segment, time, messages),
+        }
+        true
+    }
+}
+
+/// Adds segment to its flow, appends to `messages` identities of messages it completes
+fn push_message_segment(
+    framing: &Framing,
+    hash_range: &Option<Range<usize>>,
+    flows: &mut HashMap<FlowKey, Flow>,
+    segment: &TcpSegment,
+    time: PacketTime,
+    messages: &mut VecDeque<(PacketId, PacketTime)>,
+) {
+    let key = FlowKey::of(segment);
+    // SYN takes one sequence number before the data
+    let seq = segment.seq.wrapping_add(segment.syn as u32);
+    if segment.syn {
+        flows.insert(key, Flow::new(seq));
+    }
+    let flow = flows.entry(key).or_insert_with(|| Flow::new(seq));
+    if !flow.add_segment(seq, segment) {
+        flows.remove(&key);
+        return;
+    }
+    let mut too_long = false;
+    while let Some(len) = framing.message_len(&flow.buffer) {
+        if len > MAX_MESSAGE_LEN {
+            too_long = true;
+            break;
+        }
+        if flow.buffer.len() < len {
+            break;
+        }
+        let message: Vec<u8> = flow.buffer.drain(..len).collect();
+        if let Some(id) = message_id(&key, &message, hash_range) {
+            messages.push_back((id, time));
+        }
+    }
+    if segment.end || too_long || flow.buffer.len() > MAX_MESSAGE_LEN {
+        flows.remove(&key);
+    }
+}
+
+/// Identity of message, `None` if it doesn't cover the hashed window
+fn message_id(
+    key: &FlowKey,
+    message: &[u8],
+    hash_range: &Option<Range<usize>>,
+) -> Option<PacketId> {
+    let message = match hash_range {
+        Some(range) => message.get(range.clone())?,
+        None => message,
+    };
+    Some(PacketId::TcpMessage {
+        ip_src: key.ip_src,
+        ip_dst: key.ip_dst,
+        port_src: key.port_src,
+        port_dst: key.port_dst,
+        message_len: message.len() as u32,
+        message_hash: packet::fnv1a(message),
+    })
+}
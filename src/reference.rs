@@ -0,0 +1,68 @@
+//! Reader of reference logs: expected packet identities with timestamps, e.g. the schedule of
+//! a traffic generator, which take place of a capture.
+//!
+//! A log is JSON lines, one packet per line: `time_nsec` (nanoseconds since the epoch), `kind`
+//! of identity (`tcp`, `tcp_payload`, `tcp_message`, `icmp`, `rtp`, `tcp_exchange`,
+//! `http_exchange`, `icmp_echo`, `neighbor_discovery`, `dns`, `flagged` or `geneve`) and the
+//! fields of `PacketId` of that kind. Empty lines and lines starting with `#` are skipped
+
+use crate::error::{Error, Result};
+use crate::packet::PacketId;
+use crate::time::PacketTime;
+use serde::{Deserialize, Serialize};
+use std::io::BufRead;
+
+/// Line of reference log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub time_nsec: u64,
+    #[serde(flatten)]
+    pub id: PacketId,
+}
+
+/// Checks that bytes look like start of reference log rather than of a capture
+pub fn looks_like_reference(bytes: &[u8]) -> bool {
+    matches!(
+        bytes.iter().find(|byte| !byte.is_ascii_whitespace()),
+        Some(b'{' | b'#')
+    )
+}
+
+pub struct ReferenceReader<R> {
+    stream: R,
+    line: String,
+    line_number: usize,
+}
+
+impl<R: BufRead> ReferenceReader<R> {
+    pub fn new(stream: R) -> Self {
+        Self {
+            stream,
+            line: String::new(),
+            line_number: 0,
+        }
+    }
+
+    /// Next identity of the log, `None` at the end of it
+    pub fn next_entry(&mut self) -> Result<Option<(PacketId, PacketTime)>> {
+        loop {
+            self.line.clear();
+            if self.stream.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            let line = self.line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(line)
+                .map_err(|e| Error::BadReference(format!("line {}: {}", self.line_number, e)))?;
+            return Ok(Some((
+                entry.id,
+                PacketTime {
+                    nsec: entry.time_nsec,
+                },
+            )));
+        }
+    }
+}
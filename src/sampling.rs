@@ -0,0 +1,47 @@
+//! Deterministic randomized sampling. The same seed gives the same selection on every run and
+//! every platform
+
+/// SplitMix64 pseudo-random generator. Its output depends only on the seed, unlike generators
+/// of external crates which may change between versions
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Selects each packet independently with given probability
+pub struct Sampler {
+    rng: Rng,
+    rate: f64,
+}
+
+impl Sampler {
+    /// `rate` in [0, 1]: probability of a packet to be selected
+    pub fn new(rate: f64, seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            rate,
+        }
+    }
+
+    pub fn sample(&mut self) -> bool {
+        self.rate >= 1.0 || self.rng.next_f64() < self.rate
+    }
+}
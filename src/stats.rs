@@ -0,0 +1,212 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// Exponentially weighted moving average
+pub struct Ewma {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ewma {
+    /// `alpha` in (0, 1]: weight of the newest sample
+    pub fn new(alpha: f64) -> Self {
+        Self { alpha, value: None }
+    }
+
+    pub fn update(&mut self, sample: f64) {
+        self.value = Some(match self.value {
+            Some(value) => value + self.alpha * (sample - value),
+            None => sample,
+        });
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+/// Change of `Alert` state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertChange {
+    Raised,
+    Cleared,
+}
+
+/// Alert on a value checked once per interval, with hysteresis so that a value hovering around
+/// the threshold doesn't flap it: raised after the value is above `raise` for `intervals`
+/// checks in a row, cleared after it's below `clear` (at most `raise`) for as many
+pub struct Alert {
+    raise: f64,
+    clear: f64,
+    intervals: u32,
+    active: bool,
+    /// Checks in a row which count towards the change of state
+    streak: u32,
+}
+
+impl Alert {
+    pub fn new(raise: f64, clear: f64, intervals: u32) -> Self {
+        Self {
+            raise,
+            clear,
+            intervals: intervals.max(1),
+            active: false,
+            streak: 0,
+        }
+    }
+
+    /// Checks value of an interval, `None` (no value yet) breaks the streak
+    pub fn update(&mut self, value: Option<f64>) -> Option<AlertChange> {
+        let towards_change = value.is_some_and(|value| match self.active {
+            true => value < self.clear,
+            false => value > self.raise,
+        });
+        if !towards_change {
+            self.streak = 0;
+            return None;
+        }
+        self.streak += 1;
+        if self.streak < self.intervals {
+            return None;
+        }
+        self.streak = 0;
+        self.active = !self.active;
+        Some(match self.active {
+            true => AlertChange::Raised,
+            false => AlertChange::Cleared,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+/// Interarrival jitter of RFC 3550 (section 6.4.1): smoothed mean deviation of the difference
+/// between latencies of consecutive packets, `J += (|D| - J) / 16`
+#[derive(Default)]
+pub struct InterarrivalJitter {
+    previous: Option<i64>,
+    jitter: f64,
+}
+
+impl InterarrivalJitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, latency: i64) {
+        if let Some(previous) = self.previous {
+            let d = (latency - previous).abs() as f64;
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.previous = Some(latency);
+    }
+
+    pub fn value(&self) -> f64 {
+        self.jitter
+    }
+}
+
+/// Cumulative counts of matched and inbound packets, turned into the counts since the previous
+/// update for rates over the last interval
+#[derive(Default)]
+pub struct MatchRate {
+    matched: u64,
+    packets: u64,
+}
+
+impl MatchRate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matched and inbound packets since the previous update, given the counts so far
+    pub fn update(&mut self, matched: u64, packets: u64) -> (u64, u64) {
+        let since = (matched - self.matched, packets - self.packets);
+        self.matched = matched;
+        self.packets = packets;
+        since
+    }
+}
+
+/// Last `capacity` latencies, for quantiles over recent traffic
+pub struct SlidingWindow {
+    capacity: usize,
+    values: VecDeque<i64>,
+}
+
+impl SlidingWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            values: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, value: i64) {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    /// Nearest-rank percentile, `p` in [0, 100]
+    pub fn percentile(&self, p: f64) -> Option<i64> {
+        let mut sorted: Vec<i64> = self.values.iter().copied().collect();
+        sorted.sort_unstable();
+        percentile_of_sorted(&sorted, p)
+    }
+}
+
+/// The `capacity` greatest items pushed. Items are totally ordered, so the ones kept among equal
+/// keys don't depend on the order they come in, only on the rest of their order
+pub struct Top<T> {
+    capacity: usize,
+    /// Least item on top, to be dropped first
+    items: BinaryHeap<Reverse<T>>,
+}
+
+impl<T: Ord> Top<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            items: BinaryHeap::with_capacity(capacity + 1),
+        }
+    }
+
+    /// Least item kept if no more fit, so items less than it can be skipped without building
+    /// them
+    pub fn threshold(&self) -> Option<&T> {
+        match self.items.len() >= self.capacity {
+            true => self.items.peek().map(|Reverse(item)| item),
+            false => None,
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.items.push(Reverse(item));
+        if self.items.len() > self.capacity {
+            self.items.pop();
+        }
+    }
+
+    /// Items kept, the greatest first
+    pub fn into_sorted(self) -> Vec<T> {
+        let mut items: Vec<T> = self.items.into_iter().map(|Reverse(item)| item).collect();
+        items.sort_unstable_by(|a, b| b.cmp(a));
+        items
+    }
+}
+
+/// Nearest-rank percentile of sorted values, `p` in [0, 100]
+pub fn percentile_of_sorted(sorted: &[i64], p: f64) -> Option<i64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
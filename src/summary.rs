@@ -0,0 +1,786 @@
+//! Summary of a run and merging of summaries of several runs.
+//!
+//! Counts, sums, minimum, maximum and average latency of a merged summary are exact. RFC 3550
+//! jitter and percentiles can't be merged exactly without the raw latencies, so they are
+//! averaged over runs weighted by number of matches, and the summary is marked approximate.
+//! A weighted average of percentiles always lies between the smallest and the largest
+//! percentile of the runs, but may differ from the percentile of all latencies taken together.
+//!
+//! JSON layout is versioned by `schema_version`, see `SCHEMA_VERSION` and `json_schema`
+
+use crate::error::{Error, Result};
+use crate::stats::percentile_of_sorted;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Version of JSON layout of `Summary`. It is bumped on every change which can break
+/// consumers: removal or renaming of a field, change of its type or meaning. Adding a field
+/// doesn't bump the version
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Matches which must all have the same nonzero latency for the text summary to warn about it.
+/// A few equal latencies are plausible with coarse timestamps
+const CONSTANT_LATENCY_WARNING_MATCHES: u64 = 10;
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Percentiles {
+    pub p50: Option<i64>,
+    pub p90: Option<i64>,
+    pub p99: Option<i64>,
+}
+
+impl Percentiles {
+    /// Exact percentiles of absolute latencies
+    pub fn of(latencies: &[i64]) -> Self {
+        let mut sorted: Vec<i64> = latencies.iter().map(|latency| latency.abs()).collect();
+        sorted.sort_unstable();
+        Self {
+            p50: percentile_of_sorted(&sorted, 50.0),
+            p90: percentile_of_sorted(&sorted, 90.0),
+            p99: percentile_of_sorted(&sorted, 99.0),
+        }
+    }
+}
+
+/// Latencies of exact matches before correction by a known clock offset between the captures
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+pub struct Uncorrected {
+    /// Offset subtracted from every latency: how far the outbound clock is ahead of the inbound
+    /// one (nsec)
+    pub clock_offset_nsec: i64,
+    /// Sum of latencies
+    pub latency_sum: i64,
+    pub percentiles: Percentiles,
+}
+
+/// Inbound packets of one protocol and what became of them
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProtocolCounts {
+    pub packets: u64,
+    /// Matches of any kind: exact, by fallback key and fuzzy
+    pub matches: u64,
+    pub misses: u64,
+}
+
+/// Side of measurement a file was captured at
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSide {
+    Inbound,
+    Outbound,
+}
+
+/// Provenance of an input file of a run, as read from its headers and packets
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct FileMetadata {
+    pub side: FileSide,
+    /// Path as given on the command line
+    pub path: String,
+    /// Size in bytes, `None` if the file is not a regular one (e.g. a pipe)
+    pub size_bytes: Option<u64>,
+    /// `pcap`, `pcapng`, `erf` or `reference`
+    pub format: String,
+    /// Link-layer types of interfaces in order of their index, e.g. `ETHERNET`. Empty for ERF
+    /// files and reference logs, whose records carry their own
+    pub link_types: Vec<String>,
+    /// Bytes captured of a frame at most, if limited and known
+    pub snaplen: Option<u32>,
+    /// Timestamp resolution (of the coarsest interface) in nanoseconds
+    pub timestamp_resolution_nsec: u64,
+    /// Frames read which passed the byte filter. Fewer than in the file if the run stopped early
+    pub packets: u64,
+    /// Time of the earliest of `packets` (nsec since the epoch)
+    pub first_packet_nsec: Option<u64>,
+    /// Time of the latest of `packets`
+    pub last_packet_nsec: Option<u64>,
+}
+
+/// Totals of one or several runs. All latencies are absolute, in usec
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Summary {
+    /// Version of this layout, `SCHEMA_VERSION`. Summaries written before versioning have version 1
+    #[serde(default = "first_schema_version")]
+    pub schema_version: u32,
+    /// Number of runs this summary covers
+    pub runs: u64,
+    /// Inbound packets analyzed
+    pub packets: u64,
+    /// Inbound packets without identical outbound packet
+    pub misses: u64,
+    /// Exact matches
+    pub matches: u64,
+    /// Sum of latencies of exact matches
+    pub latency_sum: i64,
+    pub latency_min: Option<i64>,
+    pub latency_max: Option<i64>,
+    /// Interarrival jitter of RFC 3550
+    pub rfc3550_jitter: f64,
+    /// Percentiles of latencies of exact matches
+    pub percentiles: Percentiles,
+    /// Set if jitter and percentiles are estimated from several runs
+    pub approximate: bool,
+    /// Present if fuzzy matching was enabled
+    pub fuzzy_matches: Option<u64>,
+    /// Sum of latencies of fuzzy matches
+    pub fuzzy_latency_sum: i64,
+    /// Matches by the fallback key, included in `matches`. Present if `--fallback` was enabled
+    #[serde(default)]
+    pub fallback_matches: Option<u64>,
+    /// Outbound entries evicted from the table because of `--max-table-entries`
+    #[serde(default)]
+    pub evicted: u64,
+    /// Identities seen more than once on outbound interface (e.g. retransmissions)
+    #[serde(default)]
+    pub recurring_identities: u64,
+    /// Packets the capturing tool reported as dropped in the inbound file, if the file carries
+    /// drop counts (pcapng interface statistics or packet options)
+    #[serde(default)]
+    pub capture_drops_in: Option<u64>,
+    /// Same for the outbound file
+    #[serde(default)]
+    pub capture_drops_out: Option<u64>,
+    /// Packets of the inbound file skipped by `--min-size` or `--max-size`. Present if either
+    /// was given
+    #[serde(default)]
+    pub size_excluded_in: Option<u64>,
+    /// Same for the outbound file
+    #[serde(default)]
+    pub size_excluded_out: Option<u64>,
+    /// TCP packets of the inbound file which are extra copies of a packet paired with an
+    /// outbound one, i.e. retransmitted between the captures. Present if `--retransmissions`
+    /// was given
+    #[serde(default)]
+    pub retransmissions_in: Option<u64>,
+    /// Same for the outbound file
+    #[serde(default)]
+    pub retransmissions_out: Option<u64>,
+    /// Exact matches of a packet reassembled from IPv4 fragments on one side only, i.e.
+    /// fragmented (or reassembled) between the captures. Present if `--defragment` was given
+    #[serde(default)]
+    pub defragmented_matches: Option<u64>,
+    /// Lookups of a packet whose identity had occurrences on the other side, but all of them
+    /// further apart in time than `--match-window`. Present if it was given
+    #[serde(default)]
+    pub window_rejections: Option<u64>,
+    /// Inbound packets, matches and misses by protocol of their identity, named as by
+    /// `list-protocols` (`tcp`, `icmp`, ...)
+    #[serde(default)]
+    pub protocols: BTreeMap<String, ProtocolCounts>,
+    /// Packets of the inbound file whose time was taken from a pcapng hardware timestamp option,
+    /// the others have software timestamps
+    #[serde(default)]
+    pub hardware_timestamps_in: u64,
+    /// Same for the outbound file
+    #[serde(default)]
+    pub hardware_timestamps_out: u64,
+    /// Snapshot length of the inbound file (bytes captured of a frame at most), if it's limited
+    /// and known. Kept by merge only if all runs have the same one
+    #[serde(default)]
+    pub snaplen_in: Option<u32>,
+    /// Same for the outbound file
+    #[serde(default)]
+    pub snaplen_out: Option<u32>,
+    /// Present if latencies are corrected by a clock offset, all other latencies are corrected
+    #[serde(default)]
+    pub uncorrected: Option<Uncorrected>,
+    /// Input files of every run, inbound one first
+    #[serde(default)]
+    pub files: Vec<FileMetadata>,
+    /// Errors which stopped reading a capture in the middle with `--on-read-error partial`,
+    /// as `<path>: <error>`. If any, counts are of packets read before them
+    #[serde(default)]
+    pub incomplete: Vec<String>,
+    /// Set if latencies weren't computed (`--count-only`), so only counts are meaningful. Kept
+    /// by merge only if every run is such
+    #[serde(default)]
+    pub count_only: bool,
+}
+
+fn first_schema_version() -> u32 {
+    1
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            runs: 0,
+            packets: 0,
+            misses: 0,
+            matches: 0,
+            latency_sum: 0,
+            latency_min: None,
+            latency_max: None,
+            rfc3550_jitter: 0.0,
+            percentiles: Percentiles::default(),
+            approximate: false,
+            fuzzy_matches: None,
+            fuzzy_latency_sum: 0,
+            fallback_matches: None,
+            evicted: 0,
+            recurring_identities: 0,
+            capture_drops_in: None,
+            capture_drops_out: None,
+            size_excluded_in: None,
+            size_excluded_out: None,
+            retransmissions_in: None,
+            retransmissions_out: None,
+            defragmented_matches: None,
+            window_rejections: None,
+            protocols: BTreeMap::new(),
+            hardware_timestamps_in: 0,
+            hardware_timestamps_out: 0,
+            snaplen_in: None,
+            snaplen_out: None,
+            uncorrected: None,
+            files: Vec::new(),
+            incomplete: Vec::new(),
+            count_only: false,
+        }
+    }
+}
+
+/// JSON Schema document of `Summary`
+pub fn json_schema() -> String {
+    let schema = schemars::schema_for!(Summary);
+    serde_json::to_string_pretty(&schema).expect("schema is always serializable")
+}
+
+impl Summary {
+    pub fn average_latency(&self) -> i64 {
+        if self.matches > 0 {
+            self.latency_sum / self.matches as i64
+        } else {
+            0
+        }
+    }
+
+    /// Range of latencies (max minus min)
+    pub fn jitter(&self) -> i64 {
+        match (self.latency_min, self.latency_max) {
+            (Some(min), Some(max)) => max - min,
+            _ => 0,
+        }
+    }
+
+    pub fn average_fuzzy_latency(&self) -> i64 {
+        match self.fuzzy_matches {
+            Some(count) if count > 0 => self.fuzzy_latency_sum / count as i64,
+            _ => 0,
+        }
+    }
+
+    /// Misses per inbound packet, 0 if there are no packets
+    pub fn miss_percentage(&self) -> f64 {
+        match self.packets {
+            0 => 0.0,
+            packets => self.misses as f64 / packets as f64 * 100f64,
+        }
+    }
+
+    /// Retransmissions between the captures (of both files) per inbound packet, `None` unless
+    /// they were counted
+    pub fn retransmission_percentage(&self) -> Option<f64> {
+        let count = self.retransmissions_in? + self.retransmissions_out?;
+        Some(match self.packets {
+            0 => 0.0,
+            packets => count as f64 / packets as f64 * 100f64,
+        })
+    }
+
+    /// Parses summary, rejects other versions of the layout
+    pub fn from_json(json: &str) -> Result<Self> {
+        let bad_summary = |e: serde_json::Error| Error::BadSummary(e.to_string());
+        // Version is checked first, fields of other versions may not parse
+        let value: serde_json::Value = serde_json::from_str(json).map_err(bad_summary)?;
+        let version = match value.get("schema_version") {
+            Some(version) => version.as_u64(),
+            None => Some(first_schema_version() as u64),
+        };
+        if version != Some(SCHEMA_VERSION as u64) {
+            return Err(Error::BadSummary(format!(
+                "unsupported schema version {} (expected {})",
+                value["schema_version"], SCHEMA_VERSION
+            )));
+        }
+        serde_json::from_value(value).map_err(bad_summary)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("summary is always serializable")
+    }
+
+    /// Combines summaries of several runs, see module documentation for semantics
+    pub fn merge(summaries: &[Summary]) -> Summary {
+        let mut merged = Summary::default();
+        for summary in summaries {
+            merged.runs += summary.runs;
+            merged.packets += summary.packets;
+            merged.misses += summary.misses;
+            merged.matches += summary.matches;
+            merged.latency_sum += summary.latency_sum;
+            merged.latency_min = combine_options(merged.latency_min, summary.latency_min, i64::min);
+            merged.latency_max = combine_options(merged.latency_max, summary.latency_max, i64::max);
+            merged.approximate |= summary.approximate;
+            merged.fuzzy_matches =
+                combine_options(merged.fuzzy_matches, summary.fuzzy_matches, |a, b| a + b);
+            merged.fuzzy_latency_sum += summary.fuzzy_latency_sum;
+            merged.fallback_matches =
+                combine_options(merged.fallback_matches, summary.fallback_matches, |a, b| {
+                    a + b
+                });
+            merged.evicted += summary.evicted;
+            merged.recurring_identities += summary.recurring_identities;
+            merged.capture_drops_in =
+                combine_options(merged.capture_drops_in, summary.capture_drops_in, |a, b| {
+                    a + b
+                });
+            merged.capture_drops_out = combine_options(
+                merged.capture_drops_out,
+                summary.capture_drops_out,
+                |a, b| a + b,
+            );
+            merged.size_excluded_in =
+                combine_options(merged.size_excluded_in, summary.size_excluded_in, |a, b| {
+                    a + b
+                });
+            merged.size_excluded_out = combine_options(
+                merged.size_excluded_out,
+                summary.size_excluded_out,
+                |a, b| a + b,
+            );
+            merged.retransmissions_in = combine_options(
+                merged.retransmissions_in,
+                summary.retransmissions_in,
+                |a, b| a + b,
+            );
+            merged.retransmissions_out = combine_options(
+                merged.retransmissions_out,
+                summary.retransmissions_out,
+                |a, b| a + b,
+            );
+            merged.defragmented_matches = combine_options(
+                merged.defragmented_matches,
+                summary.defragmented_matches,
+                |a, b| a + b,
+            );
+            merged.window_rejections = combine_options(
+                merged.window_rejections,
+                summary.window_rejections,
+                |a, b| a + b,
+            );
+            for (protocol, counts) in &summary.protocols {
+                let merged = merged.protocols.entry(protocol.clone()).or_default();
+                merged.packets += counts.packets;
+                merged.matches += counts.matches;
+                merged.misses += counts.misses;
+            }
+        }
+        merged.files = summaries
+            .iter()
+            .flat_map(|s| s.files.iter().cloned())
+            .collect();
+        merged.incomplete = summaries
+            .iter()
+            .flat_map(|s| s.incomplete.iter().cloned())
+            .collect();
+        merged.hardware_timestamps_in = summaries.iter().map(|s| s.hardware_timestamps_in).sum();
+        merged.hardware_timestamps_out = summaries.iter().map(|s| s.hardware_timestamps_out).sum();
+        let common = |snaplen: fn(&Summary) -> Option<u32>| {
+            let first = summaries.first().and_then(snaplen);
+            summaries
+                .iter()
+                .all(|s| snaplen(s) == first)
+                .then_some(first)
+                .flatten()
+        };
+        merged.count_only = !summaries.is_empty() && summaries.iter().all(|s| s.count_only);
+        merged.snaplen_in = common(|s| s.snaplen_in);
+        merged.snaplen_out = common(|s| s.snaplen_out);
+        let with_matches: Vec<&Summary> = summaries.iter().filter(|s| s.matches > 0).collect();
+        if with_matches.len() > 1 {
+            merged.approximate = true;
+        }
+        let weighted = |value: &dyn Fn(&Summary) -> Option<f64>| -> Option<f64> {
+            let mut sum = 0.0;
+            let mut weight = 0.0;
+            for summary in &with_matches {
+                let value = value(summary)?;
+                sum += value * summary.matches as f64;
+                weight += summary.matches as f64;
+            }
+            (weight > 0.0).then(|| sum / weight)
+        };
+        merged.rfc3550_jitter = weighted(&|s| Some(s.rfc3550_jitter)).unwrap_or(0.0);
+        merged.percentiles = Percentiles {
+            p50: weighted(&|s| s.percentiles.p50.map(|p| p as f64)).map(|p| p.round() as i64),
+            p90: weighted(&|s| s.percentiles.p90.map(|p| p as f64)).map(|p| p.round() as i64),
+            p99: weighted(&|s| s.percentiles.p99.map(|p| p as f64)).map(|p| p.round() as i64),
+        };
+        // Uncorrected latencies are merged only if every run was corrected by the same offset
+        let offsets: Vec<Option<i64>> = summaries
+            .iter()
+            .map(|s| s.uncorrected.map(|u| u.clock_offset_nsec))
+            .collect();
+        if let Some(Some(clock_offset_nsec)) = offsets.first().copied() {
+            if offsets
+                .iter()
+                .all(|offset| *offset == Some(clock_offset_nsec))
+            {
+                let raw = |s: &Summary| s.uncorrected.expect("every run is corrected");
+                let percentile = |p: &dyn Fn(Percentiles) -> Option<i64>| {
+                    weighted(&|s| p(raw(s).percentiles).map(|p| p as f64)).map(|p| p.round() as i64)
+                };
+                merged.uncorrected = Some(Uncorrected {
+                    clock_offset_nsec,
+                    latency_sum: summaries.iter().map(|s| raw(s).latency_sum).sum(),
+                    percentiles: Percentiles {
+                        p50: percentile(&|p| p.p50),
+                        p90: percentile(&|p| p.p90),
+                        p99: percentile(&|p| p.p99),
+                    },
+                });
+            }
+        }
+        merged
+    }
+}
+
+/// Combines two optional values, taking the present one if the other is missing
+fn combine_options<T>(a: Option<T>, b: Option<T>, combine: impl Fn(T, T) -> T) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(combine(a, b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl Summary {
+    /// Text form with thousands separators and units of latencies, e.g. `1,234,567` packets
+    /// and `1.52 ms`
+    pub fn human(&self) -> Human<'_> {
+        Human(self)
+    }
+
+    fn write_text(&self, f: &mut fmt::Formatter, numbers: Numbers) -> fmt::Result {
+        if self.count_only {
+            self.write_counts(f, numbers)?;
+            return self.write_incomplete(f);
+        }
+        let approximate = if self.approximate { "~" } else { "" };
+        let latency = |value: i64| Some(value as f64);
+        write!(
+            f,
+            "{}. {}. {}. {}. Packets count: {}. Misses count: {} ({})",
+            numbers.latency("Average latency", "", latency(self.average_latency()), 0),
+            numbers.latency(
+                "Median latency",
+                approximate,
+                self.percentiles.p50.and_then(latency),
+                0
+            ),
+            numbers.latency("Jitter", "", latency(self.jitter()), 0),
+            numbers.latency("RFC 3550 jitter", approximate, Some(self.rfc3550_jitter), 1),
+            numbers.count(self.packets),
+            numbers.count(self.misses),
+            numbers.percentage(self.miss_percentage())
+        )?;
+        if self.runs > 1 {
+            let percentile = |value: Option<i64>| {
+                format!(
+                    "{}{}",
+                    approximate,
+                    numbers.value(value.and_then(latency), 0)
+                )
+            };
+            write!(
+                f,
+                "\nRuns merged: {}. Percentiles of latency ({}weighted average over runs): p50 {}, p90 {}, p99 {}",
+                numbers.count(self.runs),
+                if numbers.human { "" } else { "usec, " },
+                percentile(self.percentiles.p50),
+                percentile(self.percentiles.p90),
+                percentile(self.percentiles.p99)
+            )?;
+        }
+        if let Some(fuzzy_matches) = self.fuzzy_matches {
+            write!(
+                f,
+                "\nFuzzy matches count: {}. {}",
+                numbers.count(fuzzy_matches),
+                numbers.latency(
+                    "Average fuzzy latency",
+                    "",
+                    latency(self.average_fuzzy_latency()),
+                    0
+                )
+            )?;
+        }
+        if let Some(fallback_matches) = self.fallback_matches {
+            write!(
+                f,
+                "\nMatches by index: exact {}, fallback {}",
+                numbers.count(self.matches - fallback_matches),
+                numbers.count(fallback_matches)
+            )?;
+        }
+        self.write_protocols(f, numbers)?;
+        if self.evicted > 0 {
+            write!(
+                f,
+                "\nEvicted outbound entries (may be counted as misses): {}",
+                numbers.count(self.evicted)
+            )?;
+        }
+        // Real latencies vary at least by timestamp noise
+        match (self.latency_min, self.latency_max) {
+            (Some(0), Some(0)) => write!(
+                f,
+                "\nWARNING: every matched latency is 0. The same capture was probably given twice, or the captures are identical"
+            )?,
+            (Some(min), Some(max))
+                if min == max && self.matches >= CONSTANT_LATENCY_WARNING_MATCHES =>
+            {
+                write!(
+                    f,
+                    "\nWARNING: every matched latency is {}. The captures are probably copies of each other shifted in time",
+                    numbers.value(Some(min as f64), 0)
+                )?
+            }
+            _ => {}
+        }
+        if self.capture_drops_in.unwrap_or(0) + self.capture_drops_out.unwrap_or(0) > 0 {
+            let drops = |count: Option<u64>| count.map_or("-".to_string(), |c| numbers.count(c));
+            write!(
+                f,
+                "\nWARNING: packets dropped by the capturing tool: in {}, out {}. Some misses may be capture loss rather than network loss",
+                drops(self.capture_drops_in),
+                drops(self.capture_drops_out)
+            )?;
+        }
+        if let (Some(excluded_in), Some(excluded_out)) =
+            (self.size_excluded_in, self.size_excluded_out)
+        {
+            write!(
+                f,
+                "\nExcluded by size: in {}, out {}",
+                numbers.count(excluded_in),
+                numbers.count(excluded_out)
+            )?;
+        }
+        if let (Some(retransmissions_in), Some(retransmissions_out), Some(percentage)) = (
+            self.retransmissions_in,
+            self.retransmissions_out,
+            self.retransmission_percentage(),
+        ) {
+            write!(
+                f,
+                "\nRetransmissions between the captures: in {}, out {} ({} of inbound packets)",
+                numbers.count(retransmissions_in),
+                numbers.count(retransmissions_out),
+                numbers.percentage(percentage)
+            )?;
+        }
+        if let Some(defragmented_matches) = self.defragmented_matches {
+            write!(
+                f,
+                "\nMatches across fragmentation: {}",
+                numbers.count(defragmented_matches)
+            )?;
+        }
+        if let Some(window_rejections) = self.window_rejections {
+            write!(
+                f,
+                "\nRejected by match window: {}",
+                numbers.count(window_rejections)
+            )?;
+        }
+        if self.hardware_timestamps_in + self.hardware_timestamps_out > 0 {
+            let source = |count: u64| match count {
+                0 => "software".to_string(),
+                count => format!("hardware ({} packets)", numbers.count(count)),
+            };
+            write!(
+                f,
+                "\nTimestamp source: in {}, out {}",
+                source(self.hardware_timestamps_in),
+                source(self.hardware_timestamps_out)
+            )?;
+        }
+        if let (Some(snaplen_in), Some(snaplen_out)) = (self.snaplen_in, self.snaplen_out) {
+            if snaplen_in != snaplen_out {
+                write!(
+                    f,
+                    "\nSnapshot length (bytes): in {}, out {}",
+                    numbers.count(snaplen_in as u64),
+                    numbers.count(snaplen_out as u64)
+                )?;
+            }
+        }
+        if let Some(uncorrected) = &self.uncorrected {
+            let average = (self.matches > 0).then(|| uncorrected.latency_sum / self.matches as i64);
+            write!(
+                f,
+                "\nClock offset (nsec) subtracted from latencies: {}. Uncorrected: {}. {}",
+                uncorrected.clock_offset_nsec,
+                numbers.latency("average latency", "", average.and_then(latency), 0),
+                numbers.latency(
+                    "median latency",
+                    approximate,
+                    uncorrected.percentiles.p50.and_then(latency),
+                    0
+                )
+            )?;
+        }
+        if self.recurring_identities > 0 {
+            write!(
+                f,
+                "\nRecurring outbound identities: {}",
+                numbers.count(self.recurring_identities)
+            )?;
+        }
+        self.write_incomplete(f)
+    }
+}
+
+impl Summary {
+    /// Misses by protocol, if there are packets of more than one. A single protocol's counts
+    /// are the totals
+    fn write_protocols(&self, f: &mut fmt::Formatter, numbers: Numbers) -> fmt::Result {
+        if self.protocols.len() < 2 {
+            return Ok(());
+        }
+        let protocols: Vec<String> = self
+            .protocols
+            .iter()
+            .map(|(protocol, counts)| {
+                format!(
+                    "{} {} of {} ({})",
+                    protocol,
+                    numbers.count(counts.misses),
+                    numbers.count(counts.packets),
+                    numbers.percentage(counts.misses as f64 / counts.packets as f64 * 100f64)
+                )
+            })
+            .collect();
+        write!(f, "\nMisses by protocol: {}", protocols.join(", "))
+    }
+
+    /// Notice that reading stopped early, with the errors
+    fn write_incomplete(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.incomplete.is_empty() {
+            return Ok(());
+        }
+        write!(
+            f,
+            "\nINCOMPLETE: statistics are of packets read before an error: {}",
+            self.incomplete.join("; ")
+        )
+    }
+
+    /// Text form of summary without latencies
+    fn write_counts(&self, f: &mut fmt::Formatter, numbers: Numbers) -> fmt::Result {
+        write!(
+            f,
+            "Packets count: {}. Matches count: {}. Misses count: {} ({})",
+            numbers.count(self.packets),
+            numbers.count(self.matches),
+            numbers.count(self.misses),
+            numbers.percentage(self.miss_percentage())
+        )?;
+        if let Some(fuzzy_matches) = self.fuzzy_matches {
+            write!(f, "\nFuzzy matches count: {}", numbers.count(fuzzy_matches))?;
+        }
+        if let Some(fallback_matches) = self.fallback_matches {
+            write!(
+                f,
+                "\nMatches by index: exact {}, fallback {}",
+                numbers.count(self.matches - fallback_matches),
+                numbers.count(fallback_matches)
+            )?;
+        }
+        self.write_protocols(f, numbers)?;
+        if self.evicted > 0 {
+            write!(
+                f,
+                "\nEvicted outbound entries (may be counted as misses): {}",
+                numbers.count(self.evicted)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_text(f, Numbers { human: false })
+    }
+}
+
+/// Human-readable text form of `Summary`
+pub struct Human<'a>(&'a Summary);
+
+impl fmt::Display for Human<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.write_text(f, Numbers { human: true })
+    }
+}
+
+/// Formatting of numbers of the text summary: plain, or human-readable
+#[derive(Clone, Copy)]
+struct Numbers {
+    human: bool,
+}
+
+impl Numbers {
+    /// `<name> (usec): <value>`, or `<name>: <value with unit>`
+    fn latency(self, name: &str, approximate: &str, usec: Option<f64>, decimals: usize) -> String {
+        let unit = if self.human { "" } else { " (usec)" };
+        format!(
+            "{}{}: {}{}",
+            name,
+            unit,
+            approximate,
+            self.value(usec, decimals)
+        )
+    }
+
+    /// Latency in usec, in human form scaled to the largest fitting unit
+    fn value(self, usec: Option<f64>, decimals: usize) -> String {
+        let Some(usec) = usec else {
+            return "-".to_string();
+        };
+        if !self.human {
+            return format!("{:.*}", decimals, usec);
+        }
+        match usec.abs() {
+            abs if abs < 1e3 => format!("{:.*} us", decimals, usec),
+            abs if abs < 1e6 => format!("{:.2} ms", usec / 1e3),
+            _ => format!("{:.2} s", usec / 1e6),
+        }
+    }
+
+    fn count(self, count: u64) -> String {
+        if !self.human {
+            return count.to_string();
+        }
+        let digits = count.to_string();
+        let mut grouped = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+
+    fn percentage(self, percentage: f64) -> String {
+        if self.human {
+            format!("{:.2}%", percentage)
+        } else {
+            format!("{}%", percentage)
+        }
+    }
+}
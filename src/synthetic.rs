@@ -0,0 +1,138 @@
+//! Synthetic captures with known latencies, for `selftest`: a deterministic generator of TCP
+//! segments and a writer of pcap files
+
+use crate::sampling::Rng;
+use crate::time::PacketTime;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+/// Magic number of pcap with nanosecond timestamps
+const PCAP_MAGIC_NSEC: u32 = 0xa1b2_3c4d;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+
+const SNAPLEN: u32 = 65535;
+
+/// Payload bytes of every generated segment
+pub const PAYLOAD_LEN: usize = 64;
+
+/// Writer of pcap files of Ethernet frames with nanosecond timestamps, in byte order of the
+/// host
+pub struct PcapWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(&PCAP_MAGIC_NSEC.to_ne_bytes())?;
+        out.write_all(&2u16.to_ne_bytes())?;
+        out.write_all(&4u16.to_ne_bytes())?;
+        out.write_all(&[0; 8])?;
+        out.write_all(&SNAPLEN.to_ne_bytes())?;
+        out.write_all(&LINKTYPE_ETHERNET.to_ne_bytes())?;
+        Ok(Self { out })
+    }
+
+    pub fn write(&mut self, time: PacketTime, frame: &[u8]) -> io::Result<()> {
+        let sec = (time.nsec / 1_000_000_000) as u32;
+        let nsec = (time.nsec % 1_000_000_000) as u32;
+        let len = frame.len() as u32;
+        for field in [sec, nsec, len, len] {
+            self.out.write_all(&field.to_ne_bytes())?;
+        }
+        self.out.write_all(frame)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Segment of both captures: its frame, time on the inbound side and the latency of the
+/// outbound copy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntheticPacket {
+    pub frame: Vec<u8>,
+    pub in_time: PacketTime,
+    pub latency_nsec: u64,
+}
+
+impl SyntheticPacket {
+    pub fn out_time(&self) -> PacketTime {
+        PacketTime {
+            nsec: self.in_time.nsec + self.latency_nsec,
+        }
+    }
+}
+
+/// Generator of TCP segments of several flows sent at a fixed interval, each delayed by a
+/// random number of whole microseconds within a range. Every segment has an identity of its
+/// own, and the same seed gives the same segments
+pub struct Generator {
+    rng: Rng,
+    /// Next sequence number of each flow
+    seqs: Vec<u32>,
+    next_time: PacketTime,
+    interval_nsec: u64,
+    latency_usec: RangeInclusive<u64>,
+}
+
+impl Generator {
+    pub fn new(
+        seed: u64,
+        flows: u16,
+        interval_nsec: u64,
+        latency_usec: RangeInclusive<u64>,
+    ) -> Self {
+        let mut rng = Rng::new(seed);
+        let seqs = (0..flows.max(1)).map(|_| rng.next_u64() as u32).collect();
+        Self {
+            rng,
+            seqs,
+            next_time: PacketTime {
+                nsec: 1_000_000_000_000_000_000,
+            },
+            interval_nsec,
+            latency_usec,
+        }
+    }
+
+    /// Ethernet frame of IPv4 TCP segment of flow: from `10.0.x.y:port` of the flow to
+    /// `10.1.0.1:80`
+    fn frame(flow: u16, seq: u32) -> Vec<u8> {
+        let [high, low] = flow.to_be_bytes();
+        let mut frame = vec![0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01, 0x08, 0x00];
+        let ip_len = (20 + 20 + PAYLOAD_LEN) as u16;
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&ip_len.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]);
+        frame.extend_from_slice(&[10, 0, high, low, 10, 1, 0, 1]);
+        frame.extend_from_slice(&(1024 + flow % 64000).to_be_bytes());
+        frame.extend_from_slice(&80u16.to_be_bytes());
+        frame.extend_from_slice(&seq.to_be_bytes());
+        frame.extend_from_slice(&1u32.to_be_bytes());
+        frame.extend_from_slice(&[0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+        frame.extend(std::iter::repeat_n(0x55, PAYLOAD_LEN));
+        frame
+    }
+}
+
+impl Iterator for Generator {
+    type Item = SyntheticPacket;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let flow = (self.rng.next_u64() % self.seqs.len() as u64) as usize;
+        let seq = self.seqs[flow];
+        self.seqs[flow] = seq.wrapping_add(PAYLOAD_LEN as u32);
+        let (low, high) = (*self.latency_usec.start(), *self.latency_usec.end());
+        let latency_usec = low + self.rng.next_u64() % (high - low + 1);
+        let in_time = self.next_time;
+        self.next_time.nsec += self.interval_nsec;
+        Some(SyntheticPacket {
+            frame: Self::frame(flow as u16, seq),
+            in_time,
+            latency_nsec: latency_usec * 1000,
+        })
+    }
+}
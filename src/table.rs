@@ -0,0 +1,413 @@
+use crate::packet::{FuzzyKey, PacketId};
+use crate::time::PacketTime;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Which outbound occurrence of a recurring identity (e.g. retransmission) an inbound packet is
+/// paired with
+#[derive(clap::ValueEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pairing {
+    /// The earliest one, so occurrences are paired in order
+    #[default]
+    First,
+    /// The latest one
+    Last,
+    /// The one with time nearest to the inbound packet
+    Nearest,
+}
+
+/// Why inbound packet found no outbound pair, inferred from the history of its identity in the
+/// table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MissReason {
+    /// No outbound packet had the identity: lost, changed on the way, or not captured
+    Absent,
+    /// Every outbound occurrence was already paired with an earlier inbound packet: a duplicate,
+    /// or another packet collided with the identity
+    Taken,
+    /// Outbound occurrences were evicted because of `max_entries`
+    Evicted,
+    /// Outbound occurrences are all further apart in time than `max_distance`
+    OutsideWindow,
+}
+
+impl MissReason {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Absent => "no outbound packet with identity",
+            Self::Taken => "pair taken by earlier inbound packet",
+            Self::Evicted => "pair evicted from table",
+            Self::OutsideWindow => "pair outside time window",
+        }
+    }
+}
+
+/// Table of packets seen on outbound interface, looked up by packets of inbound interface
+#[derive(Default)]
+pub struct OutboundTable {
+    /// Times of all occurrences of identity not taken yet, sorted
+    entries: HashMap<PacketId, Vec<PacketTime>>,
+    /// Number of occurrences in `entries`
+    occurrences: usize,
+    pairing: Pairing,
+    /// Secondary index for fuzzy matching: reduced key -> identities sharing it
+    fuzzy: Option<HashMap<FuzzyKey, Vec<PacketId>>>,
+    /// Secondary index for `take_fallback`: fallback key -> occurrences in insertion order.
+    /// Occurrences taken or evicted otherwise stay in it until looked up or compacted
+    fallback: Option<HashMap<FuzzyKey, VecDeque<(PacketId, PacketTime)>>>,
+    /// Number of occurrences in `fallback`, including the stale ones
+    fallback_len: usize,
+    /// Limit of occurrences, the oldest inserted ones are evicted beyond it
+    max_entries: Option<usize>,
+    /// Insertion order for eviction, kept only if the table is bounded. Occurrences which are
+    /// already taken are skipped on eviction, or dropped by compaction
+    order: VecDeque<(PacketId, PacketTime)>,
+    evicted: u64,
+    /// Identities inserted more than once
+    recurring: u64,
+    /// Insertions of identity which was already in the table
+    collisions: u64,
+    /// Why identities no longer in the table left it, kept only if miss reasons are tracked
+    gone: Option<HashMap<PacketId, MissReason>>,
+    /// Identities of which an occurrence was taken by `take`, kept only if retransmissions are
+    /// counted
+    paired: Option<HashSet<PacketId>>,
+    /// Largest distance in time (nsec) of an occurrence `take` accepts
+    max_distance: Option<i64>,
+    /// Lookups by `take` of identity whose occurrences were all beyond `max_distance`
+    rejected: u64,
+}
+
+impl OutboundTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Estimated memory of one occurrence of an identity of its own (bytes): the entry of the
+    /// hash map, doubled for the spare capacity left by its growth, and the time, plus its place
+    /// in the insertion order if the table is bounded. Identities wrapping another one (TCP
+    /// flags, VNI, VLAN IDs) take more
+    pub fn entry_bytes(bounded: bool) -> usize {
+        let entry = size_of::<(PacketId, Vec<PacketTime>)>() + 1;
+        let order = match bounded {
+            true => size_of::<(PacketId, PacketTime)>(),
+            false => 0,
+        };
+        entry * 2 + size_of::<PacketTime>() + order
+    }
+
+    /// Table which also maintains the secondary index for `take_fuzzy`
+    pub fn with_fuzzy_index() -> Self {
+        Self {
+            fuzzy: Some(HashMap::new()),
+            ..Default::default()
+        }
+    }
+
+    /// Bounds the table by `max_entries` occurrences, evicting the oldest inserted ones beyond it
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn with_pairing(mut self, pairing: Pairing) -> Self {
+        self.pairing = pairing;
+        self
+    }
+
+    /// Limits `take` to occurrences no further than `max_distance_nsec` in time from the packet
+    /// looked up, so that a recurring identity isn't paired with an implausibly distant one
+    pub fn with_max_distance(mut self, max_distance_nsec: i64) -> Self {
+        self.max_distance = Some(max_distance_nsec);
+        self
+    }
+
+    /// Maintains the secondary index of fallback keys for `take_fallback`
+    pub fn with_fallback_index(mut self) -> Self {
+        self.fallback = Some(HashMap::new());
+        self
+    }
+
+    /// Remembers identities whose last occurrence was taken or evicted, for `miss_reason`
+    pub fn with_miss_reasons(mut self) -> Self {
+        self.gone = Some(HashMap::new());
+        self
+    }
+
+    /// Remembers identities which were paired by `take`, for `was_paired`
+    pub fn with_retransmissions(mut self) -> Self {
+        self.paired = Some(HashSet::new());
+        self
+    }
+
+    pub fn insert(&mut self, id: PacketId, time: PacketTime) {
+        self.insert_with_fallback(id, time, None);
+    }
+
+    /// Inserts occurrence which can also be taken by its fallback key, if the table was created
+    /// `with_fallback_index`
+    pub fn insert_with_fallback(
+        &mut self,
+        id: PacketId,
+        time: PacketTime,
+        fallback_key: Option<FuzzyKey>,
+    ) {
+        if let (Some(fallback), Some(key)) = (&mut self.fallback, fallback_key) {
+            fallback
+                .entry(key)
+                .or_default()
+                .push_back((id.clone(), time));
+            self.fallback_len += 1;
+        }
+        if let Some(gone) = &mut self.gone {
+            gone.remove(&id);
+        }
+        let times = self.entries.entry(id.clone()).or_default();
+        // Usually the newest occurrence, unless the capture has packets out of time order
+        let position = times.partition_point(|t| *t <= time);
+        times.insert(position, time);
+        self.occurrences += 1;
+        match times.len() {
+            1 => {
+                if let Some(fuzzy) = &mut self.fuzzy {
+                    fuzzy.entry(id.fuzzy_key()).or_default().push(id.clone());
+                }
+            }
+            2 => {
+                self.recurring += 1;
+                self.collisions += 1;
+            }
+            _ => self.collisions += 1,
+        }
+        if let Some(max_entries) = self.max_entries {
+            self.order.push_back((id, time));
+            while self.occurrences > max_entries && !self.order.is_empty() {
+                self.evict_oldest();
+            }
+            // Taken occurrences are dropped once they outnumber the live ones
+            if self.order.len() > 2 * self.occurrences + 1024 {
+                self.compact_order();
+            }
+        }
+        // Stale occurrences are dropped once they outnumber the live ones
+        if self.fallback_len > 2 * self.occurrences + 1024 {
+            self.compact_fallback();
+        }
+    }
+
+    fn compact_fallback(&mut self) {
+        let Some(fallback) = &mut self.fallback else {
+            return;
+        };
+        let entries = &self.entries;
+        fallback.retain(|_, occurrences| {
+            occurrences.retain(|(id, time)| position(entries, id, *time).is_some());
+            !occurrences.is_empty()
+        });
+        self.fallback_len = fallback.values().map(VecDeque::len).sum();
+    }
+
+    /// Drops occurrences of the insertion order which aren't in the table anymore. Of equal
+    /// ones, as many as there are left in the table are kept
+    fn compact_order(&mut self) {
+        let entries = &self.entries;
+        let mut kept: HashMap<(PacketId, PacketTime), usize> = HashMap::new();
+        self.order.retain(|(id, time)| {
+            let live = entries.get(id).map_or(0, |times| {
+                times.partition_point(|t| t <= time) - times.partition_point(|t| t < time)
+            });
+            let kept = kept.entry((id.clone(), *time)).or_default();
+            *kept += 1;
+            *kept <= live
+        });
+    }
+
+    fn evict_oldest(&mut self) {
+        let Some((id, time)) = self.order.pop_front() else {
+            return;
+        };
+        let Some(position) = position(&self.entries, &id, time) else {
+            return;
+        };
+        self.remove_occurrence(&id, position, MissReason::Evicted);
+        self.evicted += 1;
+    }
+
+    /// Removes occurrence of identity, and the identity with its fuzzy index entry if it was
+    /// the last occurrence. `reason` is remembered for the removed identity
+    fn remove_occurrence(
+        &mut self,
+        id: &PacketId,
+        position: usize,
+        reason: MissReason,
+    ) -> Option<PacketTime> {
+        let times = self.entries.get_mut(id)?;
+        let time = times.remove(position);
+        self.occurrences -= 1;
+        if times.is_empty() {
+            self.entries.remove(id);
+            if let Some(gone) = &mut self.gone {
+                gone.insert(id.clone(), reason);
+            }
+            if let Some(candidates) = self
+                .fuzzy
+                .as_mut()
+                .and_then(|fuzzy| fuzzy.get_mut(&id.fuzzy_key()))
+            {
+                candidates.retain(|candidate| candidate != id);
+            }
+        }
+        Some(time)
+    }
+
+    /// Number of occurrences evicted because of `max_entries`
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// Number of identities inserted more than once
+    pub fn recurring(&self) -> u64 {
+        self.recurring
+    }
+
+    /// Number of insertions of identity which was already in the table
+    pub fn collisions(&self) -> u64 {
+        self.collisions
+    }
+
+    /// Number of distinct identities not taken yet
+    pub fn identities(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Number of lookups by `take` which found the identity, but no occurrence within
+    /// `max_distance`
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Removes occurrence of identical identity chosen by the pairing strategy for inbound
+    /// packet of `time`, among the occurrences within `max_distance` if the table has one
+    pub fn take(&mut self, id: &PacketId, time: PacketTime) -> Option<PacketTime> {
+        let times = self.entries.get(id)?;
+        let (start, end) = match self.max_distance {
+            Some(distance) => (
+                times.partition_point(|t| PacketTime::diff(time, *t) > distance),
+                times.partition_point(|t| PacketTime::diff(*t, time) <= distance),
+            ),
+            None => (0, times.len()),
+        };
+        if start >= end {
+            self.rejected += 1;
+            return None;
+        }
+        let position = match self.pairing {
+            Pairing::First => start,
+            Pairing::Last => end - 1,
+            Pairing::Nearest => start + nearest(&times[start..end], time)?.0,
+        };
+        if let Some(paired) = &mut self.paired {
+            paired.insert(id.clone());
+        }
+        self.remove_occurrence(id, position, MissReason::Taken)
+    }
+
+    /// Whether an occurrence of identity was taken by `take`. An unmatched packet of such
+    /// identity, or an occurrence left in the table, is an extra copy of a paired packet: a
+    /// retransmission on one side only. Always `false` unless the table was created
+    /// `with_retransmissions`
+    pub fn was_paired(&self, id: &PacketId) -> bool {
+        self.paired
+            .as_ref()
+            .is_some_and(|paired| paired.contains(id))
+    }
+
+    /// Why `take` of identity found nothing. Identities which left the table are told apart
+    /// only if the table was created `with_miss_reasons`, otherwise they are `Absent`
+    pub fn miss_reason(&self, id: &PacketId) -> MissReason {
+        if self.entries.contains_key(id) {
+            return MissReason::OutsideWindow;
+        }
+        self.gone
+            .as_ref()
+            .and_then(|gone| gone.get(id))
+            .copied()
+            .unwrap_or(MissReason::Absent)
+    }
+
+    /// Removes occurrence of identity which shares reduced key with `id` and whose time differs
+    /// from `time` by no more than `window_usec`. The temporally nearest candidate is chosen.
+    pub fn take_fuzzy(
+        &mut self,
+        id: &PacketId,
+        time: PacketTime,
+        window_usec: i64,
+    ) -> Option<PacketTime> {
+        let candidates = self.fuzzy.as_ref()?.get(&id.fuzzy_key())?;
+        let (candidate, position, _) = candidates
+            .iter()
+            .filter_map(|candidate| {
+                let (position, distance) = nearest(self.entries.get(candidate)?, time)?;
+                Some((candidate.clone(), position, distance))
+            })
+            .filter(|(_, _, distance)| *distance <= window_usec * 1000)
+            .min_by_key(|(_, _, distance)| *distance)?;
+        self.remove_occurrence(&candidate, position, MissReason::Taken)
+    }
+
+    /// Removes the earliest inserted occurrence of fallback key, whatever the pairing strategy.
+    /// Unlike `take_fuzzy` and `take`, the time of the occurrence isn't limited
+    pub fn take_fallback(&mut self, key: &FuzzyKey) -> Option<PacketTime> {
+        let occurrences = self.fallback.as_mut()?.get_mut(key)?;
+        while let Some((id, time)) = occurrences.pop_front() {
+            self.fallback_len -= 1;
+            if let Some(position) = position(&self.entries, &id, time) {
+                return self.remove_occurrence(&id, position, MissReason::Taken);
+            }
+        }
+        None
+    }
+
+    /// Occurrences not taken yet, in arbitrary order of identities
+    pub fn iter(&self) -> impl Iterator<Item = (&PacketId, &PacketTime)> {
+        self.entries
+            .iter()
+            .flat_map(|(id, times)| times.iter().map(move |time| (id, time)))
+    }
+
+    /// Number of occurrences not taken yet
+    pub fn len(&self) -> usize {
+        self.occurrences
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Position of time nearest to `time` in sorted `times` and distance to it (nsec). The earlier
+/// one wins a tie
+fn nearest(times: &[PacketTime], time: PacketTime) -> Option<(usize, i64)> {
+    let after = times.partition_point(|t| *t < time);
+    let distance = |position: usize| {
+        times
+            .get(position)
+            .map(|t| (position, PacketTime::diff(*t, time).abs()))
+    };
+    let before = after.checked_sub(1).and_then(distance);
+    match (before, distance(after)) {
+        (Some(before), Some(after)) if after.1 < before.1 => Some(after),
+        (Some(before), _) => Some(before),
+        (None, after) => after,
+    }
+}
+
+/// Position of occurrence of identity at time in its times, if it's still in the table
+fn position(
+    entries: &HashMap<PacketId, Vec<PacketTime>>,
+    id: &PacketId,
+    time: PacketTime,
+) -> Option<usize> {
+    entries
+        .get(id)
+        .and_then(|times| times.binary_search(&time).ok())
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+
+/// Packet timestamp, nanoseconds since the epoch
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, PartialOrd, Ord)]
+pub struct PacketTime {
+    pub nsec: u64,
+}
+
+impl PacketTime {
+    pub fn from_sec_usec(sec: u32, usec: u32) -> Self {
+        Self::from_sec_fraction(sec as u64, usec as u64, 1_000_000)
+    }
+
+    /// Builds time from whole seconds and fraction of second in `units_per_sec` units
+    pub fn from_sec_fraction(sec: u64, fraction: u64, units_per_sec: u64) -> Self {
+        let fraction_nsec = fraction as u128 * 1_000_000_000 / units_per_sec as u128;
+        Self {
+            nsec: sec * 1_000_000_000 + fraction_nsec as u64,
+        }
+    }
+
+    /// Builds time from timestamp counted in `units_per_sec` units since `offset_sec`
+    pub fn from_units(ts: u64, units_per_sec: u64, offset_sec: u64) -> Self {
+        Self::from_sec_fraction(
+            offset_sec + ts / units_per_sec,
+            ts % units_per_sec,
+            units_per_sec,
+        )
+    }
+
+    /// Time rounded to the nearest multiple of `resolution_nsec`
+    pub fn round_to(self, resolution_nsec: u64) -> Self {
+        Self {
+            nsec: self.nsec.saturating_add(resolution_nsec / 2) / resolution_nsec * resolution_nsec,
+        }
+    }
+
+    /// Difference `t1 - t2` in nanoseconds. Converted to coarser units only for display
+    pub fn diff(t1: Self, t2: Self) -> i64 {
+        t1.nsec as i64 - t2.nsec as i64
+    }
+}
+
+/// Seconds since the epoch with nanoseconds, e.g. `1.000000150`
+impl fmt::Display for PacketTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:09}",
+            self.nsec / 1_000_000_000,
+            self.nsec % 1_000_000_000
+        )
+    }
+}
@@ -0,0 +1,124 @@
+//! ASCII timeline of the time spans of captures, for checking that they overlap before their
+//! packets are matched
+
+use crate::time::PacketTime;
+use std::fmt;
+
+/// Columns of the bar of a capture
+pub const WIDTH: usize = 60;
+
+/// Time of the first and the last packet of a capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub first: PacketTime,
+    pub last: PacketTime,
+}
+
+impl Span {
+    /// Span of packets of any time order, `None` if there are none
+    pub fn of(times: impl IntoIterator<Item = PacketTime>) -> Option<Self> {
+        times
+            .into_iter()
+            .fold(None, |span, time| Some(Self::including(span, time)))
+    }
+
+    /// Span extended to cover a packet, or of the packet alone
+    pub fn including(span: Option<Self>, time: PacketTime) -> Self {
+        match span {
+            None => Span {
+                first: time,
+                last: time,
+            },
+            Some(Span { first, last }) => Span {
+                first: first.min(time),
+                last: last.max(time),
+            },
+        }
+    }
+
+    fn duration(&self) -> f64 {
+        PacketTime::diff(self.last, self.first) as f64 / 1e9
+    }
+}
+
+/// Bars of the inbound and outbound capture on a common time axis, and their overlap
+pub struct Alignment {
+    pub inbound: Option<Span>,
+    pub outbound: Option<Span>,
+}
+
+impl Alignment {
+    /// Time covered by both captures (nsec), or minus the gap between them if they don't
+    /// overlap. `None` if either capture has no packets
+    pub fn overlap_nsec(&self) -> Option<i64> {
+        let (inbound, outbound) = (self.inbound?, self.outbound?);
+        Some(PacketTime::diff(
+            inbound.last.min(outbound.last),
+            inbound.first.max(outbound.first),
+        ))
+    }
+
+    /// Columns of the bar covered by span, within the span of both captures
+    fn bar(span: Span, whole: Span) -> String {
+        let length = whole.last.nsec - whole.first.nsec;
+        let column = |time: PacketTime| match length {
+            0 => 0,
+            _ => {
+                ((time.nsec - whole.first.nsec) as u128 * (WIDTH - 1) as u128 / length as u128)
+                    as usize
+            }
+        };
+        let (start, end) = (column(span.first), column(span.last));
+        (0..WIDTH)
+            .map(|i| if (start..=end).contains(&i) { '#' } else { ' ' })
+            .collect()
+    }
+}
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (Some(inbound), Some(outbound)) = (self.inbound, self.outbound) else {
+            write!(f, "Capture alignment:")?;
+            for (name, span) in [("in", self.inbound), ("out", self.outbound)] {
+                if span.is_none() {
+                    write!(f, "\n{:<4}no packets", name)?;
+                }
+            }
+            return Ok(());
+        };
+        let whole = Span {
+            first: inbound.first.min(outbound.first),
+            last: inbound.last.max(outbound.last),
+        };
+        write!(
+            f,
+            "Capture alignment ({} to {}, {:.6} sec):",
+            whole.first,
+            whole.last,
+            whole.duration()
+        )?;
+        for (name, span) in [("in", inbound), ("out", outbound)] {
+            write!(
+                f,
+                "\n{:<4}|{}| {} to {}",
+                name,
+                Self::bar(span, whole),
+                span.first,
+                span.last
+            )?;
+        }
+        let overlap = Span {
+            first: inbound.first.max(outbound.first),
+            last: inbound.last.min(outbound.last),
+        };
+        if overlap.first <= overlap.last {
+            write!(f, "\nOverlap (sec): {:.6}", overlap.duration())
+        } else {
+            write!(
+                f,
+                "\nWARNING: the captures don't overlap, they are {:.6} sec apart. Packets can't be matched unless the clocks differ by about this much",
+                -overlap.duration()
+            )
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use latency_measurement_tool::anonymize::Anonymizer;
+use latency_measurement_tool::PacketId;
+use std::net::IpAddr;
+
+fn icmp(ip_src: &str, ip_dst: &str) -> PacketId {
+    PacketId::Icmp {
+        ip_src: ip_src.parse().unwrap(),
+        ip_dst: ip_dst.parse().unwrap(),
+        checksum: 0x1f3c,
+    }
+}
+
+#[test]
+fn pseudonyms_are_consistent_per_key() {
+    let anonymizer = Anonymizer::new("secret");
+    let ip: IpAddr = "10.0.0.1".parse().unwrap();
+    let pseudonym = anonymizer.pseudonym(ip);
+    assert_ne!(pseudonym, ip);
+    assert!(pseudonym.is_ipv4());
+    assert_eq!(anonymizer.pseudonym(ip), pseudonym);
+    assert_ne!(Anonymizer::new("other").pseudonym(ip), pseudonym);
+    assert!(anonymizer
+        .pseudonym("2001:db8::1".parse().unwrap())
+        .is_ipv6());
+}
+
+#[test]
+fn addresses_of_identity_are_replaced() {
+    let anonymizer = Anonymizer::new("");
+    let map = |ip| anonymizer.pseudonym(ip);
+    let request = icmp("10.0.0.1", "10.0.0.2").map_addresses(&map);
+    let reply = icmp("10.0.0.2", "10.0.0.1").map_addresses(&map);
+    let (
+        PacketId::Icmp {
+            ip_src: request_src,
+            ip_dst: request_dst,
+            ..
+        },
+        PacketId::Icmp {
+            ip_src: reply_src,
+            ip_dst: reply_dst,
+            ..
+        },
+    ) = (request, reply)
+    else {
+        panic!("identities changed kind");
+    };
+    assert_ne!(request_src, "10.0.0.1".parse::<IpAddr>().unwrap());
+    assert_eq!((request_src, request_dst), (reply_dst, reply_src));
+}
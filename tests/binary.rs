@@ -0,0 +1,41 @@
+use latency_measurement_tool::binary::{MatchKind, Record, RecordReader};
+
+#[test]
+fn records_are_read_back_as_written() {
+    let records = [
+        Record {
+            latency: 150,
+            kind: MatchKind::Exact,
+        },
+        Record {
+            latency: 0,
+            kind: MatchKind::Miss,
+        },
+        Record {
+            latency: -20,
+            kind: MatchKind::Fuzzy,
+        },
+        Record {
+            latency: i64::MAX,
+            kind: MatchKind::Fallback,
+        },
+    ];
+    let mut bytes = Vec::new();
+    for record in &records {
+        record.write_to(&mut bytes).unwrap();
+    }
+    assert_eq!(bytes.len(), records.len() * Record::SIZE);
+    assert_eq!(&bytes[..Record::SIZE], &[150, 0, 0, 0, 0, 0, 0, 0, 1]);
+    let read: Vec<Record> = RecordReader::new(bytes.as_slice())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(read, records);
+}
+
+#[test]
+fn unknown_match_flag_is_an_error() {
+    let mut bytes = [0u8; Record::SIZE];
+    bytes[8] = 4;
+    let mut reader = RecordReader::new(&bytes[..]);
+    assert!(reader.next().unwrap().is_err());
+}
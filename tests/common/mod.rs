@@ -0,0 +1,52 @@
+//! Builders of frames and captures shared by the tests
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Ethernet frame from 00:11:22:33:44:55 to 66:77:88:99:aa:bb
+pub fn ethernet(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![
+        0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55,
+    ];
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Ethernet frame with IPv4 TCP segment of 10.0.0.1:1000 to 10.0.0.2:80, seq 100 and ack 5
+pub fn tcp_frame() -> Vec<u8> {
+    let mut ip = vec![0x45, 0, 0, 40, 0, 1, 0, 0, 64, 6, 0, 0];
+    ip.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    ip.extend_from_slice(&[0x03, 0xe8, 0, 80, 0, 0, 0, 100, 0, 0, 0, 5]);
+    ip.extend_from_slice(&[0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+    ethernet(0x0800, &ip)
+}
+
+/// Little-endian legacy pcap of Ethernet frames in microseconds, the i-th one at 1000 s and i
+/// usec
+pub fn pcap(frames: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for field in [0xa1b2_c3d4u32, 0x0004_0002, 0, 0, 65535, 1] {
+        bytes.extend_from_slice(&field.to_le_bytes());
+    }
+    for (i, frame) in frames.iter().enumerate() {
+        for field in [1000, i as u32, frame.len() as u32, frame.len() as u32] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(frame);
+    }
+    bytes
+}
+
+/// Path of file `name` (with extension) in the temporary directory, unique to the process
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("{}-{}", std::process::id(), name))
+}
+
+/// Writes capture to a file of the temporary directory, see `temp_path`
+pub fn write_capture(name: &str, bytes: &[u8]) -> PathBuf {
+    let path = temp_path(name);
+    fs::write(&path, bytes).unwrap();
+    path
+}
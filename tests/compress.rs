@@ -0,0 +1,159 @@
+use flate2::read::GzDecoder;
+use latency_measurement_tool::compress::{Compression, OutputFile};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+mod common;
+
+use common::temp_path;
+
+/// Lines like those of `--dump-unmatched-out`
+fn text() -> Vec<u8> {
+    let mut text = Vec::new();
+    for i in 0..10_000 {
+        writeln!(
+            text,
+            "1000.{:09} tcp 10.0.0.{}:1000 > 10.0.0.2:80 seq {} ack 5",
+            i * 7919,
+            i % 7,
+            i * 13
+        )
+        .unwrap();
+    }
+    text
+}
+
+/// Writes `input` to an output file `name` in chunks, returns its path and raw contents
+fn write(name: &str, input: &[u8]) -> (PathBuf, Vec<u8>) {
+    let path = temp_path(name);
+    let mut file = OutputFile::create(path.to_str().unwrap()).unwrap();
+    for chunk in input.chunks(1000) {
+        file.write_all(chunk).unwrap();
+    }
+    file.finish().unwrap();
+    let bytes = fs::read(&path).unwrap();
+    (path, bytes)
+}
+
+fn gunzip(bytes: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut decoded).unwrap();
+    decoded
+}
+
+#[test]
+fn compression_by_extension() {
+    assert_eq!(Compression::of_path("dump.txt.gz"), Compression::Gzip);
+    assert_eq!(Compression::of_path("loss.csv.zst"), Compression::Zstd);
+    assert_eq!(Compression::of_path("loss.csv"), Compression::None);
+}
+
+#[test]
+fn plain_file_is_written_as_is() {
+    let text = text();
+    let (path, bytes) = write("plain.txt", &text);
+    fs::remove_file(&path).unwrap();
+    assert!(bytes == text);
+}
+
+#[test]
+fn gzip_round_trip() {
+    let text = text();
+    let (path, gzip) = write("round-trip.txt.gz", &text);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(gzip[..3], [0x1f, 0x8b, 8]);
+    assert!(gzip.len() < text.len() / 3);
+    assert!(gunzip(&gzip) == text);
+}
+
+#[test]
+fn zstd_round_trip() {
+    let text = text();
+    let (path, zstd) = write("round-trip.txt.zst", &text);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(zstd[..4], 0xfd2f_b528u32.to_le_bytes());
+    assert!(zstd.len() < text.len() / 3);
+    assert!(zstd::decode_all(zstd.as_slice()).unwrap() == text);
+}
+
+#[test]
+fn empty_output_is_valid() {
+    let (path, gzip) = write("empty.txt.gz", b"");
+    fs::remove_file(&path).unwrap();
+    assert_eq!(gunzip(&gzip), b"");
+    let (path, zstd) = write("empty.txt.zst", b"");
+    fs::remove_file(&path).unwrap();
+    assert_eq!(zstd::decode_all(zstd.as_slice()).unwrap(), b"");
+}
+
+#[test]
+fn flush_makes_written_data_readable() {
+    // As with --wait, where a reader of the file follows the run before it ends
+    let text = text();
+    let (head, tail) = text.split_at(text.len() / 2);
+    for name in ["flush.txt.gz", "flush.txt.zst"] {
+        let path = temp_path(name);
+        let mut file = OutputFile::create(path.to_str().unwrap()).unwrap();
+        file.write_all(head).unwrap();
+        file.flush().unwrap();
+        // The stream isn't finished, decoding ends with an error after all data so far
+        let bytes = fs::read(&path).unwrap();
+        let mut decoded = Vec::new();
+        let _ = match Compression::of_path(name) {
+            Compression::Gzip => GzDecoder::new(bytes.as_slice()).read_to_end(&mut decoded),
+            _ => zstd::Decoder::new(bytes.as_slice())
+                .unwrap()
+                .read_to_end(&mut decoded),
+        };
+        assert!(decoded == head, "{} lacks data before flush", name);
+        file.write_all(tail).unwrap();
+        file.finish().unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let decoded = match Compression::of_path(name) {
+            Compression::Gzip => gunzip(&bytes),
+            _ => zstd::decode_all(bytes.as_slice()).unwrap(),
+        };
+        assert!(decoded == text, "{} decodes other data", name);
+    }
+}
+
+/// Output of decompressing with command `tool -d`, `None` if the tool isn't installed
+fn decode_with(tool: &str, compressed: &[u8]) -> Option<Vec<u8>> {
+    let mut child = Command::new(tool)
+        .args(["-d", "-c"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let mut stdin = child.stdin.take().unwrap();
+    let compressed = compressed.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&compressed));
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap().unwrap();
+    assert!(
+        output.status.success(),
+        "{} -d: {}",
+        tool,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(output.stdout)
+}
+
+#[test]
+fn reference_decoders_read_output() {
+    let text = text();
+    for input in [&text[..], b"latency", b""] {
+        for (tool, name) in [("gzip", "reference.txt.gz"), ("zstd", "reference.txt.zst")] {
+            let (path, compressed) = write(name, input);
+            fs::remove_file(&path).unwrap();
+            match decode_with(tool, &compressed) {
+                Some(decoded) => assert!(decoded == input, "{} decodes other data", tool),
+                None => eprintln!("skipped: {} isn't installed", tool),
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use latency_measurement_tool::defrag::{Defragmented, Defragmenter};
+use latency_measurement_tool::PacketTime;
+use pcap_parser::Linktype;
+
+/// IPv4 fragment of UDP packet 10.0.0.1 to 10.0.0.2 of identification, at offset in bytes
+fn fragment(identification: u16, offset: usize, more: bool, payload: &[u8]) -> Vec<u8> {
+    let total_len = (20 + payload.len()) as u16;
+    let flags_offset = (offset / 8) as u16 | if more { 0x2000 } else { 0 };
+    let mut packet = vec![0x45, 0];
+    packet.extend_from_slice(&total_len.to_be_bytes());
+    packet.extend_from_slice(&identification.to_be_bytes());
+    packet.extend_from_slice(&flags_offset.to_be_bytes());
+    packet.extend_from_slice(&[64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+fn time(usec: u64) -> PacketTime {
+    PacketTime { nsec: usec * 1000 }
+}
+
+#[test]
+fn fragments_in_any_order_are_reassembled() {
+    let payload: Vec<u8> = (0..40).collect();
+    let mut defragmenter = Defragmenter::new();
+    let fragments = [
+        fragment(7, 32, false, &payload[32..]),
+        fragment(7, 0, true, &payload[..16]),
+        fragment(7, 16, true, &payload[16..32]),
+    ];
+    assert_eq!(
+        defragmenter.push(Linktype::RAW, &fragments[0], time(0)),
+        Defragmented::Held
+    );
+    assert_eq!(
+        defragmenter.push(Linktype::RAW, &fragments[1], time(1)),
+        Defragmented::Held
+    );
+    let Defragmented::Complete(packet) = defragmenter.push(Linktype::RAW, &fragments[2], time(2))
+    else {
+        panic!("packet isn't complete");
+    };
+    assert_eq!(&packet[20..], &payload[..]);
+    assert_eq!(u16::from_be_bytes([packet[2], packet[3]]), 60);
+    assert_eq!(&packet[6..8], &[0, 0]);
+    // The checksum of a valid header sums to all ones
+    let sum: u32 = packet[..20]
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as u32)
+        .sum();
+    assert_eq!((sum & 0xffff) + (sum >> 16), 0xffff);
+}
+
+#[test]
+fn whole_packets_pass_and_stale_fragments_expire() {
+    let mut defragmenter = Defragmenter::new();
+    let whole = fragment(1, 0, false, b"whole");
+    assert_eq!(
+        defragmenter.push(Linktype::RAW, &whole, time(0)),
+        Defragmented::Whole
+    );
+    let first = fragment(2, 0, true, &[0; 8]);
+    let last = fragment(2, 8, false, &[0; 8]);
+    assert_eq!(
+        defragmenter.push(Linktype::RAW, &first, time(0)),
+        Defragmented::Held
+    );
+    // 31 s later the first fragment is gone
+    assert_eq!(
+        defragmenter.push(Linktype::RAW, &last, time(31_000_000)),
+        Defragmented::Held
+    );
+}
+
+#[test]
+fn fragments_with_short_header_length_pass_whole() {
+    let mut defragmenter = Defragmenter::new();
+    for (offset, more) in [(0, true), (8, false)] {
+        let mut short = fragment(3, offset, more, &[0; 8]);
+        // IHL of 1: a 4-byte header, shorter than the fixed part of IPv4 header
+        short[0] = 0x41;
+        assert_eq!(
+            defragmenter.push(Linktype::RAW, &short, time(0)),
+            Defragmented::Whole
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use latency_measurement_tool::discover::{self, matches, natural_cmp};
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+#[test]
+fn wildcards_match_whole_name() {
+    assert!(matches("*.pcap", "out.pcap"));
+    assert!(matches("out.pcap*", "out.pcap"));
+    assert!(matches("out-??.pcap", "out-07.pcap"));
+    assert!(matches("*-*.pcap", "a-b-c.pcap"));
+    assert!(!matches("*.pcap", "out.pcapng"));
+    assert!(!matches("out-?.pcap", "out-07.pcap"));
+}
+
+#[test]
+fn numbers_in_names_are_ordered_by_value() {
+    assert_eq!(natural_cmp("out.pcap9", "out.pcap10"), Ordering::Less);
+    assert_eq!(natural_cmp("out.pcap", "out.pcap1"), Ordering::Less);
+    assert_eq!(natural_cmp("a-20240101", "a-20231231"), Ordering::Greater);
+    assert_eq!(natural_cmp("b1", "a2"), Ordering::Greater);
+    assert_eq!(natural_cmp("x07", "x7"), Ordering::Greater);
+}
+
+#[test]
+fn files_of_directory_and_pattern_are_found_in_order() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/rotated_in");
+    let dir = dir.to_str().unwrap();
+    let names = |path: &str| -> Vec<String> {
+        discover::files(path)
+            .unwrap()
+            .iter()
+            .map(|file| file.rsplit('/').next().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(names(dir), ["in.pcap9", "in.pcap10", "in.pcap11"]);
+    assert_eq!(
+        names(&format!("{}/in.pcap1?", dir)),
+        ["in.pcap10", "in.pcap11"]
+    );
+    assert_eq!(
+        discover::files(&format!("{}/in.pcap9", dir)).unwrap(),
+        [format!("{}/in.pcap9", dir)]
+    );
+    assert!(discover::files(&format!("{}/*.pcapng", dir)).is_err());
+}
@@ -0,0 +1,81 @@
+use latency_measurement_tool::extractor::IdentityExtractor;
+use latency_measurement_tool::packet::{ParseOptions, Protocol};
+use latency_measurement_tool::{PacketId, PcapReader};
+use pcap_parser::Linktype;
+use std::fs;
+
+mod common;
+
+use common::{ethernet, pcap, tcp_frame, write_capture};
+
+/// Ethertype of the local experimental protocol of test frames
+const ETHERTYPE_EXPERIMENTAL: u16 = 0x88b5;
+
+/// Frame of the experimental protocol, whose sequence number is its identity
+fn experimental_frame(seq: u32) -> Vec<u8> {
+    ethernet(ETHERTYPE_EXPERIMENTAL, &seq.to_be_bytes())
+}
+
+/// Extractor of the experimental protocol
+fn experimental(link_type: Linktype, bytes: &[u8]) -> Option<PacketId> {
+    if link_type != Linktype::ETHERNET || bytes.get(12..14)? != ETHERTYPE_EXPERIMENTAL.to_be_bytes()
+    {
+        return None;
+    }
+    Some(PacketId::Custom {
+        name: "experimental".to_string(),
+        key: bytes.get(14..18)?.to_vec(),
+    })
+}
+
+/// Identities of frames read from a legacy pcap file with given options
+fn read(name: &str, frames: &[Vec<u8>], options: ParseOptions) -> Vec<PacketId> {
+    let path = write_capture(&format!("{}.pcap", name), &pcap(frames));
+    let reader = PcapReader::new_from_path(path.to_str().unwrap(), Vec::new())
+        .unwrap()
+        .with_parse_options(options);
+    let ids = reader.map(|(id, _)| id).collect();
+    fs::remove_file(&path).unwrap();
+    ids
+}
+
+#[test]
+fn custom_extractor_comes_before_built_in_ones() {
+    let mut options = ParseOptions::default();
+    options.extractors.push(experimental);
+    let ids = read(
+        "custom-extractor",
+        &[experimental_frame(42), tcp_frame()],
+        options,
+    );
+    assert_eq!(ids.len(), 2);
+    assert_eq!(ids[0].to_string(), "experimental key 0000002a");
+    assert!(matches!(ids[1], PacketId::Tcp { tcp_seq: 100, .. }));
+}
+
+#[test]
+fn frames_of_unknown_protocol_have_no_identity_without_extractor() {
+    let ids = read(
+        "no-extractor",
+        &[experimental_frame(42), tcp_frame()],
+        ParseOptions::default(),
+    );
+    assert_eq!(ids.len(), 1);
+}
+
+#[test]
+fn custom_extractors_are_skipped_unless_protocols_include_them() {
+    let mut options = ParseOptions {
+        protocols: vec![Protocol::Tcp],
+        ..Default::default()
+    };
+    options.extractors.push(experimental);
+    assert_eq!(
+        options.extract(Linktype::ETHERNET, &experimental_frame(1)),
+        None
+    );
+    options.protocols.push(Protocol::Custom);
+    assert!(options
+        .extract(Linktype::ETHERNET, &experimental_frame(1))
+        .is_some());
+}
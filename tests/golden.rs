@@ -0,0 +1,175 @@
+//! Runs the binary on captures of `tests/fixtures` and compares its stdout and exit code with
+//! `tests/golden/<case>.out`. After an intended change of output, rewrite the golden files
+//! with `UPDATE_GOLDEN=1 cargo test --test golden` and review the diff
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn check(case: &str, args: &[&str]) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let output = Command::new(env!("CARGO_BIN_EXE_latency_measurement_tool"))
+        .args(args)
+        .current_dir(root.join("fixtures"))
+        .output()
+        .unwrap();
+    let actual = format!(
+        "exit code: {}\n{}",
+        output.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let golden = root.join("golden").join(format!("{}.out", case));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden)
+        .unwrap_or_else(|e| panic!("{}: {} (run with UPDATE_GOLDEN=1)", golden.display(), e));
+    assert_eq!(
+        actual,
+        expected,
+        "output of {:?} differs from {}",
+        args,
+        golden.display()
+    );
+}
+
+macro_rules! golden {
+    ($case:ident: $($arg:expr),+) => {
+        #[test]
+        fn $case() {
+            check(stringify!($case), &[$($arg),+]);
+        }
+    };
+}
+
+// TCP and ICMP, one TCP packet is missing on the outbound side
+golden!(tcp_icmp: "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(tcp_icmp_analyze_json: "analyze", "-p", "--summary-format", "json", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(tcp_icmp_human: "-p", "--human", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(tcp_filter: "-p", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap", "-f", "23:6");
+golden!(stats: "stats", "tcp_icmp_in.pcap");
+golden!(stats_protocol: "stats", "--protocol", "icmp", "tcp_icmp_in.pcap");
+golden!(stats_l2: "stats", "--l2", "l2_in.pcap");
+golden!(stats_size: "stats", "--max-size", "40", "tcp_icmp_in.pcap");
+golden!(list_protocols: "list-protocols");
+golden!(list_protocols_json: "list-protocols", "--summary-format", "json");
+golden!(print_only_miss: "--print-only", "miss", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(print_only_outlier: "--print-only", "outlier:158", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(max_print_lines: "--max-print-lines", "2", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(max_print_lines_misses: "--max-print-lines", "1", "--print-only", "miss", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(protocol_icmp: "--protocol", "icmp", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(size_filter: "-p", "--min-size", "41", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(size_filter_json: "-p", "--min-size", "41", "--summary-format", "json", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+
+// Link-layer types
+golden!(linux_sll: "-p", "ethernet_in.pcap", "sll_out.pcap");
+golden!(raw_ip: "-p", "ethernet_in.pcap", "raw_out.pcap");
+golden!(loopback: "-p", "null_in.pcap", "loop_out.pcap");
+golden!(ipv6: "ipv6_in.pcap", "ipv6_out.pcap");
+golden!(neighbor_discovery: "--neighbor-discovery", "nd_in.pcap", "nd_out.pcap");
+golden!(icmp_errors: "--icmp-errors", "icmp_errors_in.pcap", "icmp_errors_out.pcap");
+golden!(l3_offset: "--l3-offset", "8", "l3_offset_in.pcap", "l3_offset_out.pcap");
+golden!(l3_offset_unsupported: "l3_offset_in.pcap", "l3_offset_out.pcap");
+golden!(fragmented: "fragmented_in.pcap", "fragmented_out.pcap");
+golden!(defragment: "--defragment", "fragmented_in.pcap", "fragmented_out.pcap");
+
+// Capture formats
+golden!(pcapng_hardware_timestamps: "--hw-ts-pen", "12345", "pcapng_in.pcapng", "pcapng_hw_out.pcapng");
+golden!(pcapng_hardware_timestamps_other_vendor: "--hw-ts-pen", "2989", "pcapng_in.pcapng", "pcapng_hw_out.pcapng");
+golden!(ts_source_software: "--ts-source", "software", "--hw-ts-pen", "12345", "pcapng_in.pcapng", "pcapng_hw_out.pcapng");
+golden!(stats_pcapng_names: "stats", "names_in.pcapng");
+golden!(resolve_names: "-p", "--resolve-names", "--loss-heatmap", "/dev/stdout", "names_in.pcapng", "tcp_icmp_out.pcap");
+golden!(group_prefix: "-p", "--group-prefix", "16", "--loss-heatmap", "/dev/stdout", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(group_prefix_ipv6: "-p", "--group-prefix", "24,64", "--loss-heatmap", "/dev/stdout", "ipv6_in.pcap", "ipv6_out.pcap");
+golden!(capture_drops: "-p", "tcp_flags_in.pcap", "capture_drops_out.pcapng");
+golden!(mixed_resolution: "tcp_flags_in.pcap", "tcp_flags_ns_out.pcap");
+golden!(round_to: "--round-to", "100us", "tcp_flags_in.pcap", "tcp_flags_ns_out.pcap");
+golden!(fcs_header_flag: "-p", "fcs_in.pcap", "fcs_out.pcap");
+golden!(erf: "-p", "erf_in.pcap", "erf_out.erf");
+golden!(dump_unmatched_out: "-p", "--dump-unmatched-out", "/dev/stdout", "nat_in.pcap", "tcp_icmp_out.pcap");
+golden!(reference_log: "-p", "tcp_icmp_in.pcap", "reference_out.jsonl");
+
+// Matching modes
+golden!(fuzzy_nat: "--fuzzy", "300", "nat_in.pcap", "nat_out.pcap");
+golden!(fuzzy_negative_window: "--fuzzy=-300", "nat_in.pcap", "nat_out.pcap");
+golden!(fuzzy_window_too_large: "--fuzzy", "9223372036854776", "nat_in.pcap", "nat_out.pcap");
+golden!(fallback_fuzzy_nat: "--fallback", "fuzzy", "nat_in.pcap", "nat_out.pcap");
+golden!(key_nat: "--key", "port_dst,tcp_seq,tcp_ack", "nat_in.pcap", "nat_out.pcap");
+golden!(show_alignment: "-p", "--show-alignment", "nat_in.pcap", "tcp_icmp_out.pcap");
+golden!(require_overlap: "--require-overlap", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(require_overlap_apart: "--require-overlap", "fcs_in.pcap", "tcp_icmp_out.pcap");
+golden!(overlap_tolerance: "--require-overlap", "--overlap-tolerance", "1000", "fcs_in.pcap", "tcp_icmp_out.pcap");
+golden!(fallback_payload: "--fallback", "payload", "nat_payload_in.pcap", "nat_payload_out.pcap");
+golden!(rtp: "-p", "--rtp-ports", "16384-32767", "rtp_in.pcap", "rtp_out.pcap");
+golden!(tcp_payload: "-p", "--tcp-match-mode", "payload", "rewritten_seq_in.pcap", "rewritten_seq_out.pcap");
+golden!(ip_id: "--match-mode", "ip-id", "ip_id_in.pcap", "ip_id_out.pcap");
+golden!(l2_switching: "--l2", "l2_in.pcap", "l2_out.pcap");
+golden!(vlan_translated: "-p", "vlan_in.pcap", "vlan_out.pcap");
+golden!(ignore_vlan: "-p", "--ignore-vlan", "vlan_in.pcap", "vlan_out.pcap");
+golden!(key_bytes: "-p", "--key-bytes", "58:6", "app_header_in.pcap", "app_header_out.pcap");
+// Hosts 10.0.0.1 and 10.0.0.3 send the same key bytes, the packet of 10.0.0.1 is lost
+golden!(key_bytes_shared: "--key-bytes", "58:6", "key_hosts_in.pcap", "key_hosts_out.pcap");
+golden!(key_bytes_ips: "--key-bytes", "58:6", "--key-bytes-ips", "key_hosts_in.pcap", "key_hosts_out.pcap");
+golden!(tcp_ignore_ack: "-p", "--tcp-ignore-ack", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(hash_range: "-p", "--tcp-match-mode", "payload", "--hash-range", "4:6", "app_header_in.pcap", "app_header_out.pcap");
+golden!(tcp_messages: "--tcp-match-mode", "message", "--message-framing", "length:2", "messages_in.pcap", "messages_out.pcap");
+golden!(pairing_first: "retransmission_in.pcap", "retransmission_out.pcap");
+golden!(pairing_last: "--pairing", "last", "retransmission_in.pcap", "retransmission_out.pcap");
+golden!(recurring_across_capture: "window_in.pcap", "window_out.pcap");
+golden!(match_window: "--match-window", "1000000", "--miss-reasons", "window_in.pcap", "window_out.pcap");
+golden!(match_window_too_large: "--match-window", "18446744073709551615", "window_in.pcap", "window_out.pcap");
+golden!(retransmissions_in: "--retransmissions", "retransmission_in.pcap", "retransmission_once_out.pcap");
+golden!(retransmissions_out: "--retransmissions", "retransmission_once_out.pcap", "retransmission_in.pcap");
+golden!(retransmissions_both: "--retransmissions", "retransmission_in.pcap", "retransmission_out.pcap");
+golden!(exchange_local_ip: "exchange", "--local-ip", "10.0.0.1", "exchange.pcap");
+golden!(exchange_local_mac: "exchange", "-p", "--local-mac", "00:11:22:33:44:55", "exchange.pcap");
+golden!(exchange_any_host: "exchange", "exchange.pcap");
+golden!(exchange_http: "exchange", "--http", "http.pcap");
+golden!(exchange_http_server: "exchange", "-p", "--http", "--local-ip", "10.0.0.2", "--top", "2", "http.pcap");
+golden!(auto_table_side: "--auto-table-side", "small_in.pcap", "large_out.pcap");
+golden!(max_memory_fits: "--max-memory", "1G", "small_in.pcap", "large_out.pcap");
+golden!(max_memory_bounded: "--max-memory", "1", "small_in.pcap", "large_out.pcap");
+golden!(nsec_whole_usec: "nsec_in.pcap", "nsec_out.pcap");
+golden!(nsec_precision: "--precision", "3", "nsec_in.pcap", "nsec_out.pcap");
+golden!(rotated_directory: "rotated_in", "large_out.pcap");
+golden!(rotated_pattern: "rotated_in/in.pcap1*", "large_out.pcap");
+golden!(bounded_table: "-p", "--max-table-entries", "3", "retransmission_in.pcap", "retransmission_out.pcap");
+
+// Summaries
+golden!(by_tcp_flags: "-p", "--by-tcp-flags", "tcp_flags_in.pcap", "tcp_flags_out.pcap");
+golden!(by_dscp: "-p", "--by-dscp", "dscp_in.pcap", "dscp_out.pcap");
+golden!(by_direction: "--by-direction", "bidi_client.pcap", "bidi_server.pcap");
+golden!(top: "-p", "--top", "4", "top_in.pcap", "top_out.pcap");
+golden!(count_only: "--count-only", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(count_only_json: "--count-only", "--summary-format", "json", "nat_in.pcap", "tcp_icmp_out.pcap");
+golden!(miss_reasons: "-p", "--miss-reasons", "--max-table-entries", "3", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(miss_reasons_taken: "-p", "--miss-reasons", "retransmission_in.pcap", "small_in.pcap");
+golden!(explain_miss: "-p", "--explain-miss", "tcp 10.0.0.1:1000 > 10.0.0.2:80 seq 100 ack 5", "retransmission_in.pcap", "small_in.pcap");
+golden!(explain_miss_filtered: "-p", "--explain-miss", "tcp 10.0.0.1:1000 > 10.0.0.2:80 seq 100 ack 5", "retransmission_in.pcap", "small_in.pcap", "-f", "41:150");
+golden!(clock_offset: "-p", "--clock-offset-ns", "-20500", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(clock_offset_json: "-p", "--summary-format", "json", "--clock-offset-ns", "100000", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap");
+golden!(snaplen_differs: "-p", "tcp_icmp_in.pcap", "snaplen_out.pcap");
+golden!(merge: "merge", "run_1.json", "run_2.json");
+golden!(json_records: "--format", "json", "nat_in.pcap", "tcp_icmp_out.pcap");
+golden!(json_records_pretty: "--format", "json", "--json-pretty", "--precision", "1", "nat_in.pcap", "tcp_icmp_out.pcap");
+golden!(merge_pretty: "merge", "--json-pretty", "--summary-format", "json", "run_1.json", "run_pretty.json");
+golden!(merge_incomplete: "merge", "run_1.json", "run_incomplete.json");
+golden!(compare: "compare", "run_1.json", "run_2.json");
+golden!(compare_regression: "compare", "--max-p99-increase", "10", "--max-miss-rate-increase", "1", "run_1.json", "run_2.json");
+golden!(compare_json: "compare", "--summary-format", "json", "--max-average-increase", "100", "run_1.json", "run_2.json");
+
+// Warnings
+golden!(same_capture_twice: "-p", "tcp_icmp_in.pcap", "tcp_icmp_in.pcap");
+golden!(constant_latency: "-p", "--clock-offset-ns", "500000", "tcp_icmp_in.pcap", "tcp_icmp_in.pcap");
+
+// Errors
+golden!(truncated_pcap: "-p", "truncated.pcap", "tcp_icmp_out.pcap");
+golden!(truncated_erf: "-p", "erf_in.pcap", "erf_truncated.erf");
+golden!(truncated_partial: "--on-read-error", "partial", "truncated_mid.pcap", "tcp_icmp_out.pcap");
+golden!(truncated_partial_json: "--on-read-error", "partial", "--summary-format", "json", "truncated_mid.pcap", "tcp_icmp_out.pcap");
+golden!(corrupt_partial: "--on-read-error", "partial", "corrupt_mid.pcap", "tcp_icmp_out.pcap");
+golden!(bad_reference_log: "-p", "tcp_icmp_in.pcap", "reference_bad.jsonl");
+golden!(missing_file: "-p", "no_such_file.pcap", "tcp_icmp_out.pcap");
+golden!(follow_negative_print_interval: "follow", "lo", "lo", "--print-interval=-1");
+golden!(follow_nan_match_timeout: "follow", "lo", "lo", "--match-timeout=nan");
@@ -0,0 +1,66 @@
+use latency_measurement_tool::heatmap::Heatmap;
+use latency_measurement_tool::PacketTime;
+
+fn text(heatmap: &Heatmap, json: bool) -> String {
+    let mut bytes = Vec::new();
+    match json {
+        true => heatmap.write_json(&mut bytes, false).unwrap(),
+        false => heatmap.write_csv(&mut bytes).unwrap(),
+    }
+    String::from_utf8(bytes).unwrap()
+}
+
+fn at(sec: u64, msec: u64) -> PacketTime {
+    PacketTime {
+        nsec: sec * 1_000_000_000 + msec * 1_000_000,
+    }
+}
+
+#[test]
+fn miss_rate_by_flow_and_interval() {
+    let mut heatmap = Heatmap::new(1_000_000_000);
+    heatmap.add("flow b".to_string(), at(10, 100), true);
+    heatmap.add("flow b".to_string(), at(10, 900), false);
+    heatmap.add("flow a".to_string(), at(12, 0), false);
+    heatmap.add("flow, c".to_string(), at(12, 500), true);
+    assert_eq!(
+        text(&heatmap, false),
+        "flow,10.000000000,11.000000000,12.000000000\n\
+         flow a,,,0\n\
+         flow b,0.5,,\n\
+         \"flow, c\",,,1\n"
+    );
+}
+
+#[test]
+fn json_has_counts_of_every_interval() {
+    let mut heatmap = Heatmap::new(500_000_000);
+    heatmap.add("flow a".to_string(), at(1, 0), true);
+    heatmap.add("flow a".to_string(), at(2, 0), false);
+    heatmap.add("flow a".to_string(), at(2, 100), false);
+    assert_eq!(
+        text(&heatmap, true),
+        "{\"interval_sec\":0.5,\"intervals\":[\"1.000000000\",\"1.500000000\",\"2.000000000\"],\
+         \"flows\":[{\"flow\":\"flow a\",\"packets\":[1,0,2],\"misses\":[1,0,0]}]}\n"
+    );
+}
+
+#[test]
+fn empty_heatmap_has_only_header() {
+    assert_eq!(text(&Heatmap::new(1), false), "flow\n");
+}
+
+#[test]
+fn only_intervals_with_packets_if_they_span_too_many() {
+    // A packet at the epoch next to current traffic would take a column of every second between
+    let mut heatmap = Heatmap::new(1_000_000_000);
+    heatmap.add("flow a".to_string(), at(0, 0), true);
+    heatmap.add("flow a".to_string(), at(1_700_000_000, 0), false);
+    heatmap.add("flow b".to_string(), at(1_700_000_001, 0), false);
+    assert_eq!(
+        text(&heatmap, false),
+        "flow,0.000000000,1700000000.000000000,1700000001.000000000\n\
+         flow a,1,0,\n\
+         flow b,,,0\n"
+    );
+}
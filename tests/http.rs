@@ -0,0 +1,110 @@
+use latency_measurement_tool::exchange::{Direction, Exchange, Local};
+use latency_measurement_tool::reassembly::Reassembler;
+use latency_measurement_tool::{PacketId, PacketTime};
+use pcap_parser::Linktype;
+use std::collections::VecDeque;
+
+const SYN: u8 = 0x02;
+const ACK: u8 = 0x10;
+
+/// IPv4 packet with TCP segment of client 10.0.0.1:1000 to server 10.0.0.2:80 or back
+fn segment(from_client: bool, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+    let (ip_src, ip_dst, port_src, port_dst) = match from_client {
+        true => ([10, 0, 0, 1], [10, 0, 0, 2], 1000u16, 80u16),
+        false => ([10, 0, 0, 2], [10, 0, 0, 1], 80, 1000),
+    };
+    let total_len = (40 + payload.len()) as u16;
+    let mut packet = vec![0x45, 0];
+    packet.extend_from_slice(&total_len.to_be_bytes());
+    packet.extend_from_slice(&[0, 1, 0, 0, 64, 6, 0, 0]);
+    packet.extend_from_slice(&ip_src);
+    packet.extend_from_slice(&ip_dst);
+    packet.extend_from_slice(&port_src.to_be_bytes());
+    packet.extend_from_slice(&port_dst.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+fn time(usec: u64) -> PacketTime {
+    PacketTime { nsec: usec * 1000 }
+}
+
+/// Indexes and times of exchanges of direction in segments, given as sent by the client or
+/// not, sequence number, flags and payload
+fn exchanges(direction: Direction, segments: &[(bool, u32, u8, &[u8])]) -> Vec<(u32, PacketTime)> {
+    let mut reassembler = Reassembler::http(Exchange {
+        local: Local::Any,
+        direction,
+        http: true,
+    });
+    let mut messages = VecDeque::new();
+    for (i, (from_client, seq, flags, payload)) in segments.iter().enumerate() {
+        let packet = segment(*from_client, *seq, *flags, payload);
+        assert!(reassembler.push(Linktype::RAW, &packet, time(i as u64), &mut messages));
+    }
+    messages
+        .iter()
+        .map(|(id, time)| match id {
+            PacketId::HttpExchange { index, .. } => (*index, *time),
+            id => panic!("not an HTTP exchange: {}", id),
+        })
+        .collect()
+}
+
+#[test]
+fn pipelined_requests_are_answered_in_order() {
+    let segments: [(bool, u32, u8, &[u8]); 6] = [
+        (true, 99, SYN, b""),
+        (false, 499, SYN | ACK, b""),
+        (
+            true,
+            100,
+            ACK,
+            b"GET /a HTTP/1.1\r\n\r\nPOST /b HTTP/1.1\r\nContent-Length: 3\r\n\r\nab",
+        ),
+        (true, 160, ACK, b"c"),
+        (
+            false,
+            500,
+            ACK,
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nok\r\n0\r\n\r\nHTTP/1.1 100 Continue\r\n\r\n",
+        ),
+        (false, 584, ACK, b"HTTP/1.1 204 No Content\r\n\r\n"),
+    ];
+    assert_eq!(
+        exchanges(Direction::Request, &segments),
+        vec![(0, time(2)), (1, time(3))]
+    );
+    assert_eq!(
+        exchanges(Direction::Response, &segments),
+        vec![(0, time(4)), (1, time(5))]
+    );
+}
+
+#[test]
+fn connections_open_before_the_capture_are_skipped() {
+    let segments: [(bool, u32, u8, &[u8]); 2] = [
+        (true, 100, ACK, b"GET / HTTP/1.1\r\n\r\n"),
+        (
+            false,
+            500,
+            ACK,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+        ),
+    ];
+    assert_eq!(exchanges(Direction::Request, &segments), vec![]);
+    assert_eq!(exchanges(Direction::Response, &segments), vec![]);
+}
+
+#[test]
+fn connection_which_is_not_http_is_dropped() {
+    let segments: [(bool, u32, u8, &[u8]); 4] = [
+        (true, 99, SYN, b""),
+        (false, 499, SYN | ACK, b""),
+        (true, 100, ACK, b"SSH-2.0-OpenSSH\r\n\r\n"),
+        (true, 119, ACK, b"GET / HTTP/1.1\r\n\r\n"),
+    ];
+    assert_eq!(exchanges(Direction::Request, &segments), vec![]);
+}
@@ -0,0 +1,102 @@
+use latency_measurement_tool::key::{KeyFields, KeySpec};
+use latency_measurement_tool::PacketId;
+use std::net::IpAddr;
+
+fn key(specs: &[&str]) -> KeyFields {
+    let specs: Vec<KeySpec> = specs.iter().map(|spec| spec.parse().unwrap()).collect();
+    KeyFields::new(&specs).unwrap()
+}
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn tcp() -> PacketId {
+    PacketId::Tcp {
+        ip_src: ip("10.0.0.1"),
+        ip_dst: ip("10.0.0.2"),
+        port_src: 1000,
+        port_dst: 80,
+        tcp_seq: 100,
+        tcp_ack: 5,
+    }
+}
+
+fn icmp() -> PacketId {
+    PacketId::Icmp {
+        ip_src: ip("fe80::1"),
+        ip_dst: ip("fe80::2"),
+        checksum: 0x1234,
+    }
+}
+
+#[test]
+fn fields_left_out_are_cleared() {
+    let mut id = tcp();
+    key(&["ip_dst,tcp_seq"]).apply(&mut id);
+    assert_eq!(
+        id,
+        PacketId::Tcp {
+            ip_src: ip("0.0.0.0"),
+            ip_dst: ip("10.0.0.2"),
+            port_src: 0,
+            port_dst: 0,
+            tcp_seq: 100,
+            tcp_ack: 0,
+        }
+    );
+}
+
+#[test]
+fn fields_without_kind_apply_to_kinds_which_have_them_all() {
+    let key = key(&["ip_src,ip_dst"]);
+    let (mut tcp_id, mut icmp_id) = (tcp(), icmp());
+    key.apply(&mut tcp_id);
+    key.apply(&mut icmp_id);
+    assert!(matches!(tcp_id, PacketId::Tcp { tcp_seq: 0, .. }));
+    assert_eq!(
+        icmp_id,
+        PacketId::Icmp {
+            ip_src: ip("fe80::1"),
+            ip_dst: ip("fe80::2"),
+            checksum: 0,
+        }
+    );
+}
+
+#[test]
+fn kinds_without_key_keep_their_fields() {
+    let mut id = icmp();
+    key(&["tcp:tcp_seq"]).apply(&mut id);
+    assert_eq!(id, icmp());
+}
+
+#[test]
+fn wrapped_identities_are_cleared_inside() {
+    let mut id = PacketId::Geneve {
+        vni: 7,
+        inner: Box::new(tcp()),
+    };
+    key(&["tcp:ip_src,ip_dst,port_src,port_dst,tcp_seq"]).apply(&mut id);
+    let PacketId::Geneve { vni: 7, inner } = id else {
+        panic!("{:?}", id);
+    };
+    assert!(matches!(
+        *inner,
+        PacketId::Tcp {
+            tcp_seq: 100,
+            tcp_ack: 0,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn unknown_and_repeated_fields_are_errors() {
+    assert!("tcp:checksum".parse::<KeySpec>().is_err());
+    assert!("udp:ip_src".parse::<KeySpec>().is_err());
+    assert!("tcp_seq,checksum".parse::<KeySpec>().is_err());
+    assert!("ip_src,".parse::<KeySpec>().is_err());
+    let specs = ["ip_src".parse().unwrap(), "icmp:checksum".parse().unwrap()];
+    assert!(KeyFields::new(&specs).is_err());
+}
@@ -0,0 +1,51 @@
+use latency_measurement_tool::live::{LiveEvent, LiveMatcher, Side};
+use latency_measurement_tool::{PacketId, PacketTime};
+use std::time::Duration;
+
+fn icmp(checksum: u16) -> PacketId {
+    PacketId::Icmp {
+        ip_src: "10.0.0.1".parse().unwrap(),
+        ip_dst: "10.0.0.2".parse().unwrap(),
+        checksum,
+    }
+}
+
+fn at_msec(msec: u64) -> PacketTime {
+    PacketTime {
+        nsec: msec * 1_000_000,
+    }
+}
+
+#[test]
+fn miss_after_expired_pair_is_possibly_evicted() {
+    let mut matcher = LiveMatcher::new(Duration::from_millis(100));
+    assert_eq!(
+        matcher.push(Side::Out, icmp(1), at_msec(0)),
+        LiveEvent::Pending
+    );
+    assert_eq!(
+        matcher.push(Side::Out, icmp(2), at_msec(150)),
+        LiveEvent::Pending
+    );
+    // Pair of the first packet expired at 150 ms, the inbound one is late
+    assert_eq!(
+        matcher.push(Side::In, icmp(1), at_msec(120)),
+        LiveEvent::Pending
+    );
+    // Inbound packet without any pair
+    assert_eq!(
+        matcher.push(Side::In, icmp(3), at_msec(200)),
+        LiveEvent::Pending
+    );
+    assert_eq!(
+        matcher.push(Side::In, icmp(5), at_msec(210)),
+        LiveEvent::Pending
+    );
+    assert_eq!(
+        matcher.push(Side::Out, icmp(5), at_msec(260)),
+        LiveEvent::Matched(50_000)
+    );
+    matcher.push(Side::Out, icmp(4), at_msec(400));
+    assert_eq!(matcher.misses(), 2);
+    assert_eq!(matcher.possibly_evicted(), 1);
+}
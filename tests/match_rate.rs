@@ -0,0 +1,21 @@
+//! `--match-rate` runs the binary on captures of `tests/fixtures`: the reports of every second
+//! depend on timing, but the last one, of the whole run, must be the same every time
+
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn final_report_gives_share_of_matched_packets() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let output = Command::new(env!("CARGO_BIN_EXE_latency_measurement_tool"))
+        .args(["--match-rate", "tcp_icmp_in.pcap", "tcp_icmp_out.pcap"])
+        .current_dir(fixtures)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.lines().last(),
+        Some("match rate done: 10 of 11 inbound packets matched (90.9%)")
+    );
+}
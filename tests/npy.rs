@@ -0,0 +1,39 @@
+use latency_measurement_tool::npy;
+
+/// Header dict and values of `.npy` file
+fn parse(bytes: &[u8]) -> (String, Vec<f64>) {
+    assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+    let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    let data_offset = 10 + header_len;
+    assert_eq!(data_offset % 64, 0);
+    let header = std::str::from_utf8(&bytes[10..data_offset]).unwrap();
+    assert!(header.ends_with('\n'));
+    let values = bytes[data_offset..]
+        .chunks(8)
+        .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    (header.trim_end().to_string(), values)
+}
+
+#[test]
+fn values_follow_aligned_header_with_shape() {
+    let mut bytes = Vec::new();
+    npy::write_f64(&mut bytes, &[150.0, f64::NAN, 200.5]).unwrap();
+    let (header, values) = parse(&bytes);
+    assert_eq!(
+        header,
+        "{'descr': '<f8', 'fortran_order': False, 'shape': (3,), }"
+    );
+    assert_eq!(values[0], 150.0);
+    assert!(values[1].is_nan());
+    assert_eq!(values[2], 200.5);
+}
+
+#[test]
+fn empty_array_has_zero_length_shape() {
+    let mut bytes = Vec::new();
+    npy::write_f64(&mut bytes, &[]).unwrap();
+    let (header, values) = parse(&bytes);
+    assert!(header.contains("'shape': (0,)"));
+    assert!(values.is_empty());
+}
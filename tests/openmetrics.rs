@@ -0,0 +1,39 @@
+use latency_measurement_tool::openmetrics::Histogram;
+
+fn exposition(histogram: &Histogram) -> String {
+    let mut bytes = Vec::new();
+    histogram.write(&mut bytes).unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn buckets_are_cumulative_with_first_exemplar() {
+    let mut histogram = Histogram::new();
+    histogram.observe(150, || "flow a".to_string());
+    histogram.observe(-180, || "flow b".to_string());
+    histogram.observe(2_000_000, || "flow c".to_string());
+    let text = exposition(&histogram);
+    assert!(text.contains(
+        "latency_measurement_latency_microseconds_bucket{le=\"200.0\"} 2 # {flow=\"flow a\"} 150.0\n"
+    ));
+    assert!(text.contains("latency_measurement_latency_microseconds_bucket{le=\"500.0\"} 2\n"));
+    assert!(text.contains(
+        "latency_measurement_latency_microseconds_bucket{le=\"+Inf\"} 3 # {flow=\"flow c\"} 2000000.0\n"
+    ));
+    assert!(text.contains("latency_measurement_latency_microseconds_sum 2000330.0\n"));
+    assert!(text.contains("latency_measurement_latency_microseconds_count 3\n"));
+    assert!(text.ends_with("# EOF\n"));
+}
+
+#[test]
+fn exemplar_label_is_escaped_and_bounded() {
+    let mut histogram = Histogram::new();
+    histogram.observe(1, || format!("\"{}", "x".repeat(200)));
+    let text = exposition(&histogram);
+    let line = text
+        .lines()
+        .find(|line| line.contains("le=\"1.0\""))
+        .unwrap();
+    assert!(line.contains("{flow=\"\\\"xxx"));
+    assert_eq!(line.matches('x').count(), 123);
+}
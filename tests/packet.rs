@@ -0,0 +1,477 @@
+use latency_measurement_tool::packet::{self, Decap, KeyBytes, MatchMode, ParseOptions};
+use latency_measurement_tool::PacketId;
+use pcap_parser::Linktype;
+use std::net::IpAddr;
+
+mod common;
+
+use common::ethernet;
+
+const SRC: &str = "2001:db8::1";
+const DST: &str = "2001:db8::2";
+
+fn ipv4(protocol: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x45, 0];
+    packet.extend_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 1, 0, 0, 64, protocol, 0, 0]);
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+fn ipv6(next_header: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x60, 0, 0, 0];
+    packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    packet.extend_from_slice(&[next_header, 64]);
+    packet.extend_from_slice(&SRC.parse::<std::net::Ipv6Addr>().unwrap().octets());
+    packet.extend_from_slice(&DST.parse::<std::net::Ipv6Addr>().unwrap().octets());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+fn tcp(port_src: u16, port_dst: u16, seq: u32, ack: u32) -> Vec<u8> {
+    let mut segment = Vec::new();
+    segment.extend_from_slice(&port_src.to_be_bytes());
+    segment.extend_from_slice(&port_dst.to_be_bytes());
+    segment.extend_from_slice(&seq.to_be_bytes());
+    segment.extend_from_slice(&ack.to_be_bytes());
+    segment.extend_from_slice(&[0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+    segment
+}
+
+#[test]
+fn loopback_family_header_in_either_byte_order() {
+    let raw = ipv4(6, &tcp(1000, 80, 100, 5));
+    let expected = PacketId::new_from_frame(Linktype::RAW, &raw, &ParseOptions::default());
+    assert!(expected.is_some());
+    let id = |link_type, family: [u8; 4]| {
+        let mut frame = family.to_vec();
+        frame.extend_from_slice(&raw);
+        PacketId::new_from_frame(link_type, &frame, &ParseOptions::default())
+    };
+    // DLT_NULL of little- and big-endian hosts, DLT_LOOP of OpenBSD (AF_INET is 2 everywhere)
+    assert_eq!(id(Linktype::NULL, 2u32.to_le_bytes()), expected);
+    assert_eq!(id(Linktype::NULL, 2u32.to_be_bytes()), expected);
+    assert_eq!(id(Linktype::LOOP, 2u32.to_be_bytes()), expected);
+    // DLT_LOOP is in network order only, and an unknown family isn't IPv4
+    assert_eq!(id(Linktype::LOOP, 2u32.to_le_bytes()), None);
+    assert_eq!(id(Linktype::NULL, 7u32.to_le_bytes()), None);
+}
+
+#[test]
+fn ipv6_hop_by_hop_option_before_tcp() {
+    // Hop-by-Hop header: next header TCP, length 0 (8 bytes), PadN option of 4 bytes
+    let mut payload = vec![6, 0, 1, 4, 0, 0, 0, 0];
+    payload.extend(tcp(1000, 80, 100, 5));
+    let frame = ethernet(0x86dd, &ipv6(0, &payload));
+    let id = PacketId::new_from_frame(Linktype::ETHERNET, &frame, &ParseOptions::default());
+    assert_eq!(
+        id,
+        Some(PacketId::Tcp {
+            ip_src: SRC.parse::<IpAddr>().unwrap(),
+            ip_dst: DST.parse::<IpAddr>().unwrap(),
+            port_src: 1000,
+            port_dst: 80,
+            tcp_seq: 100,
+            tcp_ack: 5,
+        })
+    );
+}
+
+#[test]
+fn tcp_ignore_ack_identifies_by_seq() {
+    let options = ParseOptions {
+        tcp_ignore_ack: true,
+        ..Default::default()
+    };
+    let id =
+        |ack| PacketId::new_from_frame(Linktype::RAW, &ipv6(6, &tcp(1000, 80, 100, ack)), &options);
+    assert_eq!(id(5), id(7));
+    assert!(matches!(id(5), Some(PacketId::Tcp { tcp_ack: 0, .. })));
+}
+
+#[test]
+fn ipv6_non_first_fragment_is_skipped() {
+    // Fragment header: next header TCP, offset 1 (8 bytes), identification 1
+    let mut payload = vec![6, 0, 0, 8, 0, 0, 0, 1];
+    payload.extend(tcp(1000, 80, 100, 5));
+    let packet = ipv6(44, &payload);
+    let id = PacketId::new_from_frame(Linktype::RAW, &packet, &ParseOptions::default());
+    assert_eq!(id, None);
+}
+
+fn udp(port_src: u16, port_dst: u16, payload: &[u8]) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    datagram.extend_from_slice(&port_src.to_be_bytes());
+    datagram.extend_from_slice(&port_dst.to_be_bytes());
+    datagram.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(payload);
+    datagram
+}
+
+fn geneve_frame() -> Vec<u8> {
+    // Options length 2 words, protocol type Ethernet, VNI 0x123456, one option of 4 bytes
+    // (class, type, length 1 word) with 4 bytes of data
+    let mut geneve = vec![0x02, 0, 0x65, 0x58, 0x12, 0x34, 0x56, 0];
+    geneve.extend_from_slice(&[0x01, 0x02, 0x03, 0x01, 0xde, 0xad, 0xbe, 0xef]);
+    geneve.extend(ethernet(0x86dd, &ipv6(6, &tcp(1000, 80, 100, 5))));
+    ethernet(0x86dd, &ipv6(17, &udp(49152, 6081, &geneve)))
+}
+
+#[test]
+fn geneve_with_options_is_decapsulated() {
+    let options = ParseOptions {
+        decap: Some(Decap::Geneve),
+        ..ParseOptions::default()
+    };
+    let inner = PacketId::Tcp {
+        ip_src: SRC.parse::<IpAddr>().unwrap(),
+        ip_dst: DST.parse::<IpAddr>().unwrap(),
+        port_src: 1000,
+        port_dst: 80,
+        tcp_seq: 100,
+        tcp_ack: 5,
+    };
+    let id = PacketId::new_from_frame(Linktype::ETHERNET, &geneve_frame(), &options);
+    assert_eq!(id, Some(inner.clone()));
+
+    let options = ParseOptions {
+        decap_vni: true,
+        ..options
+    };
+    let id = PacketId::new_from_frame(Linktype::ETHERNET, &geneve_frame(), &options);
+    assert_eq!(
+        id,
+        Some(PacketId::Geneve {
+            vni: 0x123456,
+            inner: Box::new(inner),
+        })
+    );
+}
+
+fn dns_query() -> Vec<u8> {
+    // Transaction ID 0xbeef, recursion desired, one question: example.com, type AAAA, class IN
+    let mut message = vec![0xbe, 0xef, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+    message.extend_from_slice(b"\x07example\x03com\x00");
+    message.extend_from_slice(&[0, 28, 0, 1]);
+    message
+}
+
+fn dns_id() -> PacketId {
+    PacketId::Dns {
+        transaction_id: 0xbeef,
+        query_name: "example.com".to_string(),
+        qtype: 28,
+    }
+}
+
+#[test]
+fn dns_over_udp_and_tcp() {
+    let options = ParseOptions {
+        dns: true,
+        ..ParseOptions::default()
+    };
+    let packet = ipv6(17, &udp(49152, 53, &dns_query()));
+    let id = PacketId::new_from_frame(Linktype::RAW, &packet, &options);
+    assert_eq!(id, Some(dns_id()));
+
+    let mut segment = tcp(49152, 53, 100, 5);
+    segment.extend_from_slice(&(dns_query().len() as u16).to_be_bytes());
+    segment.extend(dns_query());
+    let packet = ipv6(6, &segment);
+    let id = PacketId::new_from_frame(Linktype::RAW, &packet, &options);
+    assert_eq!(id, Some(dns_id()));
+}
+
+#[test]
+fn malformed_dns_is_skipped() {
+    let options = ParseOptions {
+        dns: true,
+        ..ParseOptions::default()
+    };
+    let mut query = dns_query();
+    query.truncate(20);
+    let packet = ipv6(17, &udp(53, 49152, &query));
+    let id = PacketId::new_from_frame(Linktype::RAW, &packet, &options);
+    assert_eq!(id, None);
+}
+
+#[test]
+fn ip_id_mode_identifies_by_ipv4_header() {
+    let options = ParseOptions {
+        match_mode: MatchMode::IpId,
+        ..Default::default()
+    };
+    // IPv4 header: total length 28, identification 0x1234, protocol UDP
+    let mut packet = vec![
+        0x45, 0, 0, 28, 0x12, 0x34, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+    ];
+    packet.extend(udp(5000, 6000, &[]));
+    let id = PacketId::new_from_frame(Linktype::RAW, &packet, &options);
+    assert_eq!(
+        id,
+        Some(PacketId::IpId {
+            ip_src: "10.0.0.1".parse().unwrap(),
+            ip_dst: "10.0.0.2".parse().unwrap(),
+            ip_id: 0x1234,
+            fragment_offset: 0,
+            payload_len: 8,
+        })
+    );
+    let ipv6 = ipv6(17, &udp(5000, 6000, &[]));
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::RAW, &ipv6, &options),
+        None
+    );
+}
+
+#[test]
+fn l2_mode_identifies_frames_of_any_protocol() {
+    let options = ParseOptions {
+        match_mode: MatchMode::L2,
+        ..Default::default()
+    };
+    // ARP isn't IP, it's identified only in L2 mode
+    let arp = ethernet(0x0806, &[0, 1, 8, 0, 6, 4, 0, 1]);
+    let id = PacketId::new_from_frame(Linktype::ETHERNET, &arp, &options).unwrap();
+    assert!(matches!(
+        id,
+        PacketId::Ethernet {
+            src_mac: [0x00, 0x11, 0x22, 0x33, 0x44, 0x55],
+            dst_mac: [0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb],
+            ethertype: 0x0806,
+            ..
+        }
+    ));
+    assert_eq!(
+        id.to_string(),
+        "eth 00:11:22:33:44:55 > 66:77:88:99:aa:bb type 0x0806 hash 1c11c820ff83b54b"
+    );
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &arp, &ParseOptions::default()),
+        None
+    );
+    // Frames differing only in payload differ
+    let other = ethernet(0x0806, &[0, 1, 8, 0, 6, 4, 0, 2]);
+    assert_ne!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &other, &options),
+        Some(id)
+    );
+    // Without Ethernet addresses nothing is identified
+    let packet = ipv4(17, &udp(5000, 6000, &[]));
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::RAW, &packet, &options),
+        None
+    );
+}
+
+#[test]
+fn bytes_mode_identifies_by_bytes_at_offset() {
+    let options = |with_ips| ParseOptions {
+        match_mode: MatchMode::Bytes,
+        key_bytes: Some(KeyBytes {
+            range: 42..46,
+            with_ips,
+        }),
+        ..Default::default()
+    };
+    let frame = ethernet(0x0800, &ipv4(17, &udp(5000, 6000, b"abcd")));
+    let id = PacketId::new_from_frame(Linktype::ETHERNET, &frame, &options(false)).unwrap();
+    assert_eq!(id.to_string(), "bytes key 61626364");
+    // Ports differ, the key doesn't
+    let other = ethernet(0x0800, &ipv4(17, &udp(5001, 6001, b"abcd")));
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &other, &options(false)),
+        Some(id)
+    );
+    let id = PacketId::new_from_frame(Linktype::ETHERNET, &frame, &options(true)).unwrap();
+    assert_eq!(id.to_string(), "bytes 10.0.0.1 > 10.0.0.2 key 61626364");
+    // Frames too short for the key are skipped
+    let short = ethernet(0x0800, &ipv4(17, &udp(5000, 6000, b"ab")));
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &short, &options(false)),
+        None
+    );
+    // Addresses in the key require IP
+    let arp = ethernet(0x0806, &[0; 46]);
+    assert!(PacketId::new_from_frame(Linktype::ETHERNET, &arp, &options(false)).is_some());
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &arp, &options(true)),
+        None
+    );
+}
+
+#[test]
+fn dscp_of_ipv4_and_ipv6() {
+    let mut packet = ipv4(17, &udp(5000, 6000, &[]));
+    packet[1] = 46 << 2 | 1;
+    assert_eq!(packet::dscp(Linktype::RAW, &packet), Some(46));
+    let mut packet = ipv6(17, &udp(5000, 6000, &[]));
+    // Traffic class spans the low 4 bits of the first byte and the high 4 bits of the second
+    packet[0] |= (34 << 2) >> 4;
+    packet[1] |= (34 << 2 & 0x0f) << 4;
+    assert_eq!(packet::dscp(Linktype::RAW, &packet), Some(34));
+    let arp = ethernet(0x0806, &[0; 28]);
+    assert_eq!(packet::dscp(Linktype::ETHERNET, &arp), None);
+}
+
+/// Ethernet frame with VLAN tags of the IDs, the outermost first
+fn tagged(tags: &[(u16, u16)], payload: &[u8]) -> Vec<u8> {
+    let mut frame = ethernet(0x0800, payload);
+    for (ethertype, id) in tags.iter().rev() {
+        let mut tag = ethertype.to_be_bytes().to_vec();
+        // Priority bits aren't part of the ID
+        tag.extend_from_slice(&(0xe000 | id).to_be_bytes());
+        frame.splice(12..12, tag);
+    }
+    frame
+}
+
+#[test]
+fn vlan_ids_are_part_of_identity_unless_ignored() {
+    let packet = ipv4(6, &tcp(1000, 80, 100, 5));
+    let qinq = tagged(&[(0x88a8, 100), (0x8100, 10)], &packet);
+    assert_eq!(packet::vlan_ids(Linktype::ETHERNET, &qinq), [100, 10]);
+    let options = ParseOptions::default();
+    let id = PacketId::new_from_frame(Linktype::ETHERNET, &qinq, &options).unwrap();
+    let untagged = ethernet(0x0800, &packet);
+    let inner = PacketId::new_from_frame(Linktype::ETHERNET, &untagged, &options).unwrap();
+    assert_eq!(
+        id,
+        PacketId::Vlan {
+            ids: vec![100, 10],
+            inner: Box::new(inner.clone())
+        }
+    );
+    assert!(id.to_string().starts_with("vlan 100/10 "));
+    assert_eq!(id.fuzzy_key(), inner.fuzzy_key());
+    // Translated to another ID, or with the tags ignored
+    let translated = tagged(&[(0x8100, 200)], &packet);
+    assert_ne!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &translated, &options),
+        Some(id)
+    );
+    let options = ParseOptions {
+        ignore_vlan: true,
+        ..Default::default()
+    };
+    for frame in [&qinq, &translated, &untagged] {
+        assert_eq!(
+            PacketId::new_from_frame(Linktype::ETHERNET, frame, &options),
+            Some(inner.clone())
+        );
+    }
+    // Frames of L2 mode are told by the EtherType and payload following the tags
+    let options = ParseOptions {
+        match_mode: MatchMode::L2,
+        ignore_vlan: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &translated, &options),
+        PacketId::new_from_frame(Linktype::ETHERNET, &untagged, &options)
+    );
+}
+
+fn inner_tcp_v4() -> PacketId {
+    PacketId::Tcp {
+        ip_src: "10.0.0.1".parse().unwrap(),
+        ip_dst: "10.0.0.2".parse().unwrap(),
+        port_src: 1000,
+        port_dst: 80,
+        tcp_seq: 100,
+        tcp_ack: 5,
+    }
+}
+
+fn inner_tcp_v6() -> PacketId {
+    PacketId::Tcp {
+        ip_src: SRC.parse().unwrap(),
+        ip_dst: DST.parse().unwrap(),
+        port_src: 1000,
+        port_dst: 80,
+        tcp_seq: 100,
+        tcp_ack: 5,
+    }
+}
+
+#[test]
+fn ip_in_ip_of_other_version_is_decapsulated() {
+    let options = ParseOptions {
+        decap: Some(Decap::IpInIp),
+        ..ParseOptions::default()
+    };
+    let id = |frame: &[u8]| PacketId::new_from_frame(Linktype::ETHERNET, frame, &options);
+    let ipv4_in_ipv6 = ethernet(0x86dd, &ipv6(4, &ipv4(6, &tcp(1000, 80, 100, 5))));
+    assert_eq!(id(&ipv4_in_ipv6), Some(inner_tcp_v4()));
+    let ipv6_in_ipv4 = ethernet(0x0800, &ipv4(41, &ipv6(6, &tcp(1000, 80, 100, 5))));
+    assert_eq!(id(&ipv6_in_ipv4), Some(inner_tcp_v6()));
+    let ipv4_in_ipv4 = ethernet(0x0800, &ipv4(4, &ipv4(6, &tcp(1000, 80, 100, 5))));
+    assert_eq!(id(&ipv4_in_ipv4), Some(inner_tcp_v4()));
+}
+
+#[test]
+fn ip_in_ip_with_wrong_inner_version_is_not_decapsulated() {
+    let options = ParseOptions {
+        decap: Some(Decap::IpInIp),
+        ..ParseOptions::default()
+    };
+    // Protocol 41 announces IPv6, but IPv4 follows: the outer packet has no transport header
+    // the tool knows, so it gets no identity
+    let frame = ethernet(0x0800, &ipv4(41, &ipv4(6, &tcp(1000, 80, 100, 5))));
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &frame, &options),
+        None
+    );
+}
+
+#[test]
+fn geneve_over_ipv4_carries_ipv6() {
+    let options = ParseOptions {
+        decap: Some(Decap::Geneve),
+        ..ParseOptions::default()
+    };
+    let mut geneve = vec![0, 0, 0x65, 0x58, 0, 0, 1, 0];
+    geneve.extend(ethernet(0x86dd, &ipv6(6, &tcp(1000, 80, 100, 5))));
+    let frame = ethernet(0x0800, &ipv4(17, &udp(49152, 6081, &geneve)));
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &frame, &options),
+        Some(inner_tcp_v6())
+    );
+}
+
+#[test]
+fn icmp_error_is_identified_as_quoted_packet() {
+    let offending = ipv4(6, &tcp(1000, 80, 100, 5));
+    let mut error = vec![11, 0, 0, 0, 0, 0, 0, 0];
+    error.extend_from_slice(&offending);
+    let frame = ethernet(0x0800, &ipv4(1, &error));
+    let options = ParseOptions {
+        icmp_errors: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &frame, &options),
+        Some(inner_tcp_v4())
+    );
+    assert!(matches!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &frame, &ParseOptions::default()),
+        Some(PacketId::Icmp { .. })
+    ));
+    // Quote of the first 8 bytes of the segment only
+    let frame = ethernet(0x0800, &ipv4(1, &error[..8 + 28]));
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &frame, &options),
+        None
+    );
+    // ICMPv6 Packet Too Big
+    let offending = ipv6(6, &tcp(1000, 80, 100, 5));
+    let mut error = vec![2, 0, 0, 0, 0, 0, 0x05, 0xdc];
+    error.extend_from_slice(&offending);
+    let frame = ethernet(0x86dd, &ipv6(58, &error));
+    assert_eq!(
+        PacketId::new_from_frame(Linktype::ETHERNET, &frame, &options),
+        Some(inner_tcp_v6())
+    );
+}
@@ -0,0 +1,208 @@
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Int64Type, TimestampNanosecondType, UInt16Type};
+use arrow_array::RecordBatch;
+use arrow_schema::{DataType, TimeUnit};
+use latency_measurement_tool::parquet::{ParquetWriter, Row, ROW_GROUP_ROWS};
+use latency_measurement_tool::{PacketId, PacketTime};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::metadata::ParquetMetaData;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+mod common;
+
+use common::temp_path;
+
+fn write(name: &str, rows: impl IntoIterator<Item = Row>) -> PathBuf {
+    let path = temp_path(name);
+    let mut writer = ParquetWriter::new(File::create(&path).unwrap()).unwrap();
+    for row in rows {
+        writer.push(row).unwrap();
+    }
+    writer.finish().unwrap();
+    path
+}
+
+fn reader(path: &Path) -> ParquetRecordBatchReaderBuilder<File> {
+    ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap()
+}
+
+fn metadata(path: &Path) -> Arc<ParquetMetaData> {
+    reader(path).metadata().clone()
+}
+
+/// Rows of a file short enough to be read as one batch
+fn read(path: &Path) -> RecordBatch {
+    let mut batches = reader(path).build().unwrap();
+    let batch = batches.next().unwrap().unwrap();
+    assert!(batches.next().is_none());
+    batch
+}
+
+fn tcp_row(nsec: u64) -> Row {
+    Row {
+        in_time: PacketTime { nsec },
+        out_time: PacketTime { nsec: nsec - 1000 },
+        latency: 1,
+        fuzzy: false,
+        id: PacketId::Tcp {
+            ip_src: "10.0.0.1".parse().unwrap(),
+            ip_dst: "10.0.0.2".parse().unwrap(),
+            port_src: 1000,
+            port_dst: 80,
+            tcp_seq: nsec as u32,
+            tcp_ack: 0,
+        },
+    }
+}
+
+fn icmp_row(nsec: u64) -> Row {
+    Row {
+        fuzzy: true,
+        id: PacketId::Icmp {
+            ip_src: "10.0.0.3".parse().unwrap(),
+            ip_dst: "10.0.0.4".parse().unwrap(),
+            checksum: 0,
+        },
+        ..tcp_row(nsec)
+    }
+}
+
+#[test]
+fn schema_has_typed_columns() {
+    let path = write("schema.parquet", [tcp_row(5000)]);
+    let batch = read(&path);
+    let schema = batch.schema();
+    let names: Vec<&str> = schema
+        .fields()
+        .iter()
+        .map(|field| field.name().as_str())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "in_time",
+            "out_time",
+            "latency_usec",
+            "fuzzy",
+            "protocol",
+            "ip_src",
+            "ip_dst",
+            "port_src",
+            "port_dst"
+        ]
+    );
+    // Timestamps in nanoseconds, adjusted to UTC
+    assert_eq!(
+        schema.field(0).data_type(),
+        &DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into()))
+    );
+    // Optional ports
+    assert_eq!(schema.field(7).data_type(), &DataType::UInt16);
+    assert!(schema.field(7).is_nullable());
+    assert!(!schema.field(4).is_nullable());
+    let created_by = metadata(&path)
+        .file_metadata()
+        .created_by()
+        .map(String::from);
+    assert!(created_by.is_some_and(|name| name.starts_with("latency_measurement_tool version ")));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rows_are_read_back() {
+    let path = write("rows.parquet", [tcp_row(5000), icmp_row(7000)]);
+    let batch = read(&path);
+    fs::remove_file(&path).unwrap();
+    let in_times = batch.column(0).as_primitive::<TimestampNanosecondType>();
+    assert_eq!(in_times.values().to_vec(), [5000, 7000]);
+    let out_times = batch.column(1).as_primitive::<TimestampNanosecondType>();
+    assert_eq!(out_times.values().to_vec(), [4000, 6000]);
+    let latencies = batch.column(2).as_primitive::<Int64Type>();
+    assert_eq!(latencies.values().to_vec(), [1, 1]);
+    let fuzzy: Vec<Option<bool>> = batch.column(3).as_boolean().iter().collect();
+    assert_eq!(fuzzy, [Some(false), Some(true)]);
+    let protocols: Vec<Option<&str>> = batch.column(4).as_string::<i32>().iter().collect();
+    assert_eq!(protocols, [Some("tcp"), Some("icmp")]);
+    let ip_src: Vec<Option<&str>> = batch.column(5).as_string::<i32>().iter().collect();
+    assert_eq!(ip_src, [Some("10.0.0.1"), Some("10.0.0.3")]);
+    // ICMP has no ports
+    let port_dst: Vec<Option<u16>> = batch
+        .column(8)
+        .as_primitive::<UInt16Type>()
+        .iter()
+        .collect();
+    assert_eq!(port_dst, [Some(80), None]);
+}
+
+#[test]
+fn rows_are_batched_in_row_groups() {
+    let rows = (0..ROW_GROUP_ROWS as u64 + 10).map(|i| tcp_row(1000 + i));
+    let path = write("row-groups.parquet", rows);
+    let metadata = metadata(&path);
+    fs::remove_file(&path).unwrap();
+    let rows: Vec<i64> = metadata
+        .row_groups()
+        .iter()
+        .map(|group| group.num_rows())
+        .collect();
+    assert_eq!(rows, [ROW_GROUP_ROWS as i64, 10]);
+    assert_eq!(
+        metadata.file_metadata().num_rows(),
+        ROW_GROUP_ROWS as i64 + 10
+    );
+}
+
+#[test]
+fn empty_file_has_schema_and_no_row_groups() {
+    let path = write("empty.parquet", []);
+    let metadata = metadata(&path);
+    fs::remove_file(&path).unwrap();
+    assert!(metadata.row_groups().is_empty());
+    assert_eq!(metadata.file_metadata().num_rows(), 0);
+    assert_eq!(metadata.file_metadata().schema_descr().num_columns(), 9);
+}
+
+/// Whether `python3` with pyarrow is there to read files like data tooling does
+fn has_pyarrow() -> bool {
+    Command::new("python3")
+        .args(["-c", "import pyarrow.parquet"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+#[test]
+fn pyarrow_reads_rows() {
+    if !has_pyarrow() {
+        eprintln!("skipped: python3 with pyarrow isn't installed");
+        return;
+    }
+    let path = write("pyarrow.parquet", [tcp_row(5000), icmp_row(7000)]);
+    let output = Command::new("python3")
+        .arg("-c")
+        .arg(
+            "import sys, pyarrow.parquet as pq\n\
+             table = pq.read_table(sys.argv[1])\n\
+             print(table.column_names)\n\
+             print(table.column('protocol').to_pylist(), table.column('port_dst').to_pylist())\n\
+             print(table.column('in_time').cast('int64').to_pylist())",
+        )
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "['in_time', 'out_time', 'latency_usec', 'fuzzy', 'protocol', 'ip_src', 'ip_dst', \
+         'port_src', 'port_dst']\n\
+         ['tcp', 'icmp'] [80, None]\n\
+         [5000, 7000]\n"
+    );
+}
@@ -0,0 +1,169 @@
+use latency_measurement_tool::packet::{ParseOptions, Protocol};
+use latency_measurement_tool::{Error, PacketId, PacketTime, PcapReader};
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+
+use common::{tcp_frame, write_capture};
+
+/// Legacy pcap of one Ethernet frame at 1000 s and `fraction` (usec or nsec by magic), with
+/// fields in byte order of the magic
+fn capture(magic: u32, big_endian: bool, fraction: u32) -> Vec<u8> {
+    let u32_bytes = |value: u32| match big_endian {
+        true => value.to_be_bytes(),
+        false => value.to_le_bytes(),
+    };
+    let u16_bytes = |value: u16| match big_endian {
+        true => value.to_be_bytes(),
+        false => value.to_le_bytes(),
+    };
+    let frame = tcp_frame();
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&u32_bytes(magic));
+    bytes.extend_from_slice(&u16_bytes(2));
+    bytes.extend_from_slice(&u16_bytes(4));
+    bytes.extend_from_slice(&[0; 8]);
+    bytes.extend_from_slice(&u32_bytes(65535));
+    bytes.extend_from_slice(&u32_bytes(1));
+    bytes.extend_from_slice(&u32_bytes(1000));
+    bytes.extend_from_slice(&u32_bytes(fraction));
+    bytes.extend_from_slice(&u32_bytes(frame.len() as u32));
+    bytes.extend_from_slice(&u32_bytes(frame.len() as u32));
+    bytes.extend_from_slice(&frame);
+    bytes
+}
+
+fn read_first(name: &str, bytes: &[u8]) -> Result<(PacketId, PacketTime), Error> {
+    let path = write_capture(&format!("{}.pcap", name), bytes);
+    let reader = PcapReader::new_from_path(path.to_str().unwrap(), Vec::new());
+    fs::remove_file(&path).unwrap();
+    let mut reader = reader?;
+    let packet = reader.next();
+    assert!(reader.take_error().is_none());
+    Ok(packet.expect("capture has one packet"))
+}
+
+#[test]
+fn every_magic_is_read_in_its_byte_order() {
+    for (name, magic, big_endian, fraction, nsec) in [
+        ("usec-le", 0xa1b2_c3d4, false, 250, 250_000),
+        ("usec-be", 0xa1b2_c3d4, true, 250, 250_000),
+        ("nsec-le", 0xa1b2_3c4d, false, 250, 250),
+        ("nsec-be", 0xa1b2_3c4d, true, 250, 250),
+    ] {
+        let (id, time) = read_first(name, &capture(magic, big_endian, fraction)).unwrap();
+        assert!(
+            matches!(
+                id,
+                PacketId::Tcp {
+                    port_src: 1000,
+                    tcp_seq: 100,
+                    ..
+                }
+            ),
+            "{}",
+            name
+        );
+        assert_eq!(
+            time,
+            PacketTime {
+                nsec: 1_000_000_000_000 + nsec
+            },
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn unknown_magic_is_reported_with_its_bytes() {
+    let mut bytes = capture(0xa1b2_c3d4, false, 0);
+    bytes[0] = 0xd5;
+    let error = read_first("bad-magic", &bytes).unwrap_err();
+    assert!(matches!(error, Error::BadMagic([0xd5, 0xc3, 0xb2, 0xa1])));
+    assert!(error.to_string().contains("d5, c3, b2, a1"));
+}
+
+#[test]
+fn read_error_stops_iteration_after_packets_read() {
+    let mut bytes = capture(0xa1b2_c3d4, false, 0);
+    let record = bytes[24..].to_vec();
+    bytes.extend_from_slice(&record[..record.len() - 10]);
+    let path = write_capture("truncated-mid", &bytes);
+    let reader = PcapReader::new_from_path(path.to_str().unwrap(), Vec::new());
+    fs::remove_file(&path).unwrap();
+    let mut reader = reader.unwrap();
+    assert_eq!(reader.by_ref().count(), 1);
+    assert!(matches!(reader.take_error(), Some(Error::Truncated)));
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn size_range_skips_and_counts_packets() {
+    // The frame carries an IPv4 packet of total length 40
+    let path = write_capture("size-range", &capture(0xa1b2_c3d4, false, 0));
+    let read = |size_range| {
+        let mut reader = PcapReader::new_from_path(path.to_str().unwrap(), Vec::new())
+            .unwrap()
+            .with_parse_options(ParseOptions {
+                size_range: Some(size_range),
+                ..Default::default()
+            });
+        let count = reader.by_ref().count();
+        (
+            count,
+            reader.size_excluded_count(),
+            reader.unidentified_count(),
+        )
+    };
+    assert_eq!(read(40..=40), (1, 0, 0));
+    assert_eq!(read(41..=usize::MAX), (0, 1, 0));
+    assert_eq!(read(0..=39), (0, 1, 0));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn frames_are_counted_whether_identified_or_not() {
+    let path = write_capture("frame-count", &capture(0xa1b2_c3d4, false, 5));
+    let mut reader = PcapReader::new_from_path(path.to_str().unwrap(), Vec::new())
+        .unwrap()
+        .with_parse_options(ParseOptions {
+            protocols: vec![Protocol::Icmp],
+            ..Default::default()
+        });
+    fs::remove_file(&path).unwrap();
+    assert_eq!(reader.by_ref().count(), 0);
+    assert_eq!(reader.format(), "pcap");
+    assert_eq!(reader.frame_count(), 1);
+    let span = reader.span().unwrap();
+    assert_eq!(
+        span.first,
+        PacketTime::from_sec_fraction(1000, 5, 1_000_000)
+    );
+    assert_eq!(span.first, span.last);
+}
+
+#[test]
+fn bytes_read_reach_size_of_file() {
+    let bytes = capture(0xa1b2_c3d4, false, 5);
+    let path = write_capture("consumed", &bytes);
+    let mut reader = PcapReader::new_from_path(path.to_str().unwrap(), Vec::new()).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(reader.input_len(), Some(bytes.len() as u64));
+    assert_eq!(reader.by_ref().count(), 1);
+    assert_eq!(reader.consumed_bytes(), bytes.len() as u64);
+}
+
+#[test]
+fn sub_microsecond_latency_is_kept() {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let read = |name: &str| {
+        let path = fixtures.join(name);
+        let mut reader = PcapReader::new_from_path(path.to_str().unwrap(), Vec::new()).unwrap();
+        reader.next().unwrap().1
+    };
+    // 500 nsec apart, neither 0 nor 1 usec
+    let latency = PacketTime::diff(read("nsec_out.pcap"), read("nsec_in.pcap"));
+    assert_eq!(latency, 500);
+}
@@ -0,0 +1,198 @@
+use latency_measurement_tool::reader::TsSource;
+use latency_measurement_tool::{PacketTime, PcapReader};
+use std::fs;
+use std::net::{IpAddr, Ipv6Addr};
+
+mod common;
+
+use common::{tcp_frame, write_capture};
+
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let len = (12 + body.len()) as u32;
+    let mut block = Vec::new();
+    block.extend_from_slice(&block_type.to_le_bytes());
+    block.extend_from_slice(&len.to_le_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&len.to_le_bytes());
+    block
+}
+
+fn section_header() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&0x1a2b_3c4du32.to_le_bytes());
+    body.extend_from_slice(&[1, 0, 0, 0]);
+    body.extend_from_slice(&(-1i64).to_le_bytes());
+    block(0x0a0d_0d0a, &body)
+}
+
+fn interface_description(if_tsresol: u8) -> Vec<u8> {
+    let mut body = vec![1, 0, 0, 0];
+    body.extend_from_slice(&65535u32.to_le_bytes());
+    // if_tsresol option padded to 4 bytes, then opt_endofopt
+    body.extend_from_slice(&[9, 0, 1, 0, if_tsresol, 0, 0, 0]);
+    body.extend_from_slice(&[0, 0, 0, 0]);
+    block(1, &body)
+}
+
+fn enhanced_packet(ts: u64, data: &[u8]) -> Vec<u8> {
+    let mut body = vec![0, 0, 0, 0];
+    body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(ts as u32).to_le_bytes());
+    body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    body.extend_from_slice(data);
+    body.resize(body.len() + (4 - data.len() % 4) % 4, 0);
+    block(6, &body)
+}
+
+/// Enhanced packet block with a custom option (PEN 2989) carrying a hardware timestamp
+fn enhanced_packet_with_hardware_ts(ts: u64, hardware_ts: u64, data: &[u8]) -> Vec<u8> {
+    let mut epb = enhanced_packet(ts, data);
+    // Options go before the trailing block length, which changes
+    epb.truncate(epb.len() - 4);
+    epb.extend_from_slice(&2989u16.to_le_bytes());
+    epb.extend_from_slice(&12u16.to_le_bytes());
+    epb.extend_from_slice(&2989u32.to_le_bytes());
+    epb.extend_from_slice(&hardware_ts.to_le_bytes());
+    epb.extend_from_slice(&[0, 0, 0, 0]);
+    let len = (epb.len() + 4) as u32;
+    epb[4..8].copy_from_slice(&len.to_le_bytes());
+    epb.extend_from_slice(&len.to_le_bytes());
+    epb
+}
+
+#[test]
+fn hardware_timestamp_option_of_given_vendor_is_preferred() {
+    let mut capture = section_header();
+    capture.extend(interface_description(6));
+    capture.extend(enhanced_packet_with_hardware_ts(
+        1_000_000,
+        1_000_150,
+        &tcp_frame(),
+    ));
+    capture.extend(enhanced_packet(2_000_000, &tcp_frame()));
+    let path = write_capture("hardware-ts.pcapng", &capture);
+    let path = path.to_str().unwrap();
+    let times = |ts_source: TsSource, pen: Option<u32>| {
+        let mut reader = PcapReader::new_from_path(path, Vec::new())
+            .unwrap()
+            .with_ts_source(ts_source)
+            .with_hardware_ts_pen(pen);
+        let times: Vec<u64> = reader.by_ref().map(|(_, time)| time.nsec).collect();
+        (times, reader.hardware_timestamp_count())
+    };
+    let hardware = times(TsSource::Hardware, Some(2989));
+    let software = times(TsSource::Software, Some(2989));
+    // The layout of custom options of other vendors is unknown
+    let other_vendor = times(TsSource::Hardware, Some(12345));
+    let no_vendor = times(TsSource::Hardware, None);
+    fs::remove_file(path).unwrap();
+    assert_eq!(hardware, (vec![1_000_150_000, 2_000_000_000], 1));
+    assert_eq!(software, (vec![1_000_000_000, 2_000_000_000], 0));
+    assert_eq!(other_vendor, software);
+    assert_eq!(no_vendor, software);
+}
+
+#[test]
+fn power_of_two_ts_resolution() {
+    // 2^20 units per second, timestamp of 3.5 seconds
+    let mut capture = section_header();
+    capture.extend(interface_description(0x80 | 20));
+    capture.extend(enhanced_packet(7 << 19, &tcp_frame()));
+    let path = write_capture("tsresol-power-of-two.pcapng", &capture);
+    let mut reader = PcapReader::new_from_path(path.to_str().unwrap(), Vec::new()).unwrap();
+    let (_, time) = reader.next().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        time,
+        PacketTime {
+            nsec: 3_500_000_000
+        }
+    );
+}
+
+#[test]
+fn interface_and_host_names() {
+    let mut capture = section_header();
+    // Interface with if_name option, then one without
+    let mut body = vec![1, 0, 0, 0];
+    body.extend_from_slice(&65535u32.to_le_bytes());
+    body.extend_from_slice(&[2, 0, 4, 0]);
+    body.extend_from_slice(b"eth0");
+    body.extend_from_slice(&[0, 0, 0, 0]);
+    capture.extend(block(1, &body));
+    capture.extend(interface_description(6));
+    // IPv4 record of two names, IPv6 record of one, end of records
+    let mut body = vec![1, 0, 18, 0, 10, 0, 0, 1];
+    body.extend_from_slice(b"client\0backup\0\0\0");
+    body.extend_from_slice(&[2, 0, 21, 0]);
+    body.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+    body.extend_from_slice(b"host\0\0\0\0");
+    body.extend_from_slice(&[0, 0, 0, 0]);
+    capture.extend(block(4, &body));
+    capture.extend(enhanced_packet(0, &tcp_frame()));
+    let path = write_capture("names.pcapng", &capture);
+    let mut reader = PcapReader::new_from_path(path.to_str().unwrap(), Vec::new()).unwrap();
+    assert_eq!(reader.by_ref().count(), 1);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(reader.interface_names(), ["eth0", "1"]);
+    let names = reader.host_names();
+    assert_eq!(names.len(), 2);
+    assert_eq!(names[&IpAddr::from([10, 0, 0, 1])], "client");
+    assert_eq!(names[&"2001:db8::1".parse::<IpAddr>().unwrap()], "host");
+}
+
+/// Section of big-endian blocks with one interface of nanosecond resolution and one packet
+fn big_endian_section(ts: u64, data: &[u8]) -> Vec<u8> {
+    let block = |block_type: u32, body: &[u8]| {
+        let len = (12 + body.len()) as u32;
+        let mut block = Vec::new();
+        block.extend_from_slice(&block_type.to_be_bytes());
+        block.extend_from_slice(&len.to_be_bytes());
+        block.extend_from_slice(body);
+        block.extend_from_slice(&len.to_be_bytes());
+        block
+    };
+    let mut body = Vec::new();
+    body.extend_from_slice(&0x1a2b_3c4du32.to_be_bytes());
+    body.extend_from_slice(&[0, 1, 0, 0]);
+    body.extend_from_slice(&(-1i64).to_be_bytes());
+    let mut section = block(0x0a0d_0d0a, &body);
+    let mut body = vec![0, 1, 0, 0];
+    body.extend_from_slice(&1500u32.to_be_bytes());
+    body.extend_from_slice(&[0, 9, 0, 1, 9, 0, 0, 0]);
+    body.extend_from_slice(&[0, 0, 0, 0]);
+    section.extend(block(1, &body));
+    let mut body = vec![0, 0, 0, 0];
+    body.extend_from_slice(&((ts >> 32) as u32).to_be_bytes());
+    body.extend_from_slice(&(ts as u32).to_be_bytes());
+    body.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    body.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    body.extend_from_slice(data);
+    body.resize(body.len() + (4 - data.len() % 4) % 4, 0);
+    section.extend(block(6, &body));
+    section
+}
+
+#[test]
+fn sections_have_interfaces_of_their_own() {
+    // Concatenated captures: microsecond interface in the first section, nanosecond one of
+    // other byte order in the second. Packets of both refer to interface 0 of their section
+    let mut capture = section_header();
+    capture.extend(interface_description(6));
+    capture.extend(enhanced_packet(1_000_000, &tcp_frame()));
+    capture.extend(big_endian_section(2_000_000_000, &tcp_frame()));
+    capture.extend(section_header());
+    capture.extend(interface_description(6));
+    capture.extend(enhanced_packet(3_000_000, &tcp_frame()));
+    let path = write_capture("sections", &capture);
+    let mut reader = PcapReader::new_from_path(path.to_str().unwrap(), Vec::new()).unwrap();
+    let times: Vec<u64> = reader.by_ref().map(|(_, time)| time.nsec).collect();
+    fs::remove_file(&path).unwrap();
+    assert!(reader.take_error().is_none());
+    assert_eq!(times, [1_000_000_000, 2_000_000_000, 3_000_000_000]);
+    // Interfaces of every section are reported, the coarsest resolution wins
+    assert_eq!(reader.interface_names(), ["0", "1", "2"]);
+    assert_eq!(reader.snaplen(), Some(1500));
+    assert_eq!(reader.timestamp_resolution_nsec(), 1000);
+}
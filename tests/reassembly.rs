@@ -0,0 +1,52 @@
+use latency_measurement_tool::reassembly::{Framing, Reassembler};
+use latency_measurement_tool::{PacketId, PacketTime};
+use pcap_parser::Linktype;
+use std::collections::VecDeque;
+
+/// IPv4 packet with TCP segment from 10.0.0.1:1000 to 10.0.0.2:80
+fn segment(seq: u32, payload: &[u8]) -> Vec<u8> {
+    let total_len = (40 + payload.len()) as u16;
+    let mut packet = vec![0x45, 0];
+    packet.extend_from_slice(&total_len.to_be_bytes());
+    packet.extend_from_slice(&[0, 1, 0, 0, 64, 6, 0, 0]);
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend_from_slice(&[0x03, 0xe8, 0, 80]);
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 5, 0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+fn time(usec: u64) -> PacketTime {
+    PacketTime { nsec: usec * 1000 }
+}
+
+fn message_lens(messages: &VecDeque<(PacketId, PacketTime)>) -> Vec<(u32, PacketTime)> {
+    messages
+        .iter()
+        .map(|(id, time)| match id {
+            PacketId::TcpMessage { message_len, .. } => (*message_len, *time),
+            id => panic!("not a message: {}", id),
+        })
+        .collect()
+}
+
+#[test]
+fn out_of_order_and_retransmitted_segments() {
+    let mut reassembler = Reassembler::new(Framing::Delimiter(b"\n".to_vec()), None);
+    let mut messages = VecDeque::new();
+    let segments: [(u32, &[u8]); 4] = [
+        (100, b"first\nsec"),
+        (113, b"third\n"),
+        (100, b"first\nsec"),
+        (109, b"ond\n"),
+    ];
+    for (i, (seq, payload)) in segments.iter().enumerate() {
+        let packet = segment(*seq, payload);
+        assert!(reassembler.push(Linktype::RAW, &packet, time(i as u64), &mut messages));
+    }
+    assert_eq!(
+        message_lens(&messages),
+        vec![(6, time(0)), (7, time(3)), (6, time(3))]
+    );
+}
@@ -0,0 +1,74 @@
+//! `--sample` with `--seed` runs the binary on generated captures: the same seed must give
+//! identical output, so sampled runs can be reproduced
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod common;
+
+use common::temp_path;
+
+/// Number of captures written so far, telling apart files of runs in parallel
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Little-endian legacy pcap of 20 Ethernet frames with IPv4 TCP segments of sequence numbers
+/// 0 to 19, the i-th one at 1000 s and `usec(i)`
+fn capture(usec: impl Fn(u32) -> u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for field in [0xa1b2_c3d4u32, 0x0004_0002, 0, 0, 65535, 1] {
+        bytes.extend_from_slice(&field.to_le_bytes());
+    }
+    for seq in 0..20u32 {
+        let mut frame = vec![
+            0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x08, 0x00,
+        ];
+        frame.extend_from_slice(&[0x45, 0, 0, 40, 0, 1, 0, 0, 64, 6, 0, 0]);
+        frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0x03, 0xe8, 0, 80]);
+        frame.extend_from_slice(&seq.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 5, 0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+        for field in [1000, usec(seq), frame.len() as u32, frame.len() as u32] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&frame);
+    }
+    bytes
+}
+
+/// Writes captures of packets whose latencies differ, so that output shows which are sampled
+fn write_captures() -> (PathBuf, PathBuf) {
+    let run = RUNS.fetch_add(1, Ordering::Relaxed);
+    let inbound = temp_path(&format!("sampling-{}-in.pcap", run));
+    let outbound = temp_path(&format!("sampling-{}-out.pcap", run));
+    fs::write(&inbound, capture(|seq| 100 * seq)).unwrap();
+    fs::write(&outbound, capture(|seq| 101 * seq + 10)).unwrap();
+    (inbound, outbound)
+}
+
+/// Stdout of a run sampling half of inbound packets with seed
+fn sampled_run(seed: &str) -> Vec<u8> {
+    let (inbound, outbound) = write_captures();
+    let output = Command::new(env!("CARGO_BIN_EXE_latency_measurement_tool"))
+        .args(["--sample", "0.5", "--seed", seed])
+        .arg(&inbound)
+        .arg(&outbound)
+        .output()
+        .unwrap();
+    fs::remove_file(inbound).unwrap();
+    fs::remove_file(outbound).unwrap();
+    assert!(output.status.success());
+    output.stdout
+}
+
+#[test]
+fn same_seed_gives_identical_output() {
+    let first = sampled_run("42");
+    assert!(!first.is_empty());
+    assert_eq!(first, sampled_run("42"));
+}
+
+#[test]
+fn other_seed_samples_other_packets() {
+    assert_ne!(sampled_run("42"), sampled_run("7"));
+}
@@ -0,0 +1,79 @@
+use latency_measurement_tool::stats::{Alert, AlertChange, InterarrivalJitter, MatchRate, Top};
+
+fn changes(alert: &mut Alert, values: &[f64]) -> Vec<Option<AlertChange>> {
+    values
+        .iter()
+        .map(|value| alert.update(Some(*value)))
+        .collect()
+}
+
+#[test]
+fn alert_is_raised_and_cleared_after_intervals_in_a_row() {
+    let mut alert = Alert::new(100.0, 80.0, 3);
+    assert_eq!(
+        changes(&mut alert, &[120.0, 120.0, 90.0, 120.0, 120.0, 120.0]),
+        [None, None, None, None, None, Some(AlertChange::Raised)]
+    );
+    assert!(alert.is_active());
+    assert_eq!(
+        changes(&mut alert, &[70.0, 70.0, 90.0, 70.0, 70.0, 70.0]),
+        [None, None, None, None, None, Some(AlertChange::Cleared)]
+    );
+    assert!(!alert.is_active());
+}
+
+#[test]
+fn value_between_thresholds_keeps_the_state() {
+    let mut alert = Alert::new(100.0, 80.0, 1);
+    assert_eq!(
+        changes(&mut alert, &[90.0, 101.0]),
+        [None, Some(AlertChange::Raised)]
+    );
+    assert_eq!(
+        changes(&mut alert, &[99.0, 85.0, 101.0]),
+        [None, None, None]
+    );
+    assert_eq!(alert.update(None), None);
+    assert_eq!(alert.update(Some(79.0)), Some(AlertChange::Cleared));
+}
+
+#[test]
+fn top_of_equal_keys_does_not_depend_on_order() {
+    // Many items of equal first key, told apart by the second one
+    let items: Vec<(u32, u32)> = (0..100).map(|i| (7 * (i % 3), (i * 37) % 100)).collect();
+    let top = |items: &mut dyn Iterator<Item = (u32, u32)>| {
+        let mut top = Top::new(10);
+        for item in items {
+            top.push(item);
+        }
+        top.into_sorted()
+    };
+    let forward = top(&mut items.iter().copied());
+    assert_eq!(forward, top(&mut items.iter().rev().copied()));
+    let mut expected: Vec<(u32, u32)> = items.clone();
+    expected.sort_unstable_by(|a, b| b.cmp(a));
+    expected.truncate(10);
+    assert_eq!(forward, expected);
+    assert!(forward.iter().all(|(key, _)| *key == 14));
+}
+
+#[test]
+fn interarrival_jitter_of_known_latencies() {
+    let mut jitter = InterarrivalJitter::new();
+    jitter.update(100);
+    assert_eq!(jitter.value(), 0.0);
+    // D = 20, then D = 10 (the sign of the difference doesn't matter)
+    jitter.update(120);
+    assert_eq!(jitter.value(), 20.0 / 16.0);
+    jitter.update(110);
+    assert_eq!(jitter.value(), 20.0 / 16.0 + (10.0 - 20.0 / 16.0) / 16.0);
+    assert_eq!(jitter.value(), 1.796875);
+}
+
+#[test]
+fn match_rate_counts_since_previous_update() {
+    let mut match_rate = MatchRate::new();
+    assert_eq!(match_rate.update(8, 10), (8, 10));
+    assert_eq!(match_rate.update(9, 20), (1, 10));
+    assert_eq!(match_rate.update(9, 20), (0, 0));
+}
@@ -0,0 +1,68 @@
+use latency_measurement_tool::synthetic::{Generator, PcapWriter, SyntheticPacket};
+use latency_measurement_tool::{OutboundTable, PacketTime, PcapReader};
+use std::collections::HashSet;
+use std::fs;
+
+mod common;
+
+use common::temp_path;
+
+fn generate(seed: u64, count: usize) -> Vec<SyntheticPacket> {
+    Generator::new(seed, 4, 10_000, 10..=20)
+        .take(count)
+        .collect()
+}
+
+#[test]
+fn same_seed_gives_same_packets() {
+    assert_eq!(generate(7, 100), generate(7, 100));
+    assert_ne!(generate(7, 100), generate(8, 100));
+}
+
+#[test]
+fn latencies_are_whole_microseconds_within_range() {
+    for packet in generate(0, 1000) {
+        assert_eq!(packet.latency_nsec % 1000, 0);
+        assert!((10_000..=20_000).contains(&packet.latency_nsec));
+        assert_eq!(
+            PacketTime::diff(packet.out_time(), packet.in_time),
+            packet.latency_nsec as i64
+        );
+    }
+}
+
+#[test]
+fn written_captures_match_with_injected_latencies() {
+    let packets = generate(3, 500);
+    let write = |side: &str, time: fn(&SyntheticPacket) -> PacketTime| {
+        let path = temp_path(&format!("synthetic-{}.pcap", side));
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        for packet in &packets {
+            writer.write(time(packet), &packet.frame).unwrap();
+        }
+        fs::write(&path, writer.finish().unwrap()).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+    let in_path = write("in", |packet| packet.in_time);
+    let out_path = write("out", SyntheticPacket::out_time);
+    let read = |path: &str| {
+        let mut reader = PcapReader::new_from_path(path, Vec::new()).unwrap();
+        let packets: Vec<_> = reader.by_ref().collect();
+        assert!(reader.take_error().is_none());
+        fs::remove_file(path).unwrap();
+        packets
+    };
+    let inbound = read(&in_path);
+    let outbound = read(&out_path);
+    assert_eq!(inbound.len(), packets.len());
+    let identities: HashSet<_> = inbound.iter().map(|(id, _)| id).collect();
+    assert_eq!(identities.len(), packets.len());
+    let mut table = OutboundTable::new();
+    for (id, time) in outbound {
+        table.insert(id, time);
+    }
+    for ((id, in_time), packet) in inbound.into_iter().zip(&packets) {
+        assert_eq!(in_time, packet.in_time);
+        assert_eq!(table.take(&id, in_time), Some(packet.out_time()));
+    }
+}
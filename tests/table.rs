@@ -0,0 +1,145 @@
+use latency_measurement_tool::table::{MissReason, Pairing};
+use latency_measurement_tool::{OutboundTable, PacketId, PacketTime};
+
+fn id(tcp_seq: u32) -> PacketId {
+    PacketId::Tcp {
+        ip_src: "10.0.0.1".parse().unwrap(),
+        ip_dst: "10.0.0.2".parse().unwrap(),
+        port_src: 1000,
+        port_dst: 80,
+        tcp_seq,
+        tcp_ack: 5,
+    }
+}
+
+fn time(usec: u64) -> PacketTime {
+    PacketTime { nsec: usec * 1000 }
+}
+
+#[test]
+fn nearest_pairing_of_interleaved_retransmissions() {
+    // Segments 1 and 2 are both retransmitted, the copies interleave with each other
+    let outbound = [(1, 110), (2, 210), (1, 5_120), (2, 5_230)];
+    let mut table = OutboundTable::new().with_pairing(Pairing::Nearest);
+    for (seq, usec) in outbound {
+        table.insert(id(seq), time(usec));
+    }
+    assert_eq!(table.recurring(), 2);
+    // Inbound copies arrive in other order than they were inserted
+    assert_eq!(table.take(&id(1), time(5_000)), Some(time(5_120)));
+    assert_eq!(table.take(&id(2), time(200)), Some(time(210)));
+    assert_eq!(table.take(&id(2), time(5_010)), Some(time(5_230)));
+    assert_eq!(table.take(&id(1), time(100)), Some(time(110)));
+    assert_eq!(table.take(&id(1), time(100)), None);
+    assert!(table.is_empty());
+}
+
+#[test]
+fn nearest_pairing_prefers_earlier_of_equally_distant() {
+    let mut table = OutboundTable::new().with_pairing(Pairing::Nearest);
+    table.insert(id(1), time(300));
+    table.insert(id(1), time(100));
+    assert_eq!(table.take(&id(1), time(200)), Some(time(100)));
+    assert_eq!(table.take(&id(1), time(0)), Some(time(300)));
+}
+
+#[test]
+fn first_pairing_follows_time_order() {
+    let mut table = OutboundTable::new();
+    table.insert(id(1), time(300));
+    table.insert(id(1), time(100));
+    assert_eq!(table.take(&id(1), time(1_000)), Some(time(100)));
+    assert_eq!(table.take(&id(1), time(1_000)), Some(time(300)));
+}
+
+#[test]
+fn miss_reasons_follow_history_of_identity() {
+    let mut table = OutboundTable::new().with_max_entries(2).with_miss_reasons();
+    for seq in 1..=3 {
+        table.insert(id(seq), time(100 * seq as u64));
+    }
+    assert_eq!(table.take(&id(2), time(150)), Some(time(200)));
+    assert_eq!(table.take(&id(1), time(50)), None);
+    assert_eq!(table.miss_reason(&id(1)), MissReason::Evicted);
+    assert_eq!(table.take(&id(2), time(150)), None);
+    assert_eq!(table.miss_reason(&id(2)), MissReason::Taken);
+    assert_eq!(table.miss_reason(&id(4)), MissReason::Absent);
+    // An identity which comes back is in the table again
+    table.insert(id(1), time(400));
+    assert_eq!(table.take(&id(1), time(350)), Some(time(400)));
+}
+
+#[test]
+fn paired_identities_are_remembered_for_retransmissions() {
+    let mut table = OutboundTable::new().with_retransmissions();
+    table.insert(id(1), time(100));
+    table.insert(id(1), time(300));
+    table.insert(id(2), time(200));
+    assert!(!table.was_paired(&id(1)));
+    assert_eq!(table.take(&id(1), time(50)), Some(time(100)));
+    // The second occurrence is left, an extra copy of a paired packet
+    assert!(table.was_paired(&id(1)));
+    assert!(!table.was_paired(&id(2)));
+    assert_eq!(table.take(&id(2), time(150)), Some(time(200)));
+    assert_eq!(table.take(&id(2), time(250)), None);
+    assert!(table.was_paired(&id(2)));
+    // Without the option nothing is remembered
+    let mut table = OutboundTable::new();
+    table.insert(id(1), time(100));
+    assert_eq!(table.take(&id(1), time(50)), Some(time(100)));
+    assert!(!table.was_paired(&id(1)));
+}
+
+#[test]
+fn fallback_takes_occurrences_left_by_exact_lookups() {
+    let mut table = OutboundTable::new().with_fallback_index();
+    let key = id(1).fuzzy_key();
+    table.insert_with_fallback(id(1), time(100), Some(key));
+    table.insert_with_fallback(id(2), time(200), Some(key));
+    table.insert(id(3), time(300));
+    // The earliest occurrence of the key is already taken exactly
+    assert_eq!(table.take(&id(1), time(0)), Some(time(100)));
+    assert_eq!(table.take_fallback(&key), Some(time(200)));
+    assert_eq!(table.take_fallback(&key), None);
+    assert_eq!(table.take_fallback(&id(3).fuzzy_key()), None);
+    assert_eq!(table.len(), 1);
+}
+
+#[test]
+fn fallback_index_is_compacted() {
+    let mut table = OutboundTable::new().with_fallback_index();
+    for seq in 0..10_000 {
+        table.insert_with_fallback(id(seq), time(seq as u64), Some(id(seq).fuzzy_key()));
+        assert!(table.take(&id(seq), time(seq as u64)).is_some());
+    }
+    table.insert_with_fallback(id(1), time(1), Some(id(1).fuzzy_key()));
+    assert_eq!(table.take_fallback(&id(1).fuzzy_key()), Some(time(1)));
+    assert!(table.is_empty());
+}
+
+#[test]
+fn bounded_table_takes_more_memory_per_entry() {
+    let unbounded = OutboundTable::entry_bytes(false);
+    let bounded = OutboundTable::entry_bytes(true);
+    assert!(unbounded >= std::mem::size_of::<PacketId>() + std::mem::size_of::<PacketTime>());
+    assert!(bounded > unbounded);
+}
+
+#[test]
+fn max_distance_limits_candidates_of_pairing() {
+    let mut table = OutboundTable::new()
+        .with_pairing(Pairing::First)
+        .with_max_distance(1_000_000);
+    // The identity recurs 100 s apart, e.g. sequence numbers wrapped on a fast link
+    table.insert(id(1), time(0));
+    table.insert(id(1), time(100_000_000));
+    assert_eq!(
+        table.take(&id(1), time(100_000_150)),
+        Some(time(100_000_000))
+    );
+    assert_eq!(table.take(&id(1), time(300_000_000)), None);
+    assert_eq!(table.rejected(), 1);
+    assert_eq!(table.miss_reason(&id(1)), MissReason::OutsideWindow);
+    assert_eq!(table.take(&id(1), time(500)), Some(time(0)));
+    assert_eq!(table.rejected(), 1);
+}
@@ -0,0 +1,18 @@
+use latency_measurement_tool::PacketTime;
+
+#[test]
+fn round_to_nearest_multiple() {
+    let time = |nsec| PacketTime { nsec };
+    assert_eq!(time(1_149).round_to(100), time(1_100));
+    assert_eq!(time(1_150).round_to(100), time(1_200));
+    assert_eq!(time(1_150).round_to(1), time(1_150));
+}
+
+#[test]
+fn round_to_huge_resolution_does_not_overflow() {
+    let time = PacketTime {
+        nsec: u64::MAX - 10,
+    };
+    assert_eq!(time.round_to(u64::MAX).nsec, u64::MAX);
+    assert_eq!(PacketTime { nsec: 10 }.round_to(u64::MAX).nsec, 0);
+}
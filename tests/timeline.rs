@@ -0,0 +1,79 @@
+use latency_measurement_tool::timeline::{Alignment, Span, WIDTH};
+use latency_measurement_tool::PacketTime;
+
+fn at(msec: u64) -> PacketTime {
+    PacketTime {
+        nsec: 1_000_000_000_000 + msec * 1_000_000,
+    }
+}
+
+fn span(first: u64, last: u64) -> Option<Span> {
+    Some(Span {
+        first: at(first),
+        last: at(last),
+    })
+}
+
+#[test]
+fn span_of_unordered_times() {
+    assert_eq!(Span::of([at(5), at(1), at(9), at(3)]), span(1, 9));
+    assert_eq!(Span::of([]), None);
+}
+
+#[test]
+fn bars_share_time_axis() {
+    let alignment = Alignment {
+        inbound: span(0, 590),
+        outbound: span(300, 1180),
+    };
+    let text = alignment.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Capture alignment (1000.000000000 to 1001.180000000, 1.180000 sec):"
+    );
+    let bar = |filled: std::ops::RangeInclusive<usize>| -> String {
+        (0..WIDTH)
+            .map(|i| if filled.contains(&i) { '#' } else { ' ' })
+            .collect()
+    };
+    assert_eq!(
+        lines[1],
+        format!("in  |{}| 1000.000000000 to 1000.590000000", bar(0..=29))
+    );
+    assert_eq!(
+        lines[2],
+        format!("out |{}| 1000.300000000 to 1001.180000000", bar(15..=59))
+    );
+    assert_eq!(lines[3], "Overlap (sec): 0.290000");
+}
+
+#[test]
+fn disjoint_captures_are_warned_about() {
+    let alignment = Alignment {
+        inbound: span(2000, 3000),
+        outbound: span(0, 500),
+    };
+    let text = alignment.to_string();
+    assert!(text.ends_with(
+        "WARNING: the captures don't overlap, they are 1.500000 sec apart. Packets can't be matched unless the clocks differ by about this much"
+    ));
+}
+
+#[test]
+fn empty_capture_has_no_bar() {
+    let alignment = Alignment {
+        inbound: span(0, 10),
+        outbound: None,
+    };
+    assert_eq!(alignment.to_string(), "Capture alignment:\nout no packets");
+}
+
+#[test]
+fn overlap_is_negative_gap_of_apart_captures() {
+    let overlap = |inbound, outbound| Alignment { inbound, outbound }.overlap_nsec();
+    assert_eq!(overlap(span(0, 500), span(300, 900)), Some(200_000_000));
+    assert_eq!(overlap(span(0, 900), span(300, 500)), Some(200_000_000));
+    assert_eq!(overlap(span(0, 100), span(400, 900)), Some(-300_000_000));
+    assert_eq!(overlap(span(0, 100), None), None);
+}
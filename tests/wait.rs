@@ -0,0 +1,113 @@
+//! `--wait` runs the binary on an inbound capture written to a FIFO in two parts, as a capture
+//! running alongside would write it. Every packet must be matched as it comes, and Ctrl-C must
+//! end the run with the summary, as if the file ended there
+#![cfg(unix)]
+
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+mod common;
+
+use common::temp_path;
+
+/// Ethernet frame with IPv4 TCP segment of 10.0.0.1:1000 to 10.0.0.2:80 with sequence number
+/// `seq`
+fn tcp_frame(seq: u32) -> Vec<u8> {
+    let mut frame = vec![
+        0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x08, 0x00,
+    ];
+    frame.extend_from_slice(&[0x45, 0, 0, 40, 0, 1, 0, 0, 64, 6, 0, 0]);
+    frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0x03, 0xe8, 0, 80]);
+    frame.extend_from_slice(&seq.to_be_bytes());
+    frame.extend_from_slice(&[0, 0, 0, 5, 0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+    frame
+}
+
+/// Header of little-endian legacy pcap of Ethernet frames in microseconds
+fn pcap_header() -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for field in [0xa1b2_c3d4u32, 0x0004_0002, 0, 0, 65535, 1] {
+        bytes.extend_from_slice(&field.to_le_bytes());
+    }
+    bytes
+}
+
+/// Records of frames with sequence numbers `seqs`, each at 1000 s and `usec(seq)`
+fn pcap_records(seqs: impl Iterator<Item = u32>, usec: impl Fn(u32) -> u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for seq in seqs {
+        let frame = tcp_frame(seq);
+        for field in [1000, usec(seq), frame.len() as u32, frame.len() as u32] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&frame);
+    }
+    bytes
+}
+
+fn run(inbound: &Path, outbound: &Path, wait: bool) -> Child {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_latency_measurement_tool"));
+    if wait {
+        command.arg("--wait");
+    }
+    command
+        .arg(inbound)
+        .arg(outbound)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap()
+}
+
+#[test]
+fn fifo_written_in_parts_is_matched_until_ctrl_c() {
+    // Packet i is seen 100 + i usec later on the outbound side
+    let outbound = temp_path("wait_out.pcap");
+    let mut bytes = pcap_header();
+    bytes.extend(pcap_records(0..10, |seq| 200 * seq + 100 + seq));
+    fs::write(&outbound, bytes).unwrap();
+    let mut first = pcap_header();
+    first.extend(pcap_records(0..5, |seq| 200 * seq));
+    let second = pcap_records(5..10, |seq| 200 * seq);
+    // Output of the whole capture read from a regular file
+    let inbound = temp_path("wait_in.pcap");
+    fs::write(&inbound, [first.as_slice(), &second].concat()).unwrap();
+    let expected = run(&inbound, &outbound, false)
+        .wait_with_output()
+        .unwrap()
+        .stdout;
+    let expected: Vec<String> = expected.as_slice().lines().map(Result::unwrap).collect();
+    assert_eq!(expected[..2], ["100", "101"]);
+
+    let fifo = temp_path("wait_in.fifo");
+    let _ = fs::remove_file(&fifo);
+    let fifo_path = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+    // SAFETY: the path is a valid C string
+    assert_eq!(unsafe { libc::mkfifo(fifo_path.as_ptr(), 0o600) }, 0);
+
+    let mut child = run(&fifo, &outbound, true);
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut writer = OpenOptions::new().write(true).open(&fifo).unwrap();
+    writer.write_all(&first).unwrap();
+    let mut actual: Vec<String> = lines.by_ref().take(5).map(Result::unwrap).collect();
+    assert_eq!(actual, expected[..5]);
+    writer.write_all(&second).unwrap();
+    actual.extend(lines.by_ref().take(5).map(Result::unwrap));
+    assert_eq!(actual, expected[..10]);
+    // The writer is still open, so the tool waits for more until Ctrl-C
+    // SAFETY: plain call of kill() on the child
+    assert_eq!(
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) },
+        0
+    );
+    actual.extend(lines.map(Result::unwrap));
+    assert!(child.wait().unwrap().success());
+    assert_eq!(actual, expected);
+    drop(writer);
+    fs::remove_file(&fifo).unwrap();
+    fs::remove_file(&inbound).unwrap();
+    fs::remove_file(&outbound).unwrap();
+}